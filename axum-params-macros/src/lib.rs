@@ -0,0 +1,102 @@
+use proc_macro::TokenStream;
+use proc_macro2::TokenStream as TokenStream2;
+use quote::{format_ident, quote};
+use syn::{Data, DeriveInput, Fields, LitStr, parse_macro_input};
+
+/// Derives `axum_params::ParamSources` from `#[param(...)]` field attributes.
+///
+/// Accepted forms: `#[param(path)]`, `#[param(query, body)]` and
+/// `#[param(source = "path")]`. The field name honors `#[serde(rename = "...")]`.
+#[proc_macro_derive(ParamSources, attributes(param))]
+pub fn derive_param_sources(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+    expand(input)
+        .unwrap_or_else(syn::Error::into_compile_error)
+        .into()
+}
+
+fn expand(input: DeriveInput) -> syn::Result<TokenStream2> {
+    let Data::Struct(data) = &input.data else {
+        return Err(syn::Error::new_spanned(
+            &input,
+            "ParamSources can only be derived for structs",
+        ));
+    };
+    let Fields::Named(fields) = &data.fields else {
+        return Err(syn::Error::new_spanned(
+            &input,
+            "ParamSources requires named fields",
+        ));
+    };
+
+    let mut entries = Vec::new();
+    for field in &fields.named {
+        let mut sources = Vec::new();
+        let mut name = field.ident.as_ref().unwrap().to_string();
+        for attr in &field.attrs {
+            if attr.path().is_ident("param") {
+                attr.parse_nested_meta(|meta| {
+                    if meta.path.is_ident("source") {
+                        let lit: LitStr = meta.value()?.parse()?;
+                        sources.extend(parse_source(&lit.value(), lit.span())?);
+                    } else if let Some(ident) = meta.path.get_ident() {
+                        sources.extend(parse_source(&ident.to_string(), ident.span())?);
+                    } else {
+                        return Err(meta.error("expected a source name"));
+                    }
+                    Ok(())
+                })?;
+            } else if attr.path().is_ident("serde") {
+                // Only look for `rename`, everything else belongs to serde
+                let _ = attr.parse_nested_meta(|meta| {
+                    if meta.path.is_ident("rename") {
+                        if let Ok(value) = meta.value() {
+                            let lit: LitStr = value.parse()?;
+                            name = lit.value();
+                        }
+                    } else if meta.input.peek(syn::token::Paren) {
+                        let content;
+                        syn::parenthesized!(content in meta.input);
+                        content.parse::<TokenStream2>()?;
+                    } else if let Ok(value) = meta.value() {
+                        value.parse::<syn::Expr>()?;
+                    }
+                    Ok(())
+                });
+            }
+        }
+        if !sources.is_empty() {
+            entries.push(quote! { (#name, &[#(::axum_params::Source::#sources),*]) });
+        }
+    }
+
+    let ident = &input.ident;
+    let (impl_generics, ty_generics, where_clause) = input.generics.split_for_impl();
+    Ok(quote! {
+        impl #impl_generics ::axum_params::ParamSources for #ident #ty_generics #where_clause {
+            fn expected_sources() -> &'static [(&'static str, &'static [::axum_params::Source])] {
+                &[#(#entries),*]
+            }
+        }
+    })
+}
+
+fn parse_source(name: &str, span: proc_macro2::Span) -> syn::Result<Vec<syn::Ident>> {
+    let variants: &[&str] = match name {
+        "path" => &["Path"],
+        "query" => &["Query"],
+        "json" => &["Json"],
+        "form" => &["Form"],
+        "multipart" => &["Multipart"],
+        "raw" => &["Raw"],
+        "extension" => &["Extension"],
+        "body" => &["Json", "Form", "Multipart", "Raw"],
+        _ => {
+            return Err(syn::Error::new(
+                span,
+                "unknown source, expected one of: path, query, json, form, multipart, raw, extension, body",
+            ));
+        }
+    };
+    Ok(variants.iter().map(|v| format_ident!("{}", v)).collect())
+}
@@ -0,0 +1,87 @@
+//! Parses large query strings, run with `cargo bench --bench query_parser`.
+//!
+//! Compare a change against `main` with `cargo bench --bench query_parser -- --save-baseline
+//! main` on `main`, then `-- --baseline main` on the branch.
+
+use std::{collections::HashMap, hint::black_box};
+
+use axum_params::query_parser::QueryParser;
+use criterion::{BenchmarkId, Criterion, Throughput, criterion_group, criterion_main};
+
+fn bench(c: &mut Criterion, group: &str, queries: impl IntoIterator<Item = (usize, String)>) {
+    let parser = QueryParser::new(None).with_max_params(usize::MAX);
+    let mut group = c.benchmark_group(group);
+    for (n, query) in queries {
+        group.throughput(Throughput::Bytes(query.len() as u64));
+        group.bench_with_input(BenchmarkId::from_parameter(n), &query, |b, query| {
+            b.iter(|| {
+                let mut params = HashMap::new();
+                parser
+                    .parse_nested_query_into(&mut params, black_box(query.as_str()))
+                    .unwrap();
+                params
+            })
+        });
+    }
+    group.finish();
+}
+
+fn join(pairs: impl Iterator<Item = String>) -> String {
+    pairs.collect::<Vec<_>>().join("&")
+}
+
+fn large_queries(c: &mut Criterion) {
+    let sizes = [100, 1_000, 10_000];
+    bench(
+        c,
+        "flat keys",
+        sizes.map(|n| (n, join((0..n).map(|i| format!("key{i}=value{i}"))))),
+    );
+    bench(
+        c,
+        "nested keys",
+        sizes.map(|n| {
+            let pairs = (0..n).map(|i| format!("user[profile][field{i}]=value{i}"));
+            (n, join(pairs))
+        }),
+    );
+    bench(
+        c,
+        "array of hashes",
+        sizes.map(|n| {
+            let pairs = (0..n).map(|i| format!("items[][id]={i}&items[][name]=item{i}"));
+            (n, join(pairs))
+        }),
+    );
+}
+
+/// Hundreds of pairs as sent by real forms, mostly plain with some encoded values.
+fn forms(c: &mut Criterion) {
+    let sizes = [100, 500];
+    bench(
+        c,
+        "plain form",
+        sizes.map(|n| {
+            let pairs = (0..n).map(|i| {
+                format!(
+                    "order[items][{i}][sku]=SKU-{i:05}&order[items][{i}][qty]={}",
+                    i % 7 + 1
+                )
+            });
+            (n, join(pairs))
+        }),
+    );
+    bench(
+        c,
+        "encoded form",
+        sizes.map(|n| {
+            let pairs = (0..n).map(|i| {
+                format!("filter[q{i}]=caf%C3%A9+au+lait+%26+cr%C3%A8me&filter[tag{i}]=new%2Fsale")
+            });
+            (n, join(pairs))
+        }),
+    );
+}
+
+criterion_group!(benches, large_queries, forms);
+criterion_main!(benches);
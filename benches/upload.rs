@@ -0,0 +1,64 @@
+//! Spools large uploads to temp files, run with `cargo bench --bench upload`.
+//!
+//! Each case extracts one multipart upload, for the upload buffer sizes and with
+//! `sync_uploads`, and reports the throughput.
+
+use std::hint::black_box;
+
+use axum_params::{Params, ParamsConfig, UploadFile, test::ParamsBuilder};
+use criterion::{Criterion, Throughput, criterion_group, criterion_main};
+use serde::Deserialize;
+use tokio::runtime::Runtime;
+
+#[derive(Deserialize)]
+struct Upload {
+    #[allow(dead_code)]
+    file: UploadFile,
+}
+
+fn uploads(c: &mut Criterion) {
+    let runtime = Runtime::new().unwrap();
+    for mib in [1, 16, 64] {
+        let bytes = (0..mib << 20).map(|i| (i % 251) as u8).collect::<Vec<_>>();
+        let mut group = c.benchmark_group(format!("{mib} MiB"));
+        group.throughput(Throughput::Bytes(bytes.len() as u64));
+        group.sample_size(10);
+        let configs = [
+            (
+                "8 KiB buffer",
+                ParamsConfig::new().upload_buffer_size(8 << 10),
+            ),
+            (
+                "64 KiB buffer",
+                ParamsConfig::new().upload_buffer_size(64 << 10),
+            ),
+            (
+                "1 MiB buffer",
+                ParamsConfig::new().upload_buffer_size(1 << 20),
+            ),
+            (
+                "64 KiB buffer, sync",
+                ParamsConfig::new().sync_uploads(true),
+            ),
+        ];
+        for (name, config) in configs {
+            group.bench_function(name, |b| {
+                b.iter(|| {
+                    runtime.block_on(async {
+                        let Params(upload, _) = ParamsBuilder::new()
+                            .multipart_file("file", black_box(bytes.as_slice()), "file.bin")
+                            .config(config.clone())
+                            .build::<Upload>()
+                            .await
+                            .unwrap();
+                        upload
+                    })
+                })
+            });
+        }
+        group.finish();
+    }
+}
+
+criterion_group!(benches, uploads);
+criterion_main!(benches);
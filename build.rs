@@ -0,0 +1,16 @@
+fn main() {
+    println!("cargo::rustc-check-cfg=cfg(axum07)");
+    println!("cargo::rustc-check-cfg=cfg(server)");
+    // The extractors, everything but the `wasm` subset
+    if std::env::var_os("CARGO_FEATURE_AXUM_07").is_some()
+        || std::env::var_os("CARGO_FEATURE_AXUM_08").is_some()
+    {
+        println!("cargo::rustc-cfg=server");
+    }
+    // `axum-08` wins when both are enabled, e.g. with `--all-features`
+    if std::env::var_os("CARGO_FEATURE_AXUM_07").is_some()
+        && std::env::var_os("CARGO_FEATURE_AXUM_08").is_none()
+    {
+        println!("cargo::rustc-cfg=axum07");
+    }
+}
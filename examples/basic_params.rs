@@ -1,11 +1,14 @@
+#[cfg(axum07)]
+extern crate axum07 as axum;
+
 use axum::{Json, Router, http::StatusCode, response::IntoResponse, routing::post};
-use axum_params::Params;
+use axum_params::{Params, route_path};
 use serde::{Deserialize, Serialize};
 
 // Simple parameters with path, query, and optional fields
 #[derive(Debug, Deserialize, Serialize)]
 struct TestParams {
-    id: i32,      // Path parameter (/users/:id)
+    id: i32,      // Path parameter (/users/{id})
     name: String, // From JSON or form
     #[serde(default)]
     extra: Option<String>, // Optional query parameter
@@ -21,7 +24,7 @@ async fn test_params_handler(Params(test, _): Params<TestParams>) -> impl IntoRe
 #[tokio::main]
 async fn main() {
     // Build our application with a route
-    let app = Router::new().route("/users/:id", post(test_params_handler));
+    let app = Router::new().route(&route_path("/users/{id}"), post(test_params_handler));
 
     // Run it
     let listener = tokio::net::TcpListener::bind("127.0.0.1:3000")
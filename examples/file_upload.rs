@@ -1,3 +1,6 @@
+#[cfg(axum07)]
+extern crate axum07 as axum;
+
 use axum::{Json, Router, response::IntoResponse, routing::post};
 use axum_params::{Params, UploadFile};
 use serde::Deserialize;
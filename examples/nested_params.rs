@@ -1,3 +1,6 @@
+#[cfg(axum07)]
+extern crate axum07 as axum;
+
 use axum::{Json, Router, routing::post};
 use axum_params::{Error, Params, UploadFile};
 use futures_util::future;
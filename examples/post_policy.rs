@@ -0,0 +1,75 @@
+#[cfg(axum07)]
+extern crate axum07 as axum;
+
+use std::{sync::Arc, time::Duration};
+
+use axum::{
+    Json, Router,
+    extract::State,
+    response::IntoResponse,
+    routing::{get, post},
+};
+use axum_params::{Error, ParamsReaderExt, PostPolicy, PostPolicySigner, RawParams, UploadFile};
+use serde_json::json;
+
+// Hands out a signed policy that lets a browser upload one image below 5 MiB under
+// `uploads/` for the next 10 minutes
+async fn policy_handler(State(signer): State<Arc<PostPolicySigner>>) -> impl IntoResponse {
+    let policy = PostPolicy::expires_in(Duration::from_secs(600))
+        .starts_with("key", "uploads/")
+        .starts_with("Content-Type", "image/")
+        .content_length_range(1, 5 << 20);
+    Json(signer.sign(&policy))
+}
+
+// Accepts the upload only if it matches the signed policy
+async fn upload_handler(
+    State(signer): State<Arc<PostPolicySigner>>,
+    RawParams(params, _metadata): RawParams,
+) -> Result<impl IntoResponse, Error> {
+    signer.verify(&params, "file").await?;
+    let key: String = params.require("key").await?;
+    let file: UploadFile = params.require("file").await?;
+    // Copy `file` to its final location under `key` here, the temp file is removed when
+    // `_metadata` is dropped
+    Ok(Json(json!({
+        "key": key,
+        "file_name": file.name,
+        "content_type": file.content_type,
+    })))
+}
+
+#[tokio::main]
+async fn main() {
+    let signer = Arc::new(PostPolicySigner::new("change me"));
+    let app = Router::new()
+        .route("/policy", get(policy_handler))
+        .route("/upload", post(upload_handler))
+        .with_state(signer);
+
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:3000")
+        .await
+        .unwrap();
+    println!("listening on {}", listener.local_addr().unwrap());
+    axum::serve(listener, app).await.unwrap();
+}
+
+/*
+Test with curl:
+
+# Get a signed policy
+curl http://localhost:3000/policy
+{"policy":"eyJleHBpcmF0aW9uIjoi...","signature":"3q2+7w..."}
+
+# Upload with the policy fields
+curl -X POST http://localhost:3000/upload \
+  -F "policy=eyJleHBpcmF0aW9uIjoi..." \
+  -F "signature=3q2+7w..." \
+  -F "key=uploads/avatar.png" \
+  -F "file=@avatar.png;type=image/png"
+
+Expected response:
+{"key":"uploads/avatar.png","file_name":"avatar.png","content_type":"image/png"}
+
+# A key outside `uploads/` is rejected with 403 Forbidden
+*/
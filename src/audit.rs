@@ -0,0 +1,138 @@
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+};
+
+use axum::{extract::Request, http::Extensions, middleware::Next, response::Response};
+
+use crate::{ParamsConfig, ParamsMetadata, Source, Value, security::redact_params};
+
+/// The params a handler extracted, for audit logs and analytics.
+///
+/// Collected for requests passing the `params_audit` middleware and inserted into the
+/// extensions of their response, requests that fail to extract have none. The values of
+/// `SecurityPolicy::sensitive_keys` are replaced by `[FILTERED]`.
+#[derive(Debug, Clone)]
+pub struct ParamsAudit {
+    /// The merged params before deserialization, an object.
+    pub params: Arc<Value>,
+    /// The source that last wrote each top level key.
+    pub sources: HashMap<String, Source>,
+}
+
+/// Where the extractors of a request leave its `ParamsAudit`, inserted by `params_audit`.
+#[derive(Clone, Default)]
+pub(crate) struct AuditSlot(Arc<Mutex<Option<ParamsAudit>>>);
+
+impl AuditSlot {
+    pub(crate) fn of(extensions: &Extensions) -> Option<Self> {
+        extensions.get::<AuditSlot>().cloned()
+    }
+
+    /// The redacted copy of `params` to record once the extraction succeeds.
+    pub(crate) fn snapshot(
+        params: &HashMap<String, Value>,
+        metadata: &ParamsMetadata,
+        config: &ParamsConfig,
+    ) -> ParamsAudit {
+        ParamsAudit {
+            params: Arc::new(Value::Object(redact_params(&config.security, params))),
+            sources: metadata.sources.clone(),
+        }
+    }
+
+    /// Keeps the params of the last extraction of the request.
+    pub(crate) fn record(&self, audit: ParamsAudit) {
+        if let Ok(mut slot) = self.0.lock() {
+            *slot = Some(audit);
+        }
+    }
+}
+
+/// Middleware collecting the `ParamsAudit` of the request into the response extensions,
+/// install with `axum::middleware::from_fn(params_audit)`. Without it the params are not
+/// copied.
+pub async fn params_audit(mut req: Request, next: Next) -> Response {
+    let slot = AuditSlot::default();
+    req.extensions_mut().insert(slot.clone());
+    let mut response = next.run(req).await;
+    let audit = slot.0.lock().ok().and_then(|mut audit| audit.take());
+    if let Some(audit) = audit {
+        response.extensions_mut().insert(audit);
+    }
+    response
+}
+
+#[cfg(test)]
+mod tests {
+    use axum::{
+        Router,
+        middleware::{self, from_fn},
+        routing::post,
+    };
+    use axum_test::TestServer;
+    use serde::Deserialize;
+    use serde_json::json;
+
+    use super::*;
+    use crate::{Params, SecurityPolicy};
+
+    #[derive(Deserialize)]
+    #[allow(dead_code)]
+    struct Login {
+        user: String,
+        password: String,
+    }
+
+    /// Logs the params in a header, like an audit log middleware.
+    async fn log(req: Request, next: middleware::Next) -> Response {
+        let mut response = next.run(req).await;
+        if let Some(audit) = response.extensions().get::<ParamsAudit>().cloned() {
+            let Value::Object(params) = &*audit.params else {
+                panic!("params are an object");
+            };
+            let mut entries = params
+                .iter()
+                .map(|(key, value)| {
+                    let source = audit.sources.get(key).map_or("", |s| s.as_str());
+                    let value = match value {
+                        Value::String(s) | Value::XStr(s) => s.as_str(),
+                        _ => "",
+                    };
+                    format!("{key}={value}@{source}")
+                })
+                .collect::<Vec<_>>();
+            entries.sort();
+            let line = entries.join(" ");
+            response
+                .headers_mut()
+                .insert("x-audit", line.parse().unwrap());
+        }
+        response
+    }
+
+    #[tokio::test]
+    async fn test_params_audit() {
+        let config = ParamsConfig::new()
+            .security_policy(SecurityPolicy::lenient().sensitive_keys(["password"]));
+        let app = Router::new()
+            .route("/login", post(|_: Params<Login>| async {}))
+            .layer(axum::Extension(config))
+            .layer(from_fn(params_audit))
+            .layer(from_fn(log));
+        let server = TestServer::new(app).unwrap();
+
+        let response = server
+            .post("/login?user=alice")
+            .json(&json!({"password": "hunter2"}))
+            .await;
+        assert_eq!(
+            response.header("x-audit"),
+            "password=[FILTERED]@json user=alice@query"
+        );
+
+        // Failed extractions have no params
+        let response = server.post("/login").json(&json!({})).await;
+        assert!(response.maybe_header("x-audit").is_none());
+    }
+}
@@ -0,0 +1,154 @@
+use std::{collections::HashMap, fmt, sync::Arc};
+
+use axum::{
+    body::Body,
+    extract::Request,
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+
+use crate::{
+    BoxFuture, Error, ParamsMetadata, ParamsReader, Value, extract_params, rejection::Rejecter,
+};
+
+/// The merged params of a request, parsed once by the `cache_params` middleware.
+///
+/// `Params`, `StrictParams` and `RawParams` extract from it instead of the body, and layers
+/// between the middleware and the handler can read it from the request extensions.
+#[derive(Clone)]
+pub struct ParamsCache(Arc<(HashMap<String, Value>, ParamsMetadata)>);
+
+impl ParamsCache {
+    pub fn params(&self) -> &HashMap<String, Value> {
+        &self.0.0
+    }
+
+    /// The metadata of the parse, its temp files live as long as the cache.
+    pub fn metadata(&self) -> &ParamsMetadata {
+        &self.0.1
+    }
+
+    /// A copy of the params for an extraction, its metadata keeps the temp files alive.
+    pub(crate) fn reuse(&self) -> (HashMap<String, Value>, ParamsMetadata) {
+        let (params, metadata) = &*self.0;
+        let metadata = ParamsMetadata {
+            temp_files: vec![],
+            timings: metadata.timings,
+            sizes: metadata.sizes,
+            sources: metadata.sources.clone(),
+            coercions: metadata.coercions.clone(),
+            field_sources: metadata.field_sources.clone(),
+            raw_body: metadata.raw_body.clone(),
+            body_length: metadata.body_length,
+            leaves: None,
+            sent: metadata.sent.clone(),
+            cache: Some(self.clone()),
+        };
+        (params.clone(), metadata)
+    }
+}
+
+impl fmt::Debug for ParamsCache {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_tuple("ParamsCache").field(self.params()).finish()
+    }
+}
+
+impl ParamsReader for ParamsCache {
+    fn get_value<'a>(&'a self, path: &'a str) -> BoxFuture<'a, Result<Option<Value>, Error>> {
+        self.params().get_value(path)
+    }
+}
+
+/// Middleware parsing the params of the request once into a `ParamsCache`, install with
+/// `axum::middleware::from_fn(cache_params)` on the router so path params are available.
+///
+/// The body is consumed, the request passed on has an empty body. Requests failing to parse
+/// are rejected like by `Params`.
+pub async fn cache_params(req: Request, next: Next) -> Response {
+    let rejecter = Rejecter::of(&req);
+    let (parts, body) = req.into_parts();
+    let mut forwarded = Request::from_parts(parts.clone(), Body::empty());
+    match extract_params(Request::from_parts(parts, body), &()).await {
+        Ok(parsed) => {
+            forwarded
+                .extensions_mut()
+                .insert(ParamsCache(Arc::new(parsed)));
+            next.run(forwarded).await
+        }
+        Err(e) => rejecter.reject(e).into_response(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Mutex;
+
+    use axum::{Extension, Router, middleware::from_fn, routing::post};
+    use axum_test::{
+        TestServer,
+        multipart::{MultipartForm, Part},
+    };
+    use serde::Deserialize;
+    use tokio::io::AsyncReadExt;
+
+    use super::*;
+    use crate::{Params, ParamsReaderExt, UploadFile, Uploaded, route_path};
+
+    #[derive(Deserialize)]
+    struct CreateDoc {
+        id: u32,
+        title: String,
+        file: UploadFile,
+        raw: Uploaded<String>,
+    }
+
+    #[tokio::test]
+    async fn test_cache_params() {
+        let logged = Arc::new(Mutex::new(vec![]));
+        let log = logged.clone();
+        let app = Router::new()
+            .route(
+                &route_path("/docs/{id}"),
+                post(
+                    |Extension(cache): Extension<ParamsCache>,
+                     Params(doc, _): Params<CreateDoc>| async move {
+                        let mut content = String::new();
+                        let mut file = doc.file.open().await.unwrap();
+                        file.read_to_string(&mut content).await.unwrap();
+                        let keys = cache.params().len();
+                        format!("{}:{}:{content}:{}:{keys}", doc.id, doc.title, *doc.raw)
+                    },
+                ),
+            )
+            .layer(from_fn(move |req: Request, next: Next| {
+                let log = log.clone();
+                async move {
+                    let cache = req.extensions().get::<ParamsCache>().unwrap();
+                    if let Some(title) = cache.get::<String>("title").await.unwrap() {
+                        log.lock().unwrap().push(title);
+                    }
+                    next.run(req).await
+                }
+            }))
+            .layer(from_fn(cache_params));
+        let server = TestServer::new(app).unwrap();
+
+        let response = server
+            .post("/docs/7")
+            .multipart(
+                MultipartForm::new()
+                    .add_text("title", "notes")
+                    .add_part("file", Part::bytes(b"abc".to_vec()).file_name("a.txt"))
+                    .add_part("raw", Part::bytes(b"xyz".to_vec()).file_name("b.txt")),
+            )
+            .await;
+        assert_eq!(response.text(), "7:notes:abc:xyz:4");
+        assert_eq!(*logged.lock().unwrap(), ["notes"]);
+
+        // Deserialization still fails in the handler
+        let response = server.post("/docs/x").await;
+        assert_eq!(response.status_code(), 400);
+        assert_eq!(logged.lock().unwrap().len(), 1);
+    }
+}
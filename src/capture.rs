@@ -0,0 +1,90 @@
+use std::{
+    pin::Pin,
+    sync::{Arc, Mutex},
+    task::{Context, Poll, ready},
+};
+
+use axum::body::{Body, Bytes, HttpBody};
+use http_body::{Frame, SizeHint};
+
+/// A copy of the body bytes read by the parser, see `ParamsConfig::capture_body`.
+#[derive(Debug, Clone)]
+pub(crate) struct BodyCapture(Arc<Mutex<Captured>>);
+
+#[derive(Debug)]
+struct Captured {
+    bytes: Vec<u8>,
+    limit: usize,
+    overflow: bool,
+    complete: bool,
+}
+
+impl BodyCapture {
+    /// Wraps `body` to copy up to `limit` bytes of it as they are read.
+    pub(crate) fn wrap(body: Body, limit: usize) -> (Body, Self) {
+        let capture = BodyCapture(Arc::new(Mutex::new(Captured {
+            bytes: vec![],
+            limit,
+            overflow: false,
+            complete: false,
+        })));
+        let body = Body::new(CaptureBody {
+            inner: body,
+            capture: capture.clone(),
+        });
+        (body, capture)
+    }
+
+    /// The whole body, `None` if it was not read to the end or exceeded the limit.
+    pub(crate) fn take(&self) -> Option<Bytes> {
+        let mut captured = self.0.lock().expect("body capture lock");
+        (captured.complete && !captured.overflow)
+            .then(|| Bytes::from(std::mem::take(&mut captured.bytes)))
+    }
+}
+
+struct CaptureBody {
+    inner: Body,
+    capture: BodyCapture,
+}
+
+impl HttpBody for CaptureBody {
+    type Data = Bytes;
+    type Error = axum::Error;
+
+    fn poll_frame(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Option<Result<Frame<Bytes>, axum::Error>>> {
+        let frame = ready!(Pin::new(&mut self.inner).poll_frame(cx));
+        let mut captured = self.capture.0.lock().expect("body capture lock");
+        match &frame {
+            Some(Ok(frame)) => {
+                if let Some(data) = frame.data_ref() {
+                    if captured.bytes.len() + data.len() > captured.limit {
+                        captured.overflow = true;
+                        captured.bytes = vec![];
+                    } else if !captured.overflow {
+                        captured.bytes.extend_from_slice(data);
+                    }
+                }
+            }
+            Some(Err(_)) => captured.overflow = true,
+            None => captured.complete = true,
+        }
+        drop(captured);
+        Poll::Ready(frame)
+    }
+
+    fn is_end_stream(&self) -> bool {
+        let end = self.inner.is_end_stream();
+        if end {
+            self.capture.0.lock().expect("body capture lock").complete = true;
+        }
+        end
+    }
+
+    fn size_hint(&self) -> SizeHint {
+        self.inner.size_hint()
+    }
+}
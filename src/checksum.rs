@@ -0,0 +1,155 @@
+use serde::{Deserialize, Serialize};
+
+/// The digest computed for uploaded files, see `ParamsConfig::checksum`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ChecksumAlgorithm {
+    Sha256,
+    Md5,
+}
+
+impl ChecksumAlgorithm {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            ChecksumAlgorithm::Sha256 => "sha256",
+            ChecksumAlgorithm::Md5 => "md5",
+        }
+    }
+}
+
+/// The digest of an uploaded file, computed while it was spooled.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Checksum {
+    pub algorithm: ChecksumAlgorithm,
+    pub(crate) hex: String,
+}
+
+impl Checksum {
+    /// The lowercase hex digest, e.g. for an `ETag` or deduplication key.
+    pub fn hex(&self) -> &str {
+        &self.hex
+    }
+
+    /// The base64 digest, the format of `Content-MD5` and S3's `x-amz-checksum-sha256`.
+    pub fn base64(&self) -> String {
+        use base64::Engine;
+
+        let bytes = (0..self.hex.len())
+            .step_by(2)
+            .filter_map(|i| u8::from_str_radix(self.hex.get(i..i + 2)?, 16).ok())
+            .collect::<Vec<_>>();
+        base64::engine::general_purpose::STANDARD.encode(bytes)
+    }
+}
+
+/// Hashes a file upload as its chunks are written, a no-op without a configured algorithm.
+#[cfg(server)]
+#[derive(Default)]
+pub(crate) struct UploadHasher {
+    #[cfg(feature = "checksum")]
+    hasher: Option<Hasher>,
+}
+
+#[cfg(feature = "checksum")]
+enum Hasher {
+    Sha256(sha2::Sha256),
+    Md5(md5::Md5),
+}
+
+#[cfg(server)]
+impl UploadHasher {
+    #[cfg(feature = "checksum")]
+    pub(crate) fn new(config: &crate::ParamsConfig) -> Self {
+        use sha2::Digest;
+
+        UploadHasher {
+            hasher: config.checksum.map(|algorithm| match algorithm {
+                ChecksumAlgorithm::Sha256 => Hasher::Sha256(sha2::Sha256::new()),
+                ChecksumAlgorithm::Md5 => Hasher::Md5(md5::Md5::new()),
+            }),
+        }
+    }
+
+    #[cfg(not(feature = "checksum"))]
+    pub(crate) fn new(_: &crate::ParamsConfig) -> Self {
+        UploadHasher {}
+    }
+
+    #[cfg_attr(not(feature = "checksum"), allow(unused_variables))]
+    pub(crate) fn update(&mut self, bytes: &[u8]) {
+        #[cfg(feature = "checksum")]
+        {
+            use sha2::Digest;
+
+            match &mut self.hasher {
+                Some(Hasher::Sha256(hasher)) => hasher.update(bytes),
+                Some(Hasher::Md5(hasher)) => hasher.update(bytes),
+                None => {}
+            }
+        }
+    }
+
+    pub(crate) fn finish(self) -> Option<Checksum> {
+        #[cfg(feature = "checksum")]
+        {
+            use sha2::Digest;
+
+            let (algorithm, digest) = match self.hasher? {
+                Hasher::Sha256(hasher) => (ChecksumAlgorithm::Sha256, hasher.finalize().to_vec()),
+                Hasher::Md5(hasher) => (ChecksumAlgorithm::Md5, hasher.finalize().to_vec()),
+            };
+            let hex = digest.iter().map(|b| format!("{b:02x}")).collect();
+            Some(Checksum { algorithm, hex })
+        }
+        #[cfg(not(feature = "checksum"))]
+        None
+    }
+}
+
+#[cfg(all(test, feature = "checksum"))]
+mod tests {
+    use super::*;
+    use crate::ParamsConfig;
+
+    #[test]
+    fn test_upload_hasher() {
+        let checksum = |algorithm| {
+            let mut hasher = UploadHasher::new(&ParamsConfig::new().checksum(algorithm));
+            hasher.update(b"hello ");
+            hasher.update(b"world");
+            hasher.finish().unwrap()
+        };
+        let sha256 = checksum(ChecksumAlgorithm::Sha256);
+        assert_eq!(
+            sha256.hex(),
+            "b94d27b9934d3e08a52e52d7da7dabfac484efe37a5380ee9088f7ace2efcde9"
+        );
+        assert_eq!(
+            sha256.base64(),
+            "uU0nuZNNPgilLlLX2n2r+sSE7+N6U4DukIj3rOLvzek="
+        );
+        let md5 = checksum(ChecksumAlgorithm::Md5);
+        assert_eq!(md5.hex(), "5eb63bbbe01eeed093cb22bb8f5acdc3");
+        assert_eq!(md5.base64(), "XrY7u+Ae7tCTyyK7j1rNww==");
+
+        assert!(UploadHasher::new(&ParamsConfig::new()).finish().is_none());
+    }
+
+    #[tokio::test]
+    async fn test_upload_checksum() {
+        #[derive(serde::Deserialize)]
+        struct Upload {
+            file: crate::UploadFile,
+        }
+
+        let crate::Params(upload, _) = crate::test::ParamsBuilder::new()
+            .multipart_file("file", &b"hello world"[..], "hello.txt")
+            .config(ParamsConfig::new().checksum(ChecksumAlgorithm::Md5))
+            .build::<Upload>()
+            .await
+            .unwrap();
+        let checksum = upload.file.checksum().unwrap();
+        assert_eq!(checksum.algorithm, ChecksumAlgorithm::Md5);
+        assert_eq!(checksum.hex(), "5eb63bbbe01eeed093cb22bb8f5acdc3");
+    }
+}
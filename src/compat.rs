@@ -0,0 +1,63 @@
+use std::borrow::Cow;
+
+/// Converts a route written in axum 0.8 syntax (`/users/{id}`, `/files/{*path}`) to the
+/// syntax of the axum version selected by the `axum-07` / `axum-08` features, so routes can
+/// be declared once for both.
+///
+/// ```
+/// use axum_params::route_path;
+///
+/// let path = route_path("/users/{id}/files/{*path}");
+/// # #[cfg(feature = "axum-08")]
+/// assert_eq!(path, "/users/{id}/files/{*path}");
+/// ```
+pub fn route_path(path: &str) -> Cow<'_, str> {
+    if cfg!(axum07) {
+        Cow::Owned(to_colon_syntax(path))
+    } else {
+        Cow::Borrowed(path)
+    }
+}
+
+/// `/users/{id}` to `/users/:id`, `{*rest}` to `*rest`, `{{` and `}}` to literal braces.
+fn to_colon_syntax(path: &str) -> String {
+    let mut out = String::with_capacity(path.len());
+    let mut chars = path.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '{' if chars.peek() == Some(&'{') => {
+                chars.next();
+                out.push('{');
+            }
+            '}' if chars.peek() == Some(&'}') => {
+                chars.next();
+                out.push('}');
+            }
+            '{' => {
+                if chars.peek() != Some(&'*') {
+                    out.push(':');
+                }
+                out.extend(chars.by_ref().take_while(|&c| c != '}'));
+            }
+            c => out.push(c),
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_to_colon_syntax() {
+        assert_eq!(to_colon_syntax("/users/{id}"), "/users/:id");
+        assert_eq!(
+            to_colon_syntax("/users/{user_id}/posts/{id}"),
+            "/users/:user_id/posts/:id"
+        );
+        assert_eq!(to_colon_syntax("/files/{*path}"), "/files/*path");
+        assert_eq!(to_colon_syntax("/literal/{{braces}}"), "/literal/{braces}");
+        assert_eq!(to_colon_syntax("/plain"), "/plain");
+    }
+}
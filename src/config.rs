@@ -0,0 +1,555 @@
+use std::{fmt, io, path::PathBuf, sync::Arc, time::Duration};
+
+use axum::{extract::Request, http::Extensions, response::Response};
+use serde::{Deserialize, Serialize};
+use tempfile::NamedTempFile;
+use tokio::sync::{OwnedSemaphorePermit, Semaphore};
+
+#[cfg(feature = "checksum")]
+use crate::ChecksumAlgorithm;
+use crate::{
+    Checkbox, Coercions, Error, MergeStrategy, NumberFormat, RejectionContext, SecurityPolicy,
+    Value,
+    query_parser::{Compat, ParseMode, QueryParser},
+    rejection::OnRejection,
+};
+
+/// What to do with a request body sent with `GET` or `HEAD`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum BodyOnGet {
+    /// Extract path and query parameters only, the body is not read.
+    #[default]
+    Ignore,
+    /// Parse the body like for any other method, e.g. for Elasticsearch style search APIs.
+    Parse,
+    /// Reject requests with a non-empty body.
+    Reject,
+}
+
+/// What to do when multipart parts of different kinds share a name, e.g. `meta` sent as a
+/// text field and as an `application/json` part by a form and a script enhancing it.
+///
+/// Parts of the same kind keep the last one, names with `[]` always append.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum DuplicateParts {
+    /// JSON and file parts win over text fields, independent of their order, the later one
+    /// wins between a JSON and a file part.
+    #[default]
+    PreferStructured,
+    /// Reject the request, naming the indices of both parts.
+    Reject,
+}
+
+/// Shared cap on the number of requests reading their body at the same time.
+#[derive(Debug, Clone)]
+pub(crate) struct ConcurrencyLimit {
+    pub(crate) semaphore: Arc<Semaphore>,
+    timeout: Duration,
+}
+
+impl ConcurrencyLimit {
+    pub(crate) async fn acquire(&self) -> Result<OwnedSemaphorePermit, Error> {
+        match tokio::time::timeout(self.timeout, self.semaphore.clone().acquire_owned()).await {
+            Ok(Ok(permit)) => Ok(permit),
+            _ => Err(Error::ServiceUnavailable(format!(
+                "Too many concurrent requests, no slot available after {:?}",
+                self.timeout
+            ))),
+        }
+    }
+}
+
+type ReadExtension = dyn Fn(&Extensions) -> Option<Result<Value, String>> + Send + Sync;
+
+/// A top level parameter read from a request extension, see `ParamsConfig::extension`.
+#[derive(Clone)]
+pub(crate) struct ExtensionParam {
+    pub(crate) key: String,
+    pub(crate) read: Arc<ReadExtension>,
+}
+
+impl fmt::Debug for ExtensionParam {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("ExtensionParam")
+            .field("key", &self.key)
+            .finish_non_exhaustive()
+    }
+}
+
+/// Progress of an upload, passed to `ParamsConfig::on_upload_progress`.
+#[derive(Debug, Clone, Copy)]
+pub struct UploadProgress<'a> {
+    /// The name of the file field, e.g. `post[cover]`, or the key of `ParamsConfig::raw_body`.
+    pub field: &'a str,
+    /// The bytes of the body received so far. For multipart bodies the contents of the parts,
+    /// without their boundaries and headers.
+    pub bytes: usize,
+    /// The `Content-Length` of the body, unknown for chunked bodies.
+    pub total: Option<u64>,
+}
+
+type UploadProgressHook = dyn Fn(&UploadProgress) -> Result<(), Error> + Send + Sync;
+
+/// A hook installed with `ParamsConfig::on_upload_progress`.
+#[derive(Clone)]
+pub(crate) struct OnUploadProgress(pub(crate) Arc<UploadProgressHook>);
+
+impl fmt::Debug for OnUploadProgress {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("OnUploadProgress")
+    }
+}
+
+/// Extraction settings, install with `ParamsLayer::new(ParamsConfig::new()...)` or
+/// `Extension(ParamsConfig::new()...)`.
+///
+/// Requests without a `ParamsConfig` extension use the defaults.
+#[derive(Debug, Clone, Default)]
+pub struct ParamsConfig {
+    pub(crate) parse_mode: ParseMode,
+    pub(crate) compat: Compat,
+    pub(crate) query_delimiters: String,
+    pub(crate) repeated_keys: bool,
+    pub(crate) body_on_get: BodyOnGet,
+    pub(crate) skip_path: bool,
+    pub(crate) skip_query: bool,
+    pub(crate) skip_body: bool,
+    pub(crate) forbidden_keys: Vec<String>,
+    pub(crate) reject_forbidden_keys: bool,
+    pub(crate) duplicate_parts: DuplicateParts,
+    pub(crate) security: SecurityPolicy,
+    pub(crate) merge: MergeStrategy,
+    pub(crate) coercions: Coercions,
+    pub(crate) extensions: Vec<ExtensionParam>,
+    pub(crate) on_rejection: Option<OnRejection>,
+    pub(crate) problem_details: bool,
+    pub(crate) deny_unknown_fields: bool,
+    pub(crate) collect_errors: bool,
+    pub(crate) raw_body: Option<String>,
+    pub(crate) text_body: Option<String>,
+    pub(crate) capture_body: Option<usize>,
+    pub(crate) field_sources: bool,
+    pub(crate) trim_strings: bool,
+    pub(crate) sanitize_file_names: bool,
+    pub(crate) temp_dir: Option<PathBuf>,
+    pub(crate) temp_file_prefix: Option<String>,
+    pub(crate) temp_file_suffix: Option<String>,
+    pub(crate) upload_buffer_size: Option<usize>,
+    pub(crate) sync_uploads: bool,
+    pub(crate) on_upload_progress: Option<OnUploadProgress>,
+    pub(crate) blocking_parse_threshold: Option<u64>,
+    pub(crate) concurrency: Option<ConcurrencyLimit>,
+    #[cfg(feature = "checksum")]
+    pub(crate) checksum: Option<ChecksumAlgorithm>,
+}
+
+impl ParamsConfig {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Parse bodies of `GET` and `HEAD` requests instead of ignoring them.
+    pub fn allow_body_on_get(self, allow: bool) -> Self {
+        self.body_on_get(if allow {
+            BodyOnGet::Parse
+        } else {
+            BodyOnGet::Ignore
+        })
+    }
+
+    pub fn body_on_get(mut self, body_on_get: BodyOnGet) -> Self {
+        self.body_on_get = body_on_get;
+        self
+    }
+
+    /// Merge the path parameters, on by default. Turn it off for routes whose fields must
+    /// not come from the URL, e.g. with `Extension(ParamsConfig::new().merge_path(false))` on
+    /// the route.
+    pub fn merge_path(mut self, merge: bool) -> Self {
+        self.skip_path = !merge;
+        self
+    }
+
+    /// Merge the query string, on by default. Turn it off for body-only routes, so the query
+    /// string can't inject sensitive fields.
+    pub fn merge_query(mut self, merge: bool) -> Self {
+        self.skip_query = !merge;
+        self
+    }
+
+    /// Merge the body, on by default. When off, the body is neither read nor checked.
+    pub fn merge_body(mut self, merge: bool) -> Self {
+        self.skip_body = !merge;
+        self
+    }
+
+    /// Strips the parameters at the key paths `keys`, whatever their source, before they are
+    /// deserialized. A defense against mass assignment for structs that accidentally have
+    /// a dangerous field:
+    ///
+    /// ```
+    /// # use axum_params::ParamsConfig;
+    /// ParamsConfig::new().forbid_keys(["admin", "role", "*.is_superuser", "**.password_digest"]);
+    /// ```
+    ///
+    /// The keys of a path are separated by `.`, `*` matches any characters of a key and a
+    /// `**` key any number of keys. Array elements are looked through, `users.admin` matches
+    /// `users[][admin]`. Extensions of `ParamsConfig::extension` are kept.
+    pub fn forbid_keys<I, S>(mut self, keys: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        self.forbidden_keys = keys.into_iter().map(Into::into).collect();
+        self
+    }
+
+    /// Reject requests sending one of `ParamsConfig::forbid_keys` instead of stripping it.
+    pub fn reject_forbidden_keys(mut self, reject: bool) -> Self {
+        self.reject_forbidden_keys = reject;
+        self
+    }
+
+    /// How multipart parts of different kinds sharing a name are resolved.
+    pub fn duplicate_parts(mut self, duplicate_parts: DuplicateParts) -> Self {
+        self.duplicate_parts = duplicate_parts;
+        self
+    }
+
+    /// Exposes bodies of other content types than JSON, form and multipart, e.g.
+    /// `application/octet-stream`, as an `UploadFile` at `key` instead of ignoring them.
+    ///
+    /// The body is spooled to a temp file like multipart uploads and limited by
+    /// `SecurityPolicy::max_file_size`, read it with `Uploaded<Bytes>` to get it in memory.
+    pub fn raw_body(mut self, key: impl Into<String>) -> Self {
+        self.raw_body = Some(key.into());
+        self
+    }
+
+    /// Exposes `text/plain` bodies, e.g. of simple webhook providers, as a string at `key`
+    /// instead of ignoring them, so `Params<Webhook { text: String }>` works with `"text"`.
+    ///
+    /// The body is read in memory up to `SecurityPolicy::max_body_size` and decoded in the
+    /// `charset` of its content type, UTF-8 by default. Strict policies only allow `text/plain`
+    /// once it is added to `SecurityPolicy::allowed_content_types`.
+    pub fn text_body(mut self, key: impl Into<String>) -> Self {
+        self.text_body = Some(key.into());
+        self
+    }
+
+    /// Keeps a copy of the body as received, before decompression, in
+    /// `ParamsMetadata::raw_body`, e.g. to verify a webhook signature or to log it.
+    ///
+    /// Bodies larger than `max_size` bytes are parsed but not kept. The copy is only made
+    /// when the body is read, i.e. it has a content type and isn't ignored by `body_on_get`.
+    pub fn capture_body(mut self, max_size: usize) -> Self {
+        self.capture_body = Some(max_size);
+        self
+    }
+
+    /// Records the source of every leaf in `ParamsMetadata::field_sources`, read it with
+    /// `Params::sources`, where `ParamsMetadata::sources` only has the top level keys.
+    ///
+    /// Off by default, the merged params are snapshotted after each source.
+    pub fn field_sources(mut self, field_sources: bool) -> Self {
+        self.field_sources = field_sources;
+        self
+    }
+
+    /// Bracket semantics for query strings, form bodies and multipart field names.
+    pub fn compat(mut self, compat: Compat) -> Self {
+        self.compat = compat;
+        self
+    }
+
+    /// The characters separating pairs in query strings and form bodies, `&` by default, see
+    /// `QueryParser::with_delimiters`.
+    pub fn query_delimiters(mut self, delimiters: impl Into<String>) -> Self {
+        self.query_delimiters = delimiters.into();
+        self
+    }
+
+    /// Collect keys repeated without brackets in query strings and form bodies into arrays,
+    /// `tag=a&tag=b` into `tag: Vec<String>`, see `QueryParser::with_repeated_keys`.
+    pub fn repeated_keys(mut self, repeated_keys: bool) -> Self {
+        self.repeated_keys = repeated_keys;
+        self
+    }
+
+    /// Maximum number of parameters in the query string, form body or multipart body.
+    pub fn max_params(mut self, max_params: usize) -> Self {
+        self.security = self.security.max_params(max_params);
+        self
+    }
+
+    /// Maximum number of elements of one array, see `SecurityPolicy::max_array_length`.
+    pub fn max_array_length(mut self, max_array_length: usize) -> Self {
+        self.security = self.security.max_array_length(max_array_length);
+        self
+    }
+
+    /// Maximum size of a multipart text field, see `SecurityPolicy::max_text_field_size`.
+    pub fn max_text_field_size(mut self, max_text_field_size: usize) -> Self {
+        self.security = self.security.max_text_field_size(max_text_field_size);
+        self
+    }
+
+    /// Maximum number of parts of a multipart body, see `SecurityPolicy::max_parts`.
+    pub fn max_parts(mut self, max_parts: usize) -> Self {
+        self.security = self.security.max_parts(max_parts);
+        self
+    }
+
+    pub fn parse_mode(mut self, mode: ParseMode) -> Self {
+        self.parse_mode = mode;
+        self
+    }
+
+    /// Let at most `max` requests read their body at the same time, others queue for up to
+    /// `timeout` and are then rejected with 503 Service Unavailable.
+    ///
+    /// The limit is shared by every request using this config.
+    pub fn concurrency_limit(mut self, max: usize, timeout: Duration) -> Self {
+        self.concurrency = Some(ConcurrencyLimit {
+            semaphore: Arc::new(Semaphore::new(max)),
+            timeout,
+        });
+        self
+    }
+
+    /// Deserialize empty strings from the path, query string, form and multipart bodies, like
+    /// blank HTML inputs, to `None` for `Option`s and to `0` / `false` for numbers and
+    /// booleans, like Rails' blank handling. On by default, JSON strings are never coerced.
+    pub fn empty_as_none(mut self, empty_as_none: bool) -> Self {
+        self.coercions.empty_as_none = empty_as_none;
+        self
+    }
+
+    /// Parse numbers from the path, query string, form and multipart bodies with the
+    /// separators of `format`, e.g. `NumberFormat::DECIMAL_COMMA` for `1.234,56`. JSON
+    /// numbers and strings are not affected.
+    pub fn number_format(mut self, format: NumberFormat) -> Self {
+        self.coercions.number_format = Some(format);
+        self
+    }
+
+    /// Trim leading and trailing whitespace from the values of the path, query string, form
+    /// and multipart bodies before deserializing them, like copy-pasted form inputs. JSON
+    /// strings are kept as sent, use `Trimmed` for single fields.
+    pub fn trim_strings(mut self, trim: bool) -> Self {
+        self.trim_strings = trim;
+        self
+    }
+
+    /// Split single values from the path, query string, form and multipart bodies on
+    /// `separator` for sequence fields, so `?ids=1,2,3` fills a `Vec<u64>`, like
+    /// `serde_with::StringWithSeparator`. Items are trimmed and an empty value gives an empty
+    /// sequence. `tag[]=a,b` and repeated keys are kept as sent.
+    pub fn seq_separator(mut self, separator: char) -> Self {
+        self.coercions.seq_separator = Some(separator);
+        self
+    }
+
+    /// Split single values for sequence fields on commas, see `seq_separator`.
+    pub fn split_seq(mut self, split: bool) -> Self {
+        self.coercions.seq_separator = split.then_some(',');
+        self
+    }
+
+    /// Which value `bool` fields take from repeated checkbox fields.
+    pub fn checkbox(mut self, checkbox: Checkbox) -> Self {
+        self.coercions.checkbox = checkbox;
+        self
+    }
+
+    /// How values for the same key from different sources are merged.
+    pub fn merge_strategy(mut self, strategy: MergeStrategy) -> Self {
+        self.merge = strategy;
+        self
+    }
+
+    /// Set the top level parameter `key` from the request extension of type `E`, e.g. the
+    /// user id inserted by an auth layer, recorded as `Source::Extension`.
+    ///
+    /// Values sent by the client for `key` are always dropped, the key is missing when the
+    /// request has no such extension.
+    pub fn extension<E>(mut self, key: impl Into<String>) -> Self
+    where
+        E: Serialize + Send + Sync + 'static,
+    {
+        self.extensions.push(ExtensionParam {
+            key: key.into(),
+            read: Arc::new(|extensions| {
+                extensions.get::<E>().map(|extension| {
+                    serde_json::to_value(extension)
+                        .and_then(Value::deserialize)
+                        .map_err(|e| e.to_string())
+                })
+            }),
+        });
+        self
+    }
+
+    /// Respond to extraction errors with `handler` instead of `Error`'s response, e.g. in the
+    /// application's error format.
+    pub fn on_rejection<F>(mut self, handler: F) -> Self
+    where
+        F: Fn(Error, &RejectionContext) -> Response + Send + Sync + 'static,
+    {
+        self.on_rejection = Some(OnRejection(Arc::new(handler)));
+        self
+    }
+
+    /// Reject parameters the target type doesn't read with `Error::UnknownFields`, naming
+    /// each key and its source. Unlike `#[serde(deny_unknown_fields)]`, path parameters and
+    /// `extension` keys are allowed, the client can't leave them out.
+    ///
+    /// Keys ignored inside `#[serde(flatten)]` fields are not detected.
+    pub fn deny_unknown_fields(mut self, deny: bool) -> Self {
+        self.deny_unknown_fields = deny;
+        self
+    }
+
+    /// Reject deserialization failures with `Error::DecodeErrors`, listing every missing or
+    /// invalid field instead of the first one, e.g. to show all the errors of a form at once.
+    ///
+    /// The params are deserialized again for each error, with placeholders for the failed
+    /// values. Only failed requests pay for it.
+    pub fn collect_errors(mut self, collect: bool) -> Self {
+        self.collect_errors = collect;
+        self
+    }
+
+    /// Respond to extraction errors with RFC 7807 `application/problem+json` bodies, see
+    /// `Error::into_problem_response`. Handlers set with `on_rejection` take precedence.
+    pub fn problem_details(mut self, problem_details: bool) -> Self {
+        self.problem_details = problem_details;
+        self
+    }
+
+    /// Compute the `algorithm` digest of uploaded files while they are received, read it with
+    /// `UploadFile::checksum`.
+    #[cfg(feature = "checksum")]
+    pub fn checksum(mut self, algorithm: ChecksumAlgorithm) -> Self {
+        self.checksum = Some(algorithm);
+        self
+    }
+
+    /// Replace the names of uploaded files by their `UploadFile::sanitized_name`, so handlers
+    /// can't store them outside of a directory by mistake.
+    pub fn sanitize_file_names(mut self, sanitize: bool) -> Self {
+        self.sanitize_file_names = sanitize;
+        self
+    }
+
+    /// The directory uploads are spooled to, e.g. a dedicated volume with a quota, instead of
+    /// the system temp dir. It must exist and be writable.
+    pub fn temp_dir(mut self, dir: impl Into<PathBuf>) -> Self {
+        self.temp_dir = Some(dir.into());
+        self
+    }
+
+    /// The start of the names of spooled uploads, `.tmp` by default.
+    pub fn temp_file_prefix(mut self, prefix: impl Into<String>) -> Self {
+        self.temp_file_prefix = Some(prefix.into());
+        self
+    }
+
+    /// The end of the names of spooled uploads, e.g. `.upload`, none by default.
+    pub fn temp_file_suffix(mut self, suffix: impl Into<String>) -> Self {
+        self.temp_file_suffix = Some(suffix.into());
+        self
+    }
+
+    /// The size of the buffer uploads are written to their temp file through, 64 KiB by
+    /// default. Larger buffers mean fewer writes for large files.
+    pub fn upload_buffer_size(mut self, size: usize) -> Self {
+        self.upload_buffer_size = Some(size);
+        self
+    }
+
+    /// Sync spooled uploads to disk with `fsync` before the handler runs, off by default.
+    /// Uploads are always flushed, syncing only matters for files that must survive a crash
+    /// of the machine while the temp file still exists.
+    pub fn sync_uploads(mut self, sync: bool) -> Self {
+        self.sync_uploads = sync;
+        self
+    }
+
+    /// Call `hook` as the chunks of uploaded files are written to their temp files, e.g. to
+    /// publish the progress of an upload or to enforce a per-user quota:
+    ///
+    /// ```ignore
+    /// let config = ParamsConfig::new().on_upload_progress(move |progress| {
+    ///     if quota.exceeded_by(progress.bytes) {
+    ///         return Err(Error::Forbidden("Upload quota exceeded".to_string()));
+    ///     }
+    ///     progress_bar.set(progress.bytes, progress.total);
+    ///     Ok(())
+    /// });
+    /// ```
+    ///
+    /// An error rejects the request at once, the body is not read any further. The hook runs
+    /// on the task extracting the request, it must not block.
+    pub fn on_upload_progress<F>(mut self, hook: F) -> Self
+    where
+        F: Fn(&UploadProgress) -> Result<(), Error> + Send + Sync + 'static,
+    {
+        self.on_upload_progress = Some(OnUploadProgress(Arc::new(hook)));
+        self
+    }
+
+    /// Parse JSON and form-urlencoded bodies of at least `bytes` on the blocking thread pool
+    /// with `tokio::task::spawn_blocking`, so large documents don't stall the other requests
+    /// of the worker thread. Such JSON bodies are buffered before they are parsed, those sent
+    /// without a `Content-Length` are always parsed while they are read.
+    pub fn blocking_parse_threshold(mut self, bytes: u64) -> Self {
+        self.blocking_parse_threshold = Some(bytes);
+        self
+    }
+
+    pub(crate) fn parses_blocking(&self, len: u64) -> bool {
+        self.blocking_parse_threshold
+            .is_some_and(|threshold| len >= threshold)
+    }
+
+    /// Limits and input checks, see the `SecurityPolicy` presets.
+    pub fn security_policy(mut self, policy: SecurityPolicy) -> Self {
+        self.security = policy;
+        self
+    }
+
+    pub(crate) fn of(req: &Request) -> ParamsConfig {
+        req.extensions()
+            .get::<ParamsConfig>()
+            .cloned()
+            .unwrap_or_default()
+    }
+
+    /// A temp file for an upload, in `temp_dir` and named with the prefix and suffix.
+    pub(crate) fn temp_file(&self) -> io::Result<NamedTempFile> {
+        let mut builder = tempfile::Builder::new();
+        if let Some(prefix) = &self.temp_file_prefix {
+            builder.prefix(prefix);
+        }
+        if let Some(suffix) = &self.temp_file_suffix {
+            builder.suffix(suffix);
+        }
+        match &self.temp_dir {
+            Some(dir) => builder.tempfile_in(dir),
+            None => builder.tempfile(),
+        }
+    }
+
+    pub(crate) fn query_parser(&self) -> QueryParser {
+        QueryParser::new(Some(self.security.max_depth))
+            .with_mode(self.parse_mode)
+            .with_max_params(self.security.max_params)
+            .with_max_array_length(self.security.max_array_length)
+            .with_compat(self.compat)
+            .with_delimiters(&self.query_delimiters)
+            .with_repeated_keys(self.repeated_keys)
+            .with_denied_keys(self.security.denied_keys.clone())
+            .with_strict_utf8(self.security.strict_utf8)
+    }
+}
@@ -0,0 +1,260 @@
+//! A compatibility corpus: query strings and multipart bodies with the params Rack
+//! returns for them, run through the crate to make every divergence explicit.
+//!
+//! The corpus checked in at `fixtures/rack.json` runs with the tests. Cases from production
+//! can be checked the same way:
+//!
+//! ```ignore
+//! let corpus = Corpus::from_json(&std::fs::read_to_string("my_cases.json")?)?;
+//! for mismatch in corpus.run().await {
+//!     eprintln!("{mismatch}");
+//! }
+//! ```
+//!
+//! A case reads the query string from `query` or the multipart parts from `parts`, and has
+//! the recorded Rack output, either `{"params": ...}` or `{"error": "ParameterTypeError"}`:
+//!
+//! ```json
+//! {
+//!   "name": "array of hashes",
+//!   "query": "x[][y]=1&x[][y]=2",
+//!   "rack": {"params": {"x": [{"y": "1"}, {"y": "2"}]}}
+//! }
+//! ```
+//!
+//! Deliberate differences are recorded next to the Rack output in `divergence`, with the
+//! `reason` and the `expected` output of this crate. Uploaded files are compared by the
+//! `filename` and `type` keys of Rack's file hash.
+
+use std::fmt;
+
+use axum::{
+    body::Body,
+    extract::{FromRequest, Request},
+    http,
+};
+use serde::{Deserialize, Serialize};
+
+use crate::{Error, N, Number, RawParams, Value};
+
+/// A set of cases, usually read from a JSON file.
+#[derive(Debug, Clone, Deserialize)]
+pub struct Corpus {
+    pub cases: Vec<Case>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct Case {
+    pub name: String,
+    #[serde(flatten)]
+    pub input: Input,
+    /// What Rack returns, missing when it can't be written as JSON.
+    pub rack: Option<Outcome>,
+    pub divergence: Option<Divergence>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Input {
+    /// A query string, without the `?`.
+    Query(String),
+    /// The parts of a `multipart/form-data` body.
+    Parts(Vec<Part>),
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct Part {
+    pub name: String,
+    #[serde(default)]
+    pub value: String,
+    pub filename: Option<String>,
+    pub content_type: Option<String>,
+}
+
+/// The parsed params, or the class of the error Rack raises, e.g. `ParameterTypeError`.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Outcome {
+    Params(serde_json::Value),
+    Error(String),
+}
+
+/// A known difference from Rack.
+#[derive(Debug, Clone, Deserialize)]
+pub struct Divergence {
+    pub reason: String,
+    pub expected: Outcome,
+}
+
+/// A case whose output differs from its expectation.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Mismatch {
+    pub case: String,
+    /// The divergence's expected output if there is one, else the Rack output.
+    pub expected: Option<Outcome>,
+    pub actual: Outcome,
+}
+
+impl fmt::Display for Mismatch {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let json = |outcome: &Outcome| serde_json::to_string(outcome).unwrap_or_default();
+        write!(
+            f,
+            "{}: expected {}, got {}",
+            self.case,
+            self.expected.as_ref().map_or("nothing".to_string(), json),
+            json(&self.actual)
+        )
+    }
+}
+
+impl Corpus {
+    pub fn from_json(json: &str) -> Result<Self, serde_json::Error> {
+        serde_json::from_str(json)
+    }
+
+    /// Runs every case with the default `ParamsConfig`.
+    pub async fn run(&self) -> Vec<Mismatch> {
+        let mut mismatches = vec![];
+        for case in &self.cases {
+            let actual = case.input.run().await;
+            let expected = case
+                .divergence
+                .as_ref()
+                .map(|divergence| &divergence.expected)
+                .or(case.rack.as_ref());
+            if expected != Some(&actual) {
+                mismatches.push(Mismatch {
+                    case: case.name.clone(),
+                    expected: expected.cloned(),
+                    actual,
+                });
+            }
+        }
+        mismatches
+    }
+}
+
+const BOUNDARY: &str = "corpus-boundary";
+
+impl Input {
+    async fn run(&self) -> Outcome {
+        let request = match self {
+            Input::Query(query) => Request::builder()
+                .method(http::Method::GET)
+                .uri(format!("/?{query}"))
+                .body(Body::empty()),
+            Input::Parts(parts) => Request::builder()
+                .method(http::Method::POST)
+                .uri("/")
+                .header(
+                    http::header::CONTENT_TYPE,
+                    format!("multipart/form-data; boundary={BOUNDARY}"),
+                )
+                .body(Body::from(multipart_body(parts))),
+        };
+        let request = match request {
+            Ok(request) => request,
+            Err(e) => return Outcome::Error(e.to_string()),
+        };
+        match RawParams::from_request(request, &()).await {
+            Ok(RawParams(params, _)) => Outcome::Params(to_json(Value::Object(params))),
+            Err(e) => Outcome::Error(rack_error(e.error()).to_string()),
+        }
+    }
+}
+
+fn multipart_body(parts: &[Part]) -> String {
+    let mut body = String::new();
+    for part in parts {
+        body.push_str(&format!(
+            "--{BOUNDARY}\r\nContent-Disposition: form-data; name=\"{}\"",
+            part.name
+        ));
+        if let Some(filename) = &part.filename {
+            body.push_str(&format!("; filename=\"{filename}\""));
+        }
+        if let Some(content_type) = &part.content_type {
+            body.push_str(&format!("\r\nContent-Type: {content_type}"));
+        }
+        body.push_str(&format!("\r\n\r\n{}\r\n", part.value));
+    }
+    body.push_str(&format!("--{BOUNDARY}--\r\n"));
+    body
+}
+
+/// The Rack error class matching an extraction error.
+fn rack_error(error: &Error) -> &'static str {
+    let message = format!("{error:?}");
+    [
+        ("Parameter type error", "ParameterTypeError"),
+        ("Invalid parameter", "InvalidParameterError"),
+        ("Parameters too deep", "ParamsTooDeepError"),
+        ("Too many parameters", "QueryLimitError"),
+    ]
+    .into_iter()
+    .find(|(prefix, _)| message.contains(prefix))
+    .map_or("Error", |(_, class)| class)
+}
+
+fn to_json(value: Value) -> serde_json::Value {
+    match value {
+        Value::Null => serde_json::Value::Null,
+        Value::Bool(b) => serde_json::Value::Bool(b),
+        Value::Number(Number(n)) => match n {
+            N::PosInt(i) => i.into(),
+            N::NegInt(i) => i.into(),
+            // Beyond the integers of `serde_json`
+            N::PosInt128(i) => (i as f64).into(),
+            N::NegInt128(i) => (i as f64).into(),
+            N::Float(f) => f.into(),
+        },
+        Value::String(s) | Value::XStr(s) => serde_json::Value::String(s.into_string()),
+        Value::Array(vec) => vec.into_iter().map(to_json).collect(),
+        Value::Object(map) => map
+            .into_iter()
+            .map(|(k, v)| (k, to_json(v)))
+            .collect::<serde_json::Map<_, _>>()
+            .into(),
+        Value::UploadFile(file) => serde_json::json!({
+            "filename": file.name,
+            "type": file.content_type,
+        }),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_rack_corpus() {
+        let corpus = Corpus::from_json(include_str!("../fixtures/rack.json")).unwrap();
+        let mismatches = corpus.run().await;
+        assert!(
+            mismatches.is_empty(),
+            "{}",
+            mismatches
+                .iter()
+                .map(ToString::to_string)
+                .collect::<Vec<_>>()
+                .join("\n")
+        );
+    }
+
+    #[tokio::test]
+    async fn test_reports_mismatches() {
+        let corpus = Corpus {
+            cases: vec![Case {
+                name: "last value wins".to_string(),
+                input: Input::Query("a=1&a=2".to_string()),
+                rack: Some(Outcome::Params(serde_json::json!({"a": "1"}))),
+                divergence: None,
+            }],
+        };
+        assert_eq!(
+            corpus.run().await[0].to_string(),
+            r#"last value wins: expected {"params":{"a":"1"}}, got {"params":{"a":"2"}}"#
+        );
+    }
+}
@@ -0,0 +1,181 @@
+use std::collections::HashMap;
+
+use ::serde::de::DeserializeOwned;
+use axum::{
+    extract::{FromRequest, Request},
+    http::request::Parts,
+};
+
+use crate::{BoxFuture, Error, Params, ParamsMetadata, Rejection, Value, rejection::Rejecter};
+
+/// Server side default values of `ParamsWithDefaults`, implemented by the app state:
+///
+/// ```ignore
+/// impl ParamsDefaults for AppState {
+///     fn defaults<'a>(
+///         &'a self,
+///         parts: &'a Parts,
+///     ) -> BoxFuture<'a, Result<HashMap<String, Value>, Error>> {
+///         Box::pin(async move {
+///             let tenant = self.db.tenant_of(&parts.headers).await?;
+///             Ok(HashMap::from([
+///                 ("tenant_id".to_string(), Value::number(tenant.id)),
+///                 ("per_page".to_string(), Value::number(tenant.page_size)),
+///             ]))
+///         })
+///     }
+/// }
+/// ```
+pub trait ParamsDefaults: Send + Sync {
+    /// The defaults for the request `parts`, they fill the keys the client didn't send,
+    /// nested objects key by key.
+    fn defaults<'a>(
+        &'a self,
+        parts: &'a Parts,
+    ) -> BoxFuture<'a, Result<HashMap<String, Value>, Error>>;
+}
+
+/// Like `Params`, with the `ParamsDefaults` of the state filled in after the sources are
+/// merged, for defaults that `#[serde(default)]` can't express, like the page size of the
+/// current tenant.
+///
+/// Defaulted keys are not recorded in `ParamsMetadata::sources`. Values the client must not
+/// override belong in `ParamsConfig::extension` instead.
+#[derive(Debug, Default)]
+pub struct ParamsWithDefaults<T>(pub T, pub ParamsMetadata);
+
+#[cfg_attr(axum07, axum::async_trait)]
+impl<T, S> FromRequest<S> for ParamsWithDefaults<T>
+where
+    T: DeserializeOwned,
+    S: ParamsDefaults,
+{
+    type Rejection = Rejection;
+
+    async fn from_request(req: Request, state: &S) -> Result<Self, Self::Rejection> {
+        let (parts, body) = req.into_parts();
+        let defaults = match state.defaults(&parts).await {
+            Ok(defaults) => defaults,
+            Err(e) => return Err(Rejecter::of_parts(&parts).reject(e)),
+        };
+        let req = Request::from_parts(parts, body);
+        let Params(payload, metadata) = Params::extract(req, state, |params, _| {
+            fill_defaults(params, defaults);
+            Ok(())
+        })
+        .await?;
+        Ok(ParamsWithDefaults(payload, metadata))
+    }
+}
+
+fn fill_defaults(params: &mut HashMap<String, Value>, defaults: HashMap<String, Value>) {
+    for (key, default) in defaults {
+        match (params.get_mut(&key), default) {
+            (None, default) => {
+                params.insert(key, default);
+            }
+            (Some(Value::Object(map)), Value::Object(defaults)) => fill_defaults(map, defaults),
+            _ => {}
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use axum::{Router, http::StatusCode, routing::get};
+    use axum_test::TestServer;
+    use serde::Deserialize;
+
+    use super::*;
+
+    #[derive(Clone)]
+    struct AppState {
+        page_size: u32,
+    }
+
+    impl ParamsDefaults for AppState {
+        fn defaults<'a>(
+            &'a self,
+            parts: &'a Parts,
+        ) -> BoxFuture<'a, Result<HashMap<String, Value>, Error>> {
+            Box::pin(async move {
+                let tenant = parts
+                    .headers
+                    .get("x-tenant")
+                    .and_then(|tenant| tenant.to_str().ok())
+                    .ok_or_else(|| Error::Forbidden("Missing tenant".to_string()))?;
+                Ok(HashMap::from([
+                    ("tenant".to_string(), Value::xstr(tenant)),
+                    (
+                        "page".to_string(),
+                        Value::Object(HashMap::from([
+                            ("number".to_string(), Value::number(1_u64)),
+                            ("size".to_string(), Value::number(u64::from(self.page_size))),
+                        ])),
+                    ),
+                ]))
+            })
+        }
+    }
+
+    #[derive(Debug, Deserialize)]
+    struct Page {
+        number: u32,
+        size: u32,
+    }
+
+    #[derive(Debug, Deserialize)]
+    struct ListPosts {
+        tenant: String,
+        page: Page,
+    }
+
+    #[tokio::test]
+    async fn test_params_with_defaults() {
+        let app = Router::new()
+            .route(
+                "/posts",
+                get(
+                    |ParamsWithDefaults(list, _): ParamsWithDefaults<ListPosts>| async move {
+                        format!("{} {} {}", list.tenant, list.page.number, list.page.size)
+                    },
+                ),
+            )
+            .with_state(AppState { page_size: 25 });
+        let server = TestServer::new(app).unwrap();
+
+        let response = server.get("/posts").add_header("x-tenant", "acme").await;
+        assert_eq!(response.status_code(), StatusCode::OK);
+        assert_eq!(response.text(), "acme 1 25");
+
+        // Sent values win, nested objects are filled key by key
+        let response = server
+            .get("/posts?tenant=other&page[number]=3")
+            .add_header("x-tenant", "acme")
+            .await;
+        assert_eq!(response.text(), "other 3 25");
+
+        let response = server.get("/posts").await;
+        assert_eq!(response.status_code(), StatusCode::FORBIDDEN);
+    }
+
+    #[tokio::test]
+    async fn test_params_with_defaults_audit() {
+        let app = Router::new()
+            .route("/posts", get(|_: ParamsWithDefaults<ListPosts>| async {}))
+            .layer(axum::middleware::from_fn(crate::params_audit))
+            .with_state(AppState { page_size: 25 });
+        let app = app.layer(axum::middleware::from_fn(
+            |req: Request, next: axum::middleware::Next| async move {
+                let response = next.run(req).await;
+                let audit = response.extensions().get::<crate::ParamsAudit>().unwrap();
+                assert_eq!(audit.params.get("tenant"), Some(&Value::xstr("acme")));
+                response
+            },
+        ));
+        let server = TestServer::new(app).unwrap();
+
+        let response = server.get("/posts").add_header("x-tenant", "acme").await;
+        assert_eq!(response.status_code(), StatusCode::OK);
+    }
+}
@@ -0,0 +1,509 @@
+use axum::{
+    body::Body,
+    http::{HeaderMap, header},
+};
+
+use encoding_rs::Encoding;
+use std::borrow::Cow;
+
+#[cfg(feature = "compression")]
+use crate::params::buffer_body;
+use crate::{Error, SecurityPolicy};
+
+/// Decodes `body` according to its `Content-Encoding`, `gzip`, `deflate` and `br` with
+/// feature `compression`. Other encodings are rejected instead of being parsed as garbage.
+///
+/// Encoded bodies are decompressed in memory, both the compressed and the decompressed size
+/// are limited to `SecurityPolicy::max_body_size` with `Error::PayloadTooLarge`.
+pub(crate) async fn decode_body(
+    headers: &HeaderMap,
+    body: Body,
+    policy: &SecurityPolicy,
+) -> Result<Body, Error> {
+    let encodings = content_encodings(headers)?;
+    if encodings.is_empty() {
+        return Ok(body);
+    }
+    decode(&encodings, body, policy).await
+}
+
+#[cfg(feature = "compression")]
+async fn decode(encodings: &[String], body: Body, policy: &SecurityPolicy) -> Result<Body, Error> {
+    let mut bytes = buffer_body(body, policy.max_body_size, "encoded")
+        .await?
+        .to_vec();
+    // Listed in the order they were applied
+    for encoding in encodings.iter().rev() {
+        bytes = decompress(encoding, &bytes, policy.max_body_size)?;
+    }
+    Ok(Body::from(bytes))
+}
+
+#[cfg(not(feature = "compression"))]
+async fn decode(encodings: &[String], _: Body, _: &SecurityPolicy) -> Result<Body, Error> {
+    Err(unsupported(encodings))
+}
+
+#[cfg(not(feature = "compression"))]
+fn unsupported(encodings: &[String]) -> Error {
+    Error::DecodeError(format!(
+        "Unsupported content encoding `{}`, enable the `compression` feature for gzip, deflate and br",
+        encodings.join(", ")
+    ))
+}
+
+/// Decodes a body according to its `Content-Encoding` chunk by chunk as it is read, like
+/// `decode_body` for bodies that are not buffered. Each piece of at most `PIECE` encoded
+/// bytes may decompress to `SecurityPolicy::max_body_size` bytes, the body as a whole is
+/// not limited.
+pub(crate) struct StreamDecoder {
+    /// The decoders in the order to run them, the last encoding applied first.
+    layers: Vec<Layer>,
+    limit: usize,
+}
+
+impl StreamDecoder {
+    /// Encoded bytes decoded at once.
+    const PIECE: usize = 1024;
+
+    pub(crate) fn new(headers: &HeaderMap, policy: &SecurityPolicy) -> Result<Self, Error> {
+        let encodings = content_encodings(headers)?;
+        let layers = encodings
+            .iter()
+            .rev()
+            .map(|encoding| Layer::new(encoding))
+            .collect::<Result<_, _>>()?;
+        Ok(StreamDecoder {
+            layers,
+            limit: policy.max_body_size,
+        })
+    }
+
+    /// `chunk` in pieces to `decode` one after another.
+    pub(crate) fn pieces<'a>(&self, chunk: &'a [u8]) -> std::slice::Chunks<'a, u8> {
+        let size = if self.layers.is_empty() {
+            chunk.len()
+        } else {
+            Self::PIECE
+        };
+        chunk.chunks(size.max(1))
+    }
+
+    pub(crate) fn decode<'a>(&mut self, piece: &'a [u8]) -> Result<Cow<'a, [u8]>, Error> {
+        let mut bytes = Cow::Borrowed(piece);
+        for layer in &mut self.layers {
+            bytes = Cow::Owned(layer.write(&bytes, self.limit)?);
+        }
+        Ok(bytes)
+    }
+
+    /// The bytes held back by the decoders at the end of the body.
+    pub(crate) fn finish(self) -> Result<Vec<u8>, Error> {
+        let mut bytes = vec![];
+        for mut layer in self.layers {
+            let mut decoded = layer.write(&bytes, self.limit)?;
+            decoded.extend(layer.finish()?);
+            bytes = decoded;
+        }
+        Ok(bytes)
+    }
+}
+
+#[cfg(feature = "compression")]
+enum Layer {
+    /// `deflate` until its first two bytes tell zlib from raw deflate data.
+    Deflate(Vec<u8>),
+    Zlib(flate2::write::ZlibDecoder<Vec<u8>>),
+    RawDeflate(flate2::write::DeflateDecoder<Vec<u8>>),
+    Gzip(flate2::write::MultiGzDecoder<Vec<u8>>),
+    Br(Box<brotli::DecompressorWriter<Vec<u8>>>),
+}
+
+#[cfg(feature = "compression")]
+impl Layer {
+    fn new(encoding: &str) -> Result<Self, Error> {
+        Ok(match encoding {
+            "gzip" | "x-gzip" => Layer::Gzip(flate2::write::MultiGzDecoder::new(vec![])),
+            "deflate" => Layer::Deflate(vec![]),
+            "br" => Layer::Br(Box::new(brotli::DecompressorWriter::new(vec![], 4096))),
+            _ => {
+                return Err(Error::DecodeError(format!(
+                    "Unsupported content encoding `{encoding}`"
+                )));
+            }
+        })
+    }
+
+    fn name(&self) -> &'static str {
+        match self {
+            Layer::Deflate(_) | Layer::Zlib(_) | Layer::RawDeflate(_) => "deflate",
+            Layer::Gzip(_) => "gzip",
+            Layer::Br(_) => "br",
+        }
+    }
+
+    fn write(&mut self, bytes: &[u8], limit: usize) -> Result<Vec<u8>, Error> {
+        use std::io::Write;
+
+        fn drain<D: Write>(
+            decoder: &mut D,
+            bytes: &[u8],
+            output: fn(&mut D) -> &mut Vec<u8>,
+        ) -> std::io::Result<Vec<u8>> {
+            decoder.write_all(bytes)?;
+            decoder.flush()?;
+            Ok(std::mem::take(output(decoder)))
+        }
+
+        let name = self.name();
+        let decoded = match self {
+            Layer::Deflate(head) => {
+                head.extend_from_slice(bytes);
+                if head.len() < 2 {
+                    return Ok(vec![]);
+                }
+                let head = std::mem::take(head);
+                *self = if is_zlib(&head) {
+                    Layer::Zlib(flate2::write::ZlibDecoder::new(vec![]))
+                } else {
+                    Layer::RawDeflate(flate2::write::DeflateDecoder::new(vec![]))
+                };
+                return self.write(&head, limit);
+            }
+            Layer::Zlib(decoder) => drain(decoder, bytes, flate2::write::ZlibDecoder::get_mut),
+            Layer::RawDeflate(decoder) => {
+                drain(decoder, bytes, flate2::write::DeflateDecoder::get_mut)
+            }
+            Layer::Gzip(decoder) => drain(decoder, bytes, flate2::write::MultiGzDecoder::get_mut),
+            Layer::Br(decoder) => {
+                drain(decoder.as_mut(), bytes, brotli::DecompressorWriter::get_mut)
+            }
+        }
+        .map_err(|e| Error::DecodeError(format!("Failed to decode {name} request body: {e}")))?;
+        if decoded.len() > limit {
+            return Err(Error::PayloadTooLarge(format!(
+                "Failed to decode {name} request body: length limit exceeded"
+            )));
+        }
+        Ok(decoded)
+    }
+
+    fn finish(self) -> Result<Vec<u8>, Error> {
+        let name = self.name();
+        match self {
+            Layer::Deflate(head) if head.is_empty() => Ok(head),
+            Layer::Deflate(_) => Err(std::io::Error::from(std::io::ErrorKind::UnexpectedEof)),
+            Layer::Zlib(decoder) => decoder.finish(),
+            Layer::RawDeflate(decoder) => decoder.finish(),
+            Layer::Gzip(decoder) => decoder.finish(),
+            Layer::Br(mut decoder) => decoder.close().map(|()| std::mem::take(decoder.get_mut())),
+        }
+        .map_err(|e| Error::DecodeError(format!("Failed to decode {name} request body: {e}")))
+    }
+}
+
+#[cfg(not(feature = "compression"))]
+enum Layer {}
+
+#[cfg(not(feature = "compression"))]
+impl Layer {
+    fn new(encoding: &str) -> Result<Self, Error> {
+        Err(unsupported(&[encoding.to_string()]))
+    }
+
+    fn write(&mut self, _: &[u8], _: usize) -> Result<Vec<u8>, Error> {
+        match *self {}
+    }
+
+    fn finish(self) -> Result<Vec<u8>, Error> {
+        match self {}
+    }
+}
+
+/// The charset of a form-urlencoded body: the `charset` parameter of its content type, else
+/// the `_charset_` field browsers fill in for forms with such a hidden input, else UTF-8.
+pub(crate) fn form_charset(
+    content_type: &str,
+    form: &[u8],
+    delimiters: &[char],
+) -> Result<&'static Encoding, Error> {
+    let field = || {
+        form_pairs(form, delimiters)
+            .find(|(key, _)| *key == b"_charset_")
+            .and_then(|(_, value)| value)
+    };
+    match charset_param(content_type).or_else(field) {
+        Some(label) => Encoding::for_label(label).ok_or_else(|| {
+            Error::DecodeError(format!(
+                "Unsupported form charset `{}`",
+                String::from_utf8_lossy(label)
+            ))
+        }),
+        None => Ok(encoding_rs::UTF_8),
+    }
+}
+
+/// The `charset` parameter of a content type.
+fn charset_param(content_type: &str) -> Option<&[u8]> {
+    content_type.split(';').skip(1).find_map(|param| {
+        let (name, value) = param.split_once('=')?;
+        name.trim()
+            .eq_ignore_ascii_case("charset")
+            .then(|| value.trim().trim_matches('"').as_bytes())
+    })
+}
+
+/// Decodes a `text/plain` body in the `charset` of its content type, UTF-8 by default. With
+/// `strict`, invalid bytes are rejected instead of replaced by U+FFFD.
+pub(crate) fn decode_text(content_type: &str, bytes: &[u8], strict: bool) -> Result<String, Error> {
+    let encoding = match charset_param(content_type) {
+        Some(label) => Encoding::for_label(label).ok_or_else(|| {
+            Error::DecodeError(format!(
+                "Unsupported text charset `{}`",
+                String::from_utf8_lossy(label)
+            ))
+        })?,
+        None => encoding_rs::UTF_8,
+    };
+    let (text, had_errors) = encoding.decode_without_bom_handling(bytes);
+    if strict && had_errors {
+        return Err(Error::DecodeError(format!(
+            "Failed to parse text body: invalid {}",
+            encoding.name()
+        )));
+    }
+    Ok(text.into_owned())
+}
+
+/// Re-encodes a form-urlencoded body sent in `encoding` as UTF-8, before it is parsed. With
+/// `strict`, bytes that are invalid in `encoding` are rejected instead of replaced by U+FFFD.
+pub(crate) fn transcode_form(
+    form: &[u8],
+    encoding: &'static Encoding,
+    delimiters: &[char],
+    strict: bool,
+) -> Result<String, Error> {
+    let transcode = |component: &[u8]| {
+        let plus_as_space = component
+            .iter()
+            .map(|&b| if b == b'+' { b' ' } else { b })
+            .collect::<Vec<_>>();
+        let bytes = percent_encoding::percent_decode(&plus_as_space).collect::<Vec<_>>();
+        let (decoded, had_errors) = encoding.decode_without_bom_handling(&bytes);
+        if strict && had_errors {
+            return Err(Error::DecodeError(format!(
+                "Failed to parse form-urlencoded body: invalid {}",
+                encoding.name()
+            )));
+        }
+        Ok(form_urlencoded::byte_serialize(decoded.as_bytes()).collect::<String>())
+    };
+    let mut transcoded = String::with_capacity(form.len());
+    for (i, (key, value)) in form_pairs(form, delimiters).enumerate() {
+        if i > 0 {
+            transcoded.push(delimiters.first().copied().unwrap_or('&'));
+        }
+        transcoded.push_str(&transcode(key)?);
+        if let Some(value) = value {
+            transcoded.push('=');
+            transcoded.push_str(&transcode(value)?);
+        }
+    }
+    Ok(transcoded)
+}
+
+/// The raw `key=value` pairs of a form body, the value is `None` without `=`.
+fn form_pairs<'a>(
+    form: &'a [u8],
+    delimiters: &'a [char],
+) -> impl Iterator<Item = (&'a [u8], Option<&'a [u8]>)> {
+    form.split(|&b| delimiters.iter().any(|&d| u32::from(b) == u32::from(d)))
+        .filter(|pair| !pair.is_empty())
+        .map(|pair| match pair.iter().position(|&b| b == b'=') {
+            Some(i) => (&pair[..i], Some(&pair[i + 1..])),
+            None => (pair, None),
+        })
+}
+
+/// The encodings of the `Content-Encoding` header, without `identity`.
+fn content_encodings(headers: &HeaderMap) -> Result<Vec<String>, Error> {
+    let mut encodings = vec![];
+    for value in headers.get_all(header::CONTENT_ENCODING) {
+        let value = value
+            .to_str()
+            .map_err(|_| Error::DecodeError("Invalid Content-Encoding header".to_string()))?;
+        for encoding in value.split(',') {
+            let encoding = encoding.trim().to_ascii_lowercase();
+            if !encoding.is_empty() && encoding != "identity" {
+                encodings.push(encoding);
+            }
+        }
+    }
+    Ok(encodings)
+}
+
+/// Decompresses `bytes` of `encoding`, failing with `Error::PayloadTooLarge` past `limit`.
+#[cfg(feature = "compression")]
+fn decompress(encoding: &str, bytes: &[u8], limit: usize) -> Result<Vec<u8>, Error> {
+    use std::io::Read;
+
+    use flate2::read::{DeflateDecoder, MultiGzDecoder, ZlibDecoder};
+
+    let decoder: Box<dyn Read + '_> = match encoding {
+        "gzip" | "x-gzip" => Box::new(MultiGzDecoder::new(bytes)),
+        // `deflate` is zlib wrapped, some clients send raw deflate data instead
+        "deflate" if is_zlib(bytes) => Box::new(ZlibDecoder::new(bytes)),
+        "deflate" => Box::new(DeflateDecoder::new(bytes)),
+        "br" => Box::new(brotli::Decompressor::new(bytes, 4096)),
+        _ => {
+            return Err(Error::DecodeError(format!(
+                "Unsupported content encoding `{encoding}`"
+            )));
+        }
+    };
+    let mut decoded = vec![];
+    decoder
+        .take(limit as u64 + 1)
+        .read_to_end(&mut decoded)
+        .map_err(|e| {
+            Error::DecodeError(format!("Failed to decode {encoding} request body: {e}"))
+        })?;
+    if decoded.len() > limit {
+        return Err(Error::PayloadTooLarge(format!(
+            "Failed to decode {encoding} request body: length limit exceeded"
+        )));
+    }
+    Ok(decoded)
+}
+
+#[cfg(feature = "compression")]
+fn is_zlib(bytes: &[u8]) -> bool {
+    matches!(bytes, [cmf, flg, ..] if cmf & 0x0f == 8 && (u16::from(*cmf) << 8 | u16::from(*flg)) % 31 == 0)
+}
+
+#[cfg(all(test, feature = "compression"))]
+mod tests {
+    use std::io::Write;
+
+    use axum::{extract::FromRequest, http};
+    use flate2::{Compression, GzBuilder, write::GzEncoder};
+
+    use super::*;
+    use crate::{ParamsConfig, RawParams, Value};
+
+    fn gzip(bytes: &[u8]) -> Vec<u8> {
+        let mut encoder = GzEncoder::new(vec![], Compression::default());
+        encoder.write_all(bytes).unwrap();
+        encoder.finish().unwrap()
+    }
+
+    fn brotli(bytes: &[u8]) -> Vec<u8> {
+        let mut encoded = vec![];
+        let mut encoder = brotli::CompressorWriter::new(&mut encoded, 4096, 5, 22);
+        encoder.write_all(bytes).unwrap();
+        drop(encoder);
+        encoded
+    }
+
+    fn request(encoding: &str, body: Body) -> http::Request<Body> {
+        http::Request::builder()
+            .method(http::Method::POST)
+            .header(header::CONTENT_TYPE, "application/json")
+            .header(header::CONTENT_ENCODING, encoding)
+            .body(body)
+            .unwrap()
+    }
+
+    #[test]
+    fn test_decompress() {
+        let json = br#"{"name": "alice", "tags": ["a", "b"]}"#;
+        assert_eq!(decompress("gzip", &gzip(json), 1024).unwrap(), json);
+        let mut zlib = flate2::write::ZlibEncoder::new(vec![], Compression::default());
+        zlib.write_all(json).unwrap();
+        assert_eq!(
+            decompress("deflate", &zlib.finish().unwrap(), 1024).unwrap(),
+            json
+        );
+        let mut raw = flate2::write::DeflateEncoder::new(vec![], Compression::default());
+        raw.write_all(json).unwrap();
+        assert_eq!(
+            decompress("deflate", &raw.finish().unwrap(), 1024).unwrap(),
+            json
+        );
+        assert_eq!(decompress("br", &brotli(json), 1024).unwrap(), json);
+
+        // Optional gzip header fields
+        let mut encoder = GzBuilder::new()
+            .extra(b"xy".to_vec())
+            .filename("body.json")
+            .comment("from a test")
+            .write(vec![], Compression::default());
+        encoder.write_all(json).unwrap();
+        let gz = encoder.finish().unwrap();
+        assert_eq!(decompress("gzip", &gz, 1024).unwrap(), json);
+
+        let mut corrupt = gzip(json);
+        let len = corrupt.len();
+        corrupt[len - 8] ^= 1;
+        let error = decompress("gzip", &corrupt, 1024).unwrap_err();
+        assert!(matches!(error, Error::DecodeError(_)), "{error:?}");
+        let error = decompress("zstd", json, 1024).unwrap_err();
+        assert!(error.message().contains("Unsupported content encoding"));
+
+        // A zip bomb stops at the limit
+        for (encoding, bomb) in [
+            ("gzip", gzip(&vec![0; 1 << 20])),
+            ("br", brotli(&vec![0; 1 << 20])),
+        ] {
+            assert!(bomb.len() < 2048);
+            let error = decompress(encoding, &bomb, 64 << 10).unwrap_err();
+            assert!(matches!(error, Error::PayloadTooLarge(_)), "{error:?}");
+        }
+    }
+
+    #[tokio::test]
+    async fn test_encoded_json_body() {
+        for (encoding, body) in [
+            ("gzip", gzip(br#"{"name": "alice"}"#)),
+            ("br", brotli(br#"{"name": "alice"}"#)),
+        ] {
+            let req = request(encoding, Body::from(body));
+            let RawParams(params, _) = RawParams::from_request(req, &()).await.unwrap();
+            assert!(matches!(&params["name"], Value::String(s) if s == "alice"));
+        }
+
+        // Too large before and after decompression, the encoded body streamed without length
+        let policy = SecurityPolicy::default().max_body_size(1024);
+        let config = ParamsConfig::new().security_policy(policy);
+        let bomb = gzip(format!(r#"{{"name": "{}"}}"#, "a".repeat(1 << 20)).as_bytes());
+        let mut seed = 1u32;
+        let noise = (0..4096)
+            .map(|_| {
+                seed = seed.wrapping_mul(1_103_515_245).wrapping_add(12345);
+                (seed >> 16) as u8
+            })
+            .collect::<Vec<_>>();
+        let noise = gzip(&noise);
+        assert!(noise.len() > 1024);
+        for body in [bomb, noise] {
+            let chunks = body
+                .chunks(512)
+                .map(|chunk| Ok::<_, std::io::Error>(chunk.to_vec()))
+                .collect::<Vec<_>>();
+            let mut req = request(
+                "gzip",
+                Body::from_stream(futures_util::stream::iter(chunks)),
+            );
+            req.extensions_mut().insert(config.clone());
+            let error = RawParams::from_request(req, &())
+                .await
+                .unwrap_err()
+                .into_error();
+            assert_eq!(
+                error.status(),
+                http::StatusCode::PAYLOAD_TOO_LARGE,
+                "{error:?}"
+            );
+        }
+    }
+}
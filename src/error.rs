@@ -1,21 +1,295 @@
+#[cfg(server)]
 use axum::{
-    http::StatusCode,
+    http::{StatusCode, header},
     response::{IntoResponse, Response},
 };
 
+use std::{fmt, io, sync::Arc};
+
+use crate::{JsonError, JsonLocation, Source, query_parser::QueryParserError};
+
 #[derive(Debug, Clone)]
 pub enum Error {
     DecodeError(String),
     ReadError(String),
-    IOError(String),
+    /// An I/O failure, e.g. writing an upload to its temp file, with the `io::Error`.
+    IOError(String, Arc<io::Error>),
     MergeError(String),
+    /// The request is not allowed, e.g. by a `PostPolicy`, responds with 403.
+    Forbidden(String),
+    /// The query string exceeds `SecurityPolicy::max_query_len`, responds with 414.
+    QueryTooLong(String),
+    /// The body exceeds a size or count limit of `SecurityPolicy`, responds with 413.
+    PayloadTooLarge(String),
+    /// The server is too busy to extract the request, responds with 503.
+    ServiceUnavailable(String),
+    /// Parameters the target type doesn't have, with `ParamsConfig::deny_unknown_fields`.
+    UnknownFields(String, Vec<UnknownField>),
+    /// Parameters rejected by validation, e.g. of `ValidatedParams`, responds with 422.
+    InvalidFields(String, Vec<InvalidField>),
+    /// Every deserialization error, with `ParamsConfig::collect_errors`.
+    DecodeErrors(String, Vec<InvalidField>),
+    /// Parameters the query parser rejects, e.g. nested too deep, in the query string, a
+    /// form body, a multipart part or the path.
+    InvalidParams(String, QueryParserError),
+    /// A malformed JSON body or multipart part, with where it fails.
+    InvalidJson(String, JsonLocation, JsonError),
+}
+
+/// A parameter sent by the client that the target type doesn't read.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct UnknownField {
+    /// The full key, e.g. `user[admin]`.
+    pub key: String,
+    pub source: Option<Source>,
+}
+
+/// A parameter that failed validation or deserialization.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct InvalidField {
+    /// The full key, e.g. `user[emails][0]`, empty for errors about the whole params.
+    pub key: String,
+    pub message: String,
+}
+
+impl Error {
+    pub fn message(&self) -> &str {
+        match self {
+            Error::DecodeError(message)
+            | Error::ReadError(message)
+            | Error::IOError(message, _)
+            | Error::MergeError(message)
+            | Error::Forbidden(message)
+            | Error::QueryTooLong(message)
+            | Error::PayloadTooLarge(message)
+            | Error::ServiceUnavailable(message)
+            | Error::UnknownFields(message, _)
+            | Error::InvalidFields(message, _)
+            | Error::DecodeErrors(message, _)
+            | Error::InvalidParams(message, _)
+            | Error::InvalidJson(message, ..) => message,
+        }
+    }
+
+    /// `Error::IOError` of `err`, `context` says what failed.
+    #[cfg_attr(not(server), allow(dead_code))]
+    pub(crate) fn io(context: &str, err: io::Error) -> Self {
+        Error::IOError(format!("{context}: {err}"), Arc::new(err))
+    }
+
+    /// `Error::InvalidParams` of `err`, `context` says what failed to parse.
+    pub(crate) fn params(context: &str, err: QueryParserError) -> Self {
+        Error::InvalidParams(format!("{context}: {err}"), err)
+    }
+
+    /// The parameter the error is about, e.g. `user[name]` in "missing field `user[name]`".
+    pub fn field(&self) -> Option<&str> {
+        let message = self.message();
+        let mut rest = message;
+        while let Some(start) = rest.find('`') {
+            let before = &message[..message.len() - rest.len() + start];
+            let name = &rest[start + 1..];
+            let end = name.find('`')?;
+            let word = before.trim_end().rsplit(' ').next().unwrap_or_default();
+            if matches!(
+                word.to_ascii_lowercase().as_str(),
+                "" | "field" | "param" | "parameter" | "file" | "for" | "deserialize"
+            ) {
+                return Some(&name[..end]);
+            }
+            rest = &name[end + 1..];
+        }
+        None
+    }
+}
+
+/// The message, e.g. "Failed to deserialize parameters: missing field `name`".
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(self.message())
+    }
+}
+
+/// `source` is the `io::Error`, `QueryParserError` or `JsonError` of the variants that have one.
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Error::IOError(_, err) => Some(err.as_ref()),
+            Error::InvalidParams(_, err) => Some(err),
+            Error::InvalidJson(_, _, err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
+impl From<QueryParserError> for Error {
+    fn from(err: QueryParserError) -> Self {
+        Error::params("Failed to parse parameters", err)
+    }
 }
 
+impl From<io::Error> for Error {
+    fn from(err: io::Error) -> Self {
+        Error::IOError(err.to_string(), Arc::new(err))
+    }
+}
+
+#[cfg(server)]
+impl Error {
+    pub fn status(&self) -> StatusCode {
+        match self {
+            Error::ServiceUnavailable(_) => StatusCode::SERVICE_UNAVAILABLE,
+            Error::QueryTooLong(_) => StatusCode::URI_TOO_LONG,
+            Error::PayloadTooLarge(_) => StatusCode::PAYLOAD_TOO_LARGE,
+            Error::Forbidden(_) => StatusCode::FORBIDDEN,
+            Error::InvalidFields(..) => StatusCode::UNPROCESSABLE_ENTITY,
+            _ => StatusCode::BAD_REQUEST,
+        }
+    }
+
+    /// An RFC 7807 `application/problem+json` response, the `errors` array lists the
+    /// parameter the error is about when it names one:
+    ///
+    /// ```json
+    /// {
+    ///   "type": "about:blank",
+    ///   "title": "Bad Request",
+    ///   "status": 400,
+    ///   "detail": "Failed to deserialize parameters: missing field `name`",
+    ///   "errors": [{"field": "name", "detail": "Failed to deserialize parameters: missing field `name`"}]
+    /// }
+    /// ```
+    ///
+    /// Used for the rejections of the extractors with `ParamsConfig::problem_details`.
+    ///
+    /// `Error::UnknownFields` lists each field with its source, `Error::InvalidFields` and
+    /// `Error::DecodeErrors` each field with its message, `Error::InvalidJson` the `line`,
+    /// `column`, `offset` and `excerpt` of its location.
+    pub fn into_problem_response(self) -> Response {
+        let status = self.status();
+        let detail = self.message();
+        let issues = match &self {
+            Error::UnknownFields(_, fields) => fields
+                .iter()
+                .map(|field| {
+                    serde_json::json!({
+                        "detail": "Unknown parameter",
+                        "field": field.key,
+                        "source": field.source.map(Source::as_str),
+                    })
+                })
+                .collect(),
+            Error::InvalidFields(_, fields) | Error::DecodeErrors(_, fields) => fields
+                .iter()
+                .map(|field| {
+                    let mut issue = serde_json::json!({ "detail": field.message });
+                    if !field.key.is_empty() {
+                        issue["field"] = field.key.as_str().into();
+                    }
+                    issue
+                })
+                .collect(),
+            Error::InvalidJson(_, location, _) => vec![serde_json::json!({
+                "detail": detail,
+                "line": location.line,
+                "column": location.column,
+                "offset": location.offset,
+                "excerpt": location.excerpt,
+            })],
+            _ => {
+                let mut issue = serde_json::json!({ "detail": detail });
+                if let Some(field) = self.field() {
+                    issue["field"] = field.into();
+                }
+                vec![issue]
+            }
+        };
+        let body = serde_json::json!({
+            "type": "about:blank",
+            "title": status.canonical_reason().unwrap_or_default(),
+            "status": status.as_u16(),
+            "detail": detail,
+            "errors": issues,
+        });
+        Response::builder()
+            .status(status)
+            .header(header::CONTENT_TYPE, "application/problem+json")
+            .body(body.to_string().into())
+            .unwrap()
+    }
+}
+
+#[cfg(server)]
 impl IntoResponse for Error {
     fn into_response(self) -> Response {
         Response::builder()
-            .status(StatusCode::BAD_REQUEST)
-            .body(format!("{:?}", self).into())
+            .status(self.status())
+            .body(self.to_string().into())
             .unwrap()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_field() {
+        let field = |message: &str| {
+            Error::DecodeError(message.to_string())
+                .field()
+                .map(String::from)
+        };
+        assert_eq!(
+            field("Failed to deserialize parameters: missing field `name`").as_deref(),
+            Some("name")
+        );
+        assert_eq!(
+            field("Conflicting values for `id`: string from query and array from json").as_deref(),
+            Some("id")
+        );
+        assert_eq!(
+            field("Failed to deserialize parameters: invalid value `x`, expected `a`").as_deref(),
+            None
+        );
+        assert_eq!(
+            field("`file` is not an uploaded file").as_deref(),
+            Some("file")
+        );
+        assert_eq!(field("Syntax error").as_deref(), None);
+    }
+
+    #[test]
+    fn test_display() {
+        let error = Error::DecodeError("Failed to parse parameters: bad key".to_string());
+        assert_eq!(error.to_string(), "Failed to parse parameters: bad key");
+
+        let error = Error::from(QueryParserError::ParamsTooDeepError("at `a`".to_string()));
+        assert_eq!(
+            error.to_string(),
+            "Failed to parse parameters: Parameters too deep: at `a`"
+        );
+
+        // Handlers can return other error types with `?`
+        fn handler() -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+            Err(Error::Forbidden("Nope".to_string()))?
+        }
+        assert_eq!(handler().unwrap_err().to_string(), "Nope");
+    }
+
+    #[test]
+    fn test_source() {
+        use std::error::Error as _;
+
+        let error = Error::from(QueryParserError::TooManyParameters(3));
+        let source = error.source().unwrap();
+        assert!(source.downcast_ref::<QueryParserError>().is_some());
+
+        let error = Error::io("Failed to open temp file", io::Error::other("disk full"));
+        assert_eq!(error.to_string(), "Failed to open temp file: disk full");
+        let source = error.source().unwrap().downcast_ref::<io::Error>().unwrap();
+        assert_eq!(source.to_string(), "disk full");
+
+        assert!(Error::Forbidden("Nope".to_string()).source().is_none());
+    }
+}
@@ -0,0 +1,199 @@
+use serde::{Deserializer, de};
+
+use crate::{Value, serde::ParamsValueVisitor};
+
+/// A type parsed from a string in a well known format, see `formatted`.
+///
+/// Implemented for `uuid::Uuid` (feature `uuid`), `chrono::DateTime<Utc>` and
+/// `chrono::NaiveDate` (feature `chrono`), `time::OffsetDateTime` (feature `time`) and
+/// `Option`s of them. Custom leaf types like `Money` or `PhoneNumber` implement it too:
+///
+/// ```ignore
+/// struct Money {
+///     cents: i64,
+/// }
+///
+/// impl ParamFormat for Money {
+///     const EXPECTED: &'static str = "an amount like `12.50`";
+///
+///     fn parse_param(s: &str) -> Option<Self> {
+///         let (units, cents) = s.split_once('.').unwrap_or((s, "00"));
+///         match (units.parse::<i64>(), cents.len(), cents.parse::<i64>()) {
+///             (Ok(units), 2, Ok(cents)) => Some(Money { cents: units * 100 + cents }),
+///             _ => None,
+///         }
+///     }
+/// }
+///
+/// impl<'de> Deserialize<'de> for Money {
+///     fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+///         axum_params::formatted(deserializer)
+///     }
+/// }
+/// ```
+///
+/// The `Deserialize` impl makes every field of the type go through `parse_param`,
+/// `Option<Money>` and `Vec<Money>` included. Types that already implement `Deserialize`
+/// opt in per field with `#[serde(deserialize_with = "axum_params::formatted")]`.
+pub trait ParamFormat: Sized {
+    /// The expected format, shown in errors.
+    const EXPECTED: &'static str;
+
+    /// Parses a string, or a JSON number as written.
+    fn parse_param(s: &str) -> Option<Self>;
+
+    /// The value of `null`, only `Option`s have one.
+    fn null() -> Option<Self> {
+        None
+    }
+}
+
+impl<T: ParamFormat> ParamFormat for Option<T> {
+    const EXPECTED: &'static str = T::EXPECTED;
+
+    /// Empty strings, e.g. from `?since=`, are `None`.
+    fn parse_param(s: &str) -> Option<Self> {
+        if s.is_empty() {
+            Some(None)
+        } else {
+            T::parse_param(s).map(Some)
+        }
+    }
+
+    fn null() -> Option<Self> {
+        Some(None)
+    }
+}
+
+/// Deserializes a `ParamFormat` field from a query, path, form or JSON string, or a JSON
+/// number, with errors that show the value and the expected format:
+///
+/// ```ignore
+/// #[derive(Deserialize)]
+/// struct Search {
+///     #[serde(deserialize_with = "axum_params::formatted")]
+///     since: chrono::NaiveDate,
+///     #[serde(default, deserialize_with = "axum_params::formatted")]
+///     owner: Option<uuid::Uuid>,
+/// }
+/// ```
+///
+/// Without it these types deserialize from strings too, but their errors don't mention what
+/// was expected.
+pub fn formatted<'de, D, T>(deserializer: D) -> Result<T, D::Error>
+where
+    D: Deserializer<'de>,
+    T: ParamFormat,
+{
+    let s = match deserializer.deserialize_any(ParamsValueVisitor)? {
+        Value::String(s) | Value::XStr(s) => s.to_string(),
+        Value::Number(n) => n.to_string(),
+        Value::Null => {
+            return T::null().ok_or_else(|| {
+                de::Error::custom(format!("invalid null, expected {}", T::EXPECTED))
+            });
+        }
+        other => {
+            return Err(de::Error::custom(format!(
+                "invalid {}, expected {}",
+                other.type_name(),
+                T::EXPECTED
+            )));
+        }
+    };
+    T::parse_param(&s)
+        .ok_or_else(|| de::Error::custom(format!("invalid value `{s}`, expected {}", T::EXPECTED)))
+}
+
+#[cfg(feature = "uuid")]
+impl ParamFormat for uuid::Uuid {
+    const EXPECTED: &'static str = "a UUID like `67e55044-10b1-426f-9247-bb680e5fe0c8`";
+
+    fn parse_param(s: &str) -> Option<Self> {
+        uuid::Uuid::parse_str(s).ok()
+    }
+}
+
+#[cfg(feature = "chrono")]
+impl ParamFormat for chrono::DateTime<chrono::Utc> {
+    const EXPECTED: &'static str = "an RFC 3339 date-time like `2024-01-31T09:30:00Z`";
+
+    fn parse_param(s: &str) -> Option<Self> {
+        chrono::DateTime::parse_from_rfc3339(s)
+            .ok()
+            .map(|dt| dt.with_timezone(&chrono::Utc))
+    }
+}
+
+#[cfg(feature = "chrono")]
+impl ParamFormat for chrono::NaiveDate {
+    const EXPECTED: &'static str = "a date like `2024-01-31`";
+
+    fn parse_param(s: &str) -> Option<Self> {
+        chrono::NaiveDate::parse_from_str(s, "%Y-%m-%d").ok()
+    }
+}
+
+#[cfg(feature = "time")]
+impl ParamFormat for time::OffsetDateTime {
+    const EXPECTED: &'static str = "an RFC 3339 date-time like `2024-01-31T09:30:00Z`";
+
+    fn parse_param(s: &str) -> Option<Self> {
+        time::OffsetDateTime::parse(s, &time::format_description::well_known::Rfc3339).ok()
+    }
+}
+
+#[cfg(all(test, feature = "uuid", feature = "chrono", feature = "time"))]
+mod tests {
+    use serde::Deserialize;
+
+    use crate::Value;
+
+    #[allow(dead_code)]
+    #[derive(Debug, Deserialize)]
+    struct Event {
+        #[serde(deserialize_with = "crate::formatted")]
+        id: uuid::Uuid,
+        #[serde(deserialize_with = "crate::formatted")]
+        at: chrono::DateTime<chrono::Utc>,
+        #[serde(default, deserialize_with = "crate::formatted")]
+        day: Option<chrono::NaiveDate>,
+        #[serde(default, deserialize_with = "crate::formatted")]
+        until: Option<time::OffsetDateTime>,
+    }
+
+    fn event(entries: &[(&str, Value)]) -> Result<Event, String> {
+        let map = entries
+            .iter()
+            .map(|(k, v)| (k.to_string(), v.clone()))
+            .collect();
+        Event::deserialize(Value::Object(map)).map_err(|e| e.to_string())
+    }
+
+    #[test]
+    fn test_formatted() {
+        let id = "67e55044-10b1-426f-9247-bb680e5fe0c8";
+        let parsed = event(&[
+            ("id", Value::xstr(id)),
+            ("at", Value::string("2024-01-31T10:30:00+01:00")),
+            ("day", Value::xstr("")),
+            ("until", Value::xstr("2024-02-01T00:00:00Z")),
+        ])
+        .unwrap();
+        assert_eq!(parsed.id.to_string(), id);
+        assert_eq!(parsed.at.to_rfc3339(), "2024-01-31T09:30:00+00:00");
+        assert_eq!(parsed.day, None);
+        assert_eq!(parsed.until.unwrap().unix_timestamp(), 1706745600);
+
+        let e = event(&[
+            ("id", Value::xstr(id)),
+            ("at", Value::xstr("2024-01-31T09:30:00Z")),
+            ("day", Value::xstr("31/01/2024")),
+        ])
+        .unwrap_err();
+        assert_eq!(
+            e,
+            "invalid value `31/01/2024`, expected a date like `2024-01-31`"
+        );
+    }
+}
@@ -1,30 +1,81 @@
-use std::collections::HashMap;
+use std::{collections::HashMap, fmt};
 
 use actson::{
     JsonEvent, JsonParser,
     feeder::{JsonFeeder, SliceJsonFeeder},
 };
-use log::debug;
+use tracing::trace;
 
-use crate::{Error, Number, Value};
+use crate::{Error, N, Number, SecurityPolicy, Value};
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub enum JsonError {
     SyntaxError(String),
     NoMoreInput,
+    /// A number out of the range of `f64`, e.g. `1e400`, with its text.
+    InvalidNumber(String),
     Other(String),
 }
 
+impl fmt::Display for JsonError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            JsonError::SyntaxError(e) => write!(f, "Syntax error: {}", e),
+            JsonError::NoMoreInput => f.write_str("Incomplete JSON input"),
+            JsonError::InvalidNumber(lexeme) => write!(f, "JSON number `{lexeme}` is out of range"),
+            JsonError::Other(msg) => f.write_str(msg),
+        }
+    }
+}
+
+impl std::error::Error for JsonError {}
+
 impl From<JsonError> for Error {
     fn from(err: JsonError) -> Self {
+        Error::DecodeError(err.to_string())
+    }
+}
+
+/// Where a JSON request body or multipart part is malformed, see `Error::InvalidJson`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct JsonLocation {
+    /// The byte offset from the start of the document.
+    pub offset: usize,
+    /// The line, starting at 1.
+    pub line: usize,
+    /// The byte column in the line, starting at 1.
+    pub column: usize,
+    /// The text of the line around the error, up to 20 bytes on each side. Empty when the
+    /// `SecurityPolicy` has `sensitive_keys`, the body could be quoted.
+    pub excerpt: String,
+}
+
+/// The bytes of context on each side of a `JsonLocation`.
+#[cfg(server)]
+const EXCERPT_LEN: usize = 20;
+
+#[cfg(server)]
+impl JsonLocation {
+    /// `Error::InvalidJson` for a syntax error at this location, limits of the
+    /// `SecurityPolicy` stay `Error::DecodeError`s.
+    fn error(self, err: JsonError) -> Error {
         match err {
-            JsonError::SyntaxError(e) => Error::DecodeError(format!("Syntax error: {}", e)),
-            JsonError::NoMoreInput => Error::DecodeError("Incomplete JSON input".to_string()),
-            JsonError::Other(msg) => Error::DecodeError(msg),
+            JsonError::Other(_) => err.into(),
+            err => Error::InvalidJson(format!("{err} {self}"), self, err),
         }
     }
 }
 
+impl fmt::Display for JsonLocation {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "at line {}, column {}", self.line, self.column)?;
+        if !self.excerpt.is_empty() {
+            write!(f, " near `{}`", self.excerpt)?;
+        }
+        Ok(())
+    }
+}
+
 impl From<&serde_json::Value> for Value {
     fn from(v: &serde_json::Value) -> Self {
         match v {
@@ -38,7 +89,7 @@ impl From<&serde_json::Value> for Value {
                     Value::Number(n.into())
                 }
             }
-            serde_json::Value::String(v) => Value::String(v.clone()),
+            serde_json::Value::String(v) => Value::string(v.as_str()),
             serde_json::Value::Array(v) => {
                 Value::Array(v.iter().map(Value::from).collect::<Vec<Value>>())
             }
@@ -51,6 +102,34 @@ impl From<&serde_json::Value> for Value {
     }
 }
 
+/// Converts parameters to JSON, e.g. to persist the fields a params struct collects with
+/// `#[serde(flatten)] extra: HashMap<String, serde_json::Value>`. Strings from every source
+/// stay strings, 128-bit integers beyond `u64` / `i64` become floats and uploaded files
+/// objects with their `name`, `content_type` and `temp_file_path`.
+impl From<Value> for serde_json::Value {
+    fn from(v: Value) -> Self {
+        match v {
+            Value::Null => serde_json::Value::Null,
+            Value::Bool(b) => serde_json::Value::Bool(b),
+            Value::Number(Number(n)) => match n {
+                N::PosInt(i) => i.into(),
+                N::NegInt(i) => i.into(),
+                N::PosInt128(i) => (i as f64).into(),
+                N::NegInt128(i) => (i as f64).into(),
+                N::Float(f) => f.into(),
+            },
+            Value::String(s) | Value::XStr(s) => serde_json::Value::String(s.into_string()),
+            Value::Array(vec) => vec.into_iter().map(serde_json::Value::from).collect(),
+            Value::Object(map) => map
+                .into_iter()
+                .map(|(k, v)| (k, serde_json::Value::from(v)))
+                .collect::<serde_json::Map<_, _>>()
+                .into(),
+            Value::UploadFile(file) => serde_json::to_value(file).unwrap_or_default(),
+        }
+    }
+}
+
 fn unescape_json_string(s: &str) -> Result<String, JsonError> {
     let mut result = String::with_capacity(s.len());
     let mut chars = s.chars().peekable();
@@ -105,16 +184,14 @@ fn json_event_to_value<T: JsonFeeder>(
     event: &JsonEvent,
     parser: &JsonParser<T>,
 ) -> Result<Value, JsonError> {
+    let current_str = || {
+        parser
+            .current_str()
+            .map_err(|e| JsonError::SyntaxError(format!("parse error:{}", e)))
+    };
     match event {
-        JsonEvent::ValueString => Ok(Value::String(unescape_json_string(
-            parser.current_str().unwrap(),
-        )?)),
-        JsonEvent::ValueInt => Ok(Value::Number(Number::from(
-            parser.current_str().unwrap().parse::<i64>().unwrap(),
-        ))),
-        JsonEvent::ValueFloat => Ok(Value::Number(Number::from(
-            parser.current_str().unwrap().parse::<f64>().unwrap(),
-        ))),
+        JsonEvent::ValueString => Ok(Value::string(unescape_json_string(current_str()?)?)),
+        JsonEvent::ValueInt | JsonEvent::ValueFloat => parse_number(current_str()?),
         JsonEvent::ValueTrue => Ok(Value::Bool(true)),
         JsonEvent::ValueFalse => Ok(Value::Bool(false)),
         JsonEvent::ValueNull => Ok(Value::Null),
@@ -125,18 +202,228 @@ fn json_event_to_value<T: JsonFeeder>(
     }
 }
 
-pub fn parse_json(feeder: SliceJsonFeeder) -> Result<Value, JsonError> {
-    let mut parser = JsonParser::new(feeder);
+/// The number of a JSON number lexeme, integers are exact up to 128 bits.
+///
+/// Larger integers keep their text, like query string values: they deserialize into strings,
+/// e.g. of big decimal types, or into floats. Floats are rounded to `f64`.
+fn parse_number(lexeme: &str) -> Result<Value, JsonError> {
+    if let Ok(i) = lexeme.parse::<i128>() {
+        return Ok(Value::Number(Number::from(i)));
+    }
+    if let Ok(i) = lexeme.parse::<u128>() {
+        return Ok(Value::Number(Number::from(i)));
+    }
+    if !lexeme.contains(['.', 'e', 'E']) {
+        return Ok(Value::xstr(lexeme));
+    }
+    match lexeme.parse::<f64>() {
+        Ok(f) if f.is_finite() => Ok(Value::Number(Number::from(f))),
+        _ => Err(JsonError::InvalidNumber(lexeme.to_string())),
+    }
+}
 
-    let mut stack = vec![];
-    let mut result = None;
-    let mut current_key = None;
+pub fn parse_json(feeder: SliceJsonFeeder) -> Result<Value, JsonError> {
+    let unlimited = SecurityPolicy {
+        max_depth: usize::MAX,
+        ..SecurityPolicy::legacy_compat()
+    };
+    parse_json_with(feeder, &unlimited)
+}
 
+/// Same as `parse_json`, rejecting documents nested deeper than `max_depth`, with more than
+/// `max_json_values` values or with object keys listed in `denied_keys` of `policy`.
+pub(crate) fn parse_json_with(
+    feeder: SliceJsonFeeder,
+    policy: &SecurityPolicy,
+) -> Result<Value, JsonError> {
+    let mut parser = JsonParser::new(feeder);
+    let mut builder = ValueBuilder::new(policy);
     while let Some(event) = parser
         .next_event()
         .map_err(|e| JsonError::SyntaxError(format!("parse error:{}", e)))?
     {
-        debug!("JSON event: {:?}", event);
+        builder.event(event, &parser)?;
+    }
+    builder.finish()
+}
+
+/// Parses a JSON document pushed in chunks as they arrive, e.g. from a request body stream.
+#[cfg(server)]
+pub(crate) struct JsonPushParser<'a> {
+    parser: JsonParser<actson::feeder::PushJsonFeeder>,
+    builder: ValueBuilder<'a>,
+    /// The bytes of the chunks pushed before, for the `JsonLocation` of errors.
+    offset: usize,
+    line: usize,
+    line_start: usize,
+    /// The last `EXCERPT_LEN` bytes pushed before.
+    tail: Vec<u8>,
+    /// The offset of the document in a larger input, e.g. a record of a JSON Lines body.
+    start: usize,
+    /// Whether `JsonLocation::excerpt` quotes the body.
+    excerpt: bool,
+}
+
+#[cfg(server)]
+impl<'a> JsonPushParser<'a> {
+    /// Applies the JSON limits of `policy`, see `parse_json_with`.
+    pub(crate) fn new(policy: &'a SecurityPolicy) -> Self {
+        Self {
+            parser: JsonParser::new(actson::feeder::PushJsonFeeder::new()),
+            builder: ValueBuilder::new(policy),
+            offset: 0,
+            line: 1,
+            line_start: 0,
+            tail: vec![],
+            start: 0,
+            excerpt: policy.sensitive_keys.is_empty(),
+        }
+    }
+
+    /// Reports error locations for a document starting on `line` at `offset` of the input.
+    pub(crate) fn starting_at(mut self, offset: usize, line: usize) -> Self {
+        self.start = offset;
+        self.line = line;
+        self
+    }
+
+    /// Parses as much of `chunk` as possible, the rest of the document follows in later
+    /// chunks. Syntax errors are `Error::InvalidJson`s with their location.
+    pub(crate) fn push(&mut self, chunk: &[u8]) -> Result<(), Error> {
+        let mut bytes = chunk;
+        while !bytes.is_empty() {
+            let pushed = self.parser.feeder.push_bytes(bytes);
+            bytes = &bytes[pushed..];
+            if let Err(err) = self.drain() {
+                return Err(self.location(chunk).error(err));
+            }
+        }
+        let (line, line_start) = self.line_at(chunk, chunk.len());
+        (self.line, self.line_start) = (line, line_start);
+        self.offset += chunk.len();
+        self.tail.extend_from_slice(chunk);
+        self.tail
+            .drain(..self.tail.len().saturating_sub(EXCERPT_LEN));
+        Ok(())
+    }
+
+    /// Ends the input and returns the document.
+    pub(crate) fn finish(mut self) -> Result<Value, Error> {
+        self.parser.feeder.done();
+        if let Err(err) = self.drain() {
+            return Err(self.location(&[]).error(err));
+        }
+        let location = self.location(&[]);
+        self.builder.finish().map_err(|err| location.error(err))
+    }
+
+    /// The line and the offset of its start at `len` bytes into `chunk`.
+    fn line_at(&self, chunk: &[u8], len: usize) -> (usize, usize) {
+        let before = &chunk[..len];
+        let lines = before.iter().filter(|&&b| b == b'\n').count();
+        match before.iter().rposition(|&b| b == b'\n') {
+            Some(i) => (self.line + lines, self.offset + i + 1),
+            None => (self.line, self.line_start),
+        }
+    }
+
+    /// The location of the last byte the parser read, in `chunk` or an earlier one.
+    fn location(&self, chunk: &[u8]) -> JsonLocation {
+        let offset = self.parser.parsed_bytes().saturating_sub(1);
+        let len = offset.saturating_sub(self.offset).min(chunk.len());
+        let (line, line_start) = self.line_at(chunk, len);
+        let context = [self.tail.as_slice(), chunk].concat();
+        let at = (self.tail.len() + offset)
+            .saturating_sub(self.offset)
+            .min(context.len());
+        let start = context[..at]
+            .iter()
+            .rposition(|&b| b == b'\n')
+            .map_or(0, |i| i + 1)
+            .max(at.saturating_sub(EXCERPT_LEN));
+        let end = context[at..]
+            .iter()
+            .position(|&b| b == b'\n')
+            .map_or(context.len(), |i| at + i)
+            .min(at + EXCERPT_LEN);
+        let excerpt = if self.excerpt {
+            String::from_utf8_lossy(&context[start..end])
+                .trim()
+                .to_string()
+        } else {
+            String::new()
+        };
+        JsonLocation {
+            offset: self.start + offset,
+            line,
+            column: offset.saturating_sub(line_start) + 1,
+            excerpt,
+        }
+    }
+
+    fn drain(&mut self) -> Result<(), JsonError> {
+        while let Some(event) = self
+            .parser
+            .next_event()
+            .map_err(|e| JsonError::SyntaxError(format!("parse error:{}", e)))?
+        {
+            if event == JsonEvent::NeedMoreInput {
+                break;
+            }
+            self.builder.event(event, &self.parser)?;
+        }
+        Ok(())
+    }
+}
+
+/// Builds a `Value` from the events of a `JsonParser`.
+struct ValueBuilder<'a> {
+    stack: Vec<(Option<String>, Value)>,
+    result: Option<Value>,
+    current_key: Option<String>,
+    /// Values started so far, objects and arrays included.
+    values: usize,
+    policy: &'a SecurityPolicy,
+}
+
+impl<'a> ValueBuilder<'a> {
+    fn new(policy: &'a SecurityPolicy) -> Self {
+        Self {
+            stack: vec![],
+            result: None,
+            current_key: None,
+            values: 0,
+            policy,
+        }
+    }
+
+    fn count_value(&mut self) -> Result<(), JsonError> {
+        self.values += 1;
+        if self.values > self.policy.max_json_values {
+            return Err(JsonError::Other(format!(
+                "JSON document has more than {} values",
+                self.policy.max_json_values
+            )));
+        }
+        Ok(())
+    }
+
+    fn event<T: JsonFeeder>(
+        &mut self,
+        event: JsonEvent,
+        parser: &JsonParser<T>,
+    ) -> Result<(), JsonError> {
+        trace!(?event, "JSON event");
+        if !matches!(
+            event,
+            JsonEvent::NeedMoreInput
+                | JsonEvent::EndObject
+                | JsonEvent::EndArray
+                | JsonEvent::FieldName
+        ) {
+            self.count_value()?;
+        }
+        let stack = &mut self.stack;
         match event {
             JsonEvent::NeedMoreInput => {}
 
@@ -146,7 +433,13 @@ pub fn parse_json(feeder: SliceJsonFeeder) -> Result<Value, JsonError> {
                 } else {
                     Value::Array(vec![])
                 };
-                stack.push((current_key.take(), v));
+                if stack.len() >= self.policy.max_depth {
+                    return Err(JsonError::Other(format!(
+                        "JSON nested deeper than {} levels",
+                        self.policy.max_depth
+                    )));
+                }
+                stack.push((self.current_key.take(), v));
             }
 
             JsonEvent::EndObject | JsonEvent::EndArray => {
@@ -168,7 +461,7 @@ pub fn parse_json(feeder: SliceJsonFeeder) -> Result<Value, JsonError> {
                         }
                     }
                 } else {
-                    result = Some(v.1);
+                    self.result = Some(v.1);
                 }
             }
 
@@ -176,7 +469,13 @@ pub fn parse_json(feeder: SliceJsonFeeder) -> Result<Value, JsonError> {
                 let str_result = parser
                     .current_str()
                     .map_err(|e| JsonError::SyntaxError(format!("parse error:{}", e)))?;
-                current_key = Some(str_result.to_string());
+                if self.policy.denied_keys.iter().any(|k| k == str_result) {
+                    return Err(JsonError::Other(format!(
+                        "JSON key `{}` is not allowed",
+                        str_result
+                    )));
+                }
+                self.current_key = Some(str_result.to_string());
             }
 
             JsonEvent::ValueString
@@ -185,14 +484,14 @@ pub fn parse_json(feeder: SliceJsonFeeder) -> Result<Value, JsonError> {
             | JsonEvent::ValueTrue
             | JsonEvent::ValueFalse
             | JsonEvent::ValueNull => {
-                let v = json_event_to_value(&event, &parser)?;
+                let v = json_event_to_value(&event, parser)?;
                 if let Some((_, top)) = stack.last_mut() {
                     match top {
                         Value::Array(a) => {
                             a.push(v);
                         }
                         Value::Object(o) => {
-                            if let Some(key) = current_key.take() {
+                            if let Some(key) = self.current_key.take() {
                                 o.insert(key, v);
                             } else {
                                 return Err(JsonError::SyntaxError(
@@ -207,23 +506,51 @@ pub fn parse_json(feeder: SliceJsonFeeder) -> Result<Value, JsonError> {
                             )));
                         }
                     }
-                } else if result.is_none() {
-                    result = Some(v);
+                } else if self.result.is_none() {
+                    self.result = Some(v);
                 } else {
                     return Err(JsonError::SyntaxError("Unexpected JSON value".to_string()));
                 }
             }
         }
+        Ok(())
     }
 
-    result.ok_or(JsonError::NoMoreInput)
+    fn finish(self) -> Result<Value, JsonError> {
+        self.result.ok_or(JsonError::NoMoreInput)
+    }
 }
 
 #[cfg(test)]
 mod tests {
     use actson::feeder::SliceJsonFeeder;
 
-    use crate::{N, Number, Value, parse_json};
+    use super::{JsonError, parse_json_with};
+    use crate::{N, Number, SecurityPolicy, Value, parse_json};
+
+    #[test]
+    fn test_parse_json_large_numbers() {
+        let json = concat!(
+            "[18446744073709551615, 18446744073709551616, -9223372036854775809, ",
+            "1000000000000000000000000000000000000000]"
+        );
+        let Value::Array(arr) = parse_json(SliceJsonFeeder::new(json.as_bytes())).unwrap() else {
+            panic!("Expected array");
+        };
+        assert!(matches!(arr[0], Value::Number(Number(N::PosInt(u64::MAX)))));
+        assert!(matches!(arr[1], Value::Number(Number(N::PosInt128(v))) if v == 1 << 64));
+        assert!(
+            matches!(arr[2], Value::Number(Number(N::NegInt128(v))) if v == i64::MIN as i128 - 1)
+        );
+        assert_eq!(
+            arr[3],
+            Value::xstr("1000000000000000000000000000000000000000")
+        );
+
+        let json = r#"{"n": 1e400}"#;
+        let error = parse_json(SliceJsonFeeder::new(json.as_bytes())).unwrap_err();
+        assert!(matches!(error, super::JsonError::InvalidNumber(ref n) if n == "1e400"));
+    }
 
     #[test]
     fn test_parse_json_numbers() {
@@ -383,4 +710,112 @@ mod tests {
         let invalid_json = r#"{"invalid": "\z"}"#;
         assert!(parse_json(SliceJsonFeeder::new(invalid_json.as_bytes())).is_err());
     }
+
+    #[test]
+    fn test_parse_json_limits() {
+        let parse = |json: &str, policy: SecurityPolicy| {
+            parse_json_with(SliceJsonFeeder::new(json.as_bytes()), &policy)
+        };
+        let json = r#"{"a": [[{"b": 1}]]}"#;
+        assert!(parse(json, SecurityPolicy::lenient().max_depth(4)).is_ok());
+        assert!(parse(json, SecurityPolicy::lenient().max_depth(3)).is_err());
+
+        let policy = SecurityPolicy::lenient().denied_keys(["__proto__"]);
+        let json = r#"{"a": {"__proto__": {"admin": true}}}"#;
+        assert!(parse(json, policy).is_err());
+
+        let json = r#"{"a": [1, 2], "b": {}}"#;
+        assert!(parse(json, SecurityPolicy::lenient().max_json_values(5)).is_ok());
+        let error = parse(json, SecurityPolicy::lenient().max_json_values(4)).unwrap_err();
+        assert!(
+            matches!(error, JsonError::Other(ref m) if m == "JSON document has more than 4 values")
+        );
+    }
+
+    #[cfg(server)]
+    #[test]
+    fn test_push_parser() {
+        use super::JsonPushParser;
+        use crate::{Error, JsonLocation};
+
+        let policy = SecurityPolicy::lenient();
+        let json = r#"{"name": "caf\u00e9 \"au lait\"", "tags": ["a", "b"], "n": -1.5e2}"#;
+        for chunk_size in [1, 3, 16, json.len()] {
+            let mut parser = JsonPushParser::new(&policy);
+            for chunk in json.as_bytes().chunks(chunk_size) {
+                parser.push(chunk).unwrap();
+            }
+            let Value::Object(map) = parser.finish().unwrap() else {
+                panic!("Expected object");
+            };
+            assert!(matches!(&map["name"], Value::String(s) if s == "café \"au lait\""));
+            assert!(matches!(&map["tags"], Value::Array(a) if a.len() == 2));
+            assert!(matches!(map["n"], Value::Number(Number(N::Float(v))) if v == -150.0));
+        }
+
+        // The excerpt shows what arrived of the line after the error
+        let json = b"{\"name\": \"alice\",\n \"tags\": [\"a\" \"b\"]}";
+        for (chunk_size, excerpt) in [
+            (1, r#""tags": ["a" ""#),
+            (json.len(), r#""tags": ["a" "b"]}"#),
+        ] {
+            let mut parser = JsonPushParser::new(&policy);
+            let error = json
+                .chunks(chunk_size)
+                .try_for_each(|chunk| parser.push(chunk))
+                .unwrap_err();
+            let Error::InvalidJson(_, location, _) = error else {
+                panic!("Expected invalid JSON");
+            };
+            assert_eq!(
+                location,
+                JsonLocation {
+                    offset: 32,
+                    line: 2,
+                    column: 15,
+                    excerpt: excerpt.to_string(),
+                }
+            );
+        }
+
+        let mut parser = JsonPushParser::new(&policy);
+        parser.push(br#"{"a": [1, 2"#).unwrap();
+        assert_eq!(
+            parser.finish().unwrap_err().to_string(),
+            "Syntax error: parse error:nothing more to parse at line 1, column 11 near `{\"a\": [1, 2`"
+        );
+
+        // Sensitive values could be quoted, the excerpt is left out
+        let policy = SecurityPolicy::lenient().sensitive_keys(["password"]);
+        let mut parser = JsonPushParser::new(&policy);
+        let error = parser
+            .push(br#"{"password": "hunter2" "name": "alice"}"#)
+            .unwrap_err();
+        let Error::InvalidJson(message, location, _) = &error else {
+            panic!("Expected invalid JSON");
+        };
+        assert_eq!(location.excerpt, "");
+        assert!(!message.contains("hunter2"), "{message}");
+        assert!(message.ends_with("at line 1, column 24"), "{message}");
+    }
+
+    #[test]
+    fn test_into_serde_json() {
+        let json =
+            r#"{"name": "alice", "age": 30, "tags": ["a", null], "big": -9223372036854775809}"#;
+        let mut value = parse_json(SliceJsonFeeder::new(json.as_bytes())).unwrap();
+        if let Value::Object(map) = &mut value {
+            map.insert("page".to_string(), Value::xstr("2"));
+        }
+        assert_eq!(
+            serde_json::Value::from(value),
+            serde_json::json!({
+                "name": "alice",
+                "age": 30,
+                "tags": ["a", null],
+                "big": -9223372036854775809.0,
+                "page": "2",
+            })
+        );
+    }
 }
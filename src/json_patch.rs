@@ -0,0 +1,507 @@
+use std::fmt;
+
+use serde::{Deserialize, Deserializer, de};
+
+#[cfg(server)]
+use axum::{
+    extract::{FromRequest, Request},
+    http,
+};
+
+use crate::{Error, Value};
+#[cfg(server)]
+use crate::{
+    ParamsConfig, Rejection,
+    encoding::decode_body,
+    json::JsonPushParser,
+    params::{buffer_body, check_content_length},
+    rejection::Rejecter,
+};
+
+/// The content type of JSON Patch (RFC 6902) bodies.
+pub const JSON_PATCH_CONTENT_TYPE: &str = "application/json-patch+json";
+
+/// The operations of an `application/json-patch+json` body, other content types are rejected
+/// with 400 Bad Request. The body is parsed with the JSON limits of `ParamsConfig::security_policy`
+/// and every `path` and `from` is checked to be a valid JSON Pointer.
+///
+/// It reads the body, take the resource identifier with `QueryPathParams`:
+///
+/// ```ignore
+/// async fn patch_user(
+///     QueryPathParams(user, _): QueryPathParams<UserId>,
+///     patch: JsonPatch,
+/// ) -> Result<Json<Value>, Error> {
+///     let mut document = load_user(user.id).await;
+///     patch.apply(&mut document)?;
+///     Ok(Json(document))
+/// }
+/// ```
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct JsonPatch(pub Vec<PatchOperation>);
+
+/// An operation of a `JsonPatch`.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(tag = "op", rename_all = "lowercase")]
+pub enum PatchOperation {
+    Add {
+        path: JsonPointer,
+        value: Value,
+    },
+    Remove {
+        path: JsonPointer,
+    },
+    Replace {
+        path: JsonPointer,
+        value: Value,
+    },
+    Move {
+        from: JsonPointer,
+        path: JsonPointer,
+    },
+    Copy {
+        from: JsonPointer,
+        path: JsonPointer,
+    },
+    Test {
+        path: JsonPointer,
+        value: Value,
+    },
+}
+
+/// A JSON Pointer (RFC 6901) like `/user/emails/0`, `""` points to the whole document.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Hash)]
+pub struct JsonPointer(String);
+
+impl JsonPointer {
+    /// Checks that `pointer` is empty or starts with `/`, and escapes `~` only as `~0` or
+    /// `~1`.
+    pub fn parse(pointer: impl Into<String>) -> Result<Self, Error> {
+        let pointer = pointer.into();
+        let mut escapes = pointer.split('~').skip(1);
+        if !(pointer.is_empty() || pointer.starts_with('/'))
+            || escapes.any(|rest| !rest.starts_with(['0', '1']))
+        {
+            return Err(Error::DecodeError(format!(
+                "Invalid JSON Pointer `{pointer}`"
+            )));
+        }
+        Ok(JsonPointer(pointer))
+    }
+
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+
+    /// The unescaped reference tokens, e.g. `["a/b", "0"]` for `/a~1b/0`.
+    pub fn tokens(&self) -> Vec<String> {
+        self.0
+            .split('/')
+            .skip(1)
+            .map(|token| token.replace("~1", "/").replace("~0", "~"))
+            .collect()
+    }
+}
+
+impl fmt::Display for JsonPointer {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+impl<'de> Deserialize<'de> for JsonPointer {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        let pointer = String::deserialize(deserializer)?;
+        JsonPointer::parse(pointer).map_err(|e| de::Error::custom(e.message()))
+    }
+}
+
+impl JsonPatch {
+    /// The operations of a parsed body, e.g. one read by other means than the extractor.
+    pub fn from_value(value: Value) -> Result<Self, Error> {
+        Vec::<PatchOperation>::deserialize(value)
+            .map(JsonPatch)
+            .map_err(|e| Error::DecodeError(format!("Failed to deserialize JSON Patch: {e}")))
+    }
+
+    /// Applies the operations in order. It is atomic: when one fails, e.g. a `test` or a
+    /// `remove` of a missing key, `document` is left unchanged and the error names it.
+    pub fn apply(&self, document: &mut Value) -> Result<(), Error> {
+        let mut patched = document.clone();
+        for (index, operation) in self.0.iter().enumerate() {
+            operation.apply(&mut patched).map_err(|e| {
+                Error::DecodeError(format!("JSON Patch operation {index} failed: {e}"))
+            })?;
+        }
+        *document = patched;
+        Ok(())
+    }
+}
+
+impl PatchOperation {
+    fn apply(&self, document: &mut Value) -> Result<(), String> {
+        match self {
+            PatchOperation::Add { path, value } => add(document, path, value.clone()),
+            PatchOperation::Remove { path } => remove(document, path).map(|_| ()),
+            PatchOperation::Replace { path, value } => {
+                *lookup(document, path)? = value.clone();
+                Ok(())
+            }
+            PatchOperation::Move { from, path } => {
+                if path.as_str().starts_with(&format!("{from}/")) {
+                    return Err(format!("cannot move `{from}` into its child `{path}`"));
+                }
+                let value = remove(document, from)?;
+                add(document, path, value)
+            }
+            PatchOperation::Copy { from, path } => {
+                let value = lookup(document, from)?.clone();
+                add(document, path, value)
+            }
+            PatchOperation::Test { path, value } => match lookup(document, path)? {
+                found if found == value => Ok(()),
+                _ => Err(format!("`{path}` is not the tested value")),
+            },
+        }
+    }
+}
+
+fn lookup<'a>(document: &'a mut Value, pointer: &JsonPointer) -> Result<&'a mut Value, String> {
+    let mut value = document;
+    for token in pointer.tokens() {
+        value = match value {
+            Value::Object(map) => map.get_mut(&token),
+            Value::Array(items) => array_index(&token).and_then(|i| items.get_mut(i)),
+            _ => None,
+        }
+        .ok_or_else(|| format!("`{pointer}` doesn't exist"))?;
+    }
+    Ok(value)
+}
+
+/// The parent of the value `pointer` points to and its last token, `None` for the document.
+fn parent<'a>(
+    document: &'a mut Value,
+    pointer: &JsonPointer,
+) -> Result<Option<(&'a mut Value, String)>, String> {
+    let Some(slash) = pointer.as_str().rfind('/') else {
+        return Ok(None);
+    };
+    let (parent, last) = pointer.as_str().split_at(slash);
+    let last = JsonPointer(last.to_string()).tokens().remove(0);
+    let parent = lookup(document, &JsonPointer(parent.to_string()))?;
+    Ok(Some((parent, last)))
+}
+
+fn add(document: &mut Value, pointer: &JsonPointer, value: Value) -> Result<(), String> {
+    let Some((parent, token)) = parent(document, pointer)? else {
+        *document = value;
+        return Ok(());
+    };
+    match parent {
+        Value::Object(map) => {
+            map.insert(token, value);
+        }
+        Value::Array(items) if token == "-" => items.push(value),
+        Value::Array(items) => match array_index(&token).filter(|&i| i <= items.len()) {
+            Some(index) => items.insert(index, value),
+            None => return Err(format!("`{pointer}` is out of bounds")),
+        },
+        _ => {
+            return Err(format!(
+                "the parent of `{pointer}` is not an object or array"
+            ));
+        }
+    }
+    Ok(())
+}
+
+fn remove(document: &mut Value, pointer: &JsonPointer) -> Result<Value, String> {
+    let removed = match parent(document, pointer)? {
+        None => return Ok(std::mem::replace(document, Value::Null)),
+        Some((Value::Object(map), token)) => map.remove(&token),
+        Some((Value::Array(items), token)) => array_index(&token)
+            .filter(|&i| i < items.len())
+            .map(|i| items.remove(i)),
+        Some(_) => None,
+    };
+    removed.ok_or_else(|| format!("`{pointer}` doesn't exist"))
+}
+
+/// An array index without leading zeros.
+fn array_index(token: &str) -> Option<usize> {
+    let digits = !token.is_empty() && token.bytes().all(|b| b.is_ascii_digit());
+    (digits && (token == "0" || !token.starts_with('0')))
+        .then(|| token.parse().ok())
+        .flatten()
+}
+
+#[cfg(server)]
+#[cfg_attr(axum07, axum::async_trait)]
+impl<S> FromRequest<S> for JsonPatch
+where
+    S: Send + Sync,
+{
+    type Rejection = Rejection;
+
+    async fn from_request(req: Request, _state: &S) -> Result<Self, Self::Rejection> {
+        let config = ParamsConfig::of(&req);
+        let rejecter = Rejecter::of(&req);
+        read_json_patch(req, &config)
+            .await
+            .map_err(|e| rejecter.reject(e))
+    }
+}
+
+#[cfg(server)]
+async fn read_json_patch(req: Request, config: &ParamsConfig) -> Result<JsonPatch, Error> {
+    let (parts, body) = req.into_parts();
+    let content_type = parts
+        .headers
+        .get(http::header::CONTENT_TYPE)
+        .and_then(|ct| ct.to_str().ok())
+        .unwrap_or_default();
+    if !is_json_patch(content_type) {
+        return Err(Error::DecodeError(format!(
+            "Expected a `{JSON_PATCH_CONTENT_TYPE}` body, got `{content_type}`"
+        )));
+    }
+    let policy = &config.security;
+    if !policy.allows_content_type(content_type) {
+        return Err(Error::DecodeError(format!(
+            "Unsupported content type: {content_type}"
+        )));
+    }
+    check_content_length(&parts.headers, content_type, config)?;
+    let _permit = match &config.concurrency {
+        Some(limit) => Some(limit.acquire().await?),
+        None => None,
+    };
+    let body = decode_body(&parts.headers, body, policy).await?;
+    let bytes = buffer_body(body, policy.max_body_size, "JSON Patch").await?;
+    let mut json = JsonPushParser::new(policy);
+    json.push(&bytes)?;
+    JsonPatch::from_value(json.finish()?)
+}
+
+#[cfg(server)]
+pub(crate) fn is_json_patch(content_type: &str) -> bool {
+    let essence = content_type.split(';').next().unwrap_or_default().trim();
+    essence.eq_ignore_ascii_case(JSON_PATCH_CONTENT_TYPE)
+}
+
+#[cfg(all(test, server))]
+mod tests {
+    use axum::body::Body;
+    use serde_json::json;
+
+    use super::*;
+
+    fn convert(json: serde_json::Value) -> Value {
+        Value::from(&json)
+    }
+
+    fn patch(operations: serde_json::Value) -> JsonPatch {
+        JsonPatch::from_value(convert(operations)).unwrap()
+    }
+
+    #[test]
+    fn test_json_pointer() {
+        let pointer = JsonPointer::parse("/a~1b/m~0n/0").unwrap();
+        assert_eq!(pointer.tokens(), vec!["a/b", "m~n", "0"]);
+        assert!(JsonPointer::parse("").unwrap().tokens().is_empty());
+        assert_eq!(JsonPointer::parse("/").unwrap().tokens(), vec![""]);
+        for invalid in ["a", "/a~", "/a~2"] {
+            assert!(JsonPointer::parse(invalid).is_err(), "{invalid}");
+        }
+
+        let error =
+            JsonPatch::from_value(convert(json!([{"op": "remove", "path": "a/b"}]))).unwrap_err();
+        assert!(
+            error.message().contains("Invalid JSON Pointer `a/b`"),
+            "{error:?}"
+        );
+        let error =
+            JsonPatch::from_value(convert(json!([{"op": "add", "path": "/a"}]))).unwrap_err();
+        assert!(
+            error.message().contains("missing field `value`"),
+            "{error:?}"
+        );
+        let error =
+            JsonPatch::from_value(convert(json!([{"op": "merge", "path": "/a"}]))).unwrap_err();
+        assert!(
+            error.message().contains("unknown variant `merge`"),
+            "{error:?}"
+        );
+    }
+
+    #[test]
+    fn test_apply() {
+        // The examples of RFC 6902, appendix A
+        let cases = [
+            (
+                json!({"foo": "bar"}),
+                json!([{"op": "add", "path": "/baz", "value": "qux"}]),
+                json!({"baz": "qux", "foo": "bar"}),
+            ),
+            (
+                json!({"foo": ["bar", "baz"]}),
+                json!([{"op": "add", "path": "/foo/1", "value": "qux"}]),
+                json!({"foo": ["bar", "qux", "baz"]}),
+            ),
+            (
+                json!({"baz": "qux", "foo": "bar"}),
+                json!([{"op": "remove", "path": "/baz"}]),
+                json!({"foo": "bar"}),
+            ),
+            (
+                json!({"foo": ["bar", "qux", "baz"]}),
+                json!([{"op": "remove", "path": "/foo/1"}]),
+                json!({"foo": ["bar", "baz"]}),
+            ),
+            (
+                json!({"baz": "qux", "foo": "bar"}),
+                json!([{"op": "replace", "path": "/baz", "value": "boo"}]),
+                json!({"baz": "boo", "foo": "bar"}),
+            ),
+            (
+                json!({"foo": {"bar": "baz", "waldo": "fred"}, "qux": {"corge": "grault"}}),
+                json!([{"op": "move", "from": "/foo/waldo", "path": "/qux/thud"}]),
+                json!({"foo": {"bar": "baz"}, "qux": {"corge": "grault", "thud": "fred"}}),
+            ),
+            (
+                json!({"foo": ["all", "grass", "cows", "eat"]}),
+                json!([{"op": "move", "from": "/foo/1", "path": "/foo/3"}]),
+                json!({"foo": ["all", "cows", "eat", "grass"]}),
+            ),
+            (
+                json!({"foo": ["bar"]}),
+                json!([{"op": "add", "path": "/foo/-", "value": ["abc", "def"]}]),
+                json!({"foo": ["bar", ["abc", "def"]]}),
+            ),
+            (
+                json!({"foo": "bar"}),
+                json!([{"op": "copy", "from": "/foo", "path": "/baz"}]),
+                json!({"foo": "bar", "baz": "bar"}),
+            ),
+            (
+                json!({"baz": "qux", "foo": ["a", 2, "c"]}),
+                json!([
+                    {"op": "test", "path": "/baz", "value": "qux"},
+                    {"op": "test", "path": "/foo/1", "value": 2}
+                ]),
+                json!({"baz": "qux", "foo": ["a", 2, "c"]}),
+            ),
+            (
+                json!({"/": 9, "~1": 10}),
+                json!([{"op": "test", "path": "/~01", "value": 10}]),
+                json!({"/": 9, "~1": 10}),
+            ),
+            (
+                json!({"a": {"~1": 10}}),
+                json!([{"op": "move", "from": "/a/~01", "path": "/a/~1b"}]),
+                json!({"a": {"/b": 10}}),
+            ),
+            (
+                json!({"foo": "bar"}),
+                json!([{"op": "replace", "path": "", "value": [1]}]),
+                json!([1]),
+            ),
+        ];
+        for (document, operations, expected) in cases {
+            let mut document = convert(document);
+            patch(operations).apply(&mut document).unwrap();
+            assert_eq!(document, convert(expected));
+        }
+
+        let failing = [
+            (
+                json!({"baz": "qux"}),
+                json!([{"op": "test", "path": "/baz", "value": "bar"}]),
+                "`/baz` is not the tested value",
+            ),
+            (
+                json!({"foo": "bar"}),
+                json!([{"op": "add", "path": "/baz/bat", "value": "qux"}]),
+                "`/baz` doesn't exist",
+            ),
+            (
+                json!({"foo": ["bar"]}),
+                json!([{"op": "add", "path": "/foo/2", "value": "qux"}]),
+                "`/foo/2` is out of bounds",
+            ),
+            (
+                json!({"foo": ["bar"]}),
+                json!([{"op": "remove", "path": "/foo/01"}]),
+                "`/foo/01` doesn't exist",
+            ),
+            (
+                json!({"foo": {"bar": 1}}),
+                json!([{"op": "move", "from": "/foo", "path": "/foo/bar/baz"}]),
+                "cannot move `/foo` into its child `/foo/bar/baz`",
+            ),
+        ];
+        for (document, operations, expected) in failing {
+            let mut document = convert(document);
+            let original = document.clone();
+            let operations = patch(operations);
+            let error = operations.apply(&mut document).unwrap_err();
+            assert!(error.message().contains(expected), "{error:?}");
+            assert_eq!(document, original);
+        }
+
+        // Atomic, the first operation is rolled back
+        let mut document = convert(json!({"foo": "bar"}));
+        let error = patch(json!([
+            {"op": "remove", "path": "/foo"},
+            {"op": "test", "path": "/foo", "value": "bar"}
+        ]))
+        .apply(&mut document)
+        .unwrap_err();
+        assert!(
+            error.message().starts_with("JSON Patch operation 1 failed"),
+            "{error:?}"
+        );
+        assert_eq!(document, convert(json!({"foo": "bar"})));
+    }
+
+    #[tokio::test]
+    async fn test_json_patch_extractor() {
+        let request = |content_type: &str, body: &'static str| {
+            Request::builder()
+                .method(http::Method::PATCH)
+                .uri("/users/7")
+                .header(http::header::CONTENT_TYPE, content_type)
+                .body(Body::from(body))
+                .unwrap()
+        };
+
+        let body = r#"[{"op": "replace", "path": "/name", "value": "bob"}]"#;
+        let patch = JsonPatch::from_request(request(JSON_PATCH_CONTENT_TYPE, body), &())
+            .await
+            .unwrap();
+        let expected = PatchOperation::Replace {
+            path: JsonPointer::parse("/name").unwrap(),
+            value: Value::String("bob".into()),
+        };
+        assert_eq!(patch, JsonPatch(vec![expected]));
+
+        let error = JsonPatch::from_request(request("application/json", body), &())
+            .await
+            .map_err(Error::from)
+            .unwrap_err();
+        assert!(
+            error.message().contains(JSON_PATCH_CONTENT_TYPE),
+            "{error:?}"
+        );
+
+        let error = JsonPatch::from_request(request(JSON_PATCH_CONTENT_TYPE, "[{"), &())
+            .await
+            .map_err(Error::from)
+            .unwrap_err();
+        assert!(matches!(error, Error::InvalidJson(..)), "{error:?}");
+    }
+}
@@ -0,0 +1,195 @@
+use ::serde::de::DeserializeOwned;
+use axum::body::{Body, Bytes, to_bytes};
+use lambda_http::{RequestExt, http};
+
+use crate::{
+    BodyOnGet, Error, Params, ParamsConfig, ParamsParser, encoding::decode_body,
+    params::check_content_length,
+};
+
+impl<T: DeserializeOwned> Params<T> {
+    /// Extracts the params of an AWS Lambda request of API Gateway, an ALB or a Lambda
+    /// function URL, like the `Params` extractor in an axum app:
+    ///
+    /// ```ignore
+    /// async fn handler(req: lambda_http::Request) -> Result<Response<Body>, Error> {
+    ///     let Params(user, _) = Params::<CreateUser>::from_lambda(req, &ParamsConfig::new()).await?;
+    ///     ...
+    /// }
+    /// ```
+    ///
+    /// Path parameters come from the route of the integration, e.g. `/users/{id}`. Bodies
+    /// API Gateway sends base64 encoded, like multipart uploads, are decoded by `lambda_http`
+    /// before they are parsed or kept by `ParamsConfig::capture_body`.
+    /// `ParamsConfig::extension` doesn't apply.
+    pub async fn from_lambda(
+        req: lambda_http::Request,
+        config: &ParamsConfig,
+    ) -> Result<Self, Error> {
+        let mut params = ParamsParser::new(config.clone());
+        let path_params = req
+            .path_parameters_ref()
+            .into_iter()
+            .flat_map(|params| params.iter())
+            .map(|(key, value)| (key.to_string(), value.to_string()))
+            .collect::<Vec<_>>();
+        if !path_params.is_empty() && !config.skip_path {
+            params.add_path_params(path_params)?;
+        }
+        if let Some(query) = req.uri().query().filter(|_| !config.skip_query) {
+            params.add_query(query)?;
+        }
+
+        let (parts, body) = req.into_parts();
+        let bytes = Bytes::from(match body {
+            lambda_http::Body::Text(text) => text.into_bytes(),
+            lambda_http::Body::Binary(bytes) => bytes,
+            body => body.to_vec(),
+        });
+        if bytes.is_empty() || config.skip_body {
+            return params.finish().await;
+        }
+        if parts.method == http::Method::GET || parts.method == http::Method::HEAD {
+            match config.body_on_get {
+                BodyOnGet::Ignore => return params.finish().await,
+                BodyOnGet::Parse => {}
+                BodyOnGet::Reject => {
+                    return Err(Error::DecodeError(format!(
+                        "Request body is not allowed for {} requests",
+                        parts.method
+                    )));
+                }
+            }
+        }
+        let Some(content_type) = parts
+            .headers
+            .get(http::header::CONTENT_TYPE)
+            .and_then(|ct| ct.to_str().ok())
+        else {
+            return params.finish().await;
+        };
+        check_content_length(&parts.headers, content_type, config)?;
+        if config
+            .capture_body
+            .is_some_and(|max_size| bytes.len() <= max_size)
+        {
+            params.metadata.raw_body = Some(bytes.clone());
+        }
+        let policy = &config.security;
+        let body = decode_body(&parts.headers, Body::from(bytes), policy).await?;
+        let bytes = to_bytes(body, policy.max_body_size)
+            .await
+            .map_err(|e| Error::ReadError(format!("Failed to read request body: {e}")))?;
+        params.add_body(content_type, bytes).await?;
+        params.finish().await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use ::serde::Deserialize;
+    use base64::Engine;
+    use lambda_http::Body as LambdaBody;
+
+    use super::*;
+
+    #[derive(Debug, Deserialize, PartialEq)]
+    struct User {
+        id: u64,
+        name: String,
+        tags: Vec<String>,
+    }
+
+    fn request(content_type: &str, body: LambdaBody) -> lambda_http::Request {
+        let path_params = HashMap::from([("id".to_string(), "7".to_string())]);
+        http::Request::builder()
+            .method(http::Method::POST)
+            .uri("https://example.com/users/7?tags[]=a&tags[]=b")
+            .header(http::header::CONTENT_TYPE, content_type)
+            .body(body)
+            .unwrap()
+            .with_path_parameters(path_params)
+    }
+
+    #[tokio::test]
+    async fn test_from_lambda() {
+        let config = ParamsConfig::new();
+        let expected = User {
+            id: 7,
+            name: "alice".to_string(),
+            tags: vec!["a".to_string(), "b".to_string()],
+        };
+
+        let req = request(
+            "application/json",
+            LambdaBody::Text(r#"{"name": "alice"}"#.to_string()),
+        );
+        let Params(user, _) = Params::<User>::from_lambda(req, &config).await.unwrap();
+        assert_eq!(user, expected);
+
+        let req = request(
+            "application/x-www-form-urlencoded",
+            LambdaBody::Text("name=alice".to_string()),
+        );
+        let Params(user, _) = Params::<User>::from_lambda(req, &config).await.unwrap();
+        assert_eq!(user, expected);
+
+        // API Gateway sends multipart bodies base64 encoded, `lambda_http` decodes them
+        let body = "--XYZ\r\n\
+            Content-Disposition: form-data; name=\"name\"\r\n\r\n\
+            alice\r\n\
+            --XYZ--\r\n";
+        let event = serde_json::json!({
+            "version": "2.0",
+            "routeKey": "POST /users/{id}",
+            "rawPath": "/users/7",
+            "rawQueryString": "tags[]=a&tags[]=b",
+            "headers": {"content-type": "multipart/form-data; boundary=XYZ"},
+            "pathParameters": {"id": "7"},
+            "requestContext": {
+                "http": {"method": "POST", "path": "/users/7", "protocol": "HTTP/1.1"},
+            },
+            "body": base64::engine::general_purpose::STANDARD.encode(body),
+            "isBase64Encoded": true,
+        });
+        let req = lambda_http::request::from_str(&event.to_string()).unwrap();
+        let Params(user, metadata) = Params::<User>::from_lambda(req, &config).await.unwrap();
+        assert_eq!(user, expected);
+        assert_eq!(metadata.sizes.multipart_fields, 1);
+    }
+
+    #[tokio::test]
+    async fn test_from_lambda_body() {
+        // The body is read like by the `Params` extractor
+        let config = ParamsConfig::new().capture_body(1024).text_body("name");
+        let req = request("text/plain", LambdaBody::Text("alice".to_string()));
+        let Params(user, metadata) = Params::<User>::from_lambda(req, &config).await.unwrap();
+        assert_eq!(user.name, "alice");
+        assert_eq!(metadata.raw_body.as_deref(), Some(b"alice".as_slice()));
+
+        let mut req = request(
+            "application/json",
+            LambdaBody::Text(r#"{"name": "alice"}"#.to_string()),
+        );
+        req.headers_mut()
+            .insert(http::header::CONTENT_LENGTH, http::HeaderValue::from(17));
+        let policy = crate::SecurityPolicy::default().max_body_size(8);
+        let config = ParamsConfig::new().security_policy(policy);
+        let result = Params::<User>::from_lambda(req, &config).await;
+        assert!(
+            matches!(result, Err(Error::PayloadTooLarge(_))),
+            "{result:?}"
+        );
+
+        let req = request(
+            "application/json",
+            LambdaBody::Text(r#"{"name": "alice"}"#.to_string()),
+        );
+        let policy = crate::SecurityPolicy::default().allowed_content_types(Some(["text/plain"]));
+        let config = ParamsConfig::new().security_policy(policy);
+        let result = Params::<User>::from_lambda(req, &config).await;
+        assert!(format!("{:?}", result.unwrap_err()).contains("Unsupported content type"),);
+    }
+}
@@ -0,0 +1,114 @@
+use std::{
+    sync::Arc,
+    task::{Context, Poll},
+};
+
+use axum::http::Request;
+use tower_layer::Layer;
+use tower_service::Service;
+
+use crate::ParamsConfig;
+
+/// Installs a `ParamsConfig` for the routes it wraps, shared by every request:
+///
+/// ```ignore
+/// let app = Router::new()
+///     .route("/posts", post(create_post))
+///     .nest("/uploads", uploads.layer(ParamsLayer::new(upload_config)))
+///     .layer(ParamsLayer::new(ParamsConfig::new().max_params(100)));
+/// ```
+///
+/// Layers closer to the handler run later and replace the config of outer ones, so a router
+/// can override the app-wide config, like with `Extension(config)`.
+#[derive(Debug, Clone, Default)]
+pub struct ParamsLayer {
+    config: Arc<ParamsConfig>,
+}
+
+impl ParamsLayer {
+    pub fn new(config: ParamsConfig) -> Self {
+        ParamsLayer {
+            config: Arc::new(config),
+        }
+    }
+
+    pub fn config(&self) -> &ParamsConfig {
+        &self.config
+    }
+}
+
+impl From<ParamsConfig> for ParamsLayer {
+    fn from(config: ParamsConfig) -> Self {
+        Self::new(config)
+    }
+}
+
+impl<S> Layer<S> for ParamsLayer {
+    type Service = ParamsService<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        ParamsService {
+            inner,
+            config: self.config.clone(),
+        }
+    }
+}
+
+/// The service of `ParamsLayer`.
+#[derive(Debug, Clone)]
+pub struct ParamsService<S> {
+    inner: S,
+    config: Arc<ParamsConfig>,
+}
+
+impl<S, B> Service<Request<B>> for ParamsService<S>
+where
+    S: Service<Request<B>>,
+{
+    type Response = S::Response;
+    type Error = S::Error;
+    type Future = S::Future;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, mut req: Request<B>) -> Self::Future {
+        req.extensions_mut()
+            .insert(ParamsConfig::clone(&self.config));
+        self.inner.call(req)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use axum::{Router, http::StatusCode, routing::get};
+    use axum_test::TestServer;
+
+    use super::*;
+    use crate::RawParams;
+
+    #[tokio::test]
+    async fn test_params_layer() {
+        async fn handler(RawParams(params, _): RawParams) -> String {
+            params.len().to_string()
+        }
+
+        let uploads = Router::new()
+            .route("/", get(handler))
+            .layer(ParamsLayer::new(ParamsConfig::new().max_params(3)));
+        let app = Router::new()
+            .route("/posts", get(handler))
+            .nest("/uploads", uploads)
+            .layer(ParamsLayer::new(ParamsConfig::new().max_params(1)));
+        let server = TestServer::new(app).unwrap();
+
+        let response = server.get("/posts?a=1&b=2").await;
+        assert_eq!(response.status_code(), StatusCode::BAD_REQUEST);
+
+        // The config of the nested router replaces the app-wide one
+        let response = server.get("/uploads?a=1&b=2").await;
+        assert_eq!(response.status_code(), StatusCode::OK);
+        assert_eq!(response.text(), "2");
+    }
+}
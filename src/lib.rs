@@ -1,14 +1,129 @@
+extern crate self as axum_params;
+
+#[cfg(not(any(feature = "axum-07", feature = "axum-08", feature = "wasm")))]
+compile_error!("enable one of the features `axum-07`, `axum-08` or `wasm`");
+
+#[cfg(axum07)]
+extern crate axum07 as axum;
+#[cfg(all(test, axum07))]
+extern crate axum_test07 as axum_test;
+
+#[cfg(server)]
+mod audit;
+#[cfg(server)]
+mod cache;
+#[cfg(server)]
+mod capture;
+mod checksum;
+#[cfg(server)]
+mod compat;
+#[cfg(server)]
+mod config;
+#[cfg(server)]
+pub mod corpus;
+#[cfg(server)]
+mod defaults;
+#[cfg(server)]
+mod encoding;
 mod error;
+mod formats;
 mod json;
+mod json_patch;
+#[cfg(all(server, feature = "lambda"))]
+mod lambda;
+#[cfg(server)]
+mod layer;
+#[cfg(server)]
+mod merge;
+mod merge_patch;
+#[cfg(server)]
+mod metadata;
+#[cfg(server)]
+mod metrics;
+#[cfg(server)]
+mod ndjson;
+mod nested;
+#[cfg(all(server, feature = "utoipa"))]
+mod openapi;
+mod pagination;
+#[cfg(server)]
 mod params;
+#[cfg(server)]
+mod parser;
+#[cfg(all(server, feature = "post-policy"))]
+mod post_policy;
 pub mod query_parser;
+pub mod query_serializer;
+mod recovery;
+#[cfg(server)]
+mod rejection;
+mod security;
 mod serde;
+#[cfg(all(server, feature = "signed"))]
+mod signed;
+mod source;
+#[cfg(server)]
+pub mod test;
+mod traits;
+#[cfg(server)]
+mod typed_upload;
 mod upload_file;
+#[cfg(server)]
+mod uploaded;
+#[cfg(all(server, feature = "garde"))]
+mod validation;
 mod value;
 
+#[cfg(server)]
+pub use audit::*;
+#[cfg(server)]
+pub use cache::*;
+pub use checksum::*;
+#[cfg(server)]
+pub use compat::*;
+#[cfg(server)]
+pub use config::*;
+#[cfg(server)]
+pub use defaults::*;
 pub use error::*;
+pub use formats::*;
 pub use json::*;
+pub use json_patch::*;
+#[cfg(server)]
+pub use layer::*;
+#[cfg(server)]
+pub use merge::*;
+pub use merge_patch::*;
+#[cfg(server)]
+pub use metadata::*;
+#[cfg(server)]
+pub use metrics::*;
+#[cfg(server)]
+pub use ndjson::*;
+pub use nested::*;
+pub use pagination::*;
+#[cfg(server)]
 pub use params::*;
+#[cfg(server)]
+pub use parser::*;
+#[cfg(all(server, feature = "post-policy"))]
+pub use post_policy::*;
+#[cfg(server)]
+pub use rejection::*;
+pub use security::*;
 pub use serde::*;
+#[cfg(all(server, feature = "signed"))]
+pub use signed::*;
+pub use source::*;
+pub use traits::*;
+#[cfg(server)]
+pub use typed_upload::*;
 pub use upload_file::*;
+#[cfg(server)]
+pub use uploaded::*;
+#[cfg(all(server, feature = "garde"))]
+pub use validation::*;
 pub use value::*;
+
+#[cfg(feature = "macros")]
+pub use axum_params_macros::ParamSources;
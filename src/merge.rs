@@ -0,0 +1,347 @@
+use std::collections::HashMap;
+
+use crate::{Error, Source, Value};
+
+/// What to do when a body gives a key an array and an earlier source a single value, or the
+/// other way around, e.g. `?id=1` with the JSON body `{"id": [1, 2]}`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum ScalarArrayMerge {
+    /// Keep the typed value: JSON wins over the strings of the path, query string and form
+    /// body, between two typed values the later one wins.
+    #[default]
+    PreferTyped,
+    /// Turn the single value into an array and join both in source order, `[1, 1, 2]` above.
+    Promote,
+    /// Reject the request.
+    Reject,
+}
+
+/// What to do when two sources both give a key an array, e.g. `?tags[]=a` with the JSON
+/// body `{"tags": ["b"]}`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum ArrayMerge {
+    /// The later source wins, `["b"]` above.
+    #[default]
+    Replace,
+    /// Join both in source order, `["a", "b"]` above.
+    Concat,
+}
+
+/// How values from different sources are merged, install with `ParamsConfig::merge_strategy`.
+///
+/// Objects are merged key by key at any depth, so `?user[age]=30` with the JSON body
+/// `{"user": {"name": "alice"}}` keeps both fields. Coercions are reported in
+/// `ParamsMetadata::coercions`.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct MergeStrategy {
+    pub(crate) scalar_array: ScalarArrayMerge,
+    pub(crate) array: ArrayMerge,
+    pub(crate) priorities: HashMap<String, Vec<Source>>,
+}
+
+impl MergeStrategy {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn scalar_array(mut self, scalar_array: ScalarArrayMerge) -> Self {
+        self.scalar_array = scalar_array;
+        self
+    }
+
+    pub fn array(mut self, array: ArrayMerge) -> Self {
+        self.array = array;
+        self
+    }
+
+    /// The sources of the top level field `key` from the highest priority to the lowest,
+    /// where later sources otherwise win. Sources not listed rank below the listed ones:
+    ///
+    /// ```
+    /// use axum_params::{MergeStrategy, Source};
+    ///
+    /// // The body wins for `status`, the query string still wins for other fields
+    /// let strategy = MergeStrategy::new().field_priority("status", [Source::Json, Source::Query]);
+    /// ```
+    ///
+    /// A source is merged as usual, except that the value of `key` from a source of higher
+    /// priority is kept whole instead of being replaced or deep merged.
+    pub fn field_priority(
+        mut self,
+        key: impl Into<String>,
+        sources: impl IntoIterator<Item = Source>,
+    ) -> Self {
+        self.priorities
+            .insert(key.into(), sources.into_iter().collect());
+        self
+    }
+
+    /// Whether the value of `key` from `old` is kept over one from `new`, see
+    /// `field_priority`.
+    pub(crate) fn keeps(&self, key: &str, old: Source, new: Source) -> bool {
+        let Some(sources) = self.priorities.get(key) else {
+            return false;
+        };
+        let rank = |source| {
+            sources
+                .iter()
+                .position(|s| *s == source)
+                .unwrap_or(sources.len())
+        };
+        rank(old) < rank(new)
+    }
+
+    /// Merges the top level object `value` read from `source` into `params`.
+    pub(crate) fn merge_into(
+        &self,
+        params: &mut HashMap<String, Value>,
+        value: Value,
+        source: Source,
+        sources: &HashMap<String, Source>,
+        coercions: &mut Vec<String>,
+    ) -> Result<(), Error> {
+        let Value::Object(map) = value else {
+            return Err(Error::MergeError(format!(
+                "Cannot merge {} with object",
+                value.type_name()
+            )));
+        };
+        for (key, new) in map {
+            let value = match params.remove(&key) {
+                Some(old) => {
+                    let old_source = sources.get(&key).copied();
+                    self.merge_value(&key, old, old_source, new, source, coercions)?
+                }
+                None => new,
+            };
+            params.insert(key, value);
+        }
+        Ok(())
+    }
+
+    fn merge_objects(
+        &self,
+        path: &str,
+        mut old: HashMap<String, Value>,
+        old_source: Option<Source>,
+        new: HashMap<String, Value>,
+        source: Source,
+        coercions: &mut Vec<String>,
+    ) -> Result<HashMap<String, Value>, Error> {
+        for (key, new) in new {
+            let value = match old.remove(&key) {
+                Some(existing) => self.merge_value(
+                    &format!("{path}[{key}]"),
+                    existing,
+                    old_source,
+                    new,
+                    source,
+                    coercions,
+                )?,
+                None => new,
+            };
+            old.insert(key, value);
+        }
+        Ok(old)
+    }
+
+    fn merge_value(
+        &self,
+        path: &str,
+        old: Value,
+        old_source: Option<Source>,
+        new: Value,
+        source: Source,
+        coercions: &mut Vec<String>,
+    ) -> Result<Value, Error> {
+        let (old, new) = match (old, new) {
+            (Value::Object(old), Value::Object(new)) => {
+                return self
+                    .merge_objects(path, old, old_source, new, source, coercions)
+                    .map(Value::Object);
+            }
+            (Value::Array(mut old), Value::Array(new)) if self.array == ArrayMerge::Concat => {
+                old.extend(new);
+                return Ok(Value::Array(old));
+            }
+            pair => pair,
+        };
+        let conflict =
+            matches!((&old, &new), (Value::Array(_), v) | (v, Value::Array(_)) if is_scalar(v));
+        if !conflict {
+            return Ok(new);
+        }
+        let old_source = old_source.map_or("an earlier source", Source::as_str);
+        match self.scalar_array {
+            ScalarArrayMerge::PreferTyped => {
+                let keep_old = is_untyped(&new) && !is_untyped(&old);
+                let (kept, kept_source, dropped, dropped_source) = if keep_old {
+                    (old, old_source, new, source.as_str())
+                } else {
+                    (new, source.as_str(), old, old_source)
+                };
+                coercions.push(format!(
+                    "`{path}`: kept the {} from {kept_source}, dropped the {} from {dropped_source}",
+                    kept.type_name(),
+                    dropped.type_name(),
+                ));
+                Ok(kept)
+            }
+            ScalarArrayMerge::Promote => {
+                let scalar = if is_scalar(&old) { &old } else { &new };
+                coercions.push(format!(
+                    "`{path}`: promoted the {} from {} to an array",
+                    scalar.type_name(),
+                    if is_scalar(&old) {
+                        old_source
+                    } else {
+                        source.as_str()
+                    },
+                ));
+                let mut items = into_items(old);
+                items.extend(into_items(new));
+                Ok(Value::Array(items))
+            }
+            ScalarArrayMerge::Reject => Err(Error::MergeError(format!(
+                "Conflicting values for `{path}`: {} from {old_source} and {} from {source}",
+                old.type_name(),
+                new.type_name(),
+            ))),
+        }
+    }
+}
+
+fn is_scalar(value: &Value) -> bool {
+    !matches!(value, Value::Null | Value::Array(_) | Value::Object(_))
+}
+
+/// Strings from the path, query string or form body, whose type is only known once they are
+/// deserialized.
+fn is_untyped(value: &Value) -> bool {
+    match value {
+        Value::XStr(_) => true,
+        Value::Array(items) => !items.is_empty() && items.iter().all(is_untyped),
+        _ => false,
+    }
+}
+
+fn into_items(value: Value) -> Vec<Value> {
+    match value {
+        Value::Array(items) => items,
+        value => vec![value],
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn merge(
+        strategy: ScalarArrayMerge,
+        old: Value,
+        new: Value,
+    ) -> (Result<(), Error>, Option<Value>) {
+        let sources = HashMap::from([("id".to_string(), Source::Query)]);
+        let mut coercions = vec![];
+        let mut params = HashMap::from([("id".to_string(), old)]);
+        let result = MergeStrategy::new().scalar_array(strategy).merge_into(
+            &mut params,
+            Value::object(HashMap::from([("id".to_string(), new)])),
+            Source::Json,
+            &sources,
+            &mut coercions,
+        );
+        assert_eq!(coercions.len(), result.is_ok() as usize);
+        (result, params.remove("id"))
+    }
+
+    #[test]
+    fn test_nested_merge() {
+        let object = |entries: Vec<(&str, Value)>| {
+            Value::object(
+                entries
+                    .into_iter()
+                    .map(|(k, v)| (k.to_string(), v))
+                    .collect(),
+            )
+        };
+        let sources = HashMap::from([("user".to_string(), Source::Query)]);
+        let query = || {
+            HashMap::from([(
+                "user".to_string(),
+                object(vec![
+                    ("age", Value::xstr("30")),
+                    ("tags", Value::array(vec![Value::xstr("a")])),
+                    ("ids", Value::xstr("1")),
+                ]),
+            )])
+        };
+        let json = || {
+            object(vec![(
+                "user",
+                object(vec![
+                    ("name", Value::string("alice")),
+                    ("tags", Value::array(vec![Value::string("b")])),
+                    ("ids", Value::array(vec![Value::number(2u64)])),
+                ]),
+            )])
+        };
+
+        let mut params = query();
+        let mut coercions = vec![];
+        MergeStrategy::new()
+            .merge_into(&mut params, json(), Source::Json, &sources, &mut coercions)
+            .unwrap();
+        assert_eq!(
+            params["user"],
+            object(vec![
+                ("age", Value::xstr("30")),
+                ("name", Value::xstr("alice")),
+                ("tags", Value::array(vec![Value::xstr("b")])),
+                ("ids", Value::array(vec![Value::number(2u64)])),
+            ])
+        );
+        assert_eq!(
+            coercions,
+            ["`user[ids]`: kept the array from json, dropped the string from query"]
+        );
+
+        let mut params = query();
+        MergeStrategy::new()
+            .array(ArrayMerge::Concat)
+            .merge_into(&mut params, json(), Source::Json, &sources, &mut vec![])
+            .unwrap();
+        let Value::Object(user) = &params["user"] else {
+            unreachable!()
+        };
+        assert_eq!(
+            user["tags"],
+            Value::array(vec![Value::xstr("a"), Value::xstr("b")])
+        );
+    }
+
+    #[test]
+    fn test_scalar_array_merge() {
+        let ids = || Value::array(vec![Value::number(1u64), Value::number(2u64)]);
+
+        let (result, value) = merge(ScalarArrayMerge::PreferTyped, Value::xstr("1"), ids());
+        assert!(result.is_ok());
+        assert_eq!(value, Some(ids()));
+
+        let (result, value) = merge(ScalarArrayMerge::Promote, Value::xstr("1"), ids());
+        assert!(result.is_ok());
+        assert_eq!(
+            value,
+            Some(Value::array(vec![
+                Value::xstr("1"),
+                Value::number(1u64),
+                Value::number(2u64)
+            ]))
+        );
+
+        let (result, _) = merge(ScalarArrayMerge::Reject, Value::xstr("1"), ids());
+        assert!(
+            matches!(result, Err(Error::MergeError(e)) if e == "Conflicting values for `id`: string from query and array from json")
+        );
+    }
+}
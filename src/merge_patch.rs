@@ -0,0 +1,267 @@
+use serde::{Deserialize, Deserializer};
+
+#[cfg(server)]
+use ::serde::de::DeserializeOwned;
+#[cfg(server)]
+use axum::{
+    extract::{FromRequest, Request},
+    http,
+};
+
+use crate::Value;
+#[cfg(server)]
+use crate::{Error, Params, ParamsMetadata, Rejection, rejection::Rejecter};
+
+/// The content type of JSON Merge Patch (RFC 7396) bodies.
+pub const MERGE_PATCH_CONTENT_TYPE: &str = "application/merge-patch+json";
+
+/// Like `Params`, for `PATCH` endpoints taking `application/merge-patch+json` bodies, other
+/// content types are rejected with 400 Bad Request. Path and query parameters are merged
+/// like for `Params`.
+///
+/// Declare the fields as `Patch<T>` with `#[serde(default)]` to tell a key set to `null`
+/// from one left out:
+///
+/// ```ignore
+/// #[derive(Deserialize)]
+/// struct UpdateUser {
+///     id: u64,
+///     #[serde(default)]
+///     nickname: Patch<String>,
+/// }
+///
+/// async fn update_user(MergePatch(patch, _): MergePatch<UpdateUser>) {
+///     let mut user = load_user(patch.id).await;
+///     patch.nickname.apply(&mut user.nickname);
+/// }
+/// ```
+///
+/// `MergePatch<Value>` keeps the whole patch, apply it with `Value::merge_patch`.
+#[cfg(server)]
+#[derive(Debug, Default)]
+pub struct MergePatch<T>(pub T, pub ParamsMetadata);
+
+#[cfg(server)]
+#[cfg_attr(axum07, axum::async_trait)]
+impl<T, S> FromRequest<S> for MergePatch<T>
+where
+    T: DeserializeOwned,
+    S: Send + Sync,
+{
+    type Rejection = Rejection;
+
+    async fn from_request(req: Request, state: &S) -> Result<Self, Self::Rejection> {
+        let content_type = req
+            .headers()
+            .get(http::header::CONTENT_TYPE)
+            .and_then(|ct| ct.to_str().ok())
+            .unwrap_or_default();
+        if !is_merge_patch(content_type) {
+            let error = Error::DecodeError(format!(
+                "Expected a `{MERGE_PATCH_CONTENT_TYPE}` body, got `{content_type}`"
+            ));
+            return Err(Rejecter::of(&req).reject(error));
+        }
+        let Params(patch, metadata) = Params::from_request(req, state).await?;
+        Ok(MergePatch(patch, metadata))
+    }
+}
+
+#[cfg(server)]
+pub(crate) fn is_merge_patch(content_type: &str) -> bool {
+    let essence = content_type.split(';').next().unwrap_or_default().trim();
+    essence.eq_ignore_ascii_case(MERGE_PATCH_CONTENT_TYPE)
+}
+
+/// A field of a merge patch: left out, set to `null` or set to a value. Needs
+/// `#[serde(default)]` to be left out.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub enum Patch<T> {
+    #[default]
+    Absent,
+    Null,
+    Value(T),
+}
+
+impl<T> Patch<T> {
+    pub fn is_absent(&self) -> bool {
+        matches!(self, Patch::Absent)
+    }
+
+    /// `None` when left out, `Some(None)` when set to `null`.
+    pub fn into_option(self) -> Option<Option<T>> {
+        match self {
+            Patch::Absent => None,
+            Patch::Null => Some(None),
+            Patch::Value(value) => Some(Some(value)),
+        }
+    }
+
+    /// Updates `target`: keeps it when left out, clears it when set to `null`.
+    pub fn apply(self, target: &mut Option<T>) {
+        if let Some(value) = self.into_option() {
+            *target = value;
+        }
+    }
+}
+
+impl<'de, T: Deserialize<'de>> Deserialize<'de> for Patch<T> {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        Ok(match Option::<T>::deserialize(deserializer)? {
+            Some(value) => Patch::Value(value),
+            None => Patch::Null,
+        })
+    }
+}
+
+impl Value {
+    /// Applies the JSON Merge Patch `patch` (RFC 7396): objects are merged recursively,
+    /// `null` members remove keys and other values, arrays included, replace the target.
+    pub fn merge_patch(&mut self, patch: Value) {
+        let Value::Object(patch) = patch else {
+            *self = patch;
+            return;
+        };
+        if !matches!(self, Value::Object(_)) {
+            *self = Value::Object(Default::default());
+        }
+        let Value::Object(target) = self else {
+            unreachable!()
+        };
+        for (key, value) in patch {
+            match value {
+                Value::Null => {
+                    target.remove(&key);
+                }
+                value => target.entry(key).or_insert(Value::Null).merge_patch(value),
+            }
+        }
+    }
+}
+
+#[cfg(all(test, server))]
+mod tests {
+    use axum::body::Body;
+    use serde_json::json;
+
+    use super::*;
+
+    fn convert(json: serde_json::Value) -> Value {
+        Value::from(&json)
+    }
+
+    #[test]
+    fn test_merge_patch() {
+        // The examples of RFC 7396, appendix A
+        let cases = [
+            (json!({"a": "b"}), json!({"a": "c"}), json!({"a": "c"})),
+            (
+                json!({"a": "b"}),
+                json!({"b": "c"}),
+                json!({"a": "b", "b": "c"}),
+            ),
+            (json!({"a": "b"}), json!({"a": null}), json!({})),
+            (
+                json!({"a": "b", "b": "c"}),
+                json!({"a": null}),
+                json!({"b": "c"}),
+            ),
+            (json!({"a": ["b"]}), json!({"a": "c"}), json!({"a": "c"})),
+            (json!({"a": "c"}), json!({"a": ["b"]}), json!({"a": ["b"]})),
+            (
+                json!({"a": {"b": "c"}}),
+                json!({"a": {"b": "d", "c": null}}),
+                json!({"a": {"b": "d"}}),
+            ),
+            (
+                json!({"a": [{"b": "c"}]}),
+                json!({"a": [1]}),
+                json!({"a": [1]}),
+            ),
+            (json!(["a", "b"]), json!(["c", "d"]), json!(["c", "d"])),
+            (json!({"a": "b"}), json!(["c"]), json!(["c"])),
+            (json!({"a": "foo"}), json!(null), json!(null)),
+            (json!({"a": "foo"}), json!("bar"), json!("bar")),
+            (
+                json!({"e": null}),
+                json!({"a": 1}),
+                json!({"e": null, "a": 1}),
+            ),
+            (
+                json!([1, 2]),
+                json!({"a": "b", "c": null}),
+                json!({"a": "b"}),
+            ),
+            (
+                json!({}),
+                json!({"a": {"bb": {"ccc": null}}}),
+                json!({"a": {"bb": {}}}),
+            ),
+        ];
+        for (target, patch, expected) in cases {
+            let mut value = convert(target);
+            value.merge_patch(convert(patch));
+            assert_eq!(value, convert(expected));
+        }
+    }
+
+    #[tokio::test]
+    async fn test_merge_patch_extractor() {
+        #[derive(Debug, Deserialize)]
+        struct UpdateUser {
+            id: u64,
+            #[serde(default)]
+            nickname: Patch<String>,
+            #[serde(default)]
+            bio: Patch<String>,
+            #[serde(default)]
+            age: Patch<u32>,
+        }
+
+        let request = |content_type: &str| {
+            Request::builder()
+                .method(http::Method::PATCH)
+                .uri("/?id=7")
+                .header(http::header::CONTENT_TYPE, content_type)
+                .body(Body::from(r#"{"nickname": null, "age": 30}"#))
+                .unwrap()
+        };
+
+        let MergePatch(patch, _) =
+            MergePatch::<UpdateUser>::from_request(request(MERGE_PATCH_CONTENT_TYPE), &())
+                .await
+                .unwrap();
+        assert_eq!(patch.id, 7);
+        assert_eq!(patch.nickname, Patch::Null);
+        assert_eq!(patch.bio, Patch::Absent);
+        assert_eq!(patch.age, Patch::Value(30));
+
+        let mut nickname = Some("al".to_string());
+        let mut bio = Some("hi".to_string());
+        patch.nickname.apply(&mut nickname);
+        patch.bio.apply(&mut bio);
+        assert_eq!(nickname, None);
+        assert_eq!(bio.as_deref(), Some("hi"));
+
+        let MergePatch(patch, _) = MergePatch::<Value>::from_request(
+            request("application/merge-patch+json; charset=utf-8"),
+            &(),
+        )
+        .await
+        .unwrap();
+        let mut user = convert(json!({"nickname": "al", "bio": "hi", "age": 29}));
+        user.merge_patch(patch);
+        assert_eq!(user.get("nickname"), None);
+        assert_eq!(user.get("bio"), Some(&Value::string("hi")));
+        assert_eq!(u32::deserialize(user.get("age").unwrap().clone()), Ok(30));
+
+        let error = MergePatch::<UpdateUser>::from_request(request("application/json"), &())
+            .await
+            .map_err(Error::from)
+            .unwrap_err();
+        assert!(matches!(error, Error::DecodeError(_)), "{error:?}");
+    }
+}
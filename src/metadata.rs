@@ -0,0 +1,192 @@
+use std::{
+    collections::HashMap,
+    time::{Duration, Instant},
+};
+
+use axum::body::Bytes;
+use tempfile::NamedTempFile;
+
+use crate::{ParamsCache, Source, Value};
+
+#[derive(Debug, Default)]
+pub struct ParamsMetadata {
+    /// Temp files backing the `UploadFile`s, they are removed when dropped.
+    pub temp_files: Vec<NamedTempFile>,
+    pub timings: Timings,
+    pub sizes: Sizes,
+    /// The source that last wrote each top level key.
+    pub sources: HashMap<String, Source>,
+    /// The source of each leaf under its bracket path like `user[emails][0]`, with
+    /// `ParamsConfig::field_sources`.
+    pub field_sources: HashMap<String, Source>,
+    /// Values changed to merge sources that disagree, see `MergeStrategy`.
+    pub coercions: Vec<String>,
+    /// The body as received, with `ParamsConfig::capture_body`.
+    pub raw_body: Option<Bytes>,
+    /// The cache extracted from, holding its temp files.
+    pub(crate) cache: Option<ParamsCache>,
+    /// The `Content-Length` of the body, for `ParamsConfig::on_upload_progress`.
+    pub(crate) body_length: Option<u64>,
+    /// The leaves at the last `track_field_sources`, while tracking them.
+    pub(crate) leaves: Option<HashMap<String, Value>>,
+    /// The keys of the query string and form body in request order.
+    pub(crate) sent: Vec<SentKey>,
+}
+
+/// A key of the query string or form body as sent, like `filter[status]` or `tag[]`.
+#[derive(Debug, Clone)]
+pub(crate) struct SentKey {
+    pub(crate) source: Source,
+    pub(crate) top: String,
+    pub(crate) key: String,
+    /// The value of a key sent without brackets.
+    pub(crate) value: Option<Value>,
+}
+
+impl SentKey {
+    pub(crate) fn new(source: Source, top: String, key: &str, value: &Value) -> Self {
+        let bare = !key.contains(['[', ']']);
+        SentKey {
+            source,
+            top,
+            key: key.to_string(),
+            value: bare.then(|| value.clone()),
+        }
+    }
+}
+
+impl ParamsMetadata {
+    /// The keys sent in the query string and form body whose source the merged params
+    /// kept, in request order.
+    pub(crate) fn sent_keys(&self) -> Vec<(String, Option<Value>)> {
+        self.sent
+            .iter()
+            .filter(|sent| self.sources.get(&sent.top) == Some(&sent.source))
+            .map(|sent| (sent.key.clone(), sent.value.clone()))
+            .collect()
+    }
+
+    /// Whether `path` is one of the temp files of the extraction.
+    pub(crate) fn owns_temp_file(&self, path: &str) -> bool {
+        self.temp_file_paths().iter().any(|p| p == path)
+    }
+
+    /// The paths of the temp files of the extraction, those of the cache included.
+    pub(crate) fn temp_file_paths(&self) -> Vec<String> {
+        self.temp_files
+            .iter()
+            .chain(
+                self.cache
+                    .iter()
+                    .flat_map(|cache| &cache.metadata().temp_files),
+            )
+            .map(|f| f.path().to_string_lossy().into_owned())
+            .collect()
+    }
+
+    /// Attributes the leaves of `params` that changed since the last call to the source of
+    /// their top level key, called after each source is merged.
+    ///
+    /// A leaf that a later source sets to the same value keeps its earlier source.
+    pub(crate) fn track_field_sources(&mut self, params: &HashMap<String, Value>) {
+        let Some(previous) = &self.leaves else {
+            return;
+        };
+        let mut leaves = HashMap::new();
+        for (key, value) in params {
+            collect_leaves(key.clone(), value, &mut leaves);
+        }
+        self.field_sources
+            .retain(|path, _| leaves.contains_key(path));
+        for (path, value) in &leaves {
+            if previous.get(path) == Some(value) {
+                continue;
+            }
+            let top = path.split('[').next().unwrap_or_default();
+            if let Some(source) = self.sources.get(top) {
+                self.field_sources.insert(path.clone(), *source);
+            }
+        }
+        self.leaves = Some(leaves);
+    }
+}
+
+/// The values of `value` that are not non-empty objects or arrays, under their bracket path.
+fn collect_leaves(path: String, value: &Value, leaves: &mut HashMap<String, Value>) {
+    match value {
+        Value::Object(map) if !map.is_empty() => {
+            for (key, value) in map {
+                collect_leaves(format!("{path}[{key}]"), value, leaves);
+            }
+        }
+        Value::Array(vec) if !vec.is_empty() => {
+            for (i, value) in vec.iter().enumerate() {
+                collect_leaves(format!("{path}[{i}]"), value, leaves);
+            }
+        }
+        _ => {
+            leaves.insert(path, value.clone());
+        }
+    }
+}
+
+/// Time spent in each phase of the extraction.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct Timings {
+    /// Waiting for a slot of `ParamsConfig::concurrency_limit`.
+    pub queue: Duration,
+    /// Path params, query string and headers.
+    pub parts: Duration,
+    /// Waiting for body bytes from the client.
+    pub body_read: Duration,
+    pub json_parse: Duration,
+    /// Writing uploaded files to temp files.
+    pub multipart_spool: Duration,
+    pub deserialize: Duration,
+    /// Running `FromUploadFile` conversions.
+    pub uploads: Duration,
+}
+
+/// Bytes and parts read from the request.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct Sizes {
+    /// The query string, without the `?`.
+    pub query: usize,
+    /// The body after decompression, the contents of the parts for multipart bodies.
+    pub body: usize,
+    /// The parts of a multipart body, files are numbered by `UploadFile::part_index`.
+    pub multipart_fields: usize,
+    /// The merged params in memory, see `SecurityPolicy::max_params_size`.
+    pub params: usize,
+}
+
+impl Timings {
+    pub fn total(&self) -> Duration {
+        self.queue
+            + self.parts
+            + self.body_read
+            + self.json_parse
+            + self.multipart_spool
+            + self.deserialize
+            + self.uploads
+    }
+
+    /// Server side parsing time, excludes waiting for the client and for a slot.
+    pub fn processing(&self) -> Duration {
+        self.total() - self.body_read - self.queue
+    }
+}
+
+pub(crate) fn timed<R>(slot: &mut Duration, f: impl FnOnce() -> R) -> R {
+    let start = Instant::now();
+    let r = f();
+    *slot += start.elapsed();
+    r
+}
+
+pub(crate) async fn timed_async<R>(slot: &mut Duration, f: impl Future<Output = R>) -> R {
+    let start = Instant::now();
+    let r = f.await;
+    *slot += start.elapsed();
+    r
+}
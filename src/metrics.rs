@@ -0,0 +1,109 @@
+use std::sync::{Arc, Mutex};
+
+use axum::{extract::Request, http::Extensions, middleware::Next, response::Response};
+
+use crate::{ParamsMetadata, Sizes, Timings};
+
+/// Timings and sizes of the extraction of a request, for e.g. Prometheus histograms.
+///
+/// Collected for requests passing the `params_metrics` middleware and inserted into the
+/// extensions of their response, requests that fail to extract have none.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct ParamsMetrics {
+    pub timings: Timings,
+    pub sizes: Sizes,
+    /// Uploaded files spooled to temp files.
+    pub temp_files: usize,
+}
+
+impl From<&ParamsMetadata> for ParamsMetrics {
+    fn from(metadata: &ParamsMetadata) -> Self {
+        ParamsMetrics {
+            timings: metadata.timings,
+            sizes: metadata.sizes,
+            temp_files: metadata.temp_files.len(),
+        }
+    }
+}
+
+/// Where the extractors of a request leave its `ParamsMetrics`, inserted by `params_metrics`.
+#[derive(Clone, Default)]
+pub(crate) struct MetricsSlot(Arc<Mutex<Option<ParamsMetrics>>>);
+
+impl MetricsSlot {
+    pub(crate) fn of(extensions: &Extensions) -> Option<Self> {
+        extensions.get::<MetricsSlot>().cloned()
+    }
+
+    /// Keeps the metrics of the last extraction of the request.
+    pub(crate) fn record(&self, metadata: &ParamsMetadata) {
+        if let Ok(mut metrics) = self.0.lock() {
+            *metrics = Some(metadata.into());
+        }
+    }
+}
+
+/// Middleware collecting the `ParamsMetrics` of the request into the response extensions,
+/// install with `axum::middleware::from_fn(params_metrics)`. Without it no metrics are kept.
+pub async fn params_metrics(mut req: Request, next: Next) -> Response {
+    let slot = MetricsSlot::default();
+    req.extensions_mut().insert(slot.clone());
+    let mut response = next.run(req).await;
+    let metrics = slot.0.lock().ok().and_then(|mut metrics| metrics.take());
+    if let Some(metrics) = metrics {
+        response.extensions_mut().insert(metrics);
+    }
+    response
+}
+
+#[cfg(test)]
+mod tests {
+    use axum::{
+        Router,
+        middleware::{self, from_fn},
+        routing::post,
+    };
+    use axum_test::TestServer;
+    use serde::Deserialize;
+    use serde_json::json;
+
+    use super::*;
+    use crate::Params;
+
+    #[derive(Deserialize)]
+    struct Search {
+        #[allow(dead_code)]
+        q: String,
+    }
+
+    /// Reports the metrics in headers, like an app feeding its histograms.
+    async fn report(req: Request, next: middleware::Next) -> Response {
+        let mut response = next.run(req).await;
+        if let Some(metrics) = response.extensions().get::<ParamsMetrics>().copied() {
+            let headers = response.headers_mut();
+            headers.insert("x-query-bytes", metrics.sizes.query.into());
+            headers.insert("x-body-bytes", metrics.sizes.body.into());
+        }
+        response
+    }
+
+    #[tokio::test]
+    async fn test_params_metrics() {
+        let app = Router::new()
+            .route("/search", post(|_: Params<Search>| async {}))
+            .layer(from_fn(params_metrics))
+            .layer(from_fn(report));
+        let server = TestServer::new(app).unwrap();
+
+        let response = server
+            .post("/search?page=1")
+            .json(&json!({"q": "rust"}))
+            .await;
+        assert_eq!(response.header("x-query-bytes"), "6");
+        assert_eq!(response.header("x-body-bytes"), "12");
+
+        // Failed extractions have no metrics
+        let response = server.post("/search").json(&json!({})).await;
+        assert!(response.maybe_header("x-body-bytes").is_none());
+    }
+}
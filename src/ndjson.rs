@@ -0,0 +1,423 @@
+use std::{
+    pin::Pin,
+    task::{Context, Poll},
+};
+
+use ::serde::de::DeserializeOwned;
+use axum::{
+    body::Body,
+    extract::{FromRequest, Request},
+    http,
+};
+use futures_core::Stream;
+use tokio::sync::{OwnedSemaphorePermit, mpsc};
+use tracing::debug;
+
+use crate::{
+    Error, ParamsConfig, Rejection, SecurityPolicy, encoding::StreamDecoder, json::JsonPushParser,
+    params::next_chunk, rejection::Rejecter,
+};
+
+/// The content type of NDJSON / JSON Lines bodies.
+pub const NDJSON_CONTENT_TYPE: &str = "application/x-ndjson";
+
+/// The records of an `application/x-ndjson` body, one JSON document per line, deserialized
+/// as they arrive instead of after the whole body is read. Other content types are rejected
+/// with 400 Bad Request.
+///
+/// ```ignore
+/// async fn import_events(mut events: NdjsonStream<Event>) -> Result<String, Error> {
+///     let mut imported = 0;
+///     while let Some(event) = events.next().await {
+///         store(event?).await;
+///         imported += 1;
+///     }
+///     Ok(format!("{imported} events imported"))
+/// }
+/// ```
+///
+/// Each record is limited by the JSON limits of `ParamsConfig::security_policy` and to
+/// `SecurityPolicy::max_body_size` bytes, the body as a whole is not. Bodies sent with a
+/// `Content-Encoding` are decompressed as they arrive with the `compression` feature and
+/// rejected without it. Blank lines are skipped. The first malformed record ends the stream with its error, its
+/// `Error::InvalidJson` location is in the whole body.
+///
+/// The body is read by a task that stays a few records ahead of the handler and stops when
+/// the stream is dropped.
+#[derive(Debug)]
+pub struct NdjsonStream<T> {
+    records: mpsc::Receiver<Result<T, Error>>,
+}
+
+/// Records parsed ahead of the handler.
+const READ_AHEAD: usize = 16;
+
+#[cfg_attr(axum07, axum::async_trait)]
+impl<T, S> FromRequest<S> for NdjsonStream<T>
+where
+    T: DeserializeOwned + Send + 'static,
+    S: Send + Sync,
+{
+    type Rejection = Rejection;
+
+    async fn from_request(req: Request, _state: &S) -> Result<Self, Self::Rejection> {
+        let config = ParamsConfig::of(&req);
+        let rejecter = Rejecter::of(&req);
+        open(req, config).await.map_err(|e| rejecter.reject(e))
+    }
+}
+
+impl<T> Stream for NdjsonStream<T> {
+    type Item = Result<T, Error>;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        self.records.poll_recv(cx)
+    }
+}
+
+async fn open<T>(req: Request, config: ParamsConfig) -> Result<NdjsonStream<T>, Error>
+where
+    T: DeserializeOwned + Send + 'static,
+{
+    let (parts, body) = req.into_parts();
+    let content_type = parts
+        .headers
+        .get(http::header::CONTENT_TYPE)
+        .and_then(|ct| ct.to_str().ok())
+        .unwrap_or_default();
+    let essence = content_type.split(';').next().unwrap_or_default().trim();
+    if !essence.eq_ignore_ascii_case(NDJSON_CONTENT_TYPE) {
+        return Err(Error::DecodeError(format!(
+            "Expected a `{NDJSON_CONTENT_TYPE}` body, got `{content_type}`"
+        )));
+    }
+    let policy = config.security;
+    if !policy.allows_content_type(content_type) {
+        return Err(Error::DecodeError(format!(
+            "Unsupported content type: {content_type}"
+        )));
+    }
+    let permit = match &config.concurrency {
+        Some(limit) => Some(limit.acquire().await?),
+        None => None,
+    };
+    let decoder = StreamDecoder::new(&parts.headers, &policy)?;
+    let (sender, records) = mpsc::channel(READ_AHEAD);
+    tokio::spawn(async move {
+        if let Err(e) = read_records(body, decoder, &policy, permit, &sender).await {
+            let _ = sender.send(Err(e)).await;
+        }
+    });
+    Ok(NdjsonStream { records })
+}
+
+/// Sends the records of `body` until it ends, fails or the stream is dropped.
+async fn read_records<T: DeserializeOwned>(
+    mut body: Body,
+    mut decoder: StreamDecoder,
+    policy: &SecurityPolicy,
+    _permit: Option<OwnedSemaphorePermit>,
+    sender: &mpsc::Sender<Result<T, Error>>,
+) -> Result<(), Error> {
+    let mut record = Record::new(policy, 0, 1);
+    while let Some(chunk) = next_chunk(&mut body)
+        .await
+        .map_err(|e| Error::ReadError(format!("Failed to read NDJSON request body: {e}")))?
+    {
+        for piece in decoder.pieces(&chunk) {
+            let bytes = decoder.decode(piece)?;
+            if !send_lines(&mut record, &bytes, policy, sender).await? {
+                debug!("NDJSON stream dropped");
+                return Ok(());
+            }
+        }
+    }
+    let rest = decoder.finish()?;
+    if !send_lines(&mut record, &rest, policy, sender).await? {
+        return Ok(());
+    }
+    if let Some(value) = record.finish::<T>()? {
+        let _ = sender.send(Ok(value)).await;
+    }
+    Ok(())
+}
+
+/// Pushes `bytes` to `record` and sends the records they complete, `false` once the stream
+/// is dropped.
+async fn send_lines<'a, T: DeserializeOwned>(
+    record: &mut Record<'a>,
+    bytes: &[u8],
+    policy: &'a SecurityPolicy,
+    sender: &mpsc::Sender<Result<T, Error>>,
+) -> Result<bool, Error> {
+    let mut lines = bytes.split(|&b| b == b'\n');
+    let mut line = lines.next().unwrap_or_default();
+    for next in lines {
+        record.push(line)?;
+        let end = record.offset + 1;
+        let number = record.number + 1;
+        let done = std::mem::replace(record, Record::new(policy, end, number));
+        if let Some(value) = done.finish::<T>()?
+            && sender.send(Ok(value)).await.is_err()
+        {
+            return Ok(false);
+        }
+        line = next;
+    }
+    record.push(line)?;
+    Ok(true)
+}
+
+/// A line of the body, parsed as its bytes arrive.
+struct Record<'a> {
+    parser: JsonPushParser<'a>,
+    limit: usize,
+    /// The offset of the end of the line read so far in the body.
+    offset: usize,
+    number: usize,
+    len: usize,
+    blank: bool,
+}
+
+impl<'a> Record<'a> {
+    fn new(policy: &'a SecurityPolicy, offset: usize, number: usize) -> Self {
+        Self {
+            parser: JsonPushParser::new(policy).starting_at(offset, number),
+            limit: policy.max_body_size,
+            offset,
+            number,
+            len: 0,
+            blank: true,
+        }
+    }
+
+    fn push(&mut self, bytes: &[u8]) -> Result<(), Error> {
+        self.len += bytes.len();
+        self.offset += bytes.len();
+        if self.len > self.limit {
+            return Err(Error::PayloadTooLarge(format!(
+                "NDJSON record on line {} exceeds the {} bytes limit",
+                self.number, self.limit
+            )));
+        }
+        self.blank &= bytes.iter().all(u8::is_ascii_whitespace);
+        self.parser.push(bytes)
+    }
+
+    /// The record, `None` for a blank line.
+    fn finish<T: DeserializeOwned>(self) -> Result<Option<T>, Error> {
+        if self.blank {
+            return Ok(None);
+        }
+        let number = self.number;
+        let value = self.parser.finish()?;
+        T::deserialize(value).map(Some).map_err(|e| {
+            Error::DecodeError(format!(
+                "Failed to deserialize NDJSON record on line {number}: {e}"
+            ))
+        })
+    }
+}
+
+impl<T> NdjsonStream<T> {
+    /// Collects the remaining records, failing with the first error.
+    pub async fn collect_records(mut self) -> Result<Vec<T>, Error> {
+        let mut records = vec![];
+        while let Some(record) = self.records.recv().await {
+            records.push(record?);
+        }
+        Ok(records)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use axum::body::Bytes;
+    use futures_util::StreamExt;
+    use serde::Deserialize;
+
+    use super::*;
+
+    #[derive(Debug, Deserialize, PartialEq)]
+    struct Event {
+        id: u64,
+        kind: String,
+    }
+
+    fn request(chunks: Vec<&'static str>) -> Request {
+        let chunks = chunks
+            .into_iter()
+            .map(|chunk| Ok::<_, std::io::Error>(Bytes::from(chunk)));
+        Request::builder()
+            .method(http::Method::POST)
+            .header(http::header::CONTENT_TYPE, NDJSON_CONTENT_TYPE)
+            .body(Body::from_stream(futures_util::stream::iter(chunks)))
+            .unwrap()
+    }
+
+    fn event(id: u64, kind: &str) -> Event {
+        Event {
+            id,
+            kind: kind.to_string(),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_ndjson_stream() {
+        // Records split across chunks, CRLF line ends, blank lines and no final newline
+        let req = request(vec![
+            "{\"id\": 1, \"kind\": \"sign",
+            "up\"}\r\n\n{\"id\": 2,",
+            " \"kind\": \"login\"}\n  \n{\"id\": 3, \"kind\": \"logout\"}",
+        ]);
+        let mut events = NdjsonStream::<Event>::from_request(req, &()).await.unwrap();
+        assert_eq!(events.next().await.unwrap().unwrap(), event(1, "signup"));
+        assert_eq!(events.next().await.unwrap().unwrap(), event(2, "login"));
+        assert_eq!(events.next().await.unwrap().unwrap(), event(3, "logout"));
+        assert!(events.next().await.is_none());
+
+        let req = request(vec![
+            "{\"id\": 1, \"kind\": \"a\"}\n{\"id\": 2, \"kind\": \"b\"}\n",
+        ]);
+        let events = NdjsonStream::<Event>::from_request(req, &()).await.unwrap();
+        assert_eq!(
+            events.collect_records().await.unwrap(),
+            vec![event(1, "a"), event(2, "b")]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_ndjson_errors() {
+        // The records before the malformed one are yielded
+        let req = request(vec![
+            "{\"id\": 1, \"kind\": \"a\"}\n{\"id\": 2,,}\n{\"id\": 3}\n",
+        ]);
+        let mut events = NdjsonStream::<Event>::from_request(req, &()).await.unwrap();
+        assert_eq!(events.next().await.unwrap().unwrap(), event(1, "a"));
+        let Err(Error::InvalidJson(message, location, _)) = events.next().await.unwrap() else {
+            panic!("expected a JSON error");
+        };
+        assert_eq!(location.line, 2, "{message}");
+        assert_eq!(location.offset, 32, "{message}");
+        assert!(events.next().await.is_none());
+
+        let req = request(vec!["{\"id\": 1}\n"]);
+        let error = NdjsonStream::<Event>::from_request(req, &())
+            .await
+            .unwrap()
+            .collect_records()
+            .await
+            .unwrap_err();
+        assert!(
+            error
+                .message()
+                .contains("NDJSON record on line 1: missing field `kind`"),
+            "{error:?}"
+        );
+
+        let mut req = request(vec!["{\"id\": 1, ", "\"kind\": \"a\"}\n"]);
+        let policy = SecurityPolicy::lenient().max_body_size(16);
+        req.extensions_mut()
+            .insert(ParamsConfig::new().security_policy(policy));
+        let error = NdjsonStream::<Event>::from_request(req, &())
+            .await
+            .unwrap()
+            .collect_records()
+            .await
+            .unwrap_err();
+        assert!(matches!(error, Error::PayloadTooLarge(_)), "{error:?}");
+
+        let mut req = request(vec![]);
+        req.headers_mut().insert(
+            http::header::CONTENT_TYPE,
+            http::HeaderValue::from_static("application/json"),
+        );
+        let error = NdjsonStream::<Event>::from_request(req, &())
+            .await
+            .map_err(Error::from)
+            .unwrap_err();
+        assert!(error.message().contains(NDJSON_CONTENT_TYPE), "{error:?}");
+    }
+
+    fn encoded(encoding: &'static str, body: Vec<u8>) -> Request {
+        let chunks = body
+            .chunks(7)
+            .map(|chunk| Ok::<_, std::io::Error>(Bytes::copy_from_slice(chunk)))
+            .collect::<Vec<_>>();
+        let mut req = request(vec![]);
+        *req.body_mut() = Body::from_stream(futures_util::stream::iter(chunks));
+        req.headers_mut().insert(
+            http::header::CONTENT_ENCODING,
+            http::HeaderValue::from_static(encoding),
+        );
+        req
+    }
+
+    #[cfg(feature = "compression")]
+    #[tokio::test]
+    async fn test_ndjson_encoded() {
+        use std::io::Write;
+
+        let lines = "{\"id\": 1, \"kind\": \"a\"}\n{\"id\": 2, \"kind\": \"b\"}\n";
+        let mut gzip = flate2::write::GzEncoder::new(vec![], flate2::Compression::default());
+        gzip.write_all(lines.as_bytes()).unwrap();
+        let mut deflate = flate2::write::ZlibEncoder::new(vec![], flate2::Compression::default());
+        deflate.write_all(lines.as_bytes()).unwrap();
+        let mut br = vec![];
+        let mut encoder = brotli::CompressorWriter::new(&mut br, 4096, 5, 22);
+        encoder.write_all(lines.as_bytes()).unwrap();
+        drop(encoder);
+        for (encoding, body) in [
+            ("gzip", gzip.finish().unwrap()),
+            ("deflate", deflate.finish().unwrap()),
+            ("br", br),
+        ] {
+            let events = NdjsonStream::<Event>::from_request(encoded(encoding, body), &())
+                .await
+                .unwrap();
+            assert_eq!(
+                events.collect_records().await.unwrap(),
+                vec![event(1, "a"), event(2, "b")],
+                "{encoding}"
+            );
+        }
+
+        // Records are limited after decompression, a bomb stops at `max_body_size`
+        let mut bomb = flate2::write::GzEncoder::new(vec![], flate2::Compression::best());
+        bomb.write_all(&vec![b' '; 4 << 20]).unwrap();
+        let mut req = encoded("gzip", bomb.finish().unwrap());
+        let policy = SecurityPolicy::lenient().max_body_size(64 << 10);
+        req.extensions_mut()
+            .insert(ParamsConfig::new().security_policy(policy));
+        let error = NdjsonStream::<Event>::from_request(req, &())
+            .await
+            .unwrap()
+            .collect_records()
+            .await
+            .unwrap_err();
+        assert!(matches!(error, Error::PayloadTooLarge(_)), "{error:?}");
+
+        let req = encoded("gzip", b"not gzip".to_vec());
+        let error = NdjsonStream::<Event>::from_request(req, &())
+            .await
+            .unwrap()
+            .collect_records()
+            .await
+            .unwrap_err();
+        assert!(matches!(error, Error::DecodeError(_)), "{error:?}");
+    }
+
+    #[cfg(not(feature = "compression"))]
+    #[tokio::test]
+    async fn test_ndjson_encoded() {
+        let req = encoded("gzip", vec![]);
+        let error = NdjsonStream::<Event>::from_request(req, &())
+            .await
+            .map_err(Error::from)
+            .unwrap_err();
+        assert!(
+            error.message().contains("`compression` feature"),
+            "{error:?}"
+        );
+    }
+}
@@ -0,0 +1,189 @@
+use std::collections::HashMap;
+
+use ::serde::de::DeserializeOwned;
+
+use crate::{Error, Value, query_parser::QueryParser};
+
+/// Builds nested parameters from bracket keys like the extractors do, without a request, e.g.
+/// for an admin panel storing form fields or a job queue replaying them:
+///
+/// ```
+/// use axum_params::{NestedParams, Value};
+///
+/// let params = NestedParams::new()
+///     .field("post[title]", "Hello")
+///     .field("post[tags][]", "rust")
+///     .field("post[tags][]", "axum")
+///     .value("post[meta]", Value::object([("draft".to_string(), Value::bool(true))].into()))
+///     .build()
+///     .unwrap();
+/// assert_eq!(params["post"].get_path("tags[1]"), Some(&Value::xstr("axum")));
+/// ```
+///
+/// Keys are normalized by a default `QueryParser`, set `parser` for other limits or
+/// `Compat::Php`. The first invalid key is reported by `build`.
+#[derive(Debug, Clone)]
+pub struct NestedParams {
+    parser: QueryParser,
+    params: HashMap<String, Value>,
+    error: Option<Error>,
+}
+
+impl Default for NestedParams {
+    fn default() -> Self {
+        Self {
+            parser: QueryParser::new(None),
+            params: HashMap::new(),
+            error: None,
+        }
+    }
+}
+
+impl NestedParams {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// The parser normalizing the keys added after it.
+    pub fn parser(mut self, parser: QueryParser) -> Self {
+        self.parser = parser;
+        self
+    }
+
+    /// A text field, like a form field or a query parameter.
+    pub fn field(self, key: &str, value: impl Into<String>) -> Self {
+        self.insert(key, Value::xstr(value.into()), false)
+    }
+
+    /// A structured value, deep merged into the values already under a bracket key like
+    /// a multipart JSON part.
+    pub fn value(self, key: &str, value: Value) -> Self {
+        let merge = key.contains('[');
+        self.insert(key, value, merge)
+    }
+
+    fn insert(mut self, key: &str, value: Value, merge: bool) -> Self {
+        if self.error.is_none() {
+            let inserted = if merge {
+                self.parser.merge_nested_value(&mut self.params, key, value)
+            } else {
+                self.parser.parse_nested_value(&mut self.params, key, value)
+            };
+            self.error = inserted
+                .err()
+                .map(|e| Error::InvalidParams(format!("Failed to parse `{key}`: {e}"), e));
+        }
+        self
+    }
+
+    /// Every pair of a query string like `a=1&b[]=2`, percent-decoded.
+    pub fn query(mut self, query: &str) -> Self {
+        if self.error.is_none() {
+            self.error = self
+                .parser
+                .parse_nested_query_into(&mut self.params, query)
+                .err()
+                .map(|e| Error::params("Failed to parse query parameters", e));
+        }
+        self
+    }
+
+    pub fn build(self) -> Result<HashMap<String, Value>, Error> {
+        match self.error {
+            Some(error) => Err(error),
+            None => Ok(self.params),
+        }
+    }
+
+    /// Builds and deserializes the parameters, strings are coerced like with the default
+    /// `ParamsConfig`.
+    pub fn deserialize<T: DeserializeOwned>(self) -> Result<T, Error> {
+        T::deserialize(Value::Object(self.build()?))
+            .map_err(|e| Error::DecodeError(format!("Failed to deserialize parameters: {e}")))
+    }
+}
+
+/// Normalizes text fields under bracket keys into nested parameters, see `NestedParams`.
+///
+/// ```
+/// use axum_params::process_nested_params;
+///
+/// let params = process_nested_params([("user[name]", "alice"), ("user[roles][]", "admin")])
+///     .unwrap();
+/// assert!(params["user"].get_path("roles[0]").is_some());
+/// ```
+pub fn process_nested_params<I, K, V>(fields: I) -> Result<HashMap<String, Value>, Error>
+where
+    I: IntoIterator<Item = (K, V)>,
+    K: AsRef<str>,
+    V: Into<String>,
+{
+    fields
+        .into_iter()
+        .fold(NestedParams::new(), |params, (key, value)| {
+            params.field(key.as_ref(), value)
+        })
+        .build()
+}
+
+#[cfg(test)]
+mod tests {
+    use serde::Deserialize;
+
+    use super::*;
+    use crate::query_parser::Compat;
+
+    #[derive(Debug, Deserialize)]
+    struct Post {
+        title: String,
+        tags: Vec<String>,
+        views: u32,
+        meta: HashMap<String, bool>,
+    }
+
+    #[test]
+    fn test_nested_params() {
+        let post: HashMap<String, Post> = NestedParams::new()
+            .query("post[title]=Hello+world&post[views]=3")
+            .field("post[tags][]", "rust")
+            .field("post[tags][]", "axum")
+            .field("post[meta][draft]", "true")
+            .value(
+                "post[meta]",
+                Value::object([("pinned".to_string(), Value::bool(false))].into()),
+            )
+            .deserialize()
+            .unwrap();
+        let post = &post["post"];
+        assert_eq!(post.title, "Hello world");
+        assert_eq!(post.tags, ["rust", "axum"]);
+        assert_eq!(post.views, 3);
+        assert_eq!(
+            post.meta,
+            [("draft".to_string(), true), ("pinned".to_string(), false)].into()
+        );
+
+        let error = NestedParams::new()
+            .field("post[title]", "Hello")
+            .field("post[title][]", "again")
+            .field("other", "ignored")
+            .build()
+            .unwrap_err();
+        assert!(error.message().contains("post[title][]"), "{error:?}");
+
+        let params = NestedParams::new()
+            .parser(QueryParser::new(None).with_compat(Compat::Php))
+            .field("a.b", "1")
+            .build()
+            .unwrap();
+        assert_eq!(params["a_b"], Value::xstr("1"));
+    }
+
+    #[test]
+    fn test_process_nested_params() {
+        let params = process_nested_params([("a[b][c]", "1"), ("a[d][]", "2")]).unwrap();
+        assert_eq!(params["a"].get_path("b[c]"), Some(&Value::xstr("1")));
+        assert_eq!(params["a"].get_path("d[0]"), Some(&Value::xstr("2")));
+        assert!(process_nested_params([("a", "1"), ("a[b]", "2")]).is_err());
+    }
+}
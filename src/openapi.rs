@@ -0,0 +1,194 @@
+use std::borrow::Cow;
+
+use utoipa::{
+    IntoParams, PartialSchema, ToSchema,
+    openapi::{
+        KnownFormat, ObjectBuilder, RefOr, Required, Schema, SchemaFormat, Type,
+        path::{Parameter, ParameterBuilder, ParameterIn},
+    },
+};
+
+use crate::{
+    ParamSources, Params, ParamsWithRejection, QueryPathParams, Source, StrictParams, TypedUpload,
+    UploadFile, Uploaded,
+};
+
+fn binary_schema() -> RefOr<Schema> {
+    ObjectBuilder::new()
+        .schema_type(Type::String)
+        .format(Some(SchemaFormat::KnownFormat(KnownFormat::Binary)))
+        .into()
+}
+
+impl PartialSchema for UploadFile {
+    fn schema() -> RefOr<Schema> {
+        binary_schema()
+    }
+}
+
+impl ToSchema for UploadFile {
+    fn name() -> Cow<'static, str> {
+        Cow::Borrowed("UploadFile")
+    }
+}
+
+impl<T> PartialSchema for Uploaded<T> {
+    fn schema() -> RefOr<Schema> {
+        binary_schema()
+    }
+}
+
+impl<T> ToSchema for Uploaded<T> {
+    fn name() -> Cow<'static, str> {
+        Cow::Borrowed("UploadFile")
+    }
+}
+
+impl<K> PartialSchema for TypedUpload<K> {
+    fn schema() -> RefOr<Schema> {
+        binary_schema()
+    }
+}
+
+impl<K> ToSchema for TypedUpload<K> {
+    fn name() -> Cow<'static, str> {
+        Cow::Borrowed("UploadFile")
+    }
+}
+
+impl<T: PartialSchema> PartialSchema for Params<T> {
+    fn schema() -> RefOr<Schema> {
+        T::schema()
+    }
+}
+
+impl<T: ToSchema> ToSchema for Params<T> {
+    fn name() -> Cow<'static, str> {
+        T::name()
+    }
+
+    fn schemas(schemas: &mut Vec<(String, RefOr<Schema>)>) {
+        T::schemas(schemas)
+    }
+}
+
+impl<T: IntoParams> IntoParams for Params<T> {
+    fn into_params(parameter_in_provider: impl Fn() -> Option<ParameterIn>) -> Vec<Parameter> {
+        T::into_params(parameter_in_provider)
+    }
+}
+
+impl<T: IntoParams, R> IntoParams for ParamsWithRejection<T, R> {
+    fn into_params(parameter_in_provider: impl Fn() -> Option<ParameterIn>) -> Vec<Parameter> {
+        T::into_params(parameter_in_provider)
+    }
+}
+
+impl<T: IntoParams> IntoParams for QueryPathParams<T> {
+    fn into_params(parameter_in_provider: impl Fn() -> Option<ParameterIn>) -> Vec<Parameter> {
+        T::into_params(parameter_in_provider)
+    }
+}
+
+impl<T: PartialSchema> PartialSchema for StrictParams<T> {
+    fn schema() -> RefOr<Schema> {
+        T::schema()
+    }
+}
+
+impl<T: ToSchema> ToSchema for StrictParams<T> {
+    fn name() -> Cow<'static, str> {
+        T::name()
+    }
+
+    fn schemas(schemas: &mut Vec<(String, RefOr<Schema>)>) {
+        T::schemas(schemas)
+    }
+}
+
+/// Path and query parameters of `T`, placed by `ParamSources`: fields allowed from the
+/// path are path parameters, fields allowed from the query string are query parameters
+/// and body only fields are left to the request body.
+impl<T: ParamSources + PartialSchema> IntoParams for StrictParams<T> {
+    fn into_params(_: impl Fn() -> Option<ParameterIn>) -> Vec<Parameter> {
+        let RefOr::T(Schema::Object(object)) = T::schema() else {
+            return vec![];
+        };
+        let mut params = vec![];
+        for (name, schema) in object.properties {
+            // Fields without a declared source are documented as query parameters
+            let sources: &[Source] = T::expected_sources()
+                .iter()
+                .find(|(field, _)| *field == name)
+                .map_or(&[Source::Query], |(_, sources)| sources);
+            let parameter_in = if sources.contains(&Source::Path) {
+                ParameterIn::Path
+            } else if sources.contains(&Source::Query) {
+                ParameterIn::Query
+            } else {
+                continue;
+            };
+            let required = parameter_in == ParameterIn::Path || object.required.contains(&name);
+            params.push(
+                ParameterBuilder::new()
+                    .name(name)
+                    .parameter_in(parameter_in)
+                    .required(if required {
+                        Required::True
+                    } else {
+                        Required::False
+                    })
+                    .schema(Some(schema))
+                    .build(),
+            );
+        }
+        params
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde::Deserialize;
+    use utoipa::{IntoParams, PartialSchema, ToSchema};
+
+    use crate::{ParamSources, Source, StrictParams, UploadFile};
+
+    #[allow(dead_code)]
+    #[derive(Deserialize, ToSchema)]
+    struct CreatePost {
+        id: i32,
+        q: Option<String>,
+        title: String,
+        cover: UploadFile,
+    }
+
+    impl ParamSources for CreatePost {
+        fn expected_sources() -> &'static [(&'static str, &'static [Source])] {
+            &[
+                ("id", &[Source::Path]),
+                ("title", Source::BODY),
+                ("cover", &[Source::Multipart]),
+            ]
+        }
+    }
+
+    #[test]
+    fn test_upload_file_schema() {
+        let schema = serde_json::to_value(UploadFile::schema()).unwrap();
+        assert_eq!(schema["type"], "string");
+        assert_eq!(schema["format"], "binary");
+    }
+
+    #[test]
+    fn test_strict_params_into_params() {
+        let params = StrictParams::<CreatePost>::into_params(|| None);
+        let params = serde_json::to_value(params).unwrap();
+        assert_eq!(params.as_array().unwrap().len(), 2);
+        assert_eq!(params[0]["name"], "id");
+        assert_eq!(params[0]["in"], "path");
+        assert_eq!(params[0]["required"], true);
+        assert_eq!(params[1]["name"], "q");
+        assert_eq!(params[1]["in"], "query");
+        assert_eq!(params[1]["required"], false);
+    }
+}
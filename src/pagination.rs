@@ -0,0 +1,323 @@
+use serde::{Deserialize, Deserializer, Serialize, de};
+
+use crate::{ParamFormat, Value, serde::ParamsValueVisitor};
+
+/// `page` and `per_page` query params, both optional:
+///
+/// ```ignore
+/// #[derive(Deserialize)]
+/// struct ListPosts {
+///     q: Option<String>,
+///     #[serde(flatten)]
+///     page: Page,
+/// }
+///
+/// // GET /posts?page=2&per_page=50
+/// async fn list_posts(Params(list, _): Params<ListPosts>) {
+///     let posts = db.posts(list.page.offset(), list.page.limit()).await;
+/// }
+/// ```
+///
+/// `page` starts at 1, `per_page` defaults to `DEFAULT_PER_PAGE` and is capped at
+/// `MAX_PER_PAGE`, e.g. `Page<500, 100>` for an export endpoint. Zero values are rejected.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub struct Page<const MAX_PER_PAGE: u32 = 100, const DEFAULT_PER_PAGE: u32 = 20> {
+    pub page: u32,
+    pub per_page: u32,
+}
+
+impl<const MAX_PER_PAGE: u32, const DEFAULT_PER_PAGE: u32> Page<MAX_PER_PAGE, DEFAULT_PER_PAGE> {
+    /// The number of items before the page, 0 for a `page` of 0 built by hand.
+    pub fn offset(&self) -> u64 {
+        u64::from(self.page.saturating_sub(1)) * u64::from(self.per_page)
+    }
+
+    pub fn limit(&self) -> u32 {
+        self.per_page
+    }
+}
+
+impl<const MAX_PER_PAGE: u32, const DEFAULT_PER_PAGE: u32> Default
+    for Page<MAX_PER_PAGE, DEFAULT_PER_PAGE>
+{
+    fn default() -> Self {
+        Page {
+            page: 1,
+            per_page: DEFAULT_PER_PAGE.min(MAX_PER_PAGE),
+        }
+    }
+}
+
+impl<'de, const MAX_PER_PAGE: u32, const DEFAULT_PER_PAGE: u32> Deserialize<'de>
+    for Page<MAX_PER_PAGE, DEFAULT_PER_PAGE>
+{
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        // Coerced field by field, so the strings buffered by a plain `#[serde(flatten)]`
+        // still parse as numbers
+        #[derive(Deserialize)]
+        struct RawPage {
+            #[serde(default, deserialize_with = "crate::coerce")]
+            page: Option<u32>,
+            #[serde(default, deserialize_with = "crate::coerce")]
+            per_page: Option<u32>,
+        }
+
+        let raw = RawPage::deserialize(deserializer)?;
+        let default = Self::default();
+        let page = match raw.page {
+            Some(0) => return Err(de::Error::custom("`page` starts at 1")),
+            page => page.unwrap_or(default.page),
+        };
+        let per_page = match raw.per_page {
+            Some(0) => return Err(de::Error::custom("`per_page` must be at least 1")),
+            per_page => per_page.unwrap_or(default.per_page).min(MAX_PER_PAGE),
+        };
+        Ok(Page { page, per_page })
+    }
+}
+
+/// The direction of a `Sort`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SortOrder {
+    #[default]
+    Asc,
+    Desc,
+}
+
+impl<'de> Deserialize<'de> for SortOrder {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        let order = String::deserialize(deserializer)?;
+        match order.to_ascii_lowercase().as_str() {
+            "asc" | "ascending" => Ok(SortOrder::Asc),
+            "desc" | "descending" => Ok(SortOrder::Desc),
+            _ => Err(de::Error::custom(format!(
+                "invalid sort order `{order}`, expected `asc` or `desc`"
+            ))),
+        }
+    }
+}
+
+/// A sort key, from `sort=created_at`, `sort=-created_at` for descending, or
+/// `sort[field]=created_at&sort[order]=desc`:
+///
+/// ```ignore
+/// #[derive(Deserialize)]
+/// #[serde(rename_all = "snake_case")]
+/// enum PostField {
+///     CreatedAt,
+///     Title,
+/// }
+///
+/// #[derive(Deserialize)]
+/// struct ListPosts {
+///     #[serde(default)]
+///     sort: Vec<Sort<PostField>>,
+/// }
+/// ```
+///
+/// An enum `F` limits the sortable fields, other names are rejected. Repeat the key with
+/// brackets, `sort[]=-created_at&sort[]=title`, to sort by several fields.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize)]
+pub struct Sort<F = String> {
+    pub field: F,
+    pub order: SortOrder,
+}
+
+impl<'de, F: de::DeserializeOwned> Deserialize<'de> for Sort<F> {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        #[derive(Deserialize)]
+        struct RawSort<F> {
+            field: F,
+            #[serde(default)]
+            order: SortOrder,
+        }
+
+        match deserializer.deserialize_any(ParamsValueVisitor)? {
+            Value::XStr(s) | Value::String(s) => {
+                let (field, order) = match s.strip_prefix('-') {
+                    Some(field) => (field, SortOrder::Desc),
+                    None => (s.strip_prefix('+').unwrap_or(&s), SortOrder::Asc),
+                };
+                let field = F::deserialize(Value::xstr(field)).map_err(de::Error::custom)?;
+                Ok(Sort { field, order })
+            }
+            value => {
+                let RawSort { field, order } =
+                    RawSort::deserialize(value).map_err(de::Error::custom)?;
+                Ok(Sort { field, order })
+            }
+        }
+    }
+}
+
+/// `from` and `to` bounds of a `ParamFormat` type like `chrono::NaiveDate`, both optional
+/// and inclusive, e.g. `from=2024-01-01&to=2024-01-31`. Rejected when `from` is after `to`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct DateRange<T> {
+    pub from: Option<T>,
+    pub to: Option<T>,
+}
+
+impl<T> Default for DateRange<T> {
+    fn default() -> Self {
+        DateRange {
+            from: None,
+            to: None,
+        }
+    }
+}
+
+impl<T: PartialOrd> DateRange<T> {
+    /// Whether `value` is within the bounds.
+    pub fn contains(&self, value: &T) -> bool {
+        self.from.as_ref().is_none_or(|from| from <= value)
+            && self.to.as_ref().is_none_or(|to| value <= to)
+    }
+}
+
+impl<'de, T: ParamFormat + PartialOrd> Deserialize<'de> for DateRange<T> {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        #[derive(Deserialize)]
+        #[serde(bound = "T: ParamFormat")]
+        struct RawRange<T> {
+            #[serde(default = "none", deserialize_with = "crate::formatted")]
+            from: Option<T>,
+            #[serde(default = "none", deserialize_with = "crate::formatted")]
+            to: Option<T>,
+        }
+
+        fn none<T>() -> Option<T> {
+            None
+        }
+
+        let RawRange { from, to } = RawRange::deserialize(deserializer)?;
+        if let (Some(from), Some(to)) = (&from, &to)
+            && from > to
+        {
+            return Err(de::Error::custom("`from` is after `to`"));
+        }
+        Ok(DateRange { from, to })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use super::*;
+
+    fn params(entries: &[(&str, &str)]) -> Value {
+        Value::Object(
+            entries
+                .iter()
+                .map(|(k, v)| (k.to_string(), Value::xstr(*v)))
+                .collect::<HashMap<_, _>>(),
+        )
+    }
+
+    #[derive(Debug, Deserialize)]
+    struct ListPosts {
+        #[serde(flatten)]
+        page: Page<50>,
+        #[serde(default)]
+        sort: Option<Sort<PostField>>,
+    }
+
+    #[derive(Debug, PartialEq, Deserialize)]
+    #[serde(rename_all = "snake_case")]
+    enum PostField {
+        CreatedAt,
+        Title,
+    }
+
+    #[test]
+    fn test_page() {
+        let list = ListPosts::deserialize(params(&[])).unwrap();
+        assert_eq!(list.page, Page::default());
+        assert_eq!((list.page.offset(), list.page.limit()), (0, 20));
+
+        let list = ListPosts::deserialize(params(&[("page", "3"), ("per_page", "10")])).unwrap();
+        assert_eq!(
+            list.page,
+            Page {
+                page: 3,
+                per_page: 10
+            }
+        );
+        assert_eq!(list.page.offset(), 20);
+
+        let list = ListPosts::deserialize(params(&[("per_page", "1000")])).unwrap();
+        assert_eq!(list.page.per_page, 50);
+
+        let list = ListPosts::deserialize(params(&[("page", ""), ("per_page", "")])).unwrap();
+        assert_eq!(list.page, Page::default());
+
+        let error = ListPosts::deserialize(params(&[("page", "0")])).unwrap_err();
+        assert!(error.to_string().contains("`page` starts at 1"), "{error}");
+        assert!(ListPosts::deserialize(params(&[("page", "x")])).is_err());
+
+        let page: Page = Page {
+            page: 0,
+            per_page: 10,
+        };
+        assert_eq!(page.offset(), 0);
+    }
+
+    #[test]
+    fn test_sort() {
+        let sort = |value: Value| Sort::<PostField>::deserialize(value);
+        assert_eq!(
+            sort(Value::xstr("-created_at")).unwrap(),
+            Sort {
+                field: PostField::CreatedAt,
+                order: SortOrder::Desc
+            }
+        );
+        assert_eq!(sort(Value::xstr("title")).unwrap().order, SortOrder::Asc);
+        assert_eq!(
+            sort(params(&[("field", "title"), ("order", "DESC")]))
+                .unwrap()
+                .order,
+            SortOrder::Desc
+        );
+        assert!(sort(Value::xstr("-password")).is_err());
+        assert!(sort(params(&[("field", "title"), ("order", "up")])).is_err());
+
+        let list = ListPosts::deserialize(params(&[("sort", "-title")])).unwrap();
+        assert_eq!(list.sort.unwrap().field, PostField::Title);
+    }
+
+    #[cfg(feature = "chrono")]
+    #[test]
+    fn test_date_range() {
+        use chrono::NaiveDate;
+
+        let range = |entries| DateRange::<NaiveDate>::deserialize(params(entries));
+        let parsed = range(&[("from", "2024-01-01"), ("to", "2024-01-31")]).unwrap();
+        let date = |d| NaiveDate::from_ymd_opt(2024, 1, d).unwrap();
+        assert_eq!(parsed.from, Some(date(1)));
+        assert!(parsed.contains(&date(31)));
+        assert!(!parsed.contains(&NaiveDate::from_ymd_opt(2024, 2, 1).unwrap()));
+
+        assert_eq!(range(&[("to", "")]).unwrap(), DateRange::default());
+        let error = range(&[("from", "2024-02-01"), ("to", "2024-01-31")]).unwrap_err();
+        assert!(
+            error.to_string().contains("`from` is after `to`"),
+            "{error}"
+        );
+        assert!(range(&[("from", "yesterday")]).is_err());
+    }
+}
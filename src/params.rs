@@ -1,1097 +1,4174 @@
-use crate::{Error, UploadFile, Value, parse_json, query_parser::QueryParser};
-use ::serde::de::DeserializeOwned;
-use actson::feeder::SliceJsonFeeder;
+use crate::{
+    BodyOnGet, BoxFuture, DuplicateParts, Error, InvalidField, OnUploadProgress, ParamsCache,
+    ParamsConfig, ParamsMetadata, ParamsParser, ParamsReader, Rejection, SecurityPolicy, Source,
+    Timings, UnknownField, UploadFile, UploadProgress, Value,
+    audit::AuditSlot,
+    capture::BodyCapture,
+    checksum::UploadHasher,
+    encoding::{decode_body, form_charset, transcode_form},
+    json::JsonPushParser,
+    json_patch::is_json_patch,
+    merge_patch::is_merge_patch,
+    metadata::SentKey,
+    metrics::MetricsSlot,
+    parser::{BodyKind, check_params_size},
+    query_parser::QueryParser,
+    recovery,
+    rejection::Rejecter,
+    security::Redactor,
+    serde::{Root, with_coercions},
+    test::TestPathParams,
+    timed, timed_async,
+    upload_file::{sanitize_file_name, with_temp_files},
+    uploaded::{collect_uploads, resolve_uploads},
+};
+use ::serde::de::{DeserializeOwned, DeserializeSeed, Deserializer};
 use axum::{
-    body::to_bytes,
+    body::{Body, Bytes, HttpBody, to_bytes},
     extract::{FromRequest, FromRequestParts, Path, Request},
-    http::{self},
+    http::{self, Extensions, request::Parts},
+    response::IntoResponse,
 };
-use log::debug;
-use std::collections::HashMap;
+use std::{borrow::Cow, collections::HashMap, fmt, marker::PhantomData, pin::Pin, time::Instant};
 use tempfile::NamedTempFile;
+use tokio::io::{AsyncWriteExt, BufWriter};
+use tracing::{Instrument, Span, debug, debug_span, field};
 
 #[derive(Debug, Default)]
-pub struct Params<T>(pub T, pub Vec<NamedTempFile>);
+pub struct Params<T>(pub T, pub ParamsMetadata);
 
+#[cfg_attr(axum07, axum::async_trait)]
 impl<T, S> FromRequest<S> for Params<T>
 where
     T: DeserializeOwned,
     S: Send + Sync,
 {
-    type Rejection = crate::Error;
+    type Rejection = Rejection;
 
     async fn from_request(req: Request, state: &S) -> Result<Self, Self::Rejection> {
-        let is_get_or_head =
-            req.method() == http::Method::GET || req.method() == http::Method::HEAD;
-        let (mut parts, body) = req.into_parts();
-
-        let parser = QueryParser::new(None);
-        let mut merged_params = HashMap::new();
-
-        // Extract path parameters
-        if let Ok(Path(params)) =
-            Path::<HashMap<String, String>>::from_request_parts(&mut parts, state).await
-        {
-            debug!("params: {:?}", params);
-
-            for (key, value) in params {
-                parser
-                    .parse_nested_value(&mut merged_params, key.as_str(), Value::xstr(value))
-                    .map_err(|e| {
-                        Error::DecodeError(format!("Failed to parse path parameters: {}", e))
-                    })?;
-            }
-        }
+        Self::extract(req, state, |_, _| Ok(())).await
+    }
+}
 
-        debug!("merged path params: {:?}", merged_params);
-        debug!("parts.uri: {:?}", parts.uri);
-        debug!("parts.uri.query(): {:?}", parts.uri.query());
+impl<T> Params<T> {
+    /// The body as received, with `ParamsConfig::capture_body`, `None` if it was larger than
+    /// the limit or not read.
+    pub fn raw_body(&self) -> Option<&Bytes> {
+        self.1.raw_body.as_ref()
+    }
 
-        // Extract query parameters from URI
-        if let Some(query) = parts.uri.query() {
-            parser
-                .parse_nested_query_into(&mut merged_params, query)
-                .map_err(|e| {
-                    Error::DecodeError(format!("Failed to parse query parameters: {}", e))
-                })?;
-        }
+    /// The source of each leaf under its bracket path like `user[address][city]`, with
+    /// `ParamsConfig::field_sources`, empty otherwise.
+    #[allow(clippy::misnamed_getters)]
+    pub fn sources(&self) -> &HashMap<String, Source> {
+        &self.1.field_sources
+    }
 
-        debug!("merged query params: {:?}", merged_params);
+    /// Extracts like the `Params` extractor, deserializing with `seed` rather than
+    /// `T: Deserialize`, for stateful deserialization like interned strings or types from a
+    /// schema registry:
+    ///
+    /// ```ignore
+    /// async fn create_record(
+    ///     State(registry): State<Arc<Registry>>,
+    ///     Path(kind): Path<String>,
+    ///     req: Request,
+    /// ) -> Result<Json<Record>, Rejection> {
+    ///     let seed = registry.seed(&kind);
+    ///     let Params(record, _) = Params::from_request_seed(req, &(), seed).await?;
+    ///     Ok(Json(record))
+    /// }
+    /// ```
+    ///
+    /// `seed` is cloned when the parameters are deserialized more than once, e.g. for
+    /// `Uploaded` fields. With `ParamsConfig::collect_errors` only the first error is reported.
+    pub async fn from_request_seed<S, Seed>(
+        req: Request,
+        state: &S,
+        seed: Seed,
+    ) -> Result<Self, Rejection>
+    where
+        S: Send + Sync,
+        Seed: for<'de> DeserializeSeed<'de, Value = T> + Clone,
+    {
+        Self::extract_seed(req, state, seed, |_, _| None, |_, _| Ok(())).await
+    }
 
-        let mut temp_files = Vec::new();
-        debug!(
-            "Content-Type: {:?}",
-            parts.headers.get(http::header::CONTENT_TYPE)
-        );
-        if let Some(content_type) = parts.headers.get(http::header::CONTENT_TYPE) {
-            debug!("Content-Type: {:?}", content_type);
-            if let Ok(content_type) = content_type.to_str() {
-                match content_type {
-                    ct if ct.starts_with("application/json") => {
-                        let bytes = to_bytes(body, usize::MAX).await.map_err(|e| {
-                            debug!("Failed to read JSON request body: {}", e);
-                            Error::DecodeError(format!("Failed to read JSON request body: {}", e))
-                        })?;
-                        let feeder = SliceJsonFeeder::new(&bytes);
-                        let value = parse_json(feeder)?;
-                        debug!("parsed json: {:#?}", value);
-                        merged_params = value.merge_into(merged_params).map_err(|e| {
-                            debug!("Failed to merge JSON data: {e:?}");
-                            Error::DecodeError(format!("Failed to merge JSON data: {e:?}"))
-                        })?;
-                        debug!("merged json: {:#?}", merged_params);
-                    }
-                    ct if ct.starts_with("application/x-www-form-urlencoded") => {
-                        if !is_get_or_head {
-                            let bytes = to_bytes(body, usize::MAX).await.map_err(|e| {
-                                Error::ReadError(format!(
-                                    "Failed to read form-urlencoded request body: {e}"
-                                ))
-                            })?;
-                            parser
-                                .parse_nested_query_into(
-                                    &mut merged_params,
-                                    String::from_utf8_lossy(&bytes).as_ref(),
-                                )
-                                .map_err(|e| {
-                                    Error::DecodeError(format!(
-                                        "Failed to parse form-urlencoded body: {}",
-                                        e
-                                    ))
-                                })?
-                        }
-                    }
-                    ct if ct.starts_with("multipart/form-data") => {
-                        let boundary = multer::parse_boundary(content_type).map_err(|e| {
-                            debug!("Failed to parse multipart boundary: {}", e);
-                            Error::DecodeError(format!("Failed to parse multipart boundary: {e}"))
-                        })?;
-                        let mut multipart =
-                            multer::Multipart::new(body.into_data_stream(), boundary);
-
-                        while let Some(mut field) = multipart.next_field().await.map_err(|e| {
-                            debug!("Failed to read multipart field: {}", e);
-                            Error::ReadError(format!("Failed to read multipart field: {e}",))
-                        })? {
-                            let content_type = field
-                                .content_type()
-                                .map(|ct| ct.to_string())
-                                .unwrap_or_else(|| "application/octet-stream".to_string());
-                            if content_type == "application/json" {
-                                let name = field.name().map(|s| s.to_string());
-                                let bytes = field.bytes().await.map_err(|e| {
-                                    debug!("Failed to read JSON field bytes: {}", e);
-                                    Error::ReadError(format!(
-                                        "Failed to read JSON field bytes: {e}",
-                                    ))
-                                })?;
-                                debug!(
-                                    "JSON field bytes: {}",
-                                    String::from_utf8(bytes.to_vec()).unwrap()
-                                );
-                                let feeder = SliceJsonFeeder::new(&bytes);
-                                let value = parse_json(feeder)?;
-                                debug!("Parsed JSON field: {:#?}", value);
-                                let name = name.unwrap_or_default();
-                                if name.is_empty() {
-                                    merged_params =
-                                        value.merge_into(merged_params).map_err(|e| {
-                                            debug!("Failed to merge JSON field: {e:?}");
-                                            Error::DecodeError(format!(
-                                                "Failed to merge JSON field: {e:?}",
-                                            ))
-                                        })?;
-                                } else {
-                                    parser
-                                        .parse_nested_value(
-                                            &mut merged_params,
-                                            name.as_str(),
-                                            value,
-                                        )
-                                        .map_err(|e| {
-                                            Error::DecodeError(format!(
-                                                "Failed to parse JSON field: {}",
-                                                e
-                                            ))
-                                        })?;
-                                }
-
-                                debug!("Merged JSON field: {:#?}", merged_params);
-                                continue;
-                            }
-                            if let Some(name) = field.name() {
-                                let name = name.to_string();
-
-                                // Check if this is a file upload field
-                                if field.file_name().is_some() {
-                                    // Handle file upload
-                                    let temp_file = NamedTempFile::new().map_err(|e| {
-                                        Error::IOError(format!("Failed to create temp file: {e}",))
-                                    })?;
-                                    debug!("Created temp file at: {:?}", temp_file.path());
-
-                                    let mut file = tokio::fs::OpenOptions::new()
-                                        .write(true)
-                                        .open(temp_file.path())
-                                        .await
-                                        .map_err(|e| {
-                                            debug!("Failed to open temp file for writing: {}", e);
-                                            Error::IOError(
-                                                format!("Failed to open temp file: {e}",),
-                                            )
-                                        })?;
-
-                                    let mut total_bytes = 0;
-                                    while let Some(chunk) = field.chunk().await.map_err(|e| {
-                                        debug!("Failed to read multipart field chunk: {}", e);
-                                        Error::ReadError(format!(
-                                            "Failed to read multipart field chunk: {e}",
-                                        ))
-                                    })? {
-                                        total_bytes += chunk.len();
-                                        debug!("Writing chunk of size {} bytes", chunk.len());
-                                        tokio::io::copy(&mut &*chunk, &mut file).await.map_err(
-                                            |e| {
-                                                debug!("Failed to write chunk to temp file: {}", e);
-                                                Error::IOError(format!(
-                                                    "Failed to write to temp file: {e}",
-                                                ))
-                                            },
-                                        )?;
-                                    }
-
-                                    // Sync the file to disk
-                                    file.sync_all().await.map_err(|e| {
-                                        debug!("Failed to sync temp file: {}", e);
-                                        Error::IOError(format!("Failed to sync temp file: {e}",))
-                                    })?;
-
-                                    debug!("Total bytes written to file: {}", total_bytes);
-
-                                    let file = Value::UploadFile(UploadFile {
-                                        name: field.file_name().unwrap().to_string(),
-                                        content_type: field
-                                            .content_type()
-                                            .map(|ct| ct.to_string())
-                                            .unwrap_or_else(|| {
-                                                "application/octet-stream".to_string()
-                                            }),
-                                        temp_file_path: temp_file
-                                            .path()
-                                            .to_string_lossy()
-                                            .to_string(),
-                                    });
-                                    parser
-                                        .parse_nested_value(&mut merged_params, name.as_str(), file)
-                                        .map_err(|e| {
-                                            Error::DecodeError(format!(
-                                                "Failed to parse file upload field: {}",
-                                                e
-                                            ))
-                                        })?;
-
-                                    // Store the temp file
-                                    temp_files.push(temp_file);
-                                } else {
-                                    // Handle text field
-                                    let value = field.text().await.map_err(|e| {
-                                        debug!("Failed to read text field: {}", e);
-                                        Error::ReadError(format!("Failed to read text field: {e}",))
-                                    })?;
-                                    parser
-                                        .parse_nested_value(
-                                            &mut merged_params,
-                                            name.as_str(),
-                                            Value::xstr(value),
-                                        )
-                                        .map_err(|e| {
-                                            Error::DecodeError(format!(
-                                                "Failed to parse text field: {}",
-                                                e
-                                            ))
-                                        })?;
-                                }
-                            }
-                        }
-                    }
-                    ct => {
-                        debug!("Unhandled content type: {}", ct);
-                    }
-                }
+    /// The extraction of `Params` and the extractors built on it: merges the sources, runs
+    /// `prepare` on the merged params, deserializes them with `seed` and records the metrics
+    /// and audit of the request.
+    pub(crate) async fn extract_seed<S, Seed>(
+        req: Request,
+        state: &S,
+        seed: Seed,
+        collect: fn(HashMap<String, Value>, &Redactor) -> Option<Error>,
+        prepare: impl FnOnce(&mut HashMap<String, Value>, &ParamsMetadata) -> Result<(), Error>,
+    ) -> Result<Self, Rejection>
+    where
+        S: Send + Sync,
+        Seed: for<'de> DeserializeSeed<'de, Value = T> + Clone,
+    {
+        let config = ParamsConfig::of(&req);
+        let rejecter = Rejecter::of(&req);
+        let metrics = MetricsSlot::of(req.extensions());
+        let audit = AuditSlot::of(req.extensions());
+        let result = async {
+            let (mut merged_params, mut metadata) = extract_params(req, state).await?;
+            prepare(&mut merged_params, &metadata)?;
+            let snapshot = audit
+                .as_ref()
+                .map(|_| AuditSlot::snapshot(&merged_params, &metadata, &config));
+            let payload =
+                deserialize_params_seed(merged_params, &mut metadata, &config, seed, collect)
+                    .await?;
+            if let Some(metrics) = metrics {
+                metrics.record(&metadata);
             }
-        }
+            if let (Some(audit), Some(snapshot)) = (audit, snapshot) {
+                audit.record(snapshot);
+            }
+            Ok(Params(payload, metadata))
+        };
+        result.await.map_err(|e| rejecter.reject(e))
+    }
+}
+
+impl<T: DeserializeOwned> Params<T> {
+    /// `extract_seed` for `T: Deserialize`, reporting every error with
+    /// `ParamsConfig::collect_errors`.
+    pub(crate) async fn extract<S: Send + Sync>(
+        req: Request,
+        state: &S,
+        prepare: impl FnOnce(&mut HashMap<String, Value>, &ParamsMetadata) -> Result<(), Error>,
+    ) -> Result<Self, Rejection> {
+        let seed = DeserializeOwnedSeed(PhantomData);
+        Self::extract_seed(req, state, seed, collect_decode_errors::<T>, prepare).await
+    }
 
-        debug!("merged: {:?}", merged_params);
-        T::deserialize(Value::Object(merged_params))
-            .map_err(|e| Error::DecodeError(format!("Failed to deserialize parameters: {e}")))
-            .map(|payload| Params(payload, temp_files))
+    /// Extracts the params in a middleware and returns an equivalent request to pass on, so
+    /// e.g. an authorization layer can inspect them before the handler extracts them again.
+    ///
+    /// The body is buffered in memory, up to `SecurityPolicy::max_body_size` of `config`, and
+    /// replayed unchanged. `config` replaces the `ParamsConfig` extension for this extraction.
+    pub async fn peek(req: Request, config: &ParamsConfig) -> Result<(Self, Request), Error> {
+        let (parts, body) = req.into_parts();
+        let bytes = to_bytes(body, config.security.max_body_size)
+            .await
+            .map_err(|e| Error::ReadError(format!("Failed to read request body: {e}")))?;
+        let mut peeked = Request::from_parts(parts.clone(), Body::from(bytes.clone()));
+        peeked.extensions_mut().insert(config.clone());
+        let params = Self::from_request(peeked, &()).await.map_err(Error::from)?;
+        Ok((params, Request::from_parts(parts, Body::from(bytes))))
     }
 }
 
-#[cfg(test)]
-mod tests {
-    use std::collections::HashMap;
+/// `Params` rejecting with a user defined `R` converted from the `Error`, like axum-extra's
+/// `WithRejection`, so every extractor of an app can answer with the same error type.
+///
+/// `ParamsConfig::on_rejection` and `ParamsConfig::problem_details` don't apply, `R` builds
+/// the response.
+pub struct ParamsWithRejection<T, R>(pub T, pub ParamsMetadata, pub PhantomData<R>);
 
-    use super::*;
-    use ::serde::{Deserialize, Serialize};
-    use axum::{
-        Json, Router,
-        body::Body,
-        extract::{FromRequest, Request},
-        http::StatusCode,
-        http::{self, HeaderValue},
-        response::IntoResponse,
-        routing::{get, post},
-    };
-    use axum_test::{
-        TestServer,
-        multipart::{MultipartForm, Part},
-    };
-    use log::debug;
-    use serde_json::json;
-    use tokio::io::AsyncReadExt;
+#[cfg_attr(axum07, axum::async_trait)]
+impl<T, R, S> FromRequest<S> for ParamsWithRejection<T, R>
+where
+    T: DeserializeOwned,
+    R: From<Error> + IntoResponse,
+    S: Send + Sync,
+{
+    type Rejection = R;
 
-    pub fn setup() {
-        let _ = env_logger::builder().is_test(true).try_init();
+    async fn from_request(req: Request, state: &S) -> Result<Self, Self::Rejection> {
+        match Params::from_request(req, state).await {
+            Ok(Params(payload, metadata)) => {
+                Ok(ParamsWithRejection(payload, metadata, PhantomData))
+            }
+            Err(rejection) => Err(R::from(rejection.into_error())),
+        }
     }
+}
 
-    #[derive(Debug, Deserialize, Serialize, PartialEq)]
-    struct TestParams {
-        id: i32,
-        name: String,
-        #[serde(default)]
-        extra: Option<String>,
+impl<T: fmt::Debug, R> fmt::Debug for ParamsWithRejection<T, R> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_tuple("ParamsWithRejection")
+            .field(&self.0)
+            .field(&self.1)
+            .finish()
     }
+}
 
-    #[derive(Debug, Serialize, Deserialize)]
-    struct UploadFileResponse {
-        name: String,
-        content_type: String,
-        content: String,
-    }
+/// Like `Params`, but only from the path and query string, the body is left to other
+/// extractors. Usable with `from_request_parts` in middleware too.
+///
+/// It is also the extractor for WebSocket handshakes, which are bodiless `GET` requests. It
+/// can sit next to `WebSocketUpgrade` in any order, `Params` only as the last argument, and
+/// invalid params reject the handshake before the connection is upgraded:
+///
+/// ```ignore
+/// #[derive(Deserialize)]
+/// struct Join {
+///     room_id: u64,
+///     token: String,
+/// }
+///
+/// // GET /rooms/{room_id}/ws?token=...
+/// async fn join(QueryPathParams(join, _): QueryPathParams<Join>, ws: WebSocketUpgrade) -> Response {
+///     ws.on_upgrade(move |socket| chat(socket, join))
+/// }
+/// ```
+#[derive(Debug, Default)]
+pub struct QueryPathParams<T>(pub T, pub ParamsMetadata);
 
-    #[axum::debug_handler]
-    async fn test_params_handler(Params(test, _): Params<TestParams>) -> impl IntoResponse {
-        (StatusCode::OK, serde_json::to_string(&test).unwrap())
+#[cfg_attr(axum07, axum::async_trait)]
+impl<T, S> FromRequestParts<S> for QueryPathParams<T>
+where
+    T: DeserializeOwned,
+    S: Send + Sync,
+{
+    type Rejection = Rejection;
+
+    async fn from_request_parts(parts: &mut Parts, state: &S) -> Result<Self, Self::Rejection> {
+        let rejecter = Rejecter::of_parts(parts);
+        let metrics = MetricsSlot::of(&parts.extensions);
+        let audit = AuditSlot::of(&parts.extensions);
+        let result = async {
+            let started = Instant::now();
+            let config = parts
+                .extensions
+                .get::<ParamsConfig>()
+                .cloned()
+                .unwrap_or_default();
+            let mut params = ParamsParser::new(config.clone());
+            extract_parts_params(parts, state, &mut params).await?;
+            inject_extensions(&parts.extensions, &mut params)?;
+            let (merged_params, mut metadata) = params.into_raw();
+            metadata.timings.parts = started.elapsed();
+            let snapshot = audit
+                .as_ref()
+                .map(|_| AuditSlot::snapshot(&merged_params, &metadata, &config));
+            let payload = deserialize_params(merged_params, &mut metadata, &config).await?;
+            if let Some(metrics) = metrics {
+                metrics.record(&metadata);
+            }
+            if let (Some(audit), Some(snapshot)) = (audit, snapshot) {
+                audit.record(snapshot);
+            }
+            Ok(QueryPathParams(payload, metadata))
+        };
+        result.await.map_err(|e| rejecter.reject(e))
     }
+}
 
-    #[derive(Debug, Deserialize)]
-    struct FileUploadParams {
-        title: String,
-        description: Option<String>,
-        file: UploadFile,
-    }
+/// The merged parameters before deserialization, read them with `ParamsReaderExt`.
+#[derive(Debug, Default)]
+pub struct RawParams(pub HashMap<String, Value>, pub ParamsMetadata);
 
-    #[axum::debug_handler]
-    async fn file_upload_handler(Params(upload, _): Params<FileUploadParams>) -> impl IntoResponse {
-        let mut temp_file = upload.file.open().await.unwrap();
-        debug!(
-            "Reading file from: {:?}",
-            temp_file.metadata().await.unwrap()
-        );
-        let mut content = String::new();
-        temp_file.read_to_string(&mut content).await.unwrap();
-        debug!("Read {} bytes from file", content.len());
-        debug!("File content: {:?}", content);
+#[cfg_attr(axum07, axum::async_trait)]
+impl<S> FromRequest<S> for RawParams
+where
+    S: Send + Sync,
+{
+    type Rejection = Rejection;
 
-        let response = json!({
-            "title": upload.title,
-            "description": upload.description,
-            "file_name": upload.file.name,
-            "file_content": content,
-        });
-        (StatusCode::OK, serde_json::to_string(&response).unwrap())
+    async fn from_request(req: Request, state: &S) -> Result<Self, Self::Rejection> {
+        let config = ParamsConfig::of(&req);
+        let rejecter = Rejecter::of(&req);
+        let metrics = MetricsSlot::of(req.extensions());
+        let audit = AuditSlot::of(req.extensions());
+        match extract_params(req, state).await {
+            Ok((merged_params, metadata)) => {
+                if let Some(metrics) = metrics {
+                    metrics.record(&metadata);
+                }
+                if let Some(audit) = audit {
+                    audit.record(AuditSlot::snapshot(&merged_params, &metadata, &config));
+                }
+                Ok(RawParams(merged_params, metadata))
+            }
+            Err(e) => Err(rejecter.reject(e)),
+        }
     }
+}
 
-    #[derive(Debug, Serialize, Deserialize)]
-    struct Attachment {
-        file: UploadFile,
-        name: String,
+impl ParamsReader for RawParams {
+    fn get_value<'a>(&'a self, path: &'a str) -> BoxFuture<'a, Result<Option<Value>, Error>> {
+        self.0.get_value(path)
     }
+}
 
-    #[derive(Debug, Serialize, Deserialize)]
-    struct CreatePostParams {
-        title: String,
-        content: String,
-        tags: Vec<String>,
-        cover: UploadFile,
-        attachments: Vec<Attachment>,
-    }
+pub(crate) async fn deserialize_params<T: DeserializeOwned>(
+    merged_params: HashMap<String, Value>,
+    metadata: &mut ParamsMetadata,
+    config: &ParamsConfig,
+) -> Result<T, Error> {
+    deserialize_params_seed(
+        merged_params,
+        metadata,
+        config,
+        DeserializeOwnedSeed(PhantomData),
+        collect_decode_errors::<T>,
+    )
+    .await
+}
 
-    #[derive(Debug, Serialize, Deserialize)]
-    struct AttachmentResponse {
-        name: String,
-        content_type: String,
-        content: String,
-    }
+/// The seed of `T: DeserializeOwned`, `Send` and `Sync` whatever `T` is, unlike
+/// `PhantomData<T>`, so extraction futures holding it are `Send`.
+struct DeserializeOwnedSeed<T>(PhantomData<fn() -> T>);
 
-    #[derive(Debug, Serialize, Deserialize)]
-    struct CreatePostResponse {
-        title: String,
-        content: String,
-        tags: Vec<String>,
-        cover: UploadFileResponse,
-        attachments: Vec<AttachmentResponse>,
+impl<T> Clone for DeserializeOwnedSeed<T> {
+    fn clone(&self) -> Self {
+        DeserializeOwnedSeed(PhantomData)
     }
+}
 
-    #[axum::debug_handler]
-    async fn test_nested_params_handler(
-        Params(post, _): Params<CreatePostParams>,
-    ) -> Result<Json<CreatePostResponse>, Error> {
-        let mut cover_file = post.cover.open().await.unwrap();
-        let mut cover_content = String::new();
-        cover_file.read_to_string(&mut cover_content).await.unwrap();
+impl<'de, T: DeserializeOwned> DeserializeSeed<'de> for DeserializeOwnedSeed<T> {
+    type Value = T;
 
-        let response = CreatePostResponse {
-            title: post.title,
-            content: post.content,
-            tags: post.tags,
-            cover: UploadFileResponse {
-                name: post.cover.name,
-                content_type: post.cover.content_type,
-                content: cover_content,
-            },
-            attachments: Vec::new(),
-        };
+    fn deserialize<D: Deserializer<'de>>(self, deserializer: D) -> Result<T, D::Error> {
+        T::deserialize(deserializer)
+    }
+}
 
-        let attachments =
-            futures_util::future::join_all(post.attachments.into_iter().map(|a| async {
-                let mut file = a.file.open().await.unwrap();
-                let mut content = String::new();
-                file.read_to_string(&mut content).await.unwrap();
+/// Like `deserialize_params` with `seed`, `collect` reports every error for
+/// `ParamsConfig::collect_errors`.
+async fn deserialize_params_seed<T, Seed>(
+    merged_params: HashMap<String, Value>,
+    metadata: &mut ParamsMetadata,
+    config: &ParamsConfig,
+    seed: Seed,
+    collect: fn(HashMap<String, Value>, &Redactor) -> Option<Error>,
+) -> Result<T, Error>
+where
+    Seed: for<'de> DeserializeSeed<'de, Value = T> + Clone,
+{
+    let span = debug_span!(
+        "params.deserialize",
+        target = std::any::type_name::<T>(),
+        keys = merged_params.len(),
+    );
+    deserialize_payload(merged_params, metadata, config, seed, collect)
+        .instrument(span)
+        .await
+}
 
-                AttachmentResponse {
-                    name: a.name,
-                    content_type: a.file.content_type,
-                    content,
+async fn deserialize_payload<T, Seed>(
+    mut merged_params: HashMap<String, Value>,
+    metadata: &mut ParamsMetadata,
+    config: &ParamsConfig,
+    seed: Seed,
+    collect: fn(HashMap<String, Value>, &Redactor) -> Option<Error>,
+) -> Result<T, Error>
+where
+    Seed: for<'de> DeserializeSeed<'de, Value = T> + Clone,
+{
+    if config.trim_strings {
+        trim_strings(&mut merged_params);
+    }
+    let coercions = config.coercions;
+    let temp_files = metadata.temp_file_paths();
+    // Deserialization errors can quote values, e.g. `invalid type: string "hunter2"`
+    let redactor = Redactor::new(&config.security, &merged_params);
+    let decode_error =
+        |e| Error::DecodeError(redactor.redact(format!("Failed to deserialize parameters: {e}")));
+    // Deserialized again from the start, `None` unless collecting errors
+    let retry = |params: &HashMap<String, Value>| config.collect_errors.then(|| params.clone());
+    let decode_errors = |e, params: Option<HashMap<String, Value>>| {
+        params
+            .and_then(|params| {
+                with_coercions(coercions, || {
+                    with_temp_files(temp_files.clone(), || collect(params, &redactor))
+                })
+            })
+            .unwrap_or_else(|| decode_error(e))
+    };
+    let mut sent = metadata.sent_keys();
+    if config.trim_strings {
+        sent.iter_mut()
+            .filter_map(|(_, value)| value.as_mut())
+            .for_each(Value::trim_strings);
+    }
+    let mut ignored = vec![];
+    let deserialize = |params: HashMap<String, Value>, ignored: &mut Vec<String>| {
+        ignored.clear();
+        with_coercions(coercions, || {
+            with_temp_files(temp_files.clone(), || {
+                let seed = seed.clone();
+                let root = Root::new(params, sent.clone());
+                if config.deny_unknown_fields {
+                    let mut on_ignored = |path: serde_ignored::Path| ignored.push(param_key(&path));
+                    seed.deserialize(serde_ignored::Deserializer::new(root, &mut on_ignored))
+                } else {
+                    seed.deserialize(root)
                 }
-            }))
-            .await;
+            })
+        })
+    };
+    if !merged_params.values().any(has_upload_file) {
+        let params = retry(&merged_params);
+        let payload = timed(&mut metadata.timings.deserialize, || {
+            deserialize(merged_params, &mut ignored)
+        })
+        .map_err(|e| decode_errors(e, params))?;
+        check_unknown(ignored, metadata)?;
+        return Ok(payload);
+    }
 
-        let mut response = response;
-        response.attachments = attachments;
+    // `Uploaded` fields are converted between two passes: the first one collects the
+    // conversions and the second one deserializes again with their results.
+    let pending = {
+        let (result, pending) = timed(&mut metadata.timings.deserialize, || {
+            collect_uploads(|| deserialize(merged_params.clone(), &mut ignored))
+        });
+        let result = result.map_err(|e| decode_errors(e, retry(&merged_params)))?;
+        if pending.is_empty() {
+            check_unknown(ignored, metadata)?;
+            return Ok(result);
+        }
+        pending
+    };
 
-        Ok(Json(response))
+    let mut ready = HashMap::new();
+    for upload in pending {
+        if !metadata.owns_temp_file(&upload.file.temp_file_path) {
+            return Err(Error::DecodeError(format!(
+                "Parameter `{}` is not an uploaded file",
+                upload.file.name
+            )));
+        }
+        let name = upload.file.name.clone();
+        let (key, value) = timed_async(&mut metadata.timings.uploads, upload.convert())
+            .await
+            .map_err(|e| {
+                Error::DecodeError(format!("Failed to process uploaded file `{name}`: {e}"))
+            })?;
+        ready.insert(key, value);
     }
 
-    #[tokio::test]
-    async fn test_path_params() {
-        let app = Router::new().route("/users/{id}", get(test_params_handler));
-        let server = TestServer::new(app).unwrap();
-
-        let response = server
-            .get("/users/123")
-            .add_query_params(&[("name", "test")])
-            .await;
-        println!("response: {:?}", response);
-        assert_eq!(response.status_code(), StatusCode::OK);
+    let payload = timed(&mut metadata.timings.deserialize, || {
+        resolve_uploads(ready, || deserialize(merged_params, &mut ignored))
+    })
+    .map_err(decode_error)?;
+    check_unknown(ignored, metadata)?;
+    Ok(payload)
+}
 
-        let body = response.text();
-        let params: TestParams = serde_json::from_str(&body).unwrap();
-        assert_eq!(params.id, 123);
-        assert_eq!(params.name, "test");
-        assert_eq!(params.extra, None);
+/// `Error::DecodeErrors` with every deserialization error of `T`, `None` if it can't tell them
+/// apart.
+fn collect_decode_errors<T: DeserializeOwned>(
+    params: HashMap<String, Value>,
+    redactor: &Redactor,
+) -> Option<Error> {
+    let fields = recovery::collect_errors::<T>(params)
+        .into_iter()
+        .map(|(key, message)| InvalidField {
+            key,
+            message: redactor.redact(message),
+        })
+        .collect::<Vec<_>>();
+    if fields.is_empty() {
+        return None;
     }
+    let message = fields
+        .iter()
+        .map(|field| match field.key.as_str() {
+            "" => field.message.clone(),
+            key => format!("`{key}`: {}", field.message),
+        })
+        .collect::<Vec<_>>()
+        .join(", ");
+    Some(Error::DecodeErrors(
+        format!("Failed to deserialize parameters: {message}"),
+        fields,
+    ))
+}
 
-    #[tokio::test]
-    async fn test_json_body() {
-        let app = Router::new().route("/api/test", post(test_params_handler));
-        let server = TestServer::new(app).unwrap();
-
-        let json_data = json!({
-            "id": 123,
-            "name": "test",
-            "extra": "data"
-        });
+/// The bracketed key of a value ignored by the target type, e.g. `user[roles][0]`.
+fn param_key(path: &serde_ignored::Path) -> String {
+    use serde_ignored::Path;
 
-        let response = server.post("/api/test").json(&json_data).await;
-        assert_eq!(response.status_code(), StatusCode::OK);
+    let nest = |parent: &Path, key: &str| match param_key(parent) {
+        parent if parent.is_empty() => key.to_string(),
+        parent => format!("{parent}[{key}]"),
+    };
+    match path {
+        Path::Root => String::new(),
+        Path::Seq { parent, index } => nest(parent, &index.to_string()),
+        Path::Map { parent, key } => nest(parent, key),
+        Path::Some { parent }
+        | Path::NewtypeStruct { parent }
+        | Path::NewtypeVariant { parent } => param_key(parent),
+    }
+}
 
-        let body = response.text();
-        let params: TestParams = serde_json::from_str(&body).unwrap();
-        assert_eq!(params.id, 123);
-        assert_eq!(params.name, "test");
-        assert_eq!(params.extra, Some("data".to_string()));
+/// Rejects the keys sent by the client that the target type ignored.
+fn check_unknown(mut ignored: Vec<String>, metadata: &ParamsMetadata) -> Result<(), Error> {
+    ignored.sort();
+    let fields = ignored
+        .into_iter()
+        .map(|key| {
+            let top_level = key.split('[').next().unwrap_or_default();
+            let source = metadata.sources.get(top_level).copied();
+            UnknownField { key, source }
+        })
+        .filter(|field| !matches!(field.source, Some(Source::Path | Source::Extension)))
+        .collect::<Vec<_>>();
+    if fields.is_empty() {
+        return Ok(());
     }
+    let keys = fields
+        .iter()
+        .map(|field| match field.source {
+            Some(source) => format!("`{}` from {source}", field.key),
+            None => format!("`{}`", field.key),
+        })
+        .collect::<Vec<_>>()
+        .join(", ");
+    Err(Error::UnknownFields(
+        format!("Unknown parameters: {keys}"),
+        fields,
+    ))
+}
 
-    #[tokio::test]
-    async fn test_form_data() {
-        let app = Router::new().route("/api/test", post(test_params_handler));
-        let server = TestServer::new(app).unwrap();
+fn has_upload_file(value: &Value) -> bool {
+    match value {
+        Value::UploadFile(_) => true,
+        Value::Object(map) => map.values().any(has_upload_file),
+        Value::Array(vec) => vec.iter().any(has_upload_file),
+        _ => false,
+    }
+}
 
-        let response = server
-            .post("/api/test")
-            .form(&[("id", "123"), ("name", "test"), ("extra", "form_data")])
-            .await;
-        assert_eq!(response.status_code(), StatusCode::OK);
+pub(crate) async fn extract_params<S: Send + Sync>(
+    req: Request,
+    state: &S,
+) -> Result<(HashMap<String, Value>, ParamsMetadata), Error> {
+    if let Some(cache) = req.extensions().get::<ParamsCache>() {
+        debug!("params from cache");
+        return Ok(cache.reuse());
+    }
+    let span = debug_span!(
+        "params.parse",
+        method = %req.method(),
+        content_type = ?req.headers().get(http::header::CONTENT_TYPE),
+        bytes = field::Empty,
+    );
+    parse_request(req, state).instrument(span).await
+}
 
-        let body = response.text();
-        let params: TestParams = serde_json::from_str(&body).unwrap();
+async fn parse_request<S: Send + Sync>(
+    req: Request,
+    state: &S,
+) -> Result<(HashMap<String, Value>, ParamsMetadata), Error> {
+    let is_get_or_head = req.method() == http::Method::GET || req.method() == http::Method::HEAD;
+    let started = Instant::now();
+    let (mut parts, body) = req.into_parts();
+
+    let config = parts
+        .extensions
+        .get::<ParamsConfig>()
+        .cloned()
+        .unwrap_or_default();
+    let (body, capture) = match config.capture_body {
+        Some(max_size) => {
+            let (body, capture) = BodyCapture::wrap(body, max_size);
+            (body, Some(capture))
+        }
+        None => (body, None),
+    };
+    let mut params = ParamsParser::new(config);
+    params.metadata.body_length = content_length(&parts.headers);
+    extract_parts_params(&mut parts, state, &mut params).await?;
+
+    let ParamsParser {
+        config,
+        parser,
+        params: merged_params,
+        metadata,
+    } = &mut params;
+    let policy = &config.security;
+    let timings = &mut metadata.timings;
+    timings.parts = started.elapsed();
+    let read_body = if config.skip_body {
+        false
+    } else if is_get_or_head {
+        match config.body_on_get {
+            BodyOnGet::Ignore => false,
+            BodyOnGet::Parse => true,
+            BodyOnGet::Reject if body.is_end_stream() => false,
+            BodyOnGet::Reject => {
+                return Err(Error::DecodeError(format!(
+                    "Request body is not allowed for {} requests",
+                    parts.method
+                )));
+            }
+        }
+    } else {
+        true
+    };
+    let content_type = parts
+        .headers
+        .get(http::header::CONTENT_TYPE)
+        .filter(|_| read_body);
+    if let Some(content_type) = content_type.and_then(|ct| ct.to_str().ok()) {
+        check_content_length(&parts.headers, content_type, config)?;
+    }
+    let _permit = match (content_type, &config.concurrency) {
+        (Some(_), Some(limit)) => Some(timed_async(&mut timings.queue, limit.acquire()).await?),
+        _ => None,
+    };
+    let body = match content_type {
+        Some(_) => {
+            timed_async(
+                &mut timings.body_read,
+                decode_body(&parts.headers, body, policy),
+            )
+            .await?
+        }
+        None => body,
+    };
+    if let Some(content_type) = content_type.and_then(|ct| ct.to_str().ok()) {
+        if !policy.allows_content_type(content_type) {
+            return Err(Error::DecodeError(format!(
+                "Unsupported content type: {content_type}"
+            )));
+        }
+        let length = HttpBody::size_hint(&body).exact();
+        match BodyKind::of(content_type, config) {
+            Some(BodyKind::Json) if !length.is_some_and(|len| config.parses_blocking(len)) => {
+                // Parsed while it is read, so the limit stops oversized bodies early
+                let mut body = body;
+                let mut json = JsonPushParser::new(policy);
+                let mut read = 0;
+                while let Some(chunk) = timed_async(&mut timings.body_read, next_chunk(&mut body))
+                    .await
+                    .map_err(|e| {
+                        Error::DecodeError(format!("Failed to read JSON request body: {}", e))
+                    })?
+                {
+                    read += chunk.len();
+                    if read > policy.max_body_size {
+                        return Err(Error::PayloadTooLarge(
+                            "Failed to read JSON request body: length limit exceeded".to_string(),
+                        ));
+                    }
+                    timed(&mut timings.json_parse, || json.push(&chunk))?;
+                }
+                let value = timed(&mut timings.json_parse, || json.finish())?;
+                params.merge_json(value, read)?;
+            }
+            Some(BodyKind::Multipart) => {
+                let boundary = multer::parse_boundary(content_type).map_err(|e| {
+                    Error::DecodeError(format!("Failed to parse multipart boundary: {e}"))
+                })?;
+                params
+                    .add_multipart(body.into_data_stream(), boundary)
+                    .await?;
+            }
+            Some(kind) => {
+                let bytes = timed_async(
+                    &mut timings.body_read,
+                    buffer_body(body, policy.max_body_size, kind.name()),
+                )
+                .await?;
+                params.add_body(content_type, bytes).await?;
+            }
+            None => match &config.raw_body {
+                Some(key) => {
+                    let mut body = body;
+                    let read = BodyRead {
+                        bytes: 0,
+                        total: metadata.body_length,
+                    };
+                    let mut spool = Spool::new(config, read, timings).await?;
+                    let copied = async {
+                        while let Some(chunk) =
+                            timed_async(&mut timings.body_read, next_chunk(&mut body))
+                                .await
+                                .map_err(|e| {
+                                    Error::ReadError(format!("Failed to read request body: {e}"))
+                                })?
+                        {
+                            spool.write(&chunk, key, policy, timings).await?;
+                        }
+                        Ok(())
+                    }
+                    .await;
+                    if let Err(e) = copied {
+                        spool.abort().await;
+                        return Err(e);
+                    }
+                    metadata.sizes.body = spool.bytes;
+                    let (file, temp_file) = spool
+                        .finish(key.clone(), content_type.to_string(), timings)
+                        .await?;
+                    parser
+                        .parse_nested_value(merged_params, key.as_str(), Value::UploadFile(file))
+                        .map_err(|e| Error::params("Failed to parse request body", e))?;
+                    record_key(&mut metadata.sources, parser, key, Source::Raw);
+                    metadata.temp_files.push(temp_file);
+                }
+                None => {
+                    debug!(content_type, "unhandled content type");
+                }
+            },
+        }
+    }
+
+    params.metadata.raw_body = capture.and_then(|capture| capture.take());
+    inject_extensions(&parts.extensions, &mut params)?;
+    Ok(params.into_raw())
+}
+
+/// Parses the form-urlencoded `bytes` into `params`, returns their top level keys.
+pub(crate) fn parse_form(
+    content_type: &str,
+    bytes: &[u8],
+    parser: &QueryParser,
+    strict_utf8: bool,
+    params: &mut HashMap<String, Value>,
+) -> Result<Vec<SentKey>, Error> {
+    let charset = form_charset(content_type, bytes, parser.delimiters())?;
+    let form = if charset == encoding_rs::UTF_8 {
+        decode_utf8(bytes, strict_utf8)
+            .map_err(|e| Error::DecodeError(format!("Failed to parse form-urlencoded body: {e}")))?
+    } else {
+        let delimiters = parser.delimiters();
+        Cow::Owned(transcode_form(bytes, charset, delimiters, strict_utf8)?)
+    };
+    let mut sent = vec![];
+    parser
+        .parse_nested_query_with(params, form.as_ref(), |top, key, value| {
+            sent.push(SentKey::new(Source::Form, top, key, value))
+        })
+        .map_err(|e| Error::params("Failed to parse form-urlencoded body", e))?;
+    Ok(sent)
+}
+
+/// Runs `parse` on the blocking thread pool, see `ParamsConfig::blocking_parse_threshold`.
+pub(crate) async fn parse_blocking<R: Send + 'static>(
+    parse: impl FnOnce() -> R + Send + 'static,
+) -> Result<R, Error> {
+    tokio::task::spawn_blocking(parse)
+        .await
+        .map_err(|e| Error::io("Failed to parse request body", e.into()))
+}
+
+/// Merges the multipart part `index` into `merged_params`, returns the number of bytes read.
+pub(crate) async fn extract_part(
+    mut field: multer::Field<'_>,
+    index: usize,
+    parts: &mut PartNames,
+    config: &ParamsConfig,
+    parser: &QueryParser,
+    merged_params: &mut HashMap<String, Value>,
+    metadata: &mut ParamsMetadata,
+) -> Result<usize, Error> {
+    let policy = &config.security;
+    let timings = &mut metadata.timings;
+    let content_type = field
+        .content_type()
+        .map(|ct| ct.to_string())
+        .unwrap_or_else(|| "application/octet-stream".to_string());
+    if content_type == "application/json" {
+        let name = field.name().map(|s| s.to_string());
+        let mut json = JsonPushParser::new(policy);
+        let mut read = 0;
+        while let Some(chunk) = timed_async(&mut timings.body_read, field.chunk())
+            .await
+            .map_err(|e| Error::ReadError(format!("Failed to read JSON field bytes: {e}",)))?
+        {
+            read += chunk.len();
+            if read > policy.max_body_size {
+                return Err(Error::ReadError(format!(
+                    "Failed to read JSON field bytes: field exceeds the {} bytes limit",
+                    policy.max_body_size
+                )));
+            }
+            timed(&mut timings.json_parse, || json.push(&chunk))?;
+        }
+        Span::current().record("bytes", read);
+        let value = timed(&mut timings.json_parse, || json.finish())?;
+        let name = name.unwrap_or_default();
+        add_params_size(metadata, policy, name.len() + value.byte_size())?;
+        // Bracketed names deep merge into the fields sent before them, a part repeating a
+        // name replaces it like other duplicates
+        let merge = name.contains('[') && !parts.contains(&name);
+        if !parts.keep(&name, index, PartKind::Json, config.duplicate_parts)? {
+            debug!("skipped duplicate part");
+            return Ok(read);
+        }
+        if name.is_empty() {
+            let keys = top_level_keys(&value);
+            debug!(?keys, "parsed JSON part");
+            config
+                .merge
+                .merge_into(
+                    merged_params,
+                    value,
+                    Source::Multipart,
+                    &metadata.sources,
+                    &mut metadata.coercions,
+                )
+                .map_err(|e| Error::DecodeError(format!("Failed to merge JSON field: {e}",)))?;
+            record_keys(&mut metadata.sources, keys, Source::Multipart);
+        } else {
+            // A JSON array sent to `attachments[]` appends its elements, like one part each
+            let values = match value {
+                Value::Array(values) if name.ends_with("[]") => values,
+                value => vec![value],
+            };
+            for value in values {
+                let inserted = if merge {
+                    parser.merge_nested_value(merged_params, name.as_str(), value)
+                } else {
+                    parser.parse_nested_value(merged_params, name.as_str(), value)
+                };
+                inserted.map_err(|e| Error::params("Failed to parse JSON field", e))?;
+            }
+            record_key(&mut metadata.sources, parser, &name, Source::Multipart);
+        }
+        return Ok(read);
+    }
+    let Some(name) = field.name() else {
+        return Ok(0);
+    };
+    let name = name.to_string();
+
+    let kind = match field.file_name() {
+        // Browsers send untouched file inputs with an empty name and body, like Rack the
+        // part is dropped so the field reads as absent
+        Some("") => {
+            debug!("skipped empty file part");
+            return Ok(0);
+        }
+        Some(_) => PartKind::File,
+        None => PartKind::Text,
+    };
+    if !parts.keep(&name, index, kind, config.duplicate_parts)? {
+        debug!("skipped duplicate part");
+        return Ok(0);
+    }
+
+    // Check if this is a file upload field
+    if kind == PartKind::File {
+        let read = BodyRead {
+            bytes: metadata.sizes.body,
+            total: metadata.body_length,
+        };
+        let mut spool = Spool::new(config, read, timings).await?;
+        let copied = async {
+            while let Some(chunk) = timed_async(&mut timings.body_read, field.chunk())
+                .await
+                .map_err(|e| {
+                    Error::ReadError(format!("Failed to read multipart field chunk: {e}",))
+                })?
+            {
+                spool.write(&chunk, &name, policy, timings).await?;
+            }
+            Ok(())
+        }
+        .await;
+        if let Err(e) = copied {
+            spool.abort().await;
+            return Err(e);
+        }
+        let total_bytes = spool.bytes;
+        let file_name = match field.file_name().unwrap() {
+            file_name if config.sanitize_file_names => sanitize_file_name(file_name),
+            file_name => file_name.to_string(),
+        };
+        let (mut file, temp_file) = spool.finish(file_name, content_type, timings).await?;
+        metadata.temp_files.push(temp_file);
+        let size = name.len() + file.name.len() + file.content_type.len();
+        add_params_size(metadata, policy, size)?;
+        file.part_index = index - 1;
+        file.headers = field
+            .headers()
+            .iter()
+            .map(|(key, value)| {
+                let value = String::from_utf8_lossy(value.as_bytes()).into_owned();
+                (key.to_string(), value)
+            })
+            .collect();
+        parser
+            .parse_nested_value(merged_params, name.as_str(), Value::UploadFile(file))
+            .map_err(|e| Error::params("Failed to parse file upload field", e))?;
+
+        record_key(&mut metadata.sources, parser, &name, Source::Multipart);
+        Ok(total_bytes)
+    } else {
+        // Handle text field
+        let bytes = timed_async(
+            &mut timings.body_read,
+            read_field(&mut field, &name, policy.max_text_field_size),
+        )
+        .await?;
+        Span::current().record("bytes", bytes.len());
+        let value = decode_utf8(&bytes, policy.strict_utf8)
+            .map_err(|e| Error::DecodeError(format!("Failed to parse text field: {e}")))?
+            .into_owned();
+        add_params_size(metadata, policy, name.len() + value.len())?;
+        parser
+            .parse_nested_value(merged_params, name.as_str(), Value::xstr(value))
+            .map_err(|e| Error::params("Failed to parse text field", e))?;
+        record_key(&mut metadata.sources, parser, &name, Source::Multipart);
+        Ok(bytes.len())
+    }
+}
+
+/// Counts the `bytes` a part adds to the merged params against
+/// `SecurityPolicy::max_params_size`.
+fn add_params_size(
+    metadata: &mut ParamsMetadata,
+    policy: &SecurityPolicy,
+    bytes: usize,
+) -> Result<(), Error> {
+    metadata.sizes.params += bytes;
+    check_params_size(metadata.sizes.params, policy)
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum PartKind {
+    Text,
+    Json,
+    File,
+}
+
+impl PartKind {
+    fn as_str(self) -> &'static str {
+        match self {
+            PartKind::Text => "text",
+            PartKind::Json => "JSON",
+            PartKind::File => "file",
+        }
+    }
+}
+
+/// The last part index and kind of each multipart field name.
+#[derive(Default)]
+pub(crate) struct PartNames(HashMap<String, (usize, PartKind)>);
+
+impl PartNames {
+    fn contains(&self, name: &str) -> bool {
+        self.0.contains_key(name)
+    }
+
+    /// Whether the part `index` named `name` is used, parts of different kinds sharing a name
+    /// are resolved by `duplicates`. Names with `[]` append and never conflict.
+    fn keep(
+        &mut self,
+        name: &str,
+        index: usize,
+        kind: PartKind,
+        duplicates: DuplicateParts,
+    ) -> Result<bool, Error> {
+        if name.is_empty() || name.contains("[]") {
+            return Ok(true);
+        }
+        match self.0.get(name) {
+            Some(&(previous, previous_kind)) if previous_kind != kind => match duplicates {
+                DuplicateParts::Reject => Err(Error::DecodeError(format!(
+                    "Multipart field `{name}` is sent as {} in part {previous} and as {} in part {index}",
+                    previous_kind.as_str(),
+                    kind.as_str()
+                ))),
+                DuplicateParts::PreferStructured if kind == PartKind::Text => Ok(false),
+                DuplicateParts::PreferStructured => {
+                    self.0.insert(name.to_string(), (index, kind));
+                    Ok(true)
+                }
+            },
+            _ => {
+                self.0.insert(name.to_string(), (index, kind));
+                Ok(true)
+            }
+        }
+    }
+}
+
+const DEFAULT_UPLOAD_BUFFER_SIZE: usize = 64 * 1024;
+
+/// The bytes of the body read before an upload and its `Content-Length`, see
+/// `UploadProgress`.
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct BodyRead {
+    pub(crate) bytes: usize,
+    pub(crate) total: Option<u64>,
+}
+
+/// An uploaded file being written to a temp file.
+struct Spool {
+    temp_file: NamedTempFile,
+    file: BufWriter<tokio::fs::File>,
+    sync: bool,
+    hasher: UploadHasher,
+    bytes: usize,
+    read: BodyRead,
+    on_progress: Option<OnUploadProgress>,
+}
+
+impl Spool {
+    async fn new(
+        config: &ParamsConfig,
+        read: BodyRead,
+        timings: &mut Timings,
+    ) -> Result<Self, Error> {
+        let started = Instant::now();
+        let temp_file = config
+            .temp_file()
+            .map_err(|e| Error::io("Failed to create temp file", e))?;
+
+        let file = tokio::fs::OpenOptions::new()
+            .write(true)
+            .open(temp_file.path())
+            .await
+            .map_err(|e| Error::io("Failed to open temp file", e))?;
+        timings.multipart_spool += started.elapsed();
+        let buffer_size = config
+            .upload_buffer_size
+            .unwrap_or(DEFAULT_UPLOAD_BUFFER_SIZE);
+        Ok(Spool {
+            temp_file,
+            file: BufWriter::with_capacity(buffer_size, file),
+            sync: config.sync_uploads,
+            hasher: UploadHasher::new(config),
+            bytes: 0,
+            read,
+            on_progress: config.on_upload_progress.clone(),
+        })
+    }
+
+    /// Appends `chunk` to the upload `name`, limited to `SecurityPolicy::max_file_size`, after
+    /// reporting it to `ParamsConfig::on_upload_progress`.
+    async fn write(
+        &mut self,
+        chunk: &[u8],
+        name: &str,
+        policy: &SecurityPolicy,
+        timings: &mut Timings,
+    ) -> Result<(), Error> {
+        self.bytes += chunk.len();
+        if self.bytes > policy.max_file_size {
+            return Err(Error::ReadError(format!(
+                "Uploaded file `{}` exceeds the {} bytes limit",
+                name, policy.max_file_size
+            )));
+        }
+        if let Some(OnUploadProgress(on_progress)) = &self.on_progress {
+            on_progress(&UploadProgress {
+                field: name,
+                bytes: self.read.bytes + self.bytes,
+                total: self.read.total,
+            })?;
+        }
+        self.hasher.update(chunk);
+        timed_async(&mut timings.multipart_spool, self.file.write_all(chunk))
+            .await
+            .map_err(|e| Error::io("Failed to write to temp file", e))?;
+        Ok(())
+    }
+
+    /// Closes and removes the temp file of an upload that failed, e.g. because the client
+    /// disconnected mid-upload, instead of leaving it until the extraction is dropped.
+    async fn abort(self) {
+        // The handle must be closed before the file can be removed on Windows
+        drop(self.file.into_inner().into_std().await);
+        remove_temp_file(self.temp_file);
+    }
+
+    /// Flushes the file, and syncs it to disk with `ParamsConfig::sync_uploads`. The temp
+    /// file must be kept while the `UploadFile` is used.
+    async fn finish(
+        mut self,
+        name: String,
+        content_type: String,
+        timings: &mut Timings,
+    ) -> Result<(UploadFile, NamedTempFile), Error> {
+        timed_async(&mut timings.multipart_spool, self.file.flush())
+            .await
+            .map_err(|e| Error::io("Failed to write to temp file", e))?;
+        if self.sync {
+            timed_async(&mut timings.multipart_spool, self.file.get_ref().sync_all())
+                .await
+                .map_err(|e| Error::io("Failed to sync temp file", e))?;
+        }
+        Span::current().record("bytes", self.bytes);
+        debug!(path = ?self.temp_file.path(), "spooled upload");
+
+        let file = UploadFile {
+            name,
+            content_type,
+            temp_file_path: self.temp_file.path().to_string_lossy().to_string(),
+            checksum: self.hasher.finish(),
+            headers: vec![],
+            part_index: 0,
+            owned: true,
+        };
+        Ok((file, self.temp_file))
+    }
+}
+
+/// Removes `temp_file` now, logging failures that dropping it would ignore.
+pub(crate) fn remove_temp_file(temp_file: NamedTempFile) {
+    let path = temp_file.path().to_path_buf();
+    if let Err(e) = temp_file.close() {
+        debug!(?path, "failed to remove temp file: {e}");
+    }
+}
+
+/// Adds the path and query parameters, the part of the extraction that doesn't need the body.
+async fn extract_parts_params<S: Send + Sync>(
+    parts: &mut Parts,
+    state: &S,
+    params: &mut ParamsParser,
+) -> Result<(), Error> {
+    // Extract path parameters, requests of `ParamsBuilder` aren't routed
+    let path_params: Option<Vec<(String, String)>> =
+        match Path::<HashMap<String, String>>::from_request_parts(parts, state).await {
+            Ok(Path(params)) => Some(params.into_iter().collect()),
+            Err(_) => parts
+                .extensions
+                .get::<TestPathParams>()
+                .map(|TestPathParams(params)| params.clone()),
+        };
+    if let Some(path_params) = path_params.filter(|_| !params.config.skip_path) {
+        params.add_path_params(path_params)?;
+    }
+
+    // Extract query parameters from URI
+    let query = parts.uri.query().filter(|_| !params.config.skip_query);
+    if let Some(query) = query {
+        params.add_query(query)?;
+    }
+
+    debug!(
+        keys = params.params.len(),
+        query_len = query.map_or(0, str::len),
+        "path and query params"
+    );
+    Ok(())
+}
+
+/// Replaces the keys of `ParamsConfig::extension` with the values of the request extensions,
+/// whatever the client sent for them.
+fn inject_extensions(extensions: &Extensions, params: &mut ParamsParser) -> Result<(), Error> {
+    let metadata = &mut params.metadata;
+    for extension in &params.config.extensions {
+        params.params.remove(&extension.key);
+        metadata.sources.remove(&extension.key);
+        if let Some(value) = (extension.read)(extensions) {
+            let value = value.map_err(|e| {
+                Error::DecodeError(format!(
+                    "Failed to read extension parameter `{}`: {e}",
+                    extension.key
+                ))
+            })?;
+            params.params.insert(extension.key.clone(), value);
+            metadata
+                .sources
+                .insert(extension.key.clone(), Source::Extension);
+        }
+    }
+    Ok(())
+}
+
+/// Rejects a body whose `Content-Length` exceeds the limit of its content type with
+/// `Error::PayloadTooLarge`, before any of it is read. Bodies without the header, e.g.
+/// chunked ones, are limited while they are read.
+///
+/// Multipart bodies are limited per part, the raw bodies of `ParamsConfig::raw_body` by
+/// `SecurityPolicy::max_file_size` and other bodies by `SecurityPolicy::max_body_size`.
+pub(crate) fn check_content_length(
+    headers: &http::HeaderMap,
+    content_type: &str,
+    config: &ParamsConfig,
+) -> Result<(), Error> {
+    let Some(length) = content_length(headers) else {
+        return Ok(());
+    };
+    let policy = &config.security;
+    let limit = match content_type {
+        ct if ct.starts_with("multipart/form-data") => return Ok(()),
+        ct if ct.starts_with("application/json")
+            || is_merge_patch(ct)
+            || is_json_patch(ct)
+            || ct.starts_with("application/x-www-form-urlencoded")
+            || (ct.starts_with("text/plain") && config.text_body.is_some()) =>
+        {
+            policy.max_body_size
+        }
+        _ if config.raw_body.is_some() => policy.max_file_size,
+        _ => return Ok(()),
+    };
+    if length > limit as u64 {
+        return Err(Error::PayloadTooLarge(format!(
+            "Request body of {length} bytes exceeds the {limit} bytes limit"
+        )));
+    }
+    Ok(())
+}
+
+fn content_length(headers: &http::HeaderMap) -> Option<u64> {
+    headers
+        .get(http::header::CONTENT_LENGTH)
+        .and_then(|length| length.to_str().ok()?.parse().ok())
+}
+
+/// Buffers `body` of the `kind` content type, failing with `Error::PayloadTooLarge` once it
+/// grows past `limit` bytes.
+pub(crate) async fn buffer_body(mut body: Body, limit: usize, kind: &str) -> Result<Bytes, Error> {
+    let mut bytes = Vec::new();
+    while let Some(chunk) = next_chunk(&mut body)
+        .await
+        .map_err(|e| Error::ReadError(format!("Failed to read {kind} request body: {e}")))?
+    {
+        if bytes.len() + chunk.len() > limit {
+            return Err(Error::PayloadTooLarge(format!(
+                "Failed to read {kind} request body: length limit exceeded"
+            )));
+        }
+        bytes.extend_from_slice(&chunk);
+    }
+    Ok(bytes.into())
+}
+
+/// The next data frame of `body`, trailers are skipped.
+pub(crate) async fn next_chunk(body: &mut Body) -> Result<Option<Bytes>, axum::Error> {
+    loop {
+        match std::future::poll_fn(|cx| Pin::new(&mut *body).poll_frame(cx)).await {
+            Some(frame) => {
+                if let Ok(data) = frame?.into_data() {
+                    return Ok(Some(data));
+                }
+            }
+            None => return Ok(None),
+        }
+    }
+}
+
+/// The content of the text field `name`, up to `limit` bytes.
+async fn read_field(
+    field: &mut multer::Field<'_>,
+    name: &str,
+    limit: usize,
+) -> Result<Vec<u8>, Error> {
+    let mut bytes = Vec::new();
+    while let Some(chunk) = field
+        .chunk()
+        .await
+        .map_err(|e| Error::ReadError(format!("Failed to read text field: {e}")))?
+    {
+        if bytes.len() + chunk.len() > limit {
+            return Err(Error::PayloadTooLarge(format!(
+                "Text field `{name}` exceeds the {limit} bytes limit"
+            )));
+        }
+        bytes.extend_from_slice(&chunk);
+    }
+    Ok(bytes)
+}
+
+/// Trims the strings from the path, query string, form and multipart bodies, see
+/// `ParamsConfig::trim_strings`.
+fn trim_strings(params: &mut HashMap<String, Value>) {
+    fn trim(value: &mut Value) {
+        match value {
+            Value::XStr(_) => value.trim_strings(),
+            Value::Object(map) => map.values_mut().for_each(trim),
+            Value::Array(vec) => vec.iter_mut().for_each(trim),
+            _ => {}
+        }
+    }
+    params.values_mut().for_each(trim);
+}
+
+fn decode_utf8(bytes: &[u8], strict: bool) -> Result<Cow<'_, str>, String> {
+    if strict {
+        std::str::from_utf8(bytes)
+            .map(Cow::Borrowed)
+            .map_err(|e| format!("invalid UTF-8: {e}"))
+    } else {
+        Ok(String::from_utf8_lossy(bytes))
+    }
+}
+
+pub(crate) fn record_key(
+    sources: &mut HashMap<String, Source>,
+    parser: &QueryParser,
+    name: &str,
+    source: Source,
+) {
+    if let Some(key) = parser.top_level_key(name) {
+        sources.insert(key, source);
+    }
+}
+
+pub(crate) fn top_level_keys(value: &Value) -> Vec<String> {
+    match value {
+        Value::Object(map) => map.keys().cloned().collect(),
+        _ => vec![],
+    }
+}
+
+pub(crate) fn record_keys(
+    sources: &mut HashMap<String, Source>,
+    keys: Vec<String>,
+    source: Source,
+) {
+    for key in keys {
+        sources.insert(key, source);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use super::*;
+    use ::serde::{Deserialize, Serialize};
+    use axum::{
+        Json, Router,
+        body::Body,
+        extract::{FromRequest, Request},
+        http::StatusCode,
+        http::{self, HeaderValue},
+        response::IntoResponse,
+        routing::{get, post},
+    };
+    use axum_test::{
+        TestServer,
+        multipart::{MultipartForm, Part},
+    };
+    use serde_json::json;
+    use tokio::io::AsyncReadExt;
+    use tracing::debug;
+
+    pub fn setup() {
+        let _ = env_logger::builder().is_test(true).try_init();
+    }
+
+    #[derive(Debug, Deserialize, Serialize, PartialEq)]
+    struct TestParams {
+        id: i32,
+        name: String,
+        #[serde(default)]
+        extra: Option<String>,
+    }
+
+    #[derive(Debug, Serialize, Deserialize)]
+    struct UploadFileResponse {
+        name: String,
+        content_type: String,
+        content: String,
+    }
+
+    #[axum::debug_handler]
+    async fn test_params_handler(Params(test, _): Params<TestParams>) -> impl IntoResponse {
+        (StatusCode::OK, serde_json::to_string(&test).unwrap())
+    }
+
+    #[derive(Debug, Deserialize)]
+    struct FileUploadParams {
+        title: String,
+        description: Option<String>,
+        file: UploadFile,
+    }
+
+    #[axum::debug_handler]
+    async fn file_upload_handler(Params(upload, _): Params<FileUploadParams>) -> impl IntoResponse {
+        let mut temp_file = upload.file.open().await.unwrap();
+        debug!(
+            "Reading file from: {:?}",
+            temp_file.metadata().await.unwrap()
+        );
+        let mut content = String::new();
+        temp_file.read_to_string(&mut content).await.unwrap();
+        debug!("Read {} bytes from file", content.len());
+        debug!("File content: {:?}", content);
+
+        let response = json!({
+            "title": upload.title,
+            "description": upload.description,
+            "file_name": upload.file.name,
+            "file_content": content,
+        });
+        (StatusCode::OK, serde_json::to_string(&response).unwrap())
+    }
+
+    #[derive(Debug, Serialize, Deserialize)]
+    struct Attachment {
+        file: UploadFile,
+        name: String,
+    }
+
+    #[derive(Debug, Serialize, Deserialize)]
+    struct CreatePostParams {
+        title: String,
+        content: String,
+        tags: Vec<String>,
+        cover: UploadFile,
+        attachments: Vec<Attachment>,
+    }
+
+    #[derive(Debug, Serialize, Deserialize)]
+    struct AttachmentResponse {
+        name: String,
+        content_type: String,
+        content: String,
+    }
+
+    #[derive(Debug, Serialize, Deserialize)]
+    struct CreatePostResponse {
+        title: String,
+        content: String,
+        tags: Vec<String>,
+        cover: UploadFileResponse,
+        attachments: Vec<AttachmentResponse>,
+    }
+
+    #[axum::debug_handler]
+    async fn test_nested_params_handler(
+        Params(post, _): Params<CreatePostParams>,
+    ) -> Result<Json<CreatePostResponse>, Error> {
+        let mut cover_file = post.cover.open().await.unwrap();
+        let mut cover_content = String::new();
+        cover_file.read_to_string(&mut cover_content).await.unwrap();
+
+        let response = CreatePostResponse {
+            title: post.title,
+            content: post.content,
+            tags: post.tags,
+            cover: UploadFileResponse {
+                name: post.cover.name,
+                content_type: post.cover.content_type,
+                content: cover_content,
+            },
+            attachments: Vec::new(),
+        };
+
+        let attachments =
+            futures_util::future::join_all(post.attachments.into_iter().map(|a| async {
+                let mut file = a.file.open().await.unwrap();
+                let mut content = String::new();
+                file.read_to_string(&mut content).await.unwrap();
+
+                AttachmentResponse {
+                    name: a.name,
+                    content_type: a.file.content_type,
+                    content,
+                }
+            }))
+            .await;
+
+        let mut response = response;
+        response.attachments = attachments;
+
+        Ok(Json(response))
+    }
+
+    #[tokio::test]
+    async fn test_path_params() {
+        let app = Router::new().route(&crate::route_path("/users/{id}"), get(test_params_handler));
+        let server = TestServer::new(app).unwrap();
+
+        let response = server
+            .get("/users/123")
+            .add_query_params(&[("name", "test")])
+            .await;
+        println!("response: {:?}", response);
+        assert_eq!(response.status_code(), StatusCode::OK);
+
+        let body = response.text();
+        let params: TestParams = serde_json::from_str(&body).unwrap();
+        assert_eq!(params.id, 123);
+        assert_eq!(params.name, "test");
+        assert_eq!(params.extra, None);
+    }
+
+    #[tokio::test]
+    async fn test_json_body() {
+        let app = Router::new().route("/api/test", post(test_params_handler));
+        let server = TestServer::new(app).unwrap();
+
+        let json_data = json!({
+            "id": 123,
+            "name": "test",
+            "extra": "data"
+        });
+
+        let response = server.post("/api/test").json(&json_data).await;
+        assert_eq!(response.status_code(), StatusCode::OK);
+
+        let body = response.text();
+        let params: TestParams = serde_json::from_str(&body).unwrap();
+        assert_eq!(params.id, 123);
+        assert_eq!(params.name, "test");
+        assert_eq!(params.extra, Some("data".to_string()));
+    }
+
+    #[tokio::test]
+    async fn test_form_data() {
+        let app = Router::new().route("/api/test", post(test_params_handler));
+        let server = TestServer::new(app).unwrap();
+
+        let response = server
+            .post("/api/test")
+            .form(&[("id", "123"), ("name", "test"), ("extra", "form_data")])
+            .await;
+        assert_eq!(response.status_code(), StatusCode::OK);
+
+        let body = response.text();
+        let params: TestParams = serde_json::from_str(&body).unwrap();
         assert_eq!(params.id, 123);
         assert_eq!(params.name, "test");
         assert_eq!(params.extra, Some("form_data".to_string()));
     }
 
     #[tokio::test]
-    async fn test_multipart_form() {
-        let app = Router::new().route("/api/upload", post(file_upload_handler));
+    async fn test_multipart_form() {
+        let app = Router::new().route("/api/upload", post(file_upload_handler));
+        let server = TestServer::new(app).unwrap();
+
+        let test_content = b"Hello, World!";
+        let test_content_str = String::from_utf8_lossy(test_content).to_string();
+
+        let response = server
+            .post("/api/upload")
+            .add_header(
+                axum::http::header::CONTENT_TYPE,
+                HeaderValue::from_static("multipart/form-data; boundary=X-BOUNDARY"),
+            )
+            .multipart(
+                MultipartForm::new()
+                    .add_text("title", "Test Upload")
+                    .add_text("description", "A test file upload")
+                    .add_part(
+                        "file",
+                        Part::bytes(test_content.to_vec()).file_name("test.txt"),
+                    ),
+            )
+            .await;
+
+        assert_eq!(response.status_code(), StatusCode::OK);
+        let body = response.text();
+        let result: serde_json::Value = serde_json::from_str(&body).unwrap();
+        assert_eq!(result["title"], "Test Upload");
+        assert_eq!(result["description"], "A test file upload");
+        assert_eq!(result["file_name"], "test.txt");
+        assert_eq!(result["file_content"], test_content_str);
+    }
+
+    struct LineCount(usize);
+
+    impl crate::FromUploadFile for LineCount {
+        type Rejection = String;
+
+        async fn from_upload_file(file: &UploadFile) -> Result<Self, Self::Rejection> {
+            let content = String::from_upload_file(file)
+                .await
+                .map_err(|e| e.to_string())?;
+            match content.lines().count() {
+                0 => Err("empty file".to_string()),
+                n => Ok(LineCount(n)),
+            }
+        }
+    }
+
+    #[derive(Deserialize)]
+    struct ImportParams {
+        title: String,
+        rows: crate::Uploaded<LineCount>,
+        raw: crate::Uploaded<String>,
+    }
+
+    async fn import_handler(Params(params, _): Params<ImportParams>) -> String {
+        format!(
+            "{}:{}:{}:{}",
+            params.title,
+            params.rows.0,
+            params.rows.file().name,
+            params.raw.as_str()
+        )
+    }
+
+    #[tokio::test]
+    async fn test_from_upload_file() {
+        setup();
+        let app = Router::new().route("/api/import", post(import_handler));
+        let server = TestServer::new(app).unwrap();
+
+        let form = |rows: &'static [u8]| {
+            MultipartForm::new()
+                .add_text("title", "users")
+                .add_part("rows", Part::bytes(rows).file_name("rows.csv"))
+                .add_part("raw", Part::bytes(&b"raw"[..]).file_name("raw.txt"))
+        };
+
+        let response = server
+            .post("/api/import")
+            .multipart(form(b"a\nb\nc\n"))
+            .await;
+        assert_eq!(response.status_code(), StatusCode::OK);
+        assert_eq!(response.text(), "users:3:rows.csv:raw");
+
+        let response = server.post("/api/import").multipart(form(b"")).await;
+        assert_eq!(response.status_code(), StatusCode::BAD_REQUEST);
+        assert!(
+            response
+                .text()
+                .contains("Failed to process uploaded file `rows.csv`: empty file")
+        );
+
+        let forged = json!({
+            "name": "passwd",
+            "content_type": "text/plain",
+            "temp_file_path": "/etc/passwd",
+        });
+        let response = server
+            .post("/api/import")
+            .json(&json!({"title": "users", "rows": forged, "raw": forged}))
+            .await;
+        assert_eq!(response.status_code(), StatusCode::BAD_REQUEST);
+        assert!(response.text().contains("is not an uploaded file"));
+    }
+
+    #[tokio::test]
+    async fn test_combined_params() {
+        let app = Router::new().route(&crate::route_path("/users/{id}"), post(test_params_handler));
+        let server = TestServer::new(app).unwrap();
+
+        let json_data = json!({
+            "name": "test"
+        });
+
+        let response = server
+            .post("/users/123")
+            .add_query_params(&[("extra", "query_param")])
+            .json(&json_data)
+            .await;
+        assert_eq!(response.status_code(), StatusCode::OK);
+
+        let body = response.text();
+        let params: TestParams = serde_json::from_str(&body).unwrap();
+        assert_eq!(params.id, 123);
+        assert_eq!(params.name, "test");
+        assert_eq!(params.extra, Some("query_param".to_string()));
+    }
+
+    #[derive(Debug, Deserialize, Serialize)]
+    struct Pagination {
+        page: u32,
+        per_page: Option<u32>,
+        #[serde(default)]
+        filter: HashMap<String, String>,
+    }
+
+    #[derive(Debug, Deserialize, Serialize)]
+    struct ListParams {
+        id: i32,
+        name: String,
+        #[serde(flatten, deserialize_with = "crate::flatten")]
+        pagination: Pagination,
+        #[serde(flatten)]
+        rest: HashMap<String, String>,
+    }
+
+    #[tokio::test]
+    async fn test_flatten_params() {
+        setup();
+        let app = Router::new().route(
+            &crate::route_path("/users/{id}"),
+            post(|Params(params, _): Params<ListParams>| async move { Json(params) }),
+        );
+        let server = TestServer::new(app).unwrap();
+
+        let response = server
+            .post("/users/1?page=2&per_page=10&filter[status]=active&sort=name")
+            .json(&json!({"name": "test"}))
+            .await;
+        assert_eq!(response.status_code(), StatusCode::OK);
+        let params: ListParams = response.json();
+        assert_eq!(params.id, 1);
+        assert_eq!(params.name, "test");
+        assert_eq!(params.pagination.page, 2);
+        assert_eq!(params.pagination.per_page, Some(10));
+        assert_eq!(params.pagination.filter["status"], "active");
+        assert_eq!(params.rest["sort"], "name");
+
+        let response = server
+            .post("/users/1?page=x")
+            .json(&json!({"name": "test"}))
+            .await;
+        assert_eq!(response.status_code(), StatusCode::BAD_REQUEST);
+    }
+
+    #[derive(Debug, Deserialize, Serialize, PartialEq)]
+    #[serde(tag = "type", rename_all = "snake_case")]
+    enum Shape {
+        Circle {
+            #[serde(deserialize_with = "crate::coerce")]
+            radius: f64,
+        },
+        Square {
+            #[serde(deserialize_with = "crate::coerce")]
+            side: u32,
+            #[serde(default, deserialize_with = "crate::coerce")]
+            filled: bool,
+        },
+    }
+
+    #[derive(Debug, Deserialize, Serialize, PartialEq)]
+    #[serde(untagged)]
+    enum Lookup {
+        Id {
+            #[serde(deserialize_with = "crate::coerce")]
+            id: u64,
+        },
+        Name {
+            name: String,
+        },
+    }
+
+    #[derive(Debug, Deserialize, Serialize, PartialEq)]
+    #[serde(rename_all = "snake_case")]
+    enum Filter {
+        All,
+        Range { min: i32, max: i32 },
+        Tags(Vec<String>),
+    }
+
+    #[derive(Debug, Deserialize, Serialize)]
+    struct Search {
+        lookup: Lookup,
+        filter: Filter,
+    }
+
+    #[tokio::test]
+    async fn test_tagged_enum_params() {
+        setup();
+        let app = Router::new()
+            .route(
+                "/shapes",
+                post(|Params(shape, _): Params<Shape>| async move { Json(shape) }),
+            )
+            .route(
+                "/search",
+                post(|Params(search, _): Params<Search>| async move { Json(search) }),
+            );
+        let server = TestServer::new(app).unwrap();
+
+        // Tag from the query string, content from the JSON body
+        let response = server
+            .post("/shapes?type=circle")
+            .json(&json!({"radius": 1.5}))
+            .await;
+        assert_eq!(response.status_code(), StatusCode::OK);
+        assert_eq!(response.json::<Shape>(), Shape::Circle { radius: 1.5 });
+
+        let response = server
+            .post("/shapes?type=square")
+            .form(&[("side", "3"), ("filled", "on")])
+            .await;
+        assert_eq!(response.status_code(), StatusCode::OK);
+        assert_eq!(
+            response.json::<Shape>(),
+            Shape::Square {
+                side: 3,
+                filled: true
+            }
+        );
+
+        let response = server.post("/shapes?type=circle&radius=x").await;
+        assert_eq!(response.status_code(), StatusCode::BAD_REQUEST);
+
+        let response = server
+            .post("/search?lookup[id]=42&filter[range][min]=-1&filter[range][max]=10")
+            .await;
+        assert_eq!(response.status_code(), StatusCode::OK);
+        let search: Search = response.json();
+        assert_eq!(search.lookup, Lookup::Id { id: 42 });
+        assert_eq!(search.filter, Filter::Range { min: -1, max: 10 });
+
+        let response = server
+            .post("/search?lookup[name]=42&filter[tags][]=a&filter[tags][]=b")
+            .await;
+        assert_eq!(response.status_code(), StatusCode::OK);
+        let search: Search = response.json();
+        assert_eq!(
+            search.lookup,
+            Lookup::Name {
+                name: "42".to_string()
+            }
+        );
+        assert_eq!(
+            search.filter,
+            Filter::Tags(vec!["a".to_string(), "b".to_string()])
+        );
+
+        let response = server.post("/search?lookup[name]=x&filter=all").await;
+        assert_eq!(response.status_code(), StatusCode::OK);
+        assert_eq!(response.json::<Search>().filter, Filter::All);
+    }
+
+    #[derive(Debug, PartialEq)]
+    struct Money {
+        cents: i64,
+    }
+
+    impl crate::ParamFormat for Money {
+        const EXPECTED: &'static str = "an amount like `12.50`";
+
+        fn parse_param(s: &str) -> Option<Self> {
+            let (units, cents) = s.split_once('.').unwrap_or((s, "0"));
+            match (units.parse::<i64>(), format!("{cents:0<2}").parse::<i64>()) {
+                (Ok(units), Ok(cents)) if cents < 100 => Some(Money {
+                    cents: units * 100 + cents,
+                }),
+                _ => None,
+            }
+        }
+    }
+
+    impl<'de> Deserialize<'de> for Money {
+        fn deserialize<D: ::serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+            crate::formatted(deserializer)
+        }
+    }
+
+    #[derive(Debug, Deserialize)]
+    struct Order {
+        price: Money,
+        tip: Option<Money>,
+        fees: Vec<Money>,
+    }
+
+    #[tokio::test]
+    async fn test_custom_param_format() {
+        let Params(order, _) = crate::test::ParamsBuilder::new()
+            .query("price", "12.5")
+            .query("fees[]", "1")
+            .query("fees[]", "0.25")
+            .build::<Order>()
+            .await
+            .unwrap();
+        assert_eq!(order.price, Money { cents: 1250 });
+        assert_eq!(order.tip, None);
+        assert_eq!(order.fees, [Money { cents: 100 }, Money { cents: 25 }]);
+
+        let Params(order, _) = crate::test::ParamsBuilder::new()
+            .json(json!({"price": 3, "tip": "0.5", "fees": []}))
+            .build::<Order>()
+            .await
+            .unwrap();
+        assert_eq!(order.price, Money { cents: 300 });
+        assert_eq!(order.tip, Some(Money { cents: 50 }));
+
+        let error = crate::test::ParamsBuilder::new()
+            .query("price", "ten")
+            .build::<Order>()
+            .await
+            .unwrap_err();
+        assert!(
+            error
+                .message()
+                .contains("invalid value `ten`, expected an amount like `12.50`"),
+            "{error:?}"
+        );
+
+        let error = crate::test::ParamsBuilder::new()
+            .query("price[amount]", "1")
+            .build::<Order>()
+            .await
+            .unwrap_err();
+        assert!(
+            error
+                .message()
+                .contains("invalid object, expected an amount like `12.50`"),
+            "{error:?}"
+        );
+    }
+
+    #[derive(Debug, Deserialize, Serialize)]
+    struct Blob {
+        #[serde(with = "serde_bytes")]
+        data: Vec<u8>,
+        key: Option<serde_bytes::ByteBuf>,
+        #[serde(default, deserialize_with = "crate::hex")]
+        digest: Vec<u8>,
+    }
+
+    #[tokio::test]
+    async fn test_bytes_params() {
+        setup();
+        let app = Router::new().route(
+            "/blobs",
+            post(|Params(blob, _): Params<Blob>| async move { Json(blob) }),
+        );
+        let server = TestServer::new(app).unwrap();
+
+        let response = server
+            .post("/blobs")
+            .json(&json!({"data": "aGVsbG8=", "key": "-_8", "digest": "00fF10"}))
+            .await;
+        assert_eq!(response.status_code(), StatusCode::OK);
+        assert_eq!(
+            response.json::<serde_json::Value>(),
+            json!({"data": b"hello", "key": [0xfb, 0xff], "digest": [0x00, 0xff, 0x10]})
+        );
+
+        let response = server.post("/blobs?data=aGk").await;
+        assert_eq!(response.status_code(), StatusCode::OK);
+        assert_eq!(response.json::<serde_json::Value>()["data"], json!(b"hi"));
+
+        let response = server.post("/blobs?data=!").await;
+        assert_eq!(response.status_code(), StatusCode::BAD_REQUEST);
+        assert!(response.text().contains("invalid base64 value"));
+
+        let response = server.post("/blobs?data=aGk&digest=abc").await;
+        assert_eq!(response.status_code(), StatusCode::BAD_REQUEST);
+        assert!(response.text().contains("invalid hex value"));
+    }
+
+    #[derive(Debug, Deserialize, Serialize)]
+    struct Range(f64, f64);
+
+    #[derive(Debug, Deserialize, Serialize)]
+    struct Confirm;
+
+    #[derive(Debug, Deserialize, Serialize)]
+    struct Chart {
+        range: Range,
+        point: (u32, u32),
+        confirm: Confirm,
+    }
+
+    #[tokio::test]
+    async fn test_tuple_and_unit_struct_params() {
+        setup();
+        let app = Router::new()
+            .route(
+                "/charts",
+                post(|Params(chart, _): Params<Chart>| async move { Json(chart) }),
+            )
+            .route("/ping", post(|_: Params<Confirm>| async { "pong" }));
+        let server = TestServer::new(app).unwrap();
+
+        let response = server
+            .post("/charts?range[]=1&range[]=2.5&point[1]=4&point[0]=3&confirm")
+            .await;
+        assert_eq!(response.status_code(), StatusCode::OK);
+        assert_eq!(
+            response.json::<serde_json::Value>(),
+            json!({"range": [1.0, 2.5], "point": [3, 4], "confirm": null})
+        );
+
+        let response = server.post("/charts?range[]=1&point[]=1&point[]=2").await;
+        assert_eq!(response.status_code(), StatusCode::BAD_REQUEST);
+        assert!(response.text().contains("invalid length 1"));
+
+        let response = server.post("/ping?ignored=1").await;
+        assert_eq!(response.status_code(), StatusCode::OK);
+        assert_eq!(response.text(), "pong");
+    }
+
+    #[tokio::test]
+    async fn test_128_bit_params() {
+        #[derive(Deserialize)]
+        struct Event {
+            id: u128,
+            offset: i128,
+        }
+
+        let Params(event, _) = crate::test::ParamsBuilder::new()
+            .query("id", "340282366920938463463374607431768211455")
+            .query("offset", "-170141183460469231731687303715884105728")
+            .build::<Event>()
+            .await
+            .unwrap();
+        assert_eq!(event.id, u128::MAX);
+        assert_eq!(event.offset, i128::MIN);
+
+        let Params(event, _) = crate::test::ParamsBuilder::new()
+            .json(json!({"id": 1}))
+            .query("offset", "1")
+            .build::<Event>()
+            .await
+            .unwrap();
+        assert_eq!((event.id, event.offset), (1, 1));
+    }
+
+    #[tokio::test]
+    async fn test_repeated_file_fields() {
+        #[derive(Deserialize)]
+        struct Gallery {
+            title: String,
+            files: Vec<UploadFile>,
+        }
+
+        async fn handler(Params(gallery, _): Params<Gallery>) -> impl IntoResponse {
+            let mut names = vec![];
+            for file in &gallery.files {
+                let mut content = String::new();
+                file.open()
+                    .await
+                    .unwrap()
+                    .read_to_string(&mut content)
+                    .await
+                    .unwrap();
+                names.push(format!("{}={content}", file.name));
+            }
+            format!("{}: {}", gallery.title, names.join(", "))
+        }
+
+        let app = Router::new().route("/api/galleries", post(handler));
+        let server = TestServer::new(app).unwrap();
+        let file = |name: &str, content: &str| {
+            Part::bytes(content.as_bytes().to_vec())
+                .file_name(name)
+                .mime_type("image/png")
+        };
+
+        let response = server
+            .post("/api/galleries")
+            .multipart(
+                MultipartForm::new()
+                    .add_text("title", "trip")
+                    .add_part("files[]", file("a.png", "A"))
+                    .add_part("files[]", file("b.png", "B"))
+                    .add_part("files[]", file("c.png", "C")),
+            )
+            .await;
+        assert_eq!(response.status_code(), StatusCode::OK);
+        assert_eq!(response.text(), "trip: a.png=A, b.png=B, c.png=C");
+
+        let response = server
+            .post("/api/galleries")
+            .multipart(
+                MultipartForm::new()
+                    .add_text("title", "one")
+                    .add_part("files[]", file("a.png", "A")),
+            )
+            .await;
+        assert_eq!(response.text(), "one: a.png=A");
+
+        // Without brackets the last file wins, like Rack, and reads as one element
+        let response = server
+            .post("/api/galleries")
+            .multipart(
+                MultipartForm::new()
+                    .add_text("title", "plain")
+                    .add_part("files", file("a.png", "A"))
+                    .add_part("files", file("b.png", "B")),
+            )
+            .await;
+        assert_eq!(response.text(), "plain: b.png=B");
+    }
+
+    #[tokio::test]
+    async fn test_upload_part_index() {
+        #[derive(Deserialize)]
+        struct Album {
+            cover: UploadFile,
+            photos: HashMap<String, UploadFile>,
+        }
+
+        async fn handler(Params(album, metadata): Params<Album>) -> impl IntoResponse {
+            let mut files = album.photos.into_values().collect::<Vec<_>>();
+            files.push(album.cover);
+            files.sort_by_key(UploadFile::part_index);
+            let names = files
+                .iter()
+                .map(|file| format!("{}:{}", file.part_index(), file.name))
+                .collect::<Vec<_>>();
+            format!(
+                "{} of {}",
+                names.join(", "),
+                metadata.sizes.multipart_fields
+            )
+        }
+
+        let app = Router::new().route("/api/albums", post(handler));
+        let server = TestServer::new(app).unwrap();
+        let file = |name: &str| Part::bytes(b"x".to_vec()).file_name(name);
+
+        let response = server
+            .post("/api/albums")
+            .multipart(
+                MultipartForm::new()
+                    .add_part("photos[z]", file("first.png"))
+                    .add_text("title", "trip")
+                    .add_part("cover", file("cover.png"))
+                    .add_part("photos[a]", file("last.png")),
+            )
+            .await;
+        assert_eq!(response.status_code(), StatusCode::OK);
+        assert_eq!(response.text(), "0:first.png, 2:cover.png, 3:last.png of 4");
+    }
+
+    #[tokio::test]
+    async fn test_absent_file_fields() {
+        #[derive(Deserialize)]
+        struct Profile {
+            name: String,
+            avatar: Option<UploadFile>,
+            #[serde(default)]
+            photos: Vec<UploadFile>,
+        }
+
+        async fn handler(Params(profile, _): Params<Profile>) -> impl IntoResponse {
+            format!(
+                "{}: {:?} {:?}",
+                profile.name,
+                profile.avatar.map(|file| file.name),
+                profile
+                    .photos
+                    .into_iter()
+                    .map(|file| file.name)
+                    .collect::<Vec<_>>()
+            )
+        }
+
+        let app = Router::new().route("/api/profile", post(handler));
+        let server = TestServer::new(app).unwrap();
+
+        let response = server
+            .post("/api/profile")
+            .multipart(MultipartForm::new().add_text("name", "alice"))
+            .await;
+        assert_eq!(response.status_code(), StatusCode::OK);
+        assert_eq!(response.text(), "alice: None []");
+
+        // Untouched file inputs
+        let empty = || {
+            Part::bytes(vec![])
+                .file_name("")
+                .mime_type("application/octet-stream")
+        };
+        let response = server
+            .post("/api/profile")
+            .multipart(
+                MultipartForm::new()
+                    .add_text("name", "alice")
+                    .add_part("avatar", empty())
+                    .add_part("photos[]", empty()),
+            )
+            .await;
+        assert_eq!(response.status_code(), StatusCode::OK);
+        assert_eq!(response.text(), "alice: None []");
+
+        let response = server
+            .post("/api/profile")
+            .multipart(
+                MultipartForm::new()
+                    .add_text("name", "alice")
+                    .add_part("avatar", Part::bytes(b"png".to_vec()).file_name("me.png"))
+                    .add_part("photos[]", empty()),
+            )
+            .await;
+        assert_eq!(response.text(), r#"alice: Some("me.png") []"#);
+    }
+
+    #[tokio::test]
+    async fn test_nested_params_with_file_upload() {
+        let app = Router::new().route("/api/posts", post(test_nested_params_handler));
+        let server = TestServer::new(app).unwrap();
+
+        // Create test file content
+        let cover_content = b"Cover image content";
+        let attachment1_content = b"Attachment 1 content";
+        let attachment2_content = b"Attachment 2 content";
+
+        // Create multipart form
+        let response = server
+            .post("/api/posts")
+            .add_header(
+                axum::http::header::CONTENT_TYPE,
+                HeaderValue::from_static("multipart/form-data; boundary=X-BOUNDARY"),
+            )
+            .multipart(
+                MultipartForm::new()
+                    .add_text("title", "Test Post")
+                    .add_text("content", "This is a test post content")
+                    .add_text("tags[]", "tag1")
+                    .add_text("tags[]", "tag2")
+                    .add_text("tags[]", "tag3")
+                    .add_part(
+                        "cover",
+                        Part::bytes(cover_content.to_vec())
+                            .file_name("cover.jpg")
+                            .mime_type("image/jpeg"),
+                    )
+                    .add_text("attachments[][name]", "First attachment")
+                    .add_part(
+                        "attachments[][file]",
+                        Part::bytes(attachment1_content.to_vec())
+                            .file_name("attachment1.txt")
+                            .mime_type("text/plain"),
+                    )
+                    .add_text("attachments[][name]", "Second attachment")
+                    .add_part(
+                        "attachments[][file]",
+                        Part::bytes(attachment2_content.to_vec())
+                            .file_name("attachment2.txt")
+                            .mime_type("text/plain"),
+                    ),
+            )
+            .await;
+
+        assert_eq!(response.status_code(), StatusCode::OK);
+
+        let body: CreatePostResponse = serde_json::from_str(&response.text()).unwrap();
+
+        // Verify the response
+        assert_eq!(body.title, "Test Post");
+        assert_eq!(body.content, "This is a test post content");
+        assert_eq!(body.tags, vec!["tag1", "tag2", "tag3"]);
+
+        // Verify cover file
+        assert_eq!(body.cover.name, "cover.jpg");
+        assert_eq!(body.cover.content_type, "image/jpeg");
+        assert_eq!(body.cover.content, String::from_utf8_lossy(cover_content));
+
+        // Verify attachments
+        assert_eq!(body.attachments.len(), 2);
+
+        let attachment1 = &body.attachments[0];
+        assert_eq!(attachment1.name, "First attachment");
+        assert_eq!(attachment1.content_type, "text/plain");
+        assert_eq!(
+            attachment1.content,
+            String::from_utf8_lossy(attachment1_content)
+        );
+
+        let attachment2 = &body.attachments[1];
+        assert_eq!(attachment2.name, "Second attachment");
+        assert_eq!(attachment2.content_type, "text/plain");
+        assert_eq!(
+            attachment2.content,
+            String::from_utf8_lossy(attachment2_content)
+        );
+    }
+
+    #[derive(Debug, Serialize, Deserialize)]
+    struct MixedAttachment {
+        name: String,
+        description: String,
+        file: Option<UploadFile>,
+    }
+
+    #[derive(Debug, Serialize, Deserialize)]
+    struct MixedPostParams {
+        title: String,
+        content: String,
+        tags: Option<Vec<String>>,
+        attachments: Option<Vec<MixedAttachment>>,
+        author: Option<String>,
+        status: Option<String>,
+        metadata: Option<HashMap<String, String>>,
+    }
+
+    #[derive(Debug, Serialize, Deserialize)]
+    struct MixedAttachmentResponse {
+        name: String,
+        description: String,
+        file_size: u64,
+    }
+
+    #[derive(Debug, Serialize, Deserialize)]
+    struct MixedPostResponse {
+        message: String,
+        title: String,
+        content: String,
+        tags: Vec<String>,
+        author: String,
+        status: String,
+        metadata: HashMap<String, String>,
+        attachments: Vec<MixedAttachmentResponse>,
+    }
+
+    #[tokio::test]
+    async fn test_mixed_create_post() {
+        setup();
+        use tokio::io::AsyncReadExt;
+
+        let app = Router::new().route(
+            &crate::route_path("/posts/{category}"),
+            post(|params: Params<MixedPostParams>| async move {
+                debug!("params: {:#?}", params);
+                let MixedPostParams {
+                    title,
+                    content,
+                    tags,
+                    attachments,
+                    author,
+                    status,
+                    metadata,
+                } = params.0;
+
+                let mut response = MixedPostResponse {
+                    message: "Success".to_string(),
+                    title,
+                    content,
+                    tags: tags.unwrap_or_default(),
+                    author: author.unwrap_or_default(),
+                    status: status.unwrap_or_default(),
+                    metadata: metadata.unwrap_or_default(),
+                    attachments: Vec::new(),
+                };
+
+                if let Some(attachments) = attachments {
+                    response.attachments =
+                        futures_util::future::join_all(attachments.into_iter().map(|a| async {
+                            let size = if let Some(file) = a.file {
+                                let mut f = file.open().await.unwrap();
+                                let mut content = Vec::new();
+                                f.read_to_end(&mut content).await.unwrap();
+                                content.len() as u64
+                            } else {
+                                0
+                            };
+
+                            MixedAttachmentResponse {
+                                name: a.name,
+                                description: a.description,
+                                file_size: size,
+                            }
+                        }))
+                        .await;
+                }
+
+                Ok::<Json<MixedPostResponse>, Error>(Json(response))
+            }),
+        );
+
+        let server = TestServer::new(app).unwrap();
+
+        // Prepare base post data in empty-named JSON part
+        let base_json = r#"{
+        "title": "Mixed Test Post",
+        "content": "This is a test post with mixed data sources",
+        "tags": ["rust", "axum", "test"]
+    }"#;
+
+        // Create multipart form with mixed data
+        let form = MultipartForm::new()
+            // Add base data as empty-named JSON part
+            .add_part("", Part::text(base_json).mime_type("application/json"))
+            // Add metadata fields individually
+            .add_part("metadata[version]", Part::text("2.0"))
+            .add_part("metadata[visibility]", Part::text("public"))
+            .add_part("metadata[created_at]", Part::text("2024-12-29"))
+            // Add first attachment with file and metadata
+            .add_part(
+                "attachments[][file]",
+                Part::bytes(vec![1, 2, 3, 4])
+                    .file_name("test1.bin")
+                    .mime_type("application/octet-stream"),
+            )
+            .add_part("attachments[][name]", Part::text("Test Attachment 1"))
+            .add_part(
+                "attachments[][description]",
+                Part::text("First test attachment"),
+            )
+            // Add second attachment with file and metadata
+            .add_part(
+                "attachments[][file]",
+                Part::bytes(vec![5, 6, 7, 8, 9])
+                    .file_name("test2.bin")
+                    .mime_type("application/octet-stream"),
+            )
+            .add_part("attachments[][name]", Part::text("Test Attachment 2"))
+            .add_part(
+                "attachments[][description]",
+                Part::text("Second test attachment"),
+            );
+
+        // Add headers for author and status
+        let response = server
+            .post("/posts/tech?author=test_user&status=draft")
+            .add_header(
+                axum::http::header::CONTENT_TYPE,
+                HeaderValue::from_static("multipart/form-data"),
+            )
+            .multipart(form)
+            .await;
+
+        debug!("Request URL: {}", response.request_url());
+        debug!("Request headers: {:?}", response.headers());
+        debug!("Response status: {}", response.status_code());
+        debug!(
+            "Response body: {}",
+            String::from_utf8_lossy(&response.as_bytes())
+        );
+
+        assert_eq!(response.status_code(), StatusCode::OK);
+
+        let body: MixedPostResponse = response.json();
+
+        // Verify base data from empty-named JSON part
+        assert_eq!(body.title, "Mixed Test Post");
+        assert_eq!(body.content, "This is a test post with mixed data sources");
+        assert_eq!(body.tags, vec!["rust", "axum", "test"]);
+
+        // Verify data from query parameters
+        assert_eq!(body.author, "test_user");
+        assert_eq!(body.status, "draft");
+
+        // Verify metadata from named JSON part
+        assert_eq!(body.metadata.get("version").unwrap(), "2.0");
+        assert_eq!(body.metadata.get("visibility").unwrap(), "public");
+        assert_eq!(body.metadata.get("created_at").unwrap(), "2024-12-29");
+
+        // Verify attachments from multipart form
+        assert_eq!(body.attachments.len(), 2);
+
+        let attachment1 = &body.attachments[0];
+        assert_eq!(attachment1.name, "Test Attachment 1");
+        assert_eq!(attachment1.description, "First test attachment");
+        assert_eq!(attachment1.file_size, 4);
+
+        let attachment2 = &body.attachments[1];
+        assert_eq!(attachment2.name, "Test Attachment 2");
+        assert_eq!(attachment2.description, "Second test attachment");
+        assert_eq!(attachment2.file_size, 5);
+    }
+
+    #[derive(Debug, Serialize, Deserialize)]
+    struct ComplexParams {
+        // Path params
+        user_id: i32,
+        // Query params
+        filter: String,
+        page: Option<i32>,
+        // JSON body
+        data: ComplexData,
+        // Multipart form
+        avatar: UploadFile,
+        profile: Option<String>,
+    }
+
+    #[derive(Debug, Serialize, Deserialize)]
+    struct ComplexData {
+        title: String,
+        tags: Vec<String>,
+        version_number: f64,
+        metadata: HashMap<String, String>,
+    }
+
+    #[derive(Debug, Serialize, Deserialize)]
+    struct ComplexResponse {
+        message: String,
+        user_id: i32,
+        filter: String,
+        page: Option<i32>,
+        title: String,
+        tags: Vec<String>,
+        version_number: f64,
+        metadata: HashMap<String, String>,
+        avatar_size: u64,
+        profile: Option<String>,
+    }
+
+    async fn complex_handler(
+        Params(params, _): Params<ComplexParams>,
+    ) -> Result<Json<ComplexResponse>, Error> {
+        let avatar_file = params
+            .avatar
+            .open()
+            .await
+            .map_err(|e| Error::ReadError(format!("Failed to open avatar file: {}", e)))?;
+        let avatar_size = avatar_file
+            .metadata()
+            .await
+            .map_err(|e| Error::ReadError(format!("Failed to get avatar metadata: {}", e)))?
+            .len();
+
+        Ok(Json(ComplexResponse {
+            message: "Success".to_string(),
+            user_id: params.user_id,
+            filter: params.filter,
+            page: params.page,
+            title: params.data.title,
+            tags: params.data.tags,
+            version_number: params.data.version_number,
+            metadata: params.data.metadata,
+            avatar_size,
+            profile: params.profile,
+        }))
+    }
+
+    #[tokio::test]
+    async fn test_complex_params() {
+        setup();
+        let app = Router::new().route(
+            &crate::route_path("/users/{user_id}"),
+            post(complex_handler),
+        );
+        let server = TestServer::new(app).unwrap();
+
+        // Prepare multipart form data
+        let avatar_content = "avatar data".as_bytes();
+        let form = MultipartForm::new()
+            .add_part(
+                "avatar",
+                Part::bytes(avatar_content.to_vec())
+                    .file_name("avatar.jpg")
+                    .mime_type("image/jpeg"),
+            )
+            .add_text("profile", "Test profile")
+            .add_part(
+                "data",
+                Part::text(
+                    r#"{
+            "title": "Test Post",
+            "tags": ["rust", "axum"],
+            "version_number": 1.0,
+            "metadata": {
+                "version": "1.0",
+                "author": "test"
+            }
+        }"#,
+                )
+                .mime_type("application/json"),
+            );
+
+        // Build the request with all parameter types
+        let user_id = 123;
+        let response = server
+            .post(&format!("/users/{}", user_id))
+            .add_query_param("filter", "active")
+            .add_query_param("page", "1")
+            .multipart(form)
+            .await;
+        debug!("Request URL: {}", response.request_url());
+        debug!("Request headers: {:?}", response.headers());
+        debug!("Response status: {}", response.status_code());
+        debug!(
+            "Response body: {}",
+            String::from_utf8_lossy(&response.as_bytes())
+        );
+        assert_eq!(response.status_code(), StatusCode::OK);
+
+        let body: ComplexResponse = response.json();
+        assert_eq!(body.message, "Success");
+        assert_eq!(body.user_id, user_id);
+        assert_eq!(body.filter, "active");
+        assert_eq!(body.page, Some(1));
+        assert_eq!(body.title, "Test Post");
+        assert_eq!(body.tags, vec!["rust", "axum"]);
+        assert_eq!(body.metadata.get("version").unwrap(), "1.0");
+        assert_eq!(body.metadata.get("author").unwrap(), "test");
+        assert_eq!(body.avatar_size, avatar_content.len() as u64);
+        assert_eq!(body.profile, Some("Test profile".to_string()));
+    }
+
+    #[derive(Debug, Deserialize, Serialize)]
+    struct Meta {
+        version: u32,
+    }
+
+    #[derive(Debug, Deserialize, Serialize)]
+    struct WithMeta {
+        meta: Meta,
+    }
+
+    #[tokio::test]
+    async fn test_duplicate_parts() {
+        setup();
+        let handler = |Params(params, _): Params<WithMeta>| async move { Json(params) };
+        let app = Router::new().route("/prefer", post(handler)).route(
+            "/reject",
+            post(handler).layer(axum::Extension(
+                ParamsConfig::new().duplicate_parts(crate::DuplicateParts::Reject),
+            )),
+        );
+        let server = TestServer::new(app).unwrap();
+        let json_part = || Part::text(r#"{"version": 2}"#).mime_type("application/json");
+
+        // The JSON part wins whether it comes before or after the text field
+        let form = MultipartForm::new()
+            .add_part("meta", json_part())
+            .add_text("meta", "[object Object]");
+        let response = server.post("/prefer").multipart(form).await;
+        assert_eq!(response.status_code(), StatusCode::OK);
+        assert_eq!(response.json::<WithMeta>().meta.version, 2);
+
+        let form = MultipartForm::new()
+            .add_text("meta", "[object Object]")
+            .add_part("meta", json_part());
+        let response = server.post("/prefer").multipart(form).await;
+        assert_eq!(response.status_code(), StatusCode::OK);
+        assert_eq!(response.json::<WithMeta>().meta.version, 2);
+
+        let form = MultipartForm::new()
+            .add_text("meta", "[object Object]")
+            .add_part("meta", json_part());
+        let response = server.post("/reject").multipart(form).await;
+        assert_eq!(response.status_code(), StatusCode::BAD_REQUEST);
+        assert!(
+            response
+                .text()
+                .contains("Multipart field `meta` is sent as text in part 1 and as JSON in part 2")
+        );
+    }
+
+    #[tokio::test]
+    async fn test_json_part_deep_merge() {
+        setup();
+        let app = Router::new().route(
+            "/test",
+            post(|Params(params, _): Params<serde_json::Value>| async move { Json(params) }),
+        );
+        let server = TestServer::new(app).unwrap();
+        let json_part = |json: &'static str| Part::text(json).mime_type("application/json");
+
+        let form = MultipartForm::new()
+            .add_text("attachments[0][name]", "a.png")
+            .add_text("attachments[0][meta][caption]", "Hello")
+            .add_part("attachments[0][meta]", json_part(r#"{"width": 640}"#))
+            .add_text("post[tags][]", "rust")
+            .add_part("post[tags]", json_part(r#"["axum", "serde"]"#));
+        let response = server.post("/test").multipart(form).await;
+        assert_eq!(response.status_code(), StatusCode::OK);
+        assert_eq!(
+            response.json::<serde_json::Value>(),
+            json!({
+                "attachments": {"0": {"name": "a.png", "meta": {"caption": "Hello", "width": 640}}},
+                "post": {"tags": ["rust", "axum", "serde"]},
+            })
+        );
+
+        let form = MultipartForm::new()
+            .add_text("post[meta][size]", "1")
+            .add_part("post[meta]", json_part(r#"{"size": {"width": 640}}"#));
+        let response = server.post("/test").multipart(form).await;
+        assert_eq!(response.status_code(), StatusCode::BAD_REQUEST);
+        assert!(
+            response
+                .text()
+                .contains("cannot merge object into string for param `post[meta][size]`")
+        );
+    }
+
+    #[tokio::test]
+    async fn test_json_part_array_append() {
+        setup();
+        let app = Router::new().route(
+            "/test",
+            post(|Params(params, _): Params<serde_json::Value>| async move { Json(params) }),
+        );
+        let server = TestServer::new(app).unwrap();
+        let json_part = |json: &'static str| Part::text(json).mime_type("application/json");
+
+        // Each `[]` part appends an element, a JSON array appends all of its elements
+        let form = MultipartForm::new()
+            .add_text("post[attachments][][name]", "a.png")
+            .add_part(
+                "post[attachments][]",
+                json_part(r#"{"name": "b.png", "size": {"width": 640}}"#),
+            )
+            .add_part(
+                "post[attachments][]",
+                json_part(r#"[{"name": "c.png"}, {"name": "d.png"}]"#),
+            )
+            .add_part("post[tags][]", json_part(r#""rust""#))
+            .add_part("post[tags][]", json_part(r#"["axum", "serde"]"#));
+        let response = server.post("/test").multipart(form).await;
+        assert_eq!(response.status_code(), StatusCode::OK);
+        assert_eq!(
+            response.json::<serde_json::Value>(),
+            json!({
+                "post": {
+                    "attachments": [
+                        {"name": "a.png"},
+                        {"name": "b.png", "size": {"width": 640}},
+                        {"name": "c.png"},
+                        {"name": "d.png"},
+                    ],
+                    "tags": ["rust", "axum", "serde"],
+                },
+            })
+        );
+
+        let form = MultipartForm::new()
+            .add_text("post[attachments]", "none")
+            .add_part("post[attachments][]", json_part(r#"{"name": "a.png"}"#));
+        let response = server.post("/test").multipart(form).await;
+        assert_eq!(response.status_code(), StatusCode::BAD_REQUEST);
+    }
+
+    #[tokio::test]
+    async fn test_json_part() {
+        setup();
+        let app = Router::new().route("/test", post(complex_handler));
+        let server = TestServer::new(app).unwrap();
+
+        // Prepare multipart form data with empty field name
+        let form = MultipartForm::new()
+            .add_part(
+                "",
+                Part::text(
+                    r#"{
+                    "data": {
+                        "title": "Test Post",
+                        "tags": ["rust", "axum"],
+                        "version_number": 1.0,
+                        "metadata": {
+                            "version": "1.0",
+                            "author": "test"
+                        }
+                    },
+                    "profile": "Test profile",
+                    "filter": "active",
+                    "page": 1,
+                    "user_id": 123
+                }"#,
+                )
+                .mime_type("application/json"),
+            )
+            .add_part(
+                "avatar",
+                Part::bytes(vec![1, 2, 3, 4])
+                    .file_name("test-avatar.bin")
+                    .mime_type("application/octet-stream"),
+            );
+
+        let response = server.post("/test").multipart(form).await;
+        debug!("Request URL: {}", response.request_url());
+        debug!("Request headers: {:?}", response.headers());
+        debug!("Response status: {}", response.status_code());
+        debug!(
+            "Response body: {}",
+            String::from_utf8_lossy(&response.as_bytes())
+        );
+        assert_eq!(response.status_code(), StatusCode::OK);
+
+        let body: ComplexResponse = response.json();
+        assert_eq!(body.message, "Success");
+        assert_eq!(body.user_id, 123);
+        assert_eq!(body.filter, "active");
+        assert_eq!(body.page, Some(1));
+        assert_eq!(body.title, "Test Post");
+        assert_eq!(body.tags, vec!["rust", "axum"]);
+        assert_eq!(body.version_number, 1.0);
+        assert_eq!(body.metadata.get("version").unwrap(), "1.0");
+        assert_eq!(body.metadata.get("author").unwrap(), "test");
+        assert_eq!(body.profile, Some("Test profile".to_string()));
+        assert_eq!(body.avatar_size, 4);
+    }
+
+    #[derive(Debug, Deserialize)]
+    struct TestNumbers {
+        pos_int: u64,
+        neg_int: i64,
+        float: f64,
+        zero: i64,
+        big_num: u64,
+        small_float: f64,
+        exp_num: f64,
+    }
+
+    #[derive(Debug, Deserialize)]
+    struct TestMixed {
+        number: i64,
+        text: String,
+        boolean: bool,
+        opt_val: Option<String>,
+        numbers: Vec<f64>,
+        nested: TestNested,
+    }
+
+    #[derive(Debug, Deserialize)]
+    struct TestNested {
+        id: u64,
+        name: String,
+    }
+
+    #[tokio::test]
+    async fn test_json_numbers() {
+        setup();
+        let json = r#"{
+        "pos_int": 42,
+        "neg_int": -42,
+        "float": 42.5,
+        "zero": 0,
+        "big_num": 9007199254740991,
+        "small_float": 0.0000123,
+        "exp_num": 1.23e5
+    }"#;
+
+        let req = Request::builder()
+            .method(http::Method::POST)
+            .header(http::header::CONTENT_TYPE, "application/json")
+            .body(Body::from(json))
+            .unwrap();
+
+        let params = Params::<TestNumbers>::from_request(req, &()).await.unwrap();
+        assert_eq!(params.0.pos_int, 42);
+        assert_eq!(params.0.neg_int, -42);
+        assert!((params.0.float - 42.5).abs() < f64::EPSILON);
+        assert_eq!(params.0.zero, 0);
+        assert_eq!(params.0.big_num, 9007199254740991);
+        assert!((params.0.small_float - 0.0000123).abs() < f64::EPSILON);
+        assert!((params.0.exp_num - 123000.0).abs() < f64::EPSILON);
+    }
+
+    #[tokio::test]
+    async fn test_extraction_timings() {
+        setup();
+        let req = Request::builder()
+            .method(http::Method::POST)
+            .uri("/test?id=1")
+            .header(http::header::CONTENT_TYPE, "application/json")
+            .body(Body::from(r#"{"name": "test"}"#))
+            .unwrap();
+
+        let Params(params, metadata) = Params::<TestParams>::from_request(req, &()).await.unwrap();
+        assert_eq!(params.id, 1);
+        let timings = metadata.timings;
+        assert!(timings.json_parse > std::time::Duration::ZERO);
+        assert!(timings.deserialize > std::time::Duration::ZERO);
+        assert_eq!(timings.multipart_spool, std::time::Duration::ZERO);
+        assert_eq!(timings.total() - timings.body_read, timings.processing());
+    }
+
+    #[tokio::test]
+    async fn test_strict_parse_mode() {
+        setup();
+        let app = Router::new()
+            .route("/api/test", get(test_params_handler))
+            .layer(axum::Extension(
+                ParamsConfig::new().parse_mode(crate::query_parser::ParseMode::Strict),
+            ));
+        let server = TestServer::new(app).unwrap();
+
+        let response = server.get("/api/test?id=1&name=test").await;
+        assert_eq!(response.status_code(), StatusCode::OK);
+
+        let response = server.get("/api/test?id=1&name=test&extra]=x").await;
+        assert_eq!(response.status_code(), StatusCode::BAD_REQUEST);
+        assert!(response.text().contains("malformed key"));
+    }
+
+    #[tokio::test]
+    async fn test_max_params() {
+        setup();
+        let app = Router::new()
+            .route("/api/test", post(test_params_handler))
+            .layer(axum::Extension(ParamsConfig::new().max_params(2)));
+        let server = TestServer::new(app).unwrap();
+
+        let response = server.post("/api/test?id=1&name=test&extra=x").await;
+        assert_eq!(response.status_code(), StatusCode::BAD_REQUEST);
+        assert!(response.text().contains("Too many parameters"));
+
+        let response = server
+            .post("/api/test")
+            .multipart(
+                MultipartForm::new()
+                    .add_text("id", "1")
+                    .add_text("name", "test")
+                    .add_text("extra", "x"),
+            )
+            .await;
+        assert_eq!(response.status_code(), StatusCode::BAD_REQUEST);
+        assert!(response.text().contains("Too many parameters"));
+    }
+
+    #[tokio::test]
+    async fn test_multipart_part_limits() {
+        setup();
+        let app = Router::new()
+            .route("/api/test", post(test_params_handler))
+            .layer(axum::Extension(
+                ParamsConfig::new().max_parts(3).max_text_field_size(8),
+            ));
         let server = TestServer::new(app).unwrap();
 
-        let test_content = b"Hello, World!";
-        let test_content_str = String::from_utf8_lossy(test_content).to_string();
+        let response = server
+            .post("/api/test")
+            .multipart(
+                MultipartForm::new()
+                    .add_text("id", "1")
+                    .add_text("name", "test"),
+            )
+            .await;
+        assert_eq!(response.status_code(), StatusCode::OK);
 
         let response = server
-            .post("/api/upload")
-            .add_header(
-                axum::http::header::CONTENT_TYPE,
-                HeaderValue::from_static("multipart/form-data; boundary=X-BOUNDARY"),
+            .post("/api/test")
+            .multipart(
+                MultipartForm::new()
+                    .add_text("id", "1")
+                    .add_text("name", "test")
+                    .add_text("a", "x")
+                    .add_text("b", "x"),
             )
+            .await;
+        assert_eq!(response.status_code(), StatusCode::PAYLOAD_TOO_LARGE);
+        assert!(response.text().contains("more than 3 parts"));
+
+        let response = server
+            .post("/api/test")
             .multipart(
                 MultipartForm::new()
-                    .add_text("title", "Test Upload")
-                    .add_text("description", "A test file upload")
-                    .add_part(
-                        "file",
-                        Part::bytes(test_content.to_vec()).file_name("test.txt"),
-                    ),
+                    .add_text("id", "1")
+                    .add_text("name", "x".repeat(9)),
             )
             .await;
+        assert_eq!(response.status_code(), StatusCode::PAYLOAD_TOO_LARGE);
+        assert!(
+            response
+                .text()
+                .contains("Text field `name` exceeds the 8 bytes limit")
+        );
+    }
+
+    #[tokio::test]
+    async fn test_on_rejection() {
+        setup();
+        let config = ParamsConfig::new()
+            .max_params(2)
+            .on_rejection(|error, context| {
+                let body = json!({
+                    "path": context.uri.path(),
+                    "method": context.method.as_str(),
+                    "error": format!("{error:?}"),
+                });
+                (StatusCode::UNPROCESSABLE_ENTITY, Json(body)).into_response()
+            });
+        let app = Router::new()
+            .route("/api/test", post(test_params_handler))
+            .layer(axum::Extension(config));
+        let server = TestServer::new(app).unwrap();
+
+        let response = server.post("/api/test?id=1&name=test&extra=x").await;
+        assert_eq!(response.status_code(), StatusCode::UNPROCESSABLE_ENTITY);
+        let body: serde_json::Value = response.json();
+        assert_eq!(body["path"], "/api/test");
+        assert_eq!(body["method"], "POST");
+        assert!(
+            body["error"]
+                .as_str()
+                .unwrap()
+                .contains("Too many parameters")
+        );
 
+        // Deserialization errors go through the handler too
+        let response = server.post("/api/test?id=x&name=test").await;
+        assert_eq!(response.status_code(), StatusCode::UNPROCESSABLE_ENTITY);
+
+        let response = server.post("/api/test?id=1&name=test").await;
         assert_eq!(response.status_code(), StatusCode::OK);
-        let body = response.text();
-        let result: serde_json::Value = serde_json::from_str(&body).unwrap();
-        assert_eq!(result["title"], "Test Upload");
-        assert_eq!(result["description"], "A test file upload");
-        assert_eq!(result["file_name"], "test.txt");
-        assert_eq!(result["file_content"], test_content_str);
     }
 
     #[tokio::test]
-    async fn test_combined_params() {
-        let app = Router::new().route("/users/{id}", post(test_params_handler));
+    async fn test_params_with_rejection() {
+        setup();
+        struct ApiError(String);
+
+        impl From<Error> for ApiError {
+            fn from(error: Error) -> Self {
+                ApiError(error.message().to_string())
+            }
+        }
+
+        impl IntoResponse for ApiError {
+            fn into_response(self) -> axum::response::Response {
+                (
+                    StatusCode::UNPROCESSABLE_ENTITY,
+                    Json(json!({"error": self.0})),
+                )
+                    .into_response()
+            }
+        }
+
+        async fn handler(
+            ParamsWithRejection(test, _, _): ParamsWithRejection<TestParams, ApiError>,
+        ) -> impl IntoResponse {
+            Json(test)
+        }
+
+        let app = Router::new().route("/api/test", post(handler));
         let server = TestServer::new(app).unwrap();
 
-        let json_data = json!({
-            "name": "test"
-        });
+        let response = server.post("/api/test?id=x&name=test").await;
+        assert_eq!(response.status_code(), StatusCode::UNPROCESSABLE_ENTITY);
+        assert_eq!(
+            response.json::<serde_json::Value>(),
+            json!({"error": "Failed to deserialize parameters: invalid digit found in string"})
+        );
+
+        let response = server.post("/api/test?id=1&name=test").await;
+        assert_eq!(response.status_code(), StatusCode::OK);
+        assert_eq!(response.json::<serde_json::Value>()["id"], 1);
+    }
+
+    #[tokio::test]
+    async fn test_deny_unknown_fields() {
+        #[derive(Deserialize)]
+        struct UpdateUser {
+            name: String,
+            profile: Profile,
+        }
+
+        #[derive(Deserialize)]
+        struct Profile {
+            email: String,
+        }
+
+        let app = Router::new()
+            .route(
+                &crate::route_path("/users/{id}"),
+                post(|Params(user, _): Params<UpdateUser>| async move {
+                    format!("{}:{}", user.name, user.profile.email)
+                }),
+            )
+            .layer(axum::Extension(
+                ParamsConfig::new()
+                    .deny_unknown_fields(true)
+                    .problem_details(true),
+            ));
+        let server = TestServer::new(app).unwrap();
+
+        // The path parameter `id` isn't part of the contract
+        let body = json!({"name": "alice", "profile": {"email": "a@example.com"}});
+        let response = server.post("/users/1").json(&body).await;
+        assert_eq!(response.status_code(), StatusCode::OK);
+        assert_eq!(response.text(), "alice:a@example.com");
+
+        let body = json!({"name": "alice", "profile": {"email": "a@example.com", "admin": true}});
+        let response = server.post("/users/1?debug=1").json(&body).await;
+        assert_eq!(response.status_code(), StatusCode::BAD_REQUEST);
+        assert_eq!(
+            response.json::<serde_json::Value>(),
+            json!({
+                "type": "about:blank",
+                "title": "Bad Request",
+                "status": 400,
+                "detail": "Unknown parameters: `debug` from query, `profile[admin]` from json",
+                "errors": [
+                    {"field": "debug", "source": "query", "detail": "Unknown parameter"},
+                    {"field": "profile[admin]", "source": "json", "detail": "Unknown parameter"},
+                ],
+            })
+        );
+    }
+
+    #[tokio::test]
+    async fn test_problem_details() {
+        setup();
+        let app = Router::new()
+            .route("/api/test", post(test_params_handler))
+            .layer(axum::Extension(ParamsConfig::new().problem_details(true)));
+        let server = TestServer::new(app).unwrap();
+
+        let response = server.post("/api/test?id=1").await;
+        assert_eq!(response.status_code(), StatusCode::BAD_REQUEST);
+        assert_eq!(
+            response.header(http::header::CONTENT_TYPE),
+            "application/problem+json"
+        );
+        let detail = "Failed to deserialize parameters: missing field `name`";
+        assert_eq!(
+            response.json::<serde_json::Value>(),
+            json!({
+                "type": "about:blank",
+                "title": "Bad Request",
+                "status": 400,
+                "detail": detail,
+                "errors": [{"field": "name", "detail": detail}],
+            })
+        );
+    }
+
+    #[tokio::test]
+    async fn test_invalid_json_location() {
+        setup();
+        let app = Router::new()
+            .route("/api/test", post(test_params_handler))
+            .layer(axum::Extension(ParamsConfig::new().problem_details(true)));
+        let server = TestServer::new(app).unwrap();
 
         let response = server
-            .post("/users/123")
-            .add_query_params(&[("extra", "query_param")])
-            .json(&json_data)
+            .post("/api/test?id=1")
+            .bytes("{\n  \"name\": \"test\",\n  \"age\": tru,\n  \"tags\": []\n}".into())
+            .content_type("application/json")
             .await;
-        assert_eq!(response.status_code(), StatusCode::OK);
+        assert_eq!(response.status_code(), StatusCode::BAD_REQUEST);
+        let detail = "Syntax error: parse error:syntax error: the parsed text is not valid JSON \
+            at line 3, column 13 near `\"age\": tru,`";
+        assert_eq!(
+            response.json::<serde_json::Value>(),
+            json!({
+                "type": "about:blank",
+                "title": "Bad Request",
+                "status": 400,
+                "detail": detail,
+                "errors": [{
+                    "detail": detail,
+                    "line": 3,
+                    "column": 13,
+                    "offset": 32,
+                    "excerpt": "\"age\": tru,",
+                }],
+            })
+        );
+    }
 
-        let body = response.text();
-        let params: TestParams = serde_json::from_str(&body).unwrap();
-        assert_eq!(params.id, 123);
+    #[tokio::test]
+    async fn test_body_on_get() {
+        setup();
+        let request = |body_on_get| {
+            let mut req = Request::builder()
+                .method(http::Method::GET)
+                .uri("/test?id=1&name=query")
+                .header(http::header::CONTENT_TYPE, "application/json")
+                .body(Body::from(r#"{"name": "body"}"#))
+                .unwrap();
+            req.extensions_mut()
+                .insert(ParamsConfig::new().body_on_get(body_on_get));
+            req
+        };
+
+        let params = Params::<TestParams>::from_request(request(BodyOnGet::Ignore), &())
+            .await
+            .unwrap();
+        assert_eq!(params.0.name, "query");
+
+        let params = Params::<TestParams>::from_request(request(BodyOnGet::Parse), &())
+            .await
+            .unwrap();
+        assert_eq!(params.0.name, "body");
+
+        let err = Params::<TestParams>::from_request(request(BodyOnGet::Reject), &())
+            .await
+            .unwrap_err();
+        assert!(format!("{err:?}").contains("Request body is not allowed for GET requests"));
+    }
+
+    #[tokio::test]
+    async fn test_concurrency_limit() {
+        setup();
+        let config = ParamsConfig::new().concurrency_limit(1, std::time::Duration::from_millis(10));
+        let request = || {
+            let mut req = Request::builder()
+                .method(http::Method::POST)
+                .uri("/test?id=1")
+                .header(http::header::CONTENT_TYPE, "application/json")
+                .body(Body::from(r#"{"name": "test"}"#))
+                .unwrap();
+            req.extensions_mut().insert(config.clone());
+            req
+        };
+
+        let semaphore = config.concurrency.as_ref().unwrap().semaphore.clone();
+        let permit = semaphore.clone().try_acquire_owned().unwrap();
+        let err = Params::<TestParams>::from_request(request(), &())
+            .await
+            .unwrap_err();
+        assert_eq!(
+            err.into_response().status(),
+            StatusCode::SERVICE_UNAVAILABLE
+        );
+
+        drop(permit);
+        let Params(params, metadata) = Params::<TestParams>::from_request(request(), &())
+            .await
+            .unwrap();
         assert_eq!(params.name, "test");
-        assert_eq!(params.extra, Some("query_param".to_string()));
+        assert!(metadata.timings.queue < std::time::Duration::from_millis(10));
+        assert_eq!(semaphore.available_permits(), 1);
     }
 
     #[tokio::test]
-    async fn test_nested_params_with_file_upload() {
-        let app = Router::new().route("/api/posts", post(test_nested_params_handler));
+    async fn test_peek_in_middleware() {
+        setup();
+        #[derive(Deserialize)]
+        struct Owner {
+            owner_id: i32,
+        }
+
+        #[derive(Deserialize, Serialize)]
+        struct Post {
+            owner_id: i32,
+            title: String,
+        }
+
+        async fn authorize(
+            req: Request,
+            next: axum::middleware::Next,
+        ) -> Result<axum::response::Response, StatusCode> {
+            let (Params(owner, _), req) = Params::<Owner>::peek(req, &ParamsConfig::new())
+                .await
+                .map_err(|_| StatusCode::BAD_REQUEST)?;
+            if owner.owner_id != 1 {
+                return Err(StatusCode::FORBIDDEN);
+            }
+            Ok(next.run(req).await)
+        }
+
+        let app = Router::new()
+            .route(
+                "/api/posts",
+                post(|Params(post, _): Params<Post>| async move { Json(post) }),
+            )
+            .layer(axum::middleware::from_fn(authorize));
         let server = TestServer::new(app).unwrap();
 
-        // Create test file content
-        let cover_content = b"Cover image content";
-        let attachment1_content = b"Attachment 1 content";
-        let attachment2_content = b"Attachment 2 content";
+        let response = server
+            .post("/api/posts")
+            .json(&json!({"owner_id": 1, "title": "hello"}))
+            .await;
+        assert_eq!(response.status_code(), StatusCode::OK);
+        assert_eq!(response.json::<Post>().title, "hello");
 
-        // Create multipart form
         let response = server
             .post("/api/posts")
-            .add_header(
-                axum::http::header::CONTENT_TYPE,
-                HeaderValue::from_static("multipart/form-data; boundary=X-BOUNDARY"),
-            )
             .multipart(
                 MultipartForm::new()
-                    .add_text("title", "Test Post")
-                    .add_text("content", "This is a test post content")
-                    .add_text("tags[]", "tag1")
-                    .add_text("tags[]", "tag2")
-                    .add_text("tags[]", "tag3")
-                    .add_part(
-                        "cover",
-                        Part::bytes(cover_content.to_vec())
-                            .file_name("cover.jpg")
-                            .mime_type("image/jpeg"),
-                    )
-                    .add_text("attachments[][name]", "First attachment")
-                    .add_part(
-                        "attachments[][file]",
-                        Part::bytes(attachment1_content.to_vec())
-                            .file_name("attachment1.txt")
-                            .mime_type("text/plain"),
-                    )
-                    .add_text("attachments[][name]", "Second attachment")
-                    .add_part(
-                        "attachments[][file]",
-                        Part::bytes(attachment2_content.to_vec())
-                            .file_name("attachment2.txt")
-                            .mime_type("text/plain"),
-                    ),
+                    .add_text("owner_id", "1")
+                    .add_text("title", "multipart"),
             )
             .await;
-
         assert_eq!(response.status_code(), StatusCode::OK);
+        assert_eq!(response.json::<Post>().title, "multipart");
 
-        let body: CreatePostResponse = serde_json::from_str(&response.text()).unwrap();
-
-        // Verify the response
-        assert_eq!(body.title, "Test Post");
-        assert_eq!(body.content, "This is a test post content");
-        assert_eq!(body.tags, vec!["tag1", "tag2", "tag3"]);
-
-        // Verify cover file
-        assert_eq!(body.cover.name, "cover.jpg");
-        assert_eq!(body.cover.content_type, "image/jpeg");
-        assert_eq!(body.cover.content, String::from_utf8_lossy(cover_content));
+        let response = server
+            .post("/api/posts?owner_id=2")
+            .form(&[("title", "hello")])
+            .await;
+        assert_eq!(response.status_code(), StatusCode::FORBIDDEN);
+    }
 
-        // Verify attachments
-        assert_eq!(body.attachments.len(), 2);
+    #[tokio::test]
+    async fn test_query_path_params() {
+        setup();
+        #[derive(Deserialize, Serialize)]
+        struct Filter {
+            id: i32,
+            page: Option<u32>,
+        }
 
-        let attachment1 = &body.attachments[0];
-        assert_eq!(attachment1.name, "First attachment");
-        assert_eq!(attachment1.content_type, "text/plain");
-        assert_eq!(
-            attachment1.content,
-            String::from_utf8_lossy(attachment1_content)
+        let app = Router::new().route(
+            &crate::route_path("/users/{id}"),
+            post(
+                |QueryPathParams(filter, _): QueryPathParams<Filter>,
+                 Json(body): Json<serde_json::Value>| async move {
+                    Json(json!({"id": filter.id, "page": filter.page, "body": body}))
+                },
+            ),
         );
+        let server = TestServer::new(app).unwrap();
 
-        let attachment2 = &body.attachments[1];
-        assert_eq!(attachment2.name, "Second attachment");
-        assert_eq!(attachment2.content_type, "text/plain");
+        let response = server
+            .post("/users/7?page=2")
+            .json(&json!({"id": "ignored"}))
+            .await;
+        assert_eq!(response.status_code(), StatusCode::OK);
         assert_eq!(
-            attachment2.content,
-            String::from_utf8_lossy(attachment2_content)
+            response.json::<serde_json::Value>(),
+            json!({"id": 7, "page": 2, "body": {"id": "ignored"}})
         );
-    }
 
-    #[derive(Debug, Serialize, Deserialize)]
-    struct MixedAttachment {
-        name: String,
-        description: String,
-        file: Option<UploadFile>,
+        let response = server.post("/users/x").json(&json!({})).await;
+        assert_eq!(response.status_code(), StatusCode::BAD_REQUEST);
     }
 
-    #[derive(Debug, Serialize, Deserialize)]
-    struct MixedPostParams {
-        title: String,
-        content: String,
-        tags: Option<Vec<String>>,
-        attachments: Option<Vec<MixedAttachment>>,
-        author: Option<String>,
-        status: Option<String>,
-        metadata: Option<HashMap<String, String>>,
-    }
+    #[tokio::test]
+    async fn test_websocket_handshake_params() {
+        use axum::{
+            extract::ws::{Message, WebSocketUpgrade},
+            response::Response,
+        };
 
-    #[derive(Debug, Serialize, Deserialize)]
-    struct MixedAttachmentResponse {
-        name: String,
-        description: String,
-        file_size: u64,
-    }
+        setup();
+        #[derive(Deserialize)]
+        struct Join {
+            room_id: u64,
+            token: String,
+        }
 
-    #[derive(Debug, Serialize, Deserialize)]
-    struct MixedPostResponse {
-        message: String,
-        title: String,
-        content: String,
-        tags: Vec<String>,
-        author: String,
-        status: String,
-        metadata: HashMap<String, String>,
-        attachments: Vec<MixedAttachmentResponse>,
+        fn greet(ws: WebSocketUpgrade, join: Join) -> Response {
+            ws.on_upgrade(move |mut socket| async move {
+                let text = format!("{}:{}", join.room_id, join.token);
+                socket.send(Message::Text(text.into())).await.unwrap();
+            })
+        }
+
+        let app = Router::new()
+            .route(
+                &crate::route_path("/rooms/{room_id}/ws"),
+                get(|ws: WebSocketUpgrade, Params(join, _): Params<Join>| async move {
+                    greet(ws, join)
+                }),
+            )
+            .route(
+                &crate::route_path("/rooms/{room_id}/parts"),
+                get(
+                    |QueryPathParams(join, _): QueryPathParams<Join>, ws: WebSocketUpgrade| async move {
+                        greet(ws, join)
+                    },
+                ),
+            );
+        let server = TestServer::builder().http_transport().build(app).unwrap();
+
+        for path in ["/rooms/42/ws", "/rooms/42/parts"] {
+            let mut socket = server
+                .get_websocket(&format!("{path}?token=secret"))
+                .await
+                .into_websocket()
+                .await;
+            socket.assert_receive_text("42:secret").await;
+        }
+
+        // Invalid params reject the handshake before the upgrade
+        let response = server.get_websocket("/rooms/lobby/ws?token=secret").await;
+        assert_eq!(response.status_code(), StatusCode::BAD_REQUEST);
+        let response = server.get_websocket("/rooms/42/parts").await;
+        assert_eq!(response.status_code(), StatusCode::BAD_REQUEST);
     }
 
     #[tokio::test]
-    async fn test_mixed_create_post() {
+    async fn test_extension_params() {
         setup();
-        use tokio::io::AsyncReadExt;
+        #[derive(Clone, Serialize)]
+        struct UserId(i64);
 
-        let app = Router::new().route(
-            "/posts/{category}",
-            post(|params: Params<MixedPostParams>| async move {
-                debug!("params: {:#?}", params);
-                let MixedPostParams {
-                    title,
-                    content,
-                    tags,
-                    attachments,
-                    author,
-                    status,
-                    metadata,
-                } = params.0;
+        #[derive(Deserialize)]
+        struct NewPost {
+            user_id: i64,
+            title: String,
+        }
 
-                let mut response = MixedPostResponse {
-                    message: "Success".to_string(),
-                    title,
-                    content,
-                    tags: tags.unwrap_or_default(),
-                    author: author.unwrap_or_default(),
-                    status: status.unwrap_or_default(),
-                    metadata: metadata.unwrap_or_default(),
-                    attachments: Vec::new(),
-                };
+        async fn create_post(Params(post, metadata): Params<NewPost>) -> impl IntoResponse {
+            assert_eq!(metadata.sources["user_id"], Source::Extension);
+            format!("{}:{}", post.user_id, post.title)
+        }
 
-                if let Some(attachments) = attachments {
-                    response.attachments =
-                        futures_util::future::join_all(attachments.into_iter().map(|a| async {
-                            let size = if let Some(file) = a.file {
-                                let mut f = file.open().await.unwrap();
-                                let mut content = Vec::new();
-                                f.read_to_end(&mut content).await.unwrap();
-                                content.len() as u64
-                            } else {
-                                0
-                            };
+        // Layers only apply to the routes added before them
+        let app = Router::new()
+            .route("/posts", post(create_post))
+            .layer(axum::Extension(UserId(42)))
+            .route("/anonymous", post(create_post))
+            .layer(axum::Extension(
+                ParamsConfig::new().extension::<UserId>("user_id"),
+            ));
+        let server = TestServer::new(app).unwrap();
 
-                            MixedAttachmentResponse {
-                                name: a.name,
-                                description: a.description,
-                                file_size: size,
-                            }
-                        }))
-                        .await;
-                }
+        let response = server
+            .post("/posts?user_id=1")
+            .json(&json!({"user_id": 2, "title": "Hello"}))
+            .await;
+        assert_eq!(response.status_code(), StatusCode::OK);
+        assert_eq!(response.text(), "42:Hello");
 
-                Ok::<Json<MixedPostResponse>, Error>(Json(response))
-            }),
-        );
+        // Client values never stand in for a missing extension
+        let response = server
+            .post("/anonymous?user_id=1")
+            .json(&json!({"title": "Hello"}))
+            .await;
+        assert_eq!(response.status_code(), StatusCode::BAD_REQUEST);
+        assert!(response.text().contains("missing field `user_id`"));
+    }
 
+    #[tokio::test]
+    async fn test_security_policy() {
+        setup();
+        let app = Router::new()
+            .route("/api/test", post(test_params_handler))
+            .layer(axum::Extension(ParamsConfig::new().security_policy(
+                crate::SecurityPolicy::strict().max_body_size(64),
+            )));
         let server = TestServer::new(app).unwrap();
 
-        // Prepare base post data in empty-named JSON part
-        let base_json = r#"{
-        "title": "Mixed Test Post",
-        "content": "This is a test post with mixed data sources",
-        "tags": ["rust", "axum", "test"]
-    }"#;
+        let response = server.post("/api/test?id=1&name=test").await;
+        assert_eq!(response.status_code(), StatusCode::OK);
 
-        // Create multipart form with mixed data
-        let form = MultipartForm::new()
-            // Add base data as empty-named JSON part
-            .add_part("", Part::text(base_json).mime_type("application/json"))
-            // Add metadata fields individually
-            .add_part("metadata[version]", Part::text("2.0"))
-            .add_part("metadata[visibility]", Part::text("public"))
-            .add_part("metadata[created_at]", Part::text("2024-12-29"))
-            // Add first attachment with file and metadata
-            .add_part(
-                "attachments[][file]",
-                Part::bytes(vec![1, 2, 3, 4])
-                    .file_name("test1.bin")
-                    .mime_type("application/octet-stream"),
-            )
-            .add_part("attachments[][name]", Part::text("Test Attachment 1"))
-            .add_part(
-                "attachments[][description]",
-                Part::text("First test attachment"),
-            )
-            // Add second attachment with file and metadata
-            .add_part(
-                "attachments[][file]",
-                Part::bytes(vec![5, 6, 7, 8, 9])
-                    .file_name("test2.bin")
-                    .mime_type("application/octet-stream"),
-            )
-            .add_part("attachments[][name]", Part::text("Test Attachment 2"))
-            .add_part(
-                "attachments[][description]",
-                Part::text("Second test attachment"),
-            );
+        let response = server.post("/api/test?id=1&name=test&a[__proto__]=x").await;
+        assert_eq!(response.status_code(), StatusCode::BAD_REQUEST);
+        assert!(response.text().contains("is not allowed"));
+
+        let response = server.post("/api/test?id=1&name=%FF").await;
+        assert_eq!(response.status_code(), StatusCode::BAD_REQUEST);
+        assert!(response.text().contains("invalid UTF-8"));
 
-        // Add headers for author and status
         let response = server
-            .post("/posts/tech?author=test_user&status=draft")
-            .add_header(
-                axum::http::header::CONTENT_TYPE,
-                HeaderValue::from_static("multipart/form-data"),
-            )
-            .multipart(form)
+            .post(&format!("/api/test?id=1&name={}", "x".repeat(4096)))
             .await;
+        assert_eq!(response.status_code(), StatusCode::URI_TOO_LONG);
+        assert!(response.text().contains("exceeds the 4096 bytes limit"));
 
-        debug!("Request URL: {}", response.request_url());
-        debug!("Request headers: {:?}", response.headers());
-        debug!("Response status: {}", response.status_code());
-        debug!(
-            "Response body: {}",
-            String::from_utf8_lossy(&response.as_bytes())
-        );
+        let response = server
+            .post("/api/test")
+            .text("id=1&name=test")
+            .content_type("text/plain")
+            .await;
+        assert_eq!(response.status_code(), StatusCode::BAD_REQUEST);
+        assert!(response.text().contains("Unsupported content type"));
 
-        assert_eq!(response.status_code(), StatusCode::OK);
+        let response = server
+            .post("/api/test")
+            .json(&json!({"id": 1, "name": "x".repeat(64)}))
+            .await;
+        assert_eq!(response.status_code(), StatusCode::PAYLOAD_TOO_LARGE);
+    }
 
-        let body: MixedPostResponse = response.json();
+    #[tokio::test]
+    async fn test_json_body_limit_while_streaming() {
+        setup();
+        // An endless body, only rejected because the limit is checked while it is read
+        let chunks = std::iter::once(b"[".as_slice())
+            .chain(std::iter::repeat(b"1,".as_slice()))
+            .map(|chunk| Ok::<_, std::io::Error>(chunk.to_vec()));
+        let mut req = Request::builder()
+            .method(http::Method::POST)
+            .header(http::header::CONTENT_TYPE, "application/json")
+            .body(Body::from_stream(futures_util::stream::iter(chunks)))
+            .unwrap();
+        req.extensions_mut().insert(
+            ParamsConfig::new().security_policy(crate::SecurityPolicy::lenient().max_body_size(64)),
+        );
+        let result = RawParams::from_request(req, &()).await;
+        assert!(matches!(
+            result.map_err(Error::from),
+            Err(Error::PayloadTooLarge(e)) if e.contains("length limit exceeded")
+        ));
+
+        // Form bodies too, chunked bodies have no `Content-Length` to check up front
+        let chunks = std::iter::repeat(b"a=1&".as_slice())
+            .map(|chunk| Ok::<_, std::io::Error>(chunk.to_vec()));
+        let mut req = Request::builder()
+            .method(http::Method::POST)
+            .header(
+                http::header::CONTENT_TYPE,
+                "application/x-www-form-urlencoded",
+            )
+            .body(Body::from_stream(futures_util::stream::iter(chunks)))
+            .unwrap();
+        req.extensions_mut().insert(
+            ParamsConfig::new().security_policy(crate::SecurityPolicy::lenient().max_body_size(64)),
+        );
+        let result = RawParams::from_request(req, &()).await;
+        assert!(matches!(
+            result.map_err(Error::from),
+            Err(Error::PayloadTooLarge(e)) if e == "Failed to read form-urlencoded request body: length limit exceeded"
+        ));
+    }
 
-        // Verify base data from empty-named JSON part
-        assert_eq!(body.title, "Mixed Test Post");
-        assert_eq!(body.content, "This is a test post with mixed data sources");
-        assert_eq!(body.tags, vec!["rust", "axum", "test"]);
+    #[tokio::test]
+    async fn test_content_length_limit() {
+        use std::sync::{
+            Arc,
+            atomic::{AtomicBool, Ordering},
+        };
 
-        // Verify data from query parameters
-        assert_eq!(body.author, "test_user");
-        assert_eq!(body.status, "draft");
+        let request = |content_type: &str, length: usize, config: ParamsConfig| {
+            // Fails the test if the body is read at all
+            let polled = Arc::new(AtomicBool::new(false));
+            let stream = futures_util::stream::poll_fn({
+                let polled = polled.clone();
+                move |_| {
+                    polled.store(true, Ordering::SeqCst);
+                    std::task::Poll::Ready(None::<Result<Bytes, std::io::Error>>)
+                }
+            });
+            let mut req = Request::builder()
+                .method(http::Method::POST)
+                .header(http::header::CONTENT_TYPE, content_type)
+                .header(http::header::CONTENT_LENGTH, length)
+                .body(Body::from_stream(stream))
+                .unwrap();
+            req.extensions_mut().insert(config);
+            (req, polled)
+        };
+        let policy = crate::SecurityPolicy::lenient()
+            .max_body_size(64)
+            .max_file_size(128);
+
+        for content_type in ["application/json", "application/x-www-form-urlencoded"] {
+            let config = ParamsConfig::new().security_policy(policy.clone());
+            let (req, polled) = request(content_type, 65, config);
+            let error = Error::from(RawParams::from_request(req, &()).await.unwrap_err());
+            assert!(matches!(
+                &error,
+                Error::PayloadTooLarge(e) if e == "Request body of 65 bytes exceeds the 64 bytes limit"
+            ));
+            assert!(!polled.load(Ordering::SeqCst), "{content_type}");
+        }
 
-        // Verify metadata from named JSON part
-        assert_eq!(body.metadata.get("version").unwrap(), "2.0");
-        assert_eq!(body.metadata.get("visibility").unwrap(), "public");
-        assert_eq!(body.metadata.get("created_at").unwrap(), "2024-12-29");
+        // Raw bodies are limited like uploads, multipart bodies per part
+        let config = ParamsConfig::new()
+            .raw_body("body")
+            .security_policy(policy.clone());
+        let (req, polled) = request("application/octet-stream", 129, config.clone());
+        assert!(RawParams::from_request(req, &()).await.is_err());
+        assert!(!polled.load(Ordering::SeqCst));
+        let (req, polled) = request("application/octet-stream", 100, config);
+        assert!(RawParams::from_request(req, &()).await.is_ok());
+        assert!(polled.load(Ordering::SeqCst));
+
+        let config = ParamsConfig::new().security_policy(policy);
+        let (req, polled) = request("multipart/form-data; boundary=XYZ", 1000, config);
+        assert!(RawParams::from_request(req, &()).await.is_err());
+        assert!(polled.load(Ordering::SeqCst));
+    }
 
-        // Verify attachments from multipart form
-        assert_eq!(body.attachments.len(), 2);
+    #[allow(dead_code)]
+    #[derive(Debug, Deserialize)]
+    struct Credentials {
+        user: String,
+        api_secret: u64,
+    }
 
-        let attachment1 = &body.attachments[0];
-        assert_eq!(attachment1.name, "Test Attachment 1");
-        assert_eq!(attachment1.description, "First test attachment");
-        assert_eq!(attachment1.file_size, 4);
+    #[tokio::test]
+    async fn test_sensitive_keys() {
+        setup();
+        let request = |policy: crate::SecurityPolicy| {
+            let mut req = Request::builder()
+                .method(http::Method::POST)
+                .header(http::header::CONTENT_TYPE, "application/json")
+                .body(Body::from(r#"{"user": "alice", "api_secret": "hunter2"}"#))
+                .unwrap();
+            req.extensions_mut()
+                .insert(ParamsConfig::new().security_policy(policy));
+            req
+        };
 
-        let attachment2 = &body.attachments[1];
-        assert_eq!(attachment2.name, "Test Attachment 2");
-        assert_eq!(attachment2.description, "Second test attachment");
-        assert_eq!(attachment2.file_size, 5);
-    }
+        let error = Params::<Credentials>::from_request(request(Default::default()), &())
+            .await
+            .unwrap_err();
+        assert!(format!("{error:?}").contains("hunter2"));
 
-    #[derive(Debug, Serialize, Deserialize)]
-    struct ComplexParams {
-        // Path params
-        user_id: i32,
-        // Query params
-        filter: String,
-        page: Option<i32>,
-        // JSON body
-        data: ComplexData,
-        // Multipart form
-        avatar: UploadFile,
-        profile: Option<String>,
+        let policy = crate::SecurityPolicy::default().sensitive_keys(["password", "*_secret"]);
+        let error = Params::<Credentials>::from_request(request(policy), &())
+            .await
+            .unwrap_err();
+        let message = format!("{error:?}");
+        assert!(!message.contains("hunter2"), "{message}");
+        assert!(message.contains("[FILTERED]"), "{message}");
     }
 
-    #[derive(Debug, Serialize, Deserialize)]
-    struct ComplexData {
-        title: String,
-        tags: Vec<String>,
-        version_number: f64,
-        metadata: HashMap<String, String>,
-    }
+    #[tokio::test]
+    async fn test_field_sources() {
+        #[allow(dead_code)]
+        #[derive(Debug, Deserialize)]
+        struct User {
+            name: String,
+            email: String,
+        }
 
-    #[derive(Debug, Serialize, Deserialize)]
-    struct ComplexResponse {
-        message: String,
-        user_id: i32,
-        filter: String,
-        page: Option<i32>,
-        title: String,
-        tags: Vec<String>,
-        version_number: f64,
-        metadata: HashMap<String, String>,
-        avatar_size: u64,
-        profile: Option<String>,
-    }
+        #[allow(dead_code)]
+        #[derive(Debug, Deserialize)]
+        struct UpdateUser {
+            id: u64,
+            user: User,
+            tags: Vec<String>,
+        }
 
-    async fn complex_handler(
-        Params(params, _): Params<ComplexParams>,
-    ) -> Result<Json<ComplexResponse>, Error> {
-        let avatar_file = params
-            .avatar
-            .open()
-            .await
-            .map_err(|e| Error::ReadError(format!("Failed to open avatar file: {}", e)))?;
-        let avatar_size = avatar_file
-            .metadata()
+        let builder = || {
+            crate::test::ParamsBuilder::new()
+                .path("id", "7")
+                .query("user[name]", "alice")
+                .query("tags[]", "a")
+                .json(json!({"user": {"email": "alice@example.com"}}))
+        };
+        let params = builder()
+            .config(ParamsConfig::new().field_sources(true))
+            .build::<UpdateUser>()
             .await
-            .map_err(|e| Error::ReadError(format!("Failed to get avatar metadata: {}", e)))?
-            .len();
+            .unwrap();
+        assert_eq!(
+            params.sources(),
+            &HashMap::from([
+                ("id".to_string(), Source::Path),
+                ("user[name]".to_string(), Source::Query),
+                ("user[email]".to_string(), Source::Json),
+                ("tags[0]".to_string(), Source::Query),
+            ])
+        );
+        assert_eq!(params.1.sources["user"], Source::Json);
+        assert!(params.1.leaves.is_none());
 
-        Ok(Json(ComplexResponse {
-            message: "Success".to_string(),
-            user_id: params.user_id,
-            filter: params.filter,
-            page: params.page,
-            title: params.data.title,
-            tags: params.data.tags,
-            version_number: params.data.version_number,
-            metadata: params.data.metadata,
-            avatar_size,
-            profile: params.profile,
-        }))
+        let params = builder().build::<UpdateUser>().await.unwrap();
+        assert!(params.sources().is_empty());
     }
 
     #[tokio::test]
-    async fn test_complex_params() {
+    async fn test_merge_strategy() {
+        setup();
+        let config = ParamsConfig::new().merge_strategy(
+            crate::MergeStrategy::new().scalar_array(crate::ScalarArrayMerge::Promote),
+        );
+        let app = Router::new()
+            .route(
+                "/api/ids",
+                post(
+                    |Params(ids, metadata): Params<HashMap<String, Vec<u32>>>| async move {
+                        Json((ids, metadata.coercions))
+                    },
+                ),
+            )
+            .layer(axum::Extension(config));
+        let server = TestServer::new(app).unwrap();
+
+        let response = server
+            .post("/api/ids?id=1")
+            .json(&json!({"id": [2, 3]}))
+            .await;
+        assert_eq!(response.status_code(), StatusCode::OK);
+        let (ids, coercions): (HashMap<String, Vec<u32>>, Vec<String>) = response.json();
+        assert_eq!(ids["id"], vec![1, 2, 3]);
+        assert_eq!(
+            coercions,
+            vec!["`id`: promoted the string from query to an array"]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_nested_merge() {
         setup();
-        let app = Router::new().route("/users/{user_id}", post(complex_handler));
+        #[derive(Debug, Deserialize, Serialize, PartialEq)]
+        struct User {
+            name: String,
+            age: u32,
+            role: Option<String>,
+        }
+        #[derive(Deserialize, Serialize)]
+        struct Update {
+            user: User,
+        }
+
+        let app = Router::new().route(
+            "/users",
+            post(|Params(update, _): Params<Update>| async move { Json(update.user) }),
+        );
         let server = TestServer::new(app).unwrap();
 
-        // Prepare multipart form data
-        let avatar_content = "avatar data".as_bytes();
+        let response = server
+            .post("/users?user[age]=30")
+            .json(&json!({"user": {"name": "alice"}}))
+            .await;
+        assert_eq!(response.status_code(), StatusCode::OK);
+        assert_eq!(
+            response.json::<User>(),
+            User {
+                name: "alice".to_string(),
+                age: 30,
+                role: None
+            }
+        );
+
         let form = MultipartForm::new()
             .add_part(
-                "avatar",
-                Part::bytes(avatar_content.to_vec())
-                    .file_name("avatar.jpg")
-                    .mime_type("image/jpeg"),
+                "",
+                Part::text(r#"{"user": {"name": "alice"}}"#).mime_type("application/json"),
             )
-            .add_text("profile", "Test profile")
-            .add_part(
-                "data",
-                Part::text(
-                    r#"{
-            "title": "Test Post",
-            "tags": ["rust", "axum"],
-            "version_number": 1.0,
-            "metadata": {
-                "version": "1.0",
-                "author": "test"
+            .add_text("user[role]", "admin");
+        let response = server.post("/users?user[age]=30").multipart(form).await;
+        assert_eq!(response.status_code(), StatusCode::OK);
+        assert_eq!(
+            response.json::<User>(),
+            User {
+                name: "alice".to_string(),
+                age: 30,
+                role: Some("admin".to_string())
             }
-        }"#,
-                )
-                .mime_type("application/json"),
+        );
+    }
+
+    #[tokio::test]
+    async fn test_catch_all_params() {
+        setup();
+        let app = Router::new()
+            .route(
+                "/map",
+                get(
+                    |Params(params, _): Params<HashMap<String, Vec<String>>>| async move {
+                        Json(params)
+                    },
+                ),
+            )
+            .route(
+                "/pairs",
+                post(|Params(pairs, _): Params<Vec<(String, String)>>| async move { Json(pairs) }),
+            )
+            .route(
+                "/last",
+                get(
+                    |Params(params, _): Params<HashMap<String, String>>| async move {
+                        params["source"].clone()
+                    },
+                ),
             );
+        let server = TestServer::new(app).unwrap();
 
-        // Build the request with all parameter types
-        let user_id = 123;
+        // Bracketless repeats keep every value
         let response = server
-            .post(&format!("/users/{}", user_id))
-            .add_query_param("filter", "active")
-            .add_query_param("page", "1")
-            .multipart(form)
+            .get("/map?tag[]=a&tag[]=b&source=web&source=app&empty=&flag")
             .await;
-        debug!("Request URL: {}", response.request_url());
-        debug!("Request headers: {:?}", response.headers());
-        debug!("Response status: {}", response.status_code());
-        debug!(
-            "Response body: {}",
-            String::from_utf8_lossy(&response.as_bytes())
+        assert_eq!(response.status_code(), StatusCode::OK);
+        assert_eq!(
+            response.json::<HashMap<String, Vec<String>>>(),
+            HashMap::from([
+                ("tag".to_string(), vec!["a".to_string(), "b".to_string()]),
+                (
+                    "source".to_string(),
+                    vec!["web".to_string(), "app".to_string()]
+                ),
+                ("empty".to_string(), vec!["".to_string()]),
+                ("flag".to_string(), vec![]),
+            ])
         );
+
+        // Every pair in request order, the body after the query string
+        let response = server
+            .post("/pairs?b=2&source=web&a=1&filter[status]=open&tag[]=x&source=app&tag[]=y&flag")
+            .await;
         assert_eq!(response.status_code(), StatusCode::OK);
+        assert_eq!(
+            response.json::<Vec<(String, String)>>(),
+            [
+                ("b", "2"),
+                ("source", "web"),
+                ("a", "1"),
+                ("filter[status]", "open"),
+                ("tag[]", "x"),
+                ("source", "app"),
+                ("tag[]", "y"),
+                ("flag", ""),
+            ]
+            .map(|(k, v)| (k.to_string(), v.to_string()))
+        );
+        let response = server
+            .post("/pairs?z=1&source=web")
+            .form(&[("source", "app"), ("a", "2"), ("source", "ios")])
+            .await;
+        assert_eq!(
+            response.json::<Vec<(String, String)>>(),
+            [("z", "1"), ("source", "app"), ("a", "2"), ("source", "ios")]
+                .map(|(k, v)| (k.to_string(), v.to_string()))
+        );
 
-        let body: ComplexResponse = response.json();
-        assert_eq!(body.message, "Success");
-        assert_eq!(body.user_id, user_id);
-        assert_eq!(body.filter, "active");
-        assert_eq!(body.page, Some(1));
-        assert_eq!(body.title, "Test Post");
-        assert_eq!(body.tags, vec!["rust", "axum"]);
-        assert_eq!(body.metadata.get("version").unwrap(), "1.0");
-        assert_eq!(body.metadata.get("author").unwrap(), "test");
-        assert_eq!(body.avatar_size, avatar_content.len() as u64);
-        assert_eq!(body.profile, Some("Test profile".to_string()));
+        // Other targets keep the last value like Rack
+        let response = server.get("/last?source=web&source=app").await;
+        assert_eq!(response.text(), "app");
     }
 
     #[tokio::test]
-    async fn test_json_part() {
-        setup();
-        let app = Router::new().route("/test", post(complex_handler));
-        let server = TestServer::new(app).unwrap();
+    async fn test_extra_json_fields() {
+        #[derive(Deserialize, Serialize)]
+        struct UpdateUser {
+            name: String,
+            #[serde(flatten)]
+            extra: HashMap<String, serde_json::Value>,
+        }
 
-        // Prepare multipart form data with empty field name
-        let form = MultipartForm::new()
-            .add_part(
-                "",
-                Part::text(
-                    r#"{
-                    "data": {
-                        "title": "Test Post",
-                        "tags": ["rust", "axum"],
-                        "version_number": 1.0,
-                        "metadata": {
-                            "version": "1.0",
-                            "author": "test"
-                        }
-                    },
-                    "profile": "Test profile",
-                    "filter": "active",
-                    "page": 1,
-                    "user_id": 123
-                }"#,
-                )
-                .mime_type("application/json"),
+        let app = Router::new()
+            .route(
+                "/users",
+                post(|Params(user, _): Params<UpdateUser>| async move { Json(user.extra) }),
             )
-            .add_part(
-                "avatar",
-                Part::bytes(vec![1, 2, 3, 4])
-                    .file_name("test-avatar.bin")
-                    .mime_type("application/octet-stream"),
-            );
+            .layer(axum::Extension(
+                ParamsConfig::new().deny_unknown_fields(true),
+            ));
+        let server = TestServer::new(app).unwrap();
 
-        let response = server.post("/test").multipart(form).await;
-        debug!("Request URL: {}", response.request_url());
-        debug!("Request headers: {:?}", response.headers());
-        debug!("Response status: {}", response.status_code());
-        debug!(
-            "Response body: {}",
-            String::from_utf8_lossy(&response.as_bytes())
+        // Unknown keys of every source are kept instead of rejected
+        let body = json!({"name": "alice", "score": 1.5, "verified": true, "bio": null});
+        let response = server
+            .post("/users?ref=mail&meta[campaign]=spring&tags[]=a")
+            .json(&body)
+            .await;
+        assert_eq!(response.status_code(), StatusCode::OK);
+        assert_eq!(
+            response.json::<serde_json::Value>(),
+            json!({
+                "ref": "mail",
+                "meta": {"campaign": "spring"},
+                "tags": ["a"],
+                "score": 1.5,
+                "verified": true,
+                "bio": null,
+            })
         );
+
+        let response = server
+            .post("/users")
+            .form(&[("name", "alice"), ("locale", "fr")])
+            .await;
         assert_eq!(response.status_code(), StatusCode::OK);
+        assert_eq!(
+            response.json::<serde_json::Value>(),
+            json!({"locale": "fr"})
+        );
+    }
 
-        let body: ComplexResponse = response.json();
-        assert_eq!(body.message, "Success");
-        assert_eq!(body.user_id, 123);
-        assert_eq!(body.filter, "active");
-        assert_eq!(body.page, Some(1));
-        assert_eq!(body.title, "Test Post");
-        assert_eq!(body.tags, vec!["rust", "axum"]);
-        assert_eq!(body.version_number, 1.0);
-        assert_eq!(body.metadata.get("version").unwrap(), "1.0");
-        assert_eq!(body.metadata.get("author").unwrap(), "test");
-        assert_eq!(body.profile, Some("Test profile".to_string()));
-        assert_eq!(body.avatar_size, 4);
+    #[tokio::test]
+    async fn test_json_mixed_types() {
+        setup();
+        let json = r#"{
+        "number": 42,
+        "text": "hello world",
+        "boolean": true,
+        "opt_val": null,
+        "numbers": [1.1, 2.2, 3.3],
+        "nested": {
+            "id": 1,
+            "name": "test"
+        }
+    }"#;
+
+        let req = Request::builder()
+            .method(http::Method::POST)
+            .header(http::header::CONTENT_TYPE, "application/json")
+            .body(Body::from(json))
+            .unwrap();
+
+        let params = Params::<TestMixed>::from_request(req, &()).await.unwrap();
+        assert_eq!(params.0.number, 42);
+        assert_eq!(params.0.text, "hello world");
+        assert!(params.0.boolean);
+        assert!(params.0.opt_val.is_none());
+        assert_eq!(params.0.numbers.len(), 3);
+        assert!((params.0.numbers[0] - 1.1).abs() < f64::EPSILON);
+        assert!((params.0.numbers[1] - 2.2).abs() < f64::EPSILON);
+        assert!((params.0.numbers[2] - 3.3).abs() < f64::EPSILON);
+        assert_eq!(params.0.nested.id, 1);
+        assert_eq!(params.0.nested.name, "test");
     }
 
-    #[derive(Debug, Deserialize)]
-    struct TestNumbers {
-        pos_int: u64,
-        neg_int: i64,
-        float: f64,
-        zero: i64,
-        big_num: u64,
-        small_float: f64,
-        exp_num: f64,
+    #[tokio::test]
+    async fn test_form_urlencoded_numbers() {
+        setup();
+        let form_data = "pos_int=42&neg_int=-42&float=42.5&zero=0&big_num=9007199254740991&small_float=0.0000123&exp_num=123000";
+
+        let req = Request::builder()
+            .method(http::Method::POST)
+            .header(
+                http::header::CONTENT_TYPE,
+                "application/x-www-form-urlencoded",
+            )
+            .body(Body::from(form_data))
+            .unwrap();
+
+        let params = Params::<TestNumbers>::from_request(req, &()).await.unwrap();
+        assert_eq!(params.0.pos_int, 42);
+        assert_eq!(params.0.neg_int, -42);
+        assert!((params.0.float - 42.5).abs() < f64::EPSILON);
+        assert_eq!(params.0.zero, 0);
+        assert_eq!(params.0.big_num, 9007199254740991);
+        assert!((params.0.small_float - 0.0000123).abs() < f64::EPSILON);
+        assert!((params.0.exp_num - 123000.0).abs() < f64::EPSILON);
     }
 
-    #[derive(Debug, Deserialize)]
-    struct TestMixed {
-        number: i64,
-        text: String,
-        boolean: bool,
-        opt_val: Option<String>,
-        numbers: Vec<f64>,
-        nested: TestNested,
+    #[tokio::test]
+    async fn test_query_delimiters() {
+        setup();
+        let mut req = Request::builder()
+            .method(http::Method::POST)
+            .uri("/?id=1;extra=x")
+            .header(
+                http::header::CONTENT_TYPE,
+                "application/x-www-form-urlencoded",
+            )
+            .body(Body::from("name=test;extra=y"))
+            .unwrap();
+        req.extensions_mut()
+            .insert(ParamsConfig::new().query_delimiters("&;"));
+        let Params(params, _) = Params::<TestParams>::from_request(req, &()).await.unwrap();
+        assert_eq!(params.id, 1);
+        assert_eq!(params.name, "test");
+        assert_eq!(params.extra.as_deref(), Some("y"));
     }
 
     #[derive(Debug, Deserialize)]
-    struct TestNested {
-        id: u64,
+    struct BlankForm {
         name: String,
+        nickname: Option<String>,
+        age: Option<i32>,
+        count: u32,
+        admin: bool,
     }
 
     #[tokio::test]
-    async fn test_json_numbers() {
+    async fn test_empty_as_none() {
         setup();
-        let json = r#"{
-        "pos_int": 42,
-        "neg_int": -42,
-        "float": 42.5,
-        "zero": 0,
-        "big_num": 9007199254740991,
-        "small_float": 0.0000123,
-        "exp_num": 1.23e5
-    }"#;
+        let form_request = |config: Option<ParamsConfig>| {
+            let mut req = Request::builder()
+                .method(http::Method::POST)
+                .uri("/?age=")
+                .header(
+                    http::header::CONTENT_TYPE,
+                    "application/x-www-form-urlencoded",
+                )
+                .body(Body::from("name=&nickname=&count=&admin="))
+                .unwrap();
+            if let Some(config) = config {
+                req.extensions_mut().insert(config);
+            }
+            req
+        };
+
+        let Params(form, _) = Params::<BlankForm>::from_request(form_request(None), &())
+            .await
+            .unwrap();
+        assert_eq!(form.name, "");
+        assert_eq!(form.nickname, None);
+        assert_eq!(form.age, None);
+        assert_eq!(form.count, 0);
+        assert!(!form.admin);
+
+        let config = ParamsConfig::new().empty_as_none(false);
+        let result = Params::<BlankForm>::from_request(form_request(Some(config)), &()).await;
+        assert!(matches!(
+            result.map_err(Error::from),
+            Err(Error::DecodeError(_))
+        ));
+
+        // JSON strings are not coerced
+        let req = Request::builder()
+            .method(http::Method::POST)
+            .header(http::header::CONTENT_TYPE, "application/json")
+            .body(Body::from(r#"{"nickname": ""}"#))
+            .unwrap();
+        let Params(nickname, _) = Params::<HashMap<String, Option<String>>>::from_request(req, &())
+            .await
+            .unwrap();
+        assert_eq!(nickname["nickname"], Some("".to_string()));
+    }
+
+    #[tokio::test]
+    async fn test_number_format() {
+        #[derive(Debug, Deserialize)]
+        struct Invoice {
+            total: f64,
+            items: u32,
+            discount: Option<f32>,
+        }
+
+        let invoice = |total: &str, format: crate::NumberFormat| {
+            crate::test::ParamsBuilder::new()
+                .query("total", total)
+                .query("items", "1.200")
+                .query("discount", "")
+                .config(ParamsConfig::new().number_format(format))
+                .build::<Invoice>()
+        };
+
+        let Params(parsed, _) = invoice("1.234,56", crate::NumberFormat::DECIMAL_COMMA)
+            .await
+            .unwrap();
+        assert_eq!(parsed.total, 1234.56);
+        assert_eq!(parsed.items, 1200);
+        assert_eq!(parsed.discount, None);
+
+        let Params(parsed, _) = invoice("-0,5", crate::NumberFormat::DECIMAL_COMMA)
+            .await
+            .unwrap();
+        assert_eq!(parsed.total, -0.5);
+
+        // `.` groups thousands, `1.5` is ambiguous
+        let error = invoice("1.5", crate::NumberFormat::DECIMAL_COMMA)
+            .await
+            .unwrap_err();
+        assert!(
+            error.message().contains("misgrouped number `1.5`"),
+            "{error:?}"
+        );
+    }
 
-        let req = Request::builder()
-            .method(http::Method::POST)
-            .header(http::header::CONTENT_TYPE, "application/json")
-            .body(Body::from(json))
+    #[tokio::test]
+    async fn test_seq_separator() {
+        #[derive(Debug, Deserialize)]
+        struct Filter {
+            ids: Vec<u64>,
+            tags: Vec<String>,
+            #[serde(default)]
+            states: Vec<String>,
+        }
+
+        let filter = |config: ParamsConfig| {
+            crate::test::ParamsBuilder::new()
+                .query("ids", "1, 2,3")
+                .query("tags[]", "a,b")
+                .query("states", "")
+                .config(config)
+                .build::<Filter>()
+        };
+        let Params(parsed, _) = filter(ParamsConfig::new().split_seq(true)).await.unwrap();
+        assert_eq!(parsed.ids, vec![1, 2, 3]);
+        // Values sent as arrays are not split
+        assert_eq!(parsed.tags, vec!["a,b"]);
+        assert!(parsed.states.is_empty());
+
+        let Params(parsed, _) = crate::test::ParamsBuilder::new()
+            .query("ids", "1|2")
+            .query("tags", "a|b")
+            .config(ParamsConfig::new().seq_separator('|'))
+            .build::<Filter>()
+            .await
             .unwrap();
+        assert_eq!(parsed.ids, vec![1, 2]);
+        assert_eq!(parsed.tags, vec!["a", "b"]);
 
-        let params = Params::<TestNumbers>::from_request(req, &()).await.unwrap();
-        assert_eq!(params.0.pos_int, 42);
-        assert_eq!(params.0.neg_int, -42);
-        assert!((params.0.float - 42.5).abs() < f64::EPSILON);
-        assert_eq!(params.0.zero, 0);
-        assert_eq!(params.0.big_num, 9007199254740991);
-        assert!((params.0.small_float - 0.0000123).abs() < f64::EPSILON);
-        assert!((params.0.exp_num - 123000.0).abs() < f64::EPSILON);
+        // Off by default, the whole value is one item
+        let error = filter(ParamsConfig::new()).await.unwrap_err();
+        assert!(error.message().contains("invalid digit"), "{error:?}");
+        let error = filter(ParamsConfig::new().split_seq(true).split_seq(false))
+            .await
+            .unwrap_err();
+        assert!(error.message().contains("invalid digit"), "{error:?}");
     }
 
     #[tokio::test]
-    async fn test_json_mixed_types() {
-        setup();
-        let json = r#"{
-        "number": 42,
-        "text": "hello world",
-        "boolean": true,
-        "opt_val": null,
-        "numbers": [1.1, 2.2, 3.3],
-        "nested": {
-            "id": 1,
-            "name": "test"
+    async fn test_trim_strings() {
+        #[derive(Debug, Deserialize)]
+        struct SignUp {
+            email: String,
+            tags: Vec<String>,
+            age: Option<u32>,
+            bio: String,
         }
-    }"#;
 
-        let req = Request::builder()
-            .method(http::Method::POST)
-            .header(http::header::CONTENT_TYPE, "application/json")
-            .body(Body::from(json))
+        let sign_up = |config: ParamsConfig| {
+            crate::test::ParamsBuilder::new()
+                .query("email", " alice@example.com\n")
+                .query("tags[]", " a ")
+                .query("age", "  ")
+                .json(json!({"bio": " hi "}))
+                .config(config)
+                .build::<SignUp>()
+        };
+        let Params(parsed, _) = sign_up(ParamsConfig::new().trim_strings(true))
+            .await
+            .unwrap();
+        assert_eq!(parsed.email, "alice@example.com");
+        assert_eq!(parsed.tags, ["a"]);
+        assert_eq!(parsed.age, None);
+        assert_eq!(parsed.bio, " hi ");
+
+        let Params(parsed, _) = crate::test::ParamsBuilder::new()
+            .query("email", " alice@example.com ")
+            .query("age", " 42 ")
+            .query("nickname", " ")
+            .json(json!({"bio": " hi "}))
+            .build::<HashMap<String, crate::Trimmed<Option<String>>>>()
+            .await
+            .unwrap();
+        assert_eq!(parsed["email"].as_deref(), Some("alice@example.com"));
+        assert_eq!(parsed["age"].as_deref(), Some("42"));
+        assert_eq!(parsed["nickname"].as_deref(), None);
+        assert_eq!(parsed["bio"].as_deref(), Some("hi"));
+
+        let Params(age, _) = crate::test::ParamsBuilder::new()
+            .query("age", " 42 ")
+            .build::<HashMap<String, crate::Trimmed<u32>>>()
+            .await
             .unwrap();
+        assert_eq!(*age["age"], 42);
+    }
 
-        let params = Params::<TestMixed>::from_request(req, &()).await.unwrap();
-        assert_eq!(params.0.number, 42);
-        assert_eq!(params.0.text, "hello world");
-        assert!(params.0.boolean);
-        assert!(params.0.opt_val.is_none());
-        assert_eq!(params.0.numbers.len(), 3);
-        assert!((params.0.numbers[0] - 1.1).abs() < f64::EPSILON);
-        assert!((params.0.numbers[1] - 2.2).abs() < f64::EPSILON);
-        assert!((params.0.numbers[2] - 3.3).abs() < f64::EPSILON);
-        assert_eq!(params.0.nested.id, 1);
-        assert_eq!(params.0.nested.name, "test");
+    #[derive(Debug, Deserialize, Serialize)]
+    struct Checkboxes {
+        #[serde(default)]
+        admin: bool,
+        #[serde(default)]
+        active: bool,
+        #[serde(default)]
+        terms: bool,
     }
 
     #[tokio::test]
-    async fn test_form_urlencoded_numbers() {
+    async fn test_checkbox_params() {
         setup();
-        let form_data = "pos_int=42&neg_int=-42&float=42.5&zero=0&big_num=9007199254740991&small_float=0.0000123&exp_num=123000";
+        let handler = |Params(form, _): Params<Checkboxes>| async move { Json(form) };
+        let app = Router::new().route("/last", post(handler)).route(
+            "/any",
+            post(handler).layer(axum::Extension(
+                ParamsConfig::new().checkbox(crate::Checkbox::AnyChecked),
+            )),
+        );
+        let server = TestServer::new(app).unwrap();
 
-        let req = Request::builder()
-            .method(http::Method::POST)
-            .header(
-                http::header::CONTENT_TYPE,
-                "application/x-www-form-urlencoded",
-            )
-            .body(Body::from(form_data))
-            .unwrap();
+        let form = "admin=0&admin=1&active[]=0&active[]=on";
+        let response = server
+            .post("/last")
+            .text(form)
+            .content_type("application/x-www-form-urlencoded")
+            .await;
+        assert_eq!(response.status_code(), StatusCode::OK);
+        assert_eq!(
+            response.json::<serde_json::Value>(),
+            json!({"admin": true, "active": true, "terms": false})
+        );
 
-        let params = Params::<TestNumbers>::from_request(req, &()).await.unwrap();
-        assert_eq!(params.0.pos_int, 42);
-        assert_eq!(params.0.neg_int, -42);
-        assert!((params.0.float - 42.5).abs() < f64::EPSILON);
-        assert_eq!(params.0.zero, 0);
-        assert_eq!(params.0.big_num, 9007199254740991);
-        assert!((params.0.small_float - 0.0000123).abs() < f64::EPSILON);
-        assert!((params.0.exp_num - 123000.0).abs() < f64::EPSILON);
+        let form = "active[]=1&active[]=0&terms[]=0";
+        let response = server
+            .post("/last")
+            .text(form)
+            .content_type("application/x-www-form-urlencoded")
+            .await;
+        assert_eq!(
+            response.json::<serde_json::Value>(),
+            json!({"admin": false, "active": false, "terms": false})
+        );
+        let response = server
+            .post("/any")
+            .text(form)
+            .content_type("application/x-www-form-urlencoded")
+            .await;
+        assert_eq!(
+            response.json::<serde_json::Value>(),
+            json!({"admin": false, "active": true, "terms": false})
+        );
+    }
+
+    #[tokio::test]
+    async fn test_form_charset() {
+        setup();
+        let app = Router::new().route("/api/test", post(test_params_handler));
+        let server = TestServer::new(app).unwrap();
+
+        let response = server
+            .post("/api/test")
+            .text("id=1&name=caf%E9+cr%E8me&extra=a%26b&_charset_=ISO-8859-1")
+            .content_type("application/x-www-form-urlencoded")
+            .await;
+        assert_eq!(response.status_code(), StatusCode::OK);
+        assert_eq!(
+            response.json::<serde_json::Value>(),
+            json!({"id": 1, "name": "café crème", "extra": "a&b"})
+        );
+
+        let response = server
+            .post("/api/test")
+            .text("id=1&name=%93hi%94")
+            .content_type("application/x-www-form-urlencoded; charset=windows-1252")
+            .await;
+        assert_eq!(
+            response.json::<serde_json::Value>()["name"],
+            "\u{201c}hi\u{201d}"
+        );
+
+        let response = server
+            .post("/api/test")
+            .text("id=1&name=caf%C3%A9")
+            .content_type("application/x-www-form-urlencoded")
+            .await;
+        assert_eq!(response.json::<serde_json::Value>()["name"], "café");
+
+        let response = server
+            .post("/api/test")
+            .text("id=1&name=x&_charset_=klingon")
+            .content_type("application/x-www-form-urlencoded")
+            .await;
+        assert_eq!(response.status_code(), StatusCode::BAD_REQUEST);
+        assert!(
+            response
+                .text()
+                .contains("Unsupported form charset `klingon`")
+        );
     }
 
     #[tokio::test]
@@ -1313,7 +4390,10 @@ mod tests {
             (StatusCode::OK, serde_json::to_string(&response).unwrap())
         }
 
-        let app = Router::new().route("/payment/{currency}", post(path_handler));
+        let app = Router::new().route(
+            &crate::route_path("/payment/{currency}"),
+            post(path_handler),
+        );
         let server = TestServer::new(app).unwrap();
 
         let json_data = json!({
@@ -1361,4 +4441,500 @@ mod tests {
         assert_eq!(body["description"], "Form payment");
         assert_eq!(body["processed"], true);
     }
+
+    #[tokio::test]
+    async fn test_raw_body() {
+        #[derive(Debug, Deserialize)]
+        struct Upload {
+            name: String,
+            body: crate::Uploaded<Bytes>,
+        }
+
+        let request = |config: Option<ParamsConfig>| {
+            let mut req = Request::builder()
+                .method(http::Method::PUT)
+                .uri("/?name=report.bin")
+                .header(http::header::CONTENT_TYPE, "application/octet-stream")
+                .body(Body::from(&b"\x00\x01raw"[..]))
+                .unwrap();
+            if let Some(config) = config {
+                req.extensions_mut().insert(config);
+            }
+            req
+        };
+
+        // Ignored by default
+        let RawParams(params, _) = RawParams::from_request(request(None), &()).await.unwrap();
+        assert!(!params.contains_key("body"));
+
+        let config = ParamsConfig::new().raw_body("body");
+        let Params(upload, metadata) = Params::<Upload>::from_request(request(Some(config)), &())
+            .await
+            .unwrap();
+        assert_eq!(upload.name, "report.bin");
+        assert_eq!(&upload.body[..], b"\x00\x01raw");
+        assert_eq!(upload.body.file().content_type, "application/octet-stream");
+        assert_eq!(metadata.sources["body"], Source::Raw);
+        assert_eq!(metadata.sizes.body, 5);
+
+        // Limited like uploads
+        let config = ParamsConfig::new()
+            .raw_body("body")
+            .security_policy(SecurityPolicy::default().max_file_size(4));
+        let result = RawParams::from_request(request(Some(config)), &()).await;
+        assert!(
+            format!("{:?}", result.unwrap_err())
+                .contains("Uploaded file `body` exceeds the 4 bytes limit")
+        );
+    }
+
+    #[tokio::test]
+    async fn test_merge_sources() {
+        #[derive(Debug, Deserialize)]
+        struct UpdateUser {
+            id: Option<u64>,
+            name: Option<String>,
+            role: Option<String>,
+        }
+
+        let request = |config: ParamsConfig| {
+            crate::test::ParamsBuilder::new()
+                .path("id", "1")
+                .query("role", "admin")
+                .json(json!({"name": "alice"}))
+                .config(config)
+        };
+
+        let Params(user, _) = request(ParamsConfig::new())
+            .build::<UpdateUser>()
+            .await
+            .unwrap();
+        assert_eq!(user.id, Some(1));
+        assert_eq!(user.role.as_deref(), Some("admin"));
+
+        let Params(user, metadata) = request(ParamsConfig::new().merge_query(false))
+            .build::<UpdateUser>()
+            .await
+            .unwrap();
+        assert_eq!(user.id, Some(1));
+        assert_eq!(user.name.as_deref(), Some("alice"));
+        assert_eq!(user.role, None);
+        assert_eq!(metadata.sizes.query, 0);
+
+        let Params(user, _) = request(ParamsConfig::new().merge_path(false))
+            .build::<UpdateUser>()
+            .await
+            .unwrap();
+        assert_eq!(user.id, None);
+        assert_eq!(user.role.as_deref(), Some("admin"));
+
+        let Params(user, metadata) = request(ParamsConfig::new().merge_body(false))
+            .build::<UpdateUser>()
+            .await
+            .unwrap();
+        assert_eq!(user.name, None);
+        assert_eq!(user.role.as_deref(), Some("admin"));
+        assert_eq!(metadata.sizes.body, 0);
+
+        // Body-only
+        let RawParams(params, _) =
+            request(ParamsConfig::new().merge_path(false).merge_query(false))
+                .build_raw()
+                .await
+                .unwrap();
+        assert_eq!(params.keys().collect::<Vec<_>>(), ["name"]);
+    }
+
+    #[tokio::test]
+    async fn test_forbid_keys() {
+        #[derive(Debug, Deserialize)]
+        struct Account {
+            name: String,
+            #[serde(default)]
+            admin: bool,
+            profile: HashMap<String, String>,
+            #[serde(default)]
+            members: Vec<HashMap<String, String>>,
+        }
+
+        let request = |config: ParamsConfig| {
+            crate::test::ParamsBuilder::new()
+                .query("admin", "true")
+                .query("profile[is_superuser]", "1")
+                .json(json!({
+                    "name": "alice",
+                    "profile": {"bio": "hi", "is_superuser": "1"},
+                    "members": [{"name": "bob", "role": "owner"}],
+                }))
+                .config(config)
+        };
+
+        let config = ParamsConfig::new().forbid_keys(["admin", "*.is_superuser", "**.role"]);
+        let Params(account, metadata) = request(config.clone()).build::<Account>().await.unwrap();
+        assert_eq!(account.name, "alice");
+        assert!(!account.admin);
+        assert_eq!(
+            account.profile,
+            HashMap::from([("bio".into(), "hi".into())])
+        );
+        assert_eq!(
+            account.members,
+            [HashMap::from([("name".into(), "bob".into())])]
+        );
+        assert!(!metadata.sources.contains_key("admin"));
+
+        let error = request(config.reject_forbidden_keys(true))
+            .build::<Account>()
+            .await
+            .unwrap_err();
+        assert_eq!(error.to_string(), "Parameter `admin` is not allowed");
+    }
+
+    #[tokio::test]
+    async fn test_text_body() {
+        #[derive(Debug, Deserialize)]
+        struct Webhook {
+            source: String,
+            text: String,
+        }
+
+        let request = |content_type: &str, body: &'static [u8], config: ParamsConfig| {
+            let mut req = Request::builder()
+                .method(http::Method::POST)
+                .uri("/?source=alerts")
+                .header(http::header::CONTENT_TYPE, content_type)
+                .body(Body::from(body))
+                .unwrap();
+            req.extensions_mut().insert(config);
+            req
+        };
+
+        // Ignored by default
+        let req = request("text/plain", b"disk full", ParamsConfig::new());
+        let RawParams(params, _) = RawParams::from_request(req, &()).await.unwrap();
+        assert!(!params.contains_key("text"));
+
+        let config = ParamsConfig::new().text_body("text");
+        let req = request("text/plain", b"disk full", config.clone());
+        let Params(webhook, metadata) = Params::<Webhook>::from_request(req, &()).await.unwrap();
+        assert_eq!(webhook.source, "alerts");
+        assert_eq!(webhook.text, "disk full");
+        assert_eq!(metadata.sources["text"], Source::Raw);
+        assert_eq!(metadata.sizes.body, 9);
+
+        let req = request("text/plain; charset=iso-8859-1", b"caf\xe9", config.clone());
+        let Params(webhook, _) = Params::<Webhook>::from_request(req, &()).await.unwrap();
+        assert_eq!(webhook.text, "café");
+
+        // Other content types still go to `raw_body`
+        let req = request("text/csv", b"a,b", config.clone());
+        let RawParams(params, _) = RawParams::from_request(req, &()).await.unwrap();
+        assert!(!params.contains_key("text"));
+
+        let config = config.security_policy(SecurityPolicy::default().max_body_size(4));
+        let req = request("text/plain", b"disk full", config);
+        let result = RawParams::from_request(req, &()).await;
+        assert!(format!("{:?}", result.unwrap_err()).contains("Failed to read text request body"));
+
+        let config = ParamsConfig::new()
+            .text_body("text")
+            .security_policy(SecurityPolicy::strict());
+        let req = request("text/plain", b"disk full", config);
+        let result = RawParams::from_request(req, &()).await;
+        assert!(format!("{:?}", result.unwrap_err()).contains("Unsupported content type"));
+    }
+
+    #[tokio::test]
+    async fn test_capture_body() {
+        #[derive(Debug, Deserialize)]
+        struct Event {
+            kind: String,
+        }
+
+        let json = json!({"kind": "push"});
+        let params = crate::test::ParamsBuilder::new()
+            .json(json.clone())
+            .config(ParamsConfig::new().capture_body(1024))
+            .build::<Event>()
+            .await
+            .unwrap();
+        assert_eq!(params.0.kind, "push");
+        assert_eq!(
+            params.raw_body().map(|body| body.to_vec()),
+            Some(serde_json::to_vec(&json).unwrap())
+        );
+
+        let params = crate::test::ParamsBuilder::new()
+            .multipart_field("kind", "push")
+            .config(ParamsConfig::new().capture_body(1024))
+            .build::<Event>()
+            .await
+            .unwrap();
+        let body = params.raw_body().unwrap();
+        assert!(body.starts_with(b"--"), "{body:?}");
+
+        // Too large bodies are parsed but not kept, and nothing is kept by default
+        let params = crate::test::ParamsBuilder::new()
+            .json(json.clone())
+            .config(ParamsConfig::new().capture_body(4))
+            .build::<Event>()
+            .await
+            .unwrap();
+        assert_eq!(params.0.kind, "push");
+        assert_eq!(params.raw_body(), None);
+        let params = crate::test::ParamsBuilder::new()
+            .json(json)
+            .build::<Event>()
+            .await
+            .unwrap();
+        assert_eq!(params.raw_body(), None);
+    }
+
+    #[tokio::test]
+    async fn test_temp_dir() {
+        #[derive(Deserialize)]
+        struct Avatar {
+            avatar: UploadFile,
+        }
+
+        let dir = tempfile::tempdir().unwrap();
+        let config = ParamsConfig::new()
+            .temp_dir(dir.path())
+            .temp_file_prefix("avatar-")
+            .temp_file_suffix(".upload");
+        let Params(params, _) = crate::test::ParamsBuilder::new()
+            .multipart_file("avatar", &b"png"[..], "a.png")
+            .config(config)
+            .build::<Avatar>()
+            .await
+            .unwrap();
+        let path = std::path::Path::new(&params.avatar.temp_file_path);
+        assert_eq!(path.parent(), Some(dir.path()));
+        let name = path.file_name().unwrap().to_str().unwrap();
+        assert!(
+            name.starts_with("avatar-") && name.ends_with(".upload"),
+            "{name}"
+        );
+
+        // A missing directory fails the extraction
+        let config = ParamsConfig::new().temp_dir(dir.path().join("missing"));
+        let result = crate::test::ParamsBuilder::new()
+            .multipart_file("avatar", &b"png"[..], "a.png")
+            .config(config)
+            .build::<Avatar>()
+            .await;
+        assert!(matches!(result, Err(Error::IOError(..))));
+    }
+
+    #[tokio::test]
+    async fn test_upload_buffer() {
+        #[derive(Deserialize)]
+        struct Archive {
+            archive: UploadFile,
+        }
+
+        let bytes = (0..300_000).map(|i| (i % 251) as u8).collect::<Vec<_>>();
+        for config in [
+            ParamsConfig::new(),
+            ParamsConfig::new()
+                .upload_buffer_size(1024)
+                .sync_uploads(true),
+        ] {
+            // The metadata keeps the temp file
+            let Params(params, _metadata) = crate::test::ParamsBuilder::new()
+                .multipart_file("archive", bytes.clone(), "a.bin")
+                .config(config)
+                .build::<Archive>()
+                .await
+                .unwrap();
+            assert_eq!(
+                std::fs::read(&params.archive.temp_file_path).unwrap(),
+                bytes
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn test_upload_progress() {
+        use std::sync::{Arc, Mutex};
+
+        #[derive(Debug, Deserialize)]
+        struct Upload {
+            title: String,
+            file: UploadFile,
+        }
+
+        let request = |config: ParamsConfig| {
+            let mut req = crate::test::ParamsBuilder::new()
+                .multipart_field("title", "report")
+                .multipart_file("file", vec![7; 100_000], "a.bin")
+                .config(config)
+                .into_request();
+            let length = HttpBody::size_hint(req.body()).exact().unwrap();
+            req.headers_mut()
+                .insert(http::header::CONTENT_LENGTH, length.into());
+            (req, length)
+        };
+
+        let reports = Arc::new(Mutex::new(vec![]));
+        let config = ParamsConfig::new().on_upload_progress({
+            let reports = reports.clone();
+            move |progress| {
+                let report = (progress.field.to_string(), progress.bytes, progress.total);
+                reports.lock().unwrap().push(report);
+                Ok(())
+            }
+        });
+        let (req, length) = request(config);
+        let Params(upload, _) = Params::<Upload>::from_request(req, &()).await.unwrap();
+        assert_eq!(upload.title, "report");
+        assert_eq!(upload.file.name, "a.bin");
+        let reports = reports.lock().unwrap().clone();
+        assert!(!reports.is_empty());
+        assert!(reports.windows(2).all(|w| w[0].1 < w[1].1), "{reports:?}");
+        // The title is read before the file
+        assert_eq!(
+            reports.last().unwrap(),
+            &("file".to_string(), 6 + 100_000, Some(length))
+        );
+
+        // A quota stops the upload midway
+        let config = ParamsConfig::new().on_upload_progress(|progress| match progress.bytes {
+            bytes if bytes > 50_000 => Err(Error::Forbidden("Upload quota exceeded".to_string())),
+            _ => Ok(()),
+        });
+        let (req, _) = request(config);
+        let error = Params::<Upload>::from_request(req, &())
+            .await
+            .map_err(Error::from)
+            .unwrap_err();
+        assert!(matches!(error, Error::Forbidden(_)), "{error:?}");
+    }
+
+    #[tokio::test]
+    async fn test_blocking_parse_threshold() {
+        #[derive(Debug, PartialEq, Deserialize)]
+        struct Post {
+            title: String,
+            tags: Vec<String>,
+        }
+
+        let request = |content_type, body: &str, config: ParamsConfig| {
+            let mut req = Request::builder()
+                .method(http::Method::POST)
+                .header(http::header::CONTENT_TYPE, content_type)
+                .body(Body::from(body.to_string()))
+                .unwrap();
+            req.extensions_mut().insert(config);
+            req
+        };
+        let json = r#"{"title": "Hello", "tags": ["rust", "axum"]}"#;
+        let form = "title=Hello&tags[]=rust&tags[]=axum";
+        for threshold in [0, 1 << 20] {
+            let config = ParamsConfig::new().blocking_parse_threshold(threshold);
+            for (content_type, body) in [
+                ("application/json", json),
+                ("application/x-www-form-urlencoded", form),
+            ] {
+                let Params(post, metadata) =
+                    Params::<Post>::from_request(request(content_type, body, config.clone()), &())
+                        .await
+                        .unwrap();
+                assert_eq!(post.tags, ["rust", "axum"], "{content_type}");
+                assert_eq!(metadata.sizes.body, body.len());
+            }
+        }
+
+        let config = ParamsConfig::new()
+            .blocking_parse_threshold(0)
+            .security_policy(crate::SecurityPolicy::lenient().max_body_size(16));
+        let error = Params::<Post>::from_request(request("application/json", json, config), &())
+            .await
+            .unwrap_err();
+        assert!(
+            error.to_string().contains("length limit exceeded"),
+            "{error}"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_from_request_seed() {
+        use std::sync::Arc;
+
+        /// The fields of a record kind, in order, from a registry known at runtime.
+        #[derive(Clone)]
+        struct Schema(Arc<Vec<&'static str>>);
+
+        impl<'de> DeserializeSeed<'de> for Schema {
+            type Value = Vec<String>;
+
+            fn deserialize<D: Deserializer<'de>>(
+                self,
+                deserializer: D,
+            ) -> Result<Self::Value, D::Error> {
+                let mut record = HashMap::<String, String>::deserialize(deserializer)?;
+                if let Some(key) = record.keys().find(|key| !self.0.contains(&key.as_str())) {
+                    return Err(::serde::de::Error::custom(format!("unknown field `{key}`")));
+                }
+                Ok(self
+                    .0
+                    .iter()
+                    .map(|field| record.remove(*field).unwrap_or_default())
+                    .collect())
+            }
+        }
+
+        let schema = Schema(Arc::new(vec!["title", "body"]));
+        let app = Router::new().route(
+            "/records",
+            post(move |req: Request| async move {
+                let Params(record, _) = Params::from_request_seed(req, &(), schema).await?;
+                Ok::<_, Rejection>(record.join("|"))
+            }),
+        );
+        let server = TestServer::new(app).unwrap();
+
+        let response = server
+            .post("/records?title=Hello")
+            .json(&json!({"body": "World"}))
+            .await;
+        assert_eq!(response.status_code(), StatusCode::OK);
+        assert_eq!(response.text(), "Hello|World");
+
+        let response = server.post("/records?author=me").await;
+        assert_eq!(response.status_code(), StatusCode::BAD_REQUEST);
+        assert!(response.text().contains("unknown field `author`"));
+    }
+
+    #[tokio::test]
+    async fn test_upload_part_headers() {
+        #[derive(Deserialize)]
+        struct Chunk {
+            chunk: UploadFile,
+        }
+
+        let body = "--X\r\n\
+            Content-Disposition: form-data; name=\"chunk\"; filename=\"a.bin\"\r\n\
+            Content-Type: application/octet-stream\r\n\
+            Upload-Offset: 1024\r\n\
+            X-Chunk-Id: 7\r\n\r\n\
+            data\r\n\
+            --X--\r\n";
+        let req = Request::builder()
+            .method(http::Method::POST)
+            .header(
+                http::header::CONTENT_TYPE,
+                "multipart/form-data; boundary=X",
+            )
+            .body(Body::from(body))
+            .unwrap();
+        let Params(params, _) = Params::<Chunk>::from_request(req, &()).await.unwrap();
+        assert_eq!(params.chunk.header("upload-offset"), Some("1024"));
+        assert_eq!(params.chunk.header("X-Chunk-Id"), Some("7"));
+        assert_eq!(
+            params.chunk.header("content-disposition"),
+            Some("form-data; name=\"chunk\"; filename=\"a.bin\"")
+        );
+        assert_eq!(params.chunk.headers().len(), 4);
+    }
 }
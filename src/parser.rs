@@ -0,0 +1,744 @@
+use std::collections::HashMap;
+
+use ::serde::de::DeserializeOwned;
+use axum::body::{Body, Bytes};
+use futures_core::Stream;
+use tracing::{Instrument, Span, debug, debug_span, field};
+
+use crate::{
+    Error, Params, ParamsConfig, ParamsMetadata, SecurityPolicy, Source, Value, deserialize_params,
+    encoding::decode_text,
+    json::JsonPushParser,
+    merge_patch::is_merge_patch,
+    metadata::SentKey,
+    params::{
+        PartNames, extract_part, parse_blocking, parse_form, record_key, record_keys,
+        remove_temp_file, top_level_keys,
+    },
+    query_parser::{QueryParser, QueryParserError},
+    security::glob_match,
+    timed, timed_async,
+};
+
+/// Merges parameters like the `Params` extractor without an axum `Request`, for Lambda
+/// adapters, test harnesses or other frameworks:
+///
+/// ```ignore
+/// let mut parser = ParamsParser::new(ParamsConfig::new());
+/// parser.add_path_params([("id".to_string(), "1".to_string())])?;
+/// parser.add_query("tags[]=a&tags[]=b")?;
+/// parser.add_json(br#"{"name": "alice"}"#.as_slice()).await?;
+/// let Params(user, metadata) = parser.finish::<User>().await?;
+/// ```
+///
+/// Each source is merged over the ones added before, add them in the order of a request:
+/// path parameters, the query string, then the body. The limits of the `SecurityPolicy` of
+/// the config apply to each source, `ParamsConfig::extension` and the options reading the
+/// request itself, like `body_on_get` or `capture_body`, don't.
+pub struct ParamsParser {
+    pub(crate) config: ParamsConfig,
+    pub(crate) parser: QueryParser,
+    pub(crate) params: HashMap<String, Value>,
+    pub(crate) metadata: ParamsMetadata,
+}
+
+/// The bodies parsed into params, other content types are ignored or, with
+/// `ParamsConfig::raw_body`, kept as a file.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum BodyKind {
+    Json,
+    Form,
+    Multipart,
+    Text,
+}
+
+impl BodyKind {
+    pub(crate) fn of(content_type: &str, config: &ParamsConfig) -> Option<Self> {
+        match content_type {
+            ct if ct.starts_with("application/json") || is_merge_patch(ct) => Some(Self::Json),
+            ct if ct.starts_with("application/x-www-form-urlencoded") => Some(Self::Form),
+            ct if ct.starts_with("multipart/form-data") => Some(Self::Multipart),
+            ct if ct.starts_with("text/plain") && config.text_body.is_some() => Some(Self::Text),
+            _ => None,
+        }
+    }
+
+    /// The name of the kind in errors.
+    pub(crate) fn name(self) -> &'static str {
+        match self {
+            Self::Json => "JSON",
+            Self::Form => "form-urlencoded",
+            Self::Multipart => "multipart",
+            Self::Text => "text",
+        }
+    }
+}
+
+impl Default for ParamsParser {
+    fn default() -> Self {
+        Self::new(ParamsConfig::default())
+    }
+}
+
+impl ParamsParser {
+    pub fn new(config: ParamsConfig) -> Self {
+        let parser = config.query_parser();
+        let metadata = ParamsMetadata {
+            leaves: config.field_sources.then(HashMap::new),
+            ..Default::default()
+        };
+        Self {
+            config,
+            parser,
+            params: HashMap::new(),
+            metadata,
+        }
+    }
+
+    /// Adds the parameters of the route, like `id` of `/users/{id}`.
+    pub fn add_path_params(
+        &mut self,
+        params: impl IntoIterator<Item = (String, String)>,
+    ) -> Result<(), Error> {
+        let held = self.hold_priorities(Source::Path);
+        let mut keys = vec![];
+        for (key, value) in params {
+            self.parser
+                .parse_nested_value(&mut self.params, key.as_str(), Value::xstr(value))
+                .map_err(|e| Error::params("Failed to parse path parameters", e))?;
+            record_key(&mut self.metadata.sources, &self.parser, &key, Source::Path);
+            keys.push(key);
+        }
+        debug!(?keys, "path params");
+        self.restore_priorities(held, Source::Path);
+        self.strip_forbidden_keys()?;
+        self.metadata.track_field_sources(&self.params);
+        self.check_size()
+    }
+
+    /// Adds a query string, without the `?`.
+    pub fn add_query(&mut self, query: &str) -> Result<(), Error> {
+        let policy = &self.config.security;
+        self.metadata.sizes.query = query.len();
+        if query.len() > policy.max_query_len {
+            return Err(Error::QueryTooLong(format!(
+                "Query string of {} bytes exceeds the {} bytes limit",
+                query.len(),
+                policy.max_query_len
+            )));
+        }
+        let held = self.hold_priorities(Source::Query);
+        let (sources, sent) = (&mut self.metadata.sources, &mut self.metadata.sent);
+        self.parser
+            .parse_nested_query_with(&mut self.params, query, |top, key, value| {
+                sources.insert(top.clone(), Source::Query);
+                sent.push(SentKey::new(Source::Query, top, key, value));
+            })
+            .map_err(|e| Error::params("Failed to parse query parameters", e))?;
+        self.restore_priorities(held, Source::Query);
+        self.strip_forbidden_keys()?;
+        self.metadata.track_field_sources(&self.params);
+        self.check_size()
+    }
+
+    /// Adds a JSON body, parsed on the blocking thread pool from the
+    /// `ParamsConfig::blocking_parse_threshold`.
+    pub async fn add_json(&mut self, bytes: impl Into<Bytes>) -> Result<(), Error> {
+        let bytes = bytes.into();
+        let policy = &self.config.security;
+        if bytes.len() > policy.max_body_size {
+            return Err(Error::PayloadTooLarge(
+                "Failed to read JSON request body: length limit exceeded".to_string(),
+            ));
+        }
+        let read = bytes.len();
+        let timings = &mut self.metadata.timings;
+        let value = if self.config.parses_blocking(read as u64) {
+            let policy = policy.clone();
+            let parse = parse_blocking(move || {
+                let mut json = JsonPushParser::new(&policy);
+                json.push(&bytes)?;
+                json.finish()
+            });
+            timed_async(&mut timings.json_parse, parse).await??
+        } else {
+            timed(&mut timings.json_parse, || {
+                let mut json = JsonPushParser::new(policy);
+                json.push(&bytes)?;
+                json.finish()
+            })?
+        };
+        self.merge_json(value, read)
+    }
+
+    /// Merges a JSON body of `read` bytes parsed into `value`.
+    pub(crate) fn merge_json(&mut self, value: Value, read: usize) -> Result<(), Error> {
+        Span::current().record("bytes", read);
+        self.metadata.sizes.body = read;
+        let keys = top_level_keys(&value);
+        debug!(?keys, "parsed JSON body");
+        let held = self.hold_priorities(Source::Json);
+        self.config
+            .merge
+            .merge_into(
+                &mut self.params,
+                value,
+                Source::Json,
+                &self.metadata.sources,
+                &mut self.metadata.coercions,
+            )
+            .map_err(|e| Error::DecodeError(format!("Failed to merge JSON data: {e}")))?;
+        record_keys(&mut self.metadata.sources, keys, Source::Json);
+        self.restore_priorities(held, Source::Json);
+        self.strip_forbidden_keys()?;
+        self.check_size()
+    }
+
+    /// Adds an `application/x-www-form-urlencoded` body in UTF-8, or in the charset of its
+    /// `_charset_` field.
+    pub async fn add_form(&mut self, bytes: impl Into<Bytes>) -> Result<(), Error> {
+        self.merge_form("application/x-www-form-urlencoded", bytes.into())
+            .await
+    }
+
+    /// Merges a form body sent with the content type `ct`, which can name its charset.
+    pub(crate) async fn merge_form(&mut self, ct: &str, bytes: Bytes) -> Result<(), Error> {
+        Span::current().record("bytes", bytes.len());
+        self.metadata.sizes.body = bytes.len();
+        let strict = self.config.security.strict_utf8;
+        let held = self.hold_priorities(Source::Form);
+        let sent = if self.config.parses_blocking(bytes.len() as u64) {
+            let (ct, parser) = (ct.to_string(), self.parser.clone());
+            let mut params = std::mem::take(&mut self.params);
+            let (params, sent) = parse_blocking(move || {
+                parse_form(&ct, &bytes, &parser, strict, &mut params).map(|sent| (params, sent))
+            })
+            .await??;
+            self.params = params;
+            sent
+        } else {
+            parse_form(ct, &bytes, &self.parser, strict, &mut self.params)?
+        };
+        for key in &sent {
+            self.metadata.sources.insert(key.top.clone(), Source::Form);
+        }
+        self.metadata.sent.extend(sent);
+        self.restore_priorities(held, Source::Form);
+        self.strip_forbidden_keys()?;
+        self.check_size()
+    }
+
+    /// Adds a `text/plain` body sent with the content type `ct` as a string at `key`, see
+    /// `ParamsConfig::text_body`.
+    pub(crate) fn merge_text(&mut self, key: &str, ct: &str, bytes: Bytes) -> Result<(), Error> {
+        Span::current().record("bytes", bytes.len());
+        self.metadata.sizes.body = bytes.len();
+        let text = decode_text(ct, &bytes, self.config.security.strict_utf8)?;
+        let held = self.hold_priorities(Source::Raw);
+        self.parser
+            .parse_nested_value(&mut self.params, key, Value::xstr(text))
+            .map_err(|e| Error::params("Failed to parse text body", e))?;
+        record_key(&mut self.metadata.sources, &self.parser, key, Source::Raw);
+        self.restore_priorities(held, Source::Raw);
+        self.strip_forbidden_keys()?;
+        self.check_size()
+    }
+
+    /// Adds a buffered body sent with the content type `content_type`, parsed like the body
+    /// of the `Params` extractor: JSON and JSON Merge Patch, form-urlencoded, multipart and,
+    /// with `ParamsConfig::text_body`, `text/plain`. Other content types are ignored.
+    pub async fn add_body(
+        &mut self,
+        content_type: &str,
+        bytes: impl Into<Bytes>,
+    ) -> Result<(), Error> {
+        let bytes = bytes.into();
+        if !self.config.security.allows_content_type(content_type) {
+            return Err(Error::DecodeError(format!(
+                "Unsupported content type: {content_type}"
+            )));
+        }
+        match BodyKind::of(content_type, &self.config) {
+            Some(BodyKind::Json) => self.add_json(bytes).await,
+            Some(BodyKind::Form) => self.merge_form(content_type, bytes).await,
+            Some(BodyKind::Multipart) => {
+                let boundary = multer::parse_boundary(content_type).map_err(|e| {
+                    Error::DecodeError(format!("Failed to parse multipart boundary: {e}"))
+                })?;
+                self.metadata.body_length = Some(bytes.len() as u64);
+                let stream = Body::from(bytes).into_data_stream();
+                self.add_multipart(stream, boundary).await
+            }
+            Some(BodyKind::Text) => {
+                let key = self.config.text_body.clone().unwrap_or_default();
+                self.merge_text(&key, content_type, bytes)
+            }
+            None => {
+                debug!(content_type, "unhandled content type");
+                Ok(())
+            }
+        }
+    }
+
+    /// Adds a `multipart/form-data` body, streamed from `stream` and split at `boundary`,
+    /// the boundary parameter of the content type. Files are spooled to temp files owned by
+    /// the `ParamsMetadata`.
+    ///
+    /// When the body fails, e.g. the client disconnects mid-upload or a limit is exceeded,
+    /// the temp files of its uploads are removed before the error is returned.
+    pub async fn add_multipart<S, O, E>(
+        &mut self,
+        stream: S,
+        boundary: impl Into<String>,
+    ) -> Result<(), Error>
+    where
+        S: Stream<Item = Result<O, E>> + Send + 'static,
+        O: Into<Bytes> + 'static,
+        E: Into<Box<dyn std::error::Error + Send + Sync>> + 'static,
+    {
+        let uploaded = self.metadata.temp_files.len();
+        let held = self.hold_priorities(Source::Multipart);
+        let result = self.read_multipart(stream, boundary).await;
+        self.restore_priorities(held, Source::Multipart);
+        let result = result
+            .and_then(|()| self.strip_forbidden_keys())
+            .and_then(|()| self.check_size());
+        if result.is_err() {
+            let temp_files = self.metadata.temp_files.drain(uploaded..);
+            debug!(count = temp_files.len(), "removing uploads of failed body");
+            temp_files.for_each(remove_temp_file);
+        }
+        result
+    }
+
+    async fn read_multipart<S, O, E>(
+        &mut self,
+        stream: S,
+        boundary: impl Into<String>,
+    ) -> Result<(), Error>
+    where
+        S: Stream<Item = Result<O, E>> + Send + 'static,
+        O: Into<Bytes> + 'static,
+        E: Into<Box<dyn std::error::Error + Send + Sync>> + 'static,
+    {
+        let policy = &self.config.security;
+        let mut multipart = multer::Multipart::new(stream, boundary);
+        let mut field_count = 0;
+        let mut total_bytes = 0;
+        let mut parts = PartNames::default();
+        // Grown by each part, so the limit applies before the rest of the body is read
+        self.metadata.sizes.params = params_size(&self.params);
+
+        while let Some(field) =
+            timed_async(&mut self.metadata.timings.body_read, multipart.next_field())
+                .await
+                .map_err(|e| Error::ReadError(format!("Failed to read multipart field: {e}",)))?
+        {
+            field_count += 1;
+            if field_count > policy.max_parts {
+                return Err(Error::PayloadTooLarge(format!(
+                    "Multipart body has more than {} parts",
+                    policy.max_parts
+                )));
+            }
+            if field_count > policy.max_params {
+                return Err(Error::params(
+                    "Failed to parse multipart body",
+                    QueryParserError::TooManyParameters(policy.max_params),
+                ));
+            }
+            let span = debug_span!(
+                "params.multipart.field",
+                index = field_count,
+                name = field.name(),
+                file_name = field.file_name(),
+                content_type = field.content_type().map(|ct| ct.essence_str()),
+                bytes = field::Empty,
+            );
+            total_bytes += extract_part(
+                field,
+                field_count,
+                &mut parts,
+                &self.config,
+                &self.parser,
+                &mut self.params,
+                &mut self.metadata,
+            )
+            .instrument(span)
+            .await?;
+            // The bytes before the next part, for `ParamsConfig::on_upload_progress`
+            self.metadata.sizes.body = total_bytes;
+        }
+        Span::current().record("bytes", total_bytes);
+        self.metadata.sizes.multipart_fields = field_count;
+        Ok(())
+    }
+
+    /// The fields of `MergeStrategy::field_priority` whose value comes from a source that
+    /// outranks `source`, with that value and source.
+    fn hold_priorities(&self, source: Source) -> Vec<(String, Value, Source)> {
+        let merge = &self.config.merge;
+        merge
+            .priorities
+            .keys()
+            .filter_map(|key| {
+                let old = *self.metadata.sources.get(key)?;
+                let value = self
+                    .params
+                    .get(key)
+                    .filter(|_| merge.keeps(key, old, source))?;
+                Some((key.clone(), value.clone(), old))
+            })
+            .collect()
+    }
+
+    /// Puts back the values `hold_priorities` kept from being replaced by `source`.
+    fn restore_priorities(&mut self, held: Vec<(String, Value, Source)>, source: Source) {
+        for (key, value, old) in held {
+            if self.params.get(&key) != Some(&value) {
+                self.metadata.coercions.push(format!(
+                    "`{key}`: kept the value from {old}, dropped the value from {source} by priority"
+                ));
+            }
+            self.metadata.sources.insert(key.clone(), old);
+            self.params.insert(key, value);
+        }
+    }
+
+    /// Removes the keys of `ParamsConfig::forbid_keys`, or rejects them with
+    /// `ParamsConfig::reject_forbidden_keys`.
+    fn strip_forbidden_keys(&mut self) -> Result<(), Error> {
+        if self.config.forbidden_keys.is_empty() {
+            return Ok(());
+        }
+        let patterns = self
+            .config
+            .forbidden_keys
+            .iter()
+            .map(|key| key.split('.').collect::<Vec<_>>())
+            .collect::<Vec<_>>();
+        let patterns = patterns.iter().map(Vec::as_slice).collect::<Vec<_>>();
+        let mut stripped = vec![];
+        strip_keys(&mut self.params, "", &patterns, &mut stripped);
+        stripped.sort();
+        if let Some(key) = stripped
+            .first()
+            .filter(|_| self.config.reject_forbidden_keys)
+        {
+            return Err(Error::DecodeError(format!(
+                "Parameter `{key}` is not allowed"
+            )));
+        }
+        if !stripped.is_empty() {
+            debug!(keys = ?stripped, "stripped forbidden keys");
+            let params = &self.params;
+            self.metadata
+                .sources
+                .retain(|key, _| params.contains_key(key));
+        }
+        Ok(())
+    }
+
+    /// Rejects merged params larger than `SecurityPolicy::max_params_size`.
+    fn check_size(&mut self) -> Result<(), Error> {
+        self.metadata.sizes.params = params_size(&self.params);
+        check_params_size(self.metadata.sizes.params, &self.config.security)
+    }
+
+    /// The merged parameters before deserialization, like `RawParams`.
+    pub fn into_raw(mut self) -> (HashMap<String, Value>, ParamsMetadata) {
+        // The last source merged, sources after the query string are told apart by their keys
+        self.metadata.track_field_sources(&self.params);
+        self.metadata.leaves = None;
+        (self.params, self.metadata)
+    }
+
+    /// Deserializes the merged parameters like `Params`.
+    pub async fn finish<T: DeserializeOwned>(self) -> Result<Params<T>, Error> {
+        let config = self.config.clone();
+        let (params, mut metadata) = self.into_raw();
+        let payload = deserialize_params(params, &mut metadata, &config).await?;
+        Ok(Params(payload, metadata))
+    }
+}
+
+fn params_size(params: &HashMap<String, Value>) -> usize {
+    params
+        .iter()
+        .map(|(key, value)| key.len() + value.byte_size())
+        .sum()
+}
+
+pub(crate) fn check_params_size(size: usize, policy: &SecurityPolicy) -> Result<(), Error> {
+    let limit = policy.max_params_size;
+    if size > limit {
+        return Err(Error::PayloadTooLarge(format!(
+            "Parameters of {size} bytes exceed the {limit} bytes limit"
+        )));
+    }
+    Ok(())
+}
+
+/// Removes the keys of `map`, at the bracket path `path`, that match one of `patterns`,
+/// collecting the paths removed in `stripped`.
+fn strip_keys(
+    map: &mut HashMap<String, Value>,
+    path: &str,
+    patterns: &[&[&str]],
+    stripped: &mut Vec<String>,
+) {
+    map.retain(|key, value| {
+        let path = match path {
+            "" => key.clone(),
+            path => format!("{path}[{key}]"),
+        };
+        let mut nested = vec![];
+        if patterns
+            .iter()
+            .any(|pattern| match_key(pattern, key, &mut nested))
+        {
+            stripped.push(path);
+            return false;
+        }
+        if !nested.is_empty() {
+            strip_nested(value, &path, &nested, stripped);
+        }
+        true
+    });
+}
+
+fn strip_nested(value: &mut Value, path: &str, patterns: &[&[&str]], stripped: &mut Vec<String>) {
+    match value {
+        Value::Object(map) => strip_keys(map, path, patterns, stripped),
+        Value::Array(vec) => {
+            let path = format!("{path}[]");
+            for value in vec {
+                strip_nested(value, &path, patterns, stripped);
+            }
+        }
+        _ => {}
+    }
+}
+
+/// Whether `pattern` ends at `key`, pushing the rest of it to match the keys below `key`.
+fn match_key<'a>(pattern: &'a [&'a str], key: &str, nested: &mut Vec<&'a [&'a str]>) -> bool {
+    match pattern {
+        ["**", rest @ ..] => {
+            nested.push(pattern);
+            match_key(rest, key, nested)
+        }
+        [first, rest @ ..] if glob_match(first, key) => {
+            if rest.is_empty() {
+                return true;
+            }
+            nested.push(rest);
+            false
+        }
+        _ => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use ::serde::Deserialize;
+    use futures_util::stream;
+
+    use super::*;
+
+    #[derive(Debug, Deserialize, PartialEq)]
+    struct User {
+        id: u64,
+        name: String,
+        tags: Vec<String>,
+    }
+
+    #[tokio::test]
+    async fn test_sources() {
+        let mut parser = ParamsParser::new(ParamsConfig::new().field_sources(true));
+        parser
+            .add_path_params([("id".to_string(), "1".to_string())])
+            .unwrap();
+        parser.add_query("tags[]=a&tags[]=b&name=query").unwrap();
+        parser
+            .add_json(br#"{"name": "alice"}"#.as_slice())
+            .await
+            .unwrap();
+        let Params(user, metadata) = parser.finish::<User>().await.unwrap();
+        assert_eq!(
+            user,
+            User {
+                id: 1,
+                name: "alice".to_string(),
+                tags: vec!["a".to_string(), "b".to_string()],
+            }
+        );
+        assert_eq!(metadata.sources["id"], Source::Path);
+        assert_eq!(metadata.sources["tags"], Source::Query);
+        assert_eq!(metadata.field_sources["name"], Source::Json);
+
+        let mut parser = ParamsParser::default();
+        parser.add_query("id=2&tags[]=c").unwrap();
+        parser.add_form("name=bob&tags[]=d").await.unwrap();
+        let Params(user, _) = parser.finish::<User>().await.unwrap();
+        assert_eq!(user.name, "bob");
+        assert_eq!(user.tags, ["c", "d"]);
+
+        let mut parser =
+            ParamsParser::new(ParamsConfig::new().security_policy(crate::SecurityPolicy {
+                max_query_len: 4,
+                ..Default::default()
+            }));
+        assert!(matches!(
+            parser.add_query("id=12"),
+            Err(Error::QueryTooLong(_))
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_field_priority() {
+        let strategy = crate::MergeStrategy::new()
+            .field_priority("status", [Source::Query])
+            .field_priority("owner", [Source::Json, Source::Form, Source::Query]);
+        let config = ParamsConfig::new().merge_strategy(strategy);
+
+        let mut parser = ParamsParser::new(config.clone());
+        parser.add_query("status=open&owner[id]=1&page=2").unwrap();
+        parser
+            .add_json(br#"{"status": "closed", "owner": {"name": "alice"}, "page": 3}"#.as_slice())
+            .await
+            .unwrap();
+        let (params, metadata) = parser.into_raw();
+        // The query string wins for `status`, the body for `owner` and the fields not listed
+        assert_eq!(params["status"], Value::xstr("open"));
+        assert_eq!(metadata.sources["status"], Source::Query);
+        assert_eq!(
+            params["owner"],
+            Value::from(&serde_json::json!({"id": "1", "name": "alice"}))
+        );
+        assert_eq!(params["page"], Value::number(3u64));
+        assert_eq!(
+            metadata.coercions,
+            ["`status`: kept the value from query, dropped the value from json by priority"]
+        );
+
+        // Whatever the order the sources are added in
+        let mut parser = ParamsParser::new(config);
+        parser.add_form("owner[id]=2&status=pending").await.unwrap();
+        parser.add_query("owner[id]=1&status=open").unwrap();
+        let (params, metadata) = parser.into_raw();
+        assert_eq!(
+            params["owner"],
+            Value::from(&serde_json::json!({"id": "2"}))
+        );
+        assert_eq!(metadata.sources["owner"], Source::Form);
+        assert_eq!(params["status"], Value::xstr("open"));
+    }
+
+    #[tokio::test]
+    async fn test_max_params_size() {
+        let policy = crate::SecurityPolicy::default()
+            .max_query_len(16)
+            .max_body_size(32)
+            .max_params_size(20);
+        let mut parser = ParamsParser::new(ParamsConfig::new().security_policy(policy));
+        parser.add_query("name=alice").unwrap();
+        assert_eq!(parser.metadata.sizes.params, 9);
+        // Each source is within its own limit, together they aren't
+        let result = parser
+            .add_json(br#"{"bio": "hello world!"}"#.as_slice())
+            .await;
+        assert!(matches!(
+            result,
+            Err(Error::PayloadTooLarge(message)) if message == "Parameters of 24 bytes exceed the 20 bytes limit"
+        ));
+
+        // Multipart parts count as they are read, the body never ends here
+        let policy = crate::SecurityPolicy::lenient().max_params_size(20);
+        let mut parser = ParamsParser::new(ParamsConfig::new().security_policy(policy));
+        let part = |i| {
+            format!("--XYZ\r\nContent-Disposition: form-data; name=\"f{i}\"\r\n\r\n0123456789\r\n")
+        };
+        let chunks = (0..3)
+            .map(|i| Ok::<_, std::io::Error>(Bytes::from(part(i))))
+            .collect::<Vec<_>>();
+        let body = futures_util::StreamExt::chain(stream::iter(chunks), stream::pending());
+        let result = tokio::time::timeout(
+            std::time::Duration::from_secs(5),
+            parser.add_multipart(body, "XYZ"),
+        )
+        .await
+        .expect("the limit applies before the body ends");
+        assert!(
+            matches!(&result, Err(Error::PayloadTooLarge(message)) if message == "Parameters of 24 bytes exceed the 20 bytes limit"),
+            "{result:?}"
+        );
+
+        // Numbers and files count one byte, nested keys their own bytes
+        let value = Value::from(&serde_json::json!({"user": {"id": 1, "tags": ["ab"]}}));
+        assert_eq!(value.byte_size(), 4 + 2 + 1 + 4 + 2);
+    }
+
+    #[tokio::test]
+    async fn test_multipart() {
+        let body = "--XYZ\r\n\
+            Content-Disposition: form-data; name=\"name\"\r\n\r\n\
+            alice\r\n\
+            --XYZ\r\n\
+            Content-Disposition: form-data; name=\"avatar\"; filename=\"a.png\"\r\n\
+            Content-Type: image/png\r\n\r\n\
+            PNG\r\n\
+            --XYZ--\r\n";
+        let chunks = body
+            .as_bytes()
+            .chunks(7)
+            .map(|chunk| Ok::<_, std::io::Error>(Bytes::copy_from_slice(chunk)))
+            .collect::<Vec<_>>();
+        let mut parser = ParamsParser::default();
+        parser.add_query("id=1").unwrap();
+        parser
+            .add_multipart(stream::iter(chunks), "XYZ")
+            .await
+            .unwrap();
+        let (params, metadata) = parser.into_raw();
+        assert_eq!(params["name"], Value::xstr("alice"));
+        let Value::UploadFile(avatar) = &params["avatar"] else {
+            panic!("Expected a file");
+        };
+        assert_eq!(std::fs::read(avatar.path()).unwrap(), b"PNG");
+        assert_eq!(metadata.sizes.multipart_fields, 2);
+        assert_eq!(metadata.sources["avatar"], Source::Multipart);
+    }
+
+    #[tokio::test]
+    async fn test_multipart_disconnect() {
+        let body = "--XYZ\r\n\
+            Content-Disposition: form-data; name=\"avatar\"; filename=\"a.png\"\r\n\
+            Content-Type: image/png\r\n\r\n\
+            PNG\r\n\
+            --XYZ\r\n\
+            Content-Disposition: form-data; name=\"photo\"; filename=\"b.png\"\r\n\
+            Content-Type: image/png\r\n\r\n\
+            PNG";
+        let dir = tempfile::tempdir().unwrap();
+        let config = ParamsConfig::new().temp_dir(dir.path());
+        let uploads = || std::fs::read_dir(dir.path()).unwrap().count();
+
+        // The client goes away in the middle of the second file
+        let chunks = vec![
+            Ok(Bytes::from(body)),
+            Err(std::io::Error::from(std::io::ErrorKind::ConnectionReset)),
+        ];
+        let mut parser = ParamsParser::new(config.clone());
+        let result = parser.add_multipart(stream::iter(chunks), "XYZ").await;
+        assert!(matches!(result, Err(Error::ReadError(_))));
+        assert!(parser.metadata.temp_files.is_empty());
+        assert_eq!(uploads(), 0);
+
+        // The stream ends without the closing boundary
+        let chunks = vec![Ok::<_, std::io::Error>(Bytes::from(body))];
+        let mut parser = ParamsParser::new(config);
+        let result = parser.add_multipart(stream::iter(chunks), "XYZ").await;
+        assert!(matches!(result, Err(Error::ReadError(_))));
+        assert_eq!(uploads(), 0);
+    }
+}
@@ -0,0 +1,391 @@
+use std::{
+    collections::{HashMap, HashSet},
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use base64::{Engine, engine::general_purpose::STANDARD as BASE64};
+use hmac::{Hmac, Mac};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use sha2::Sha256;
+
+use crate::{Error, Value};
+
+type HmacSha256 = Hmac<Sha256>;
+
+/// A policy document for browser direct uploads, like S3's POST policy.
+///
+/// The server signs it with `PostPolicySigner::sign` and renders the result as the `policy`
+/// and `signature` fields of the upload form, `PostPolicySigner::verify` checks the submitted
+/// form against it.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct PostPolicy {
+    /// UTC expiry like `2025-01-31T12:00:00Z`.
+    pub expiration: String,
+    /// S3 style conditions: `{"field": "value"}`, `["eq", "$field", "value"]`,
+    /// `["starts-with", "$field", "prefix"]` and `["content-length-range", min, max]`.
+    pub conditions: Vec<serde_json::Value>,
+}
+
+impl PostPolicy {
+    pub fn new(expires_at: SystemTime) -> Self {
+        let secs = expires_at
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_secs();
+        Self {
+            expiration: format_utc(secs),
+            conditions: vec![],
+        }
+    }
+
+    /// Expires `ttl` from now.
+    pub fn expires_in(ttl: Duration) -> Self {
+        Self::new(SystemTime::now() + ttl)
+    }
+
+    /// Form field `field` must be `value`.
+    pub fn equals(mut self, field: &str, value: &str) -> Self {
+        self.conditions
+            .push(json!(["eq", format!("${field}"), value]));
+        self
+    }
+
+    /// Form field `field` must start with `prefix`, an empty prefix allows any value.
+    ///
+    /// `Content-Type` is checked against the content type of the uploaded file.
+    pub fn starts_with(mut self, field: &str, prefix: &str) -> Self {
+        self.conditions
+            .push(json!(["starts-with", format!("${field}"), prefix]));
+        self
+    }
+
+    /// The uploaded file must be between `min` and `max` bytes.
+    pub fn content_length_range(mut self, min: u64, max: u64) -> Self {
+        self.conditions
+            .push(json!(["content-length-range", min, max]));
+        self
+    }
+}
+
+/// The form fields of a signed `PostPolicy`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SignedPostPolicy {
+    /// Base64 encoded policy document.
+    pub policy: String,
+    /// Base64 encoded HMAC-SHA256 of `policy`.
+    pub signature: String,
+}
+
+/// Signs `PostPolicy` documents and verifies uploads against them.
+#[derive(Clone)]
+pub struct PostPolicySigner {
+    secret: Vec<u8>,
+}
+
+impl std::fmt::Debug for PostPolicySigner {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("PostPolicySigner").finish_non_exhaustive()
+    }
+}
+
+impl PostPolicySigner {
+    pub fn new(secret: impl Into<Vec<u8>>) -> Self {
+        Self {
+            secret: secret.into(),
+        }
+    }
+
+    fn mac(&self) -> HmacSha256 {
+        HmacSha256::new_from_slice(&self.secret).expect("HMAC takes keys of any size")
+    }
+
+    pub fn sign(&self, policy: &PostPolicy) -> SignedPostPolicy {
+        let policy = BASE64.encode(serde_json::to_vec(policy).expect("policy is valid JSON"));
+        let mut mac = self.mac();
+        mac.update(policy.as_bytes());
+        SignedPostPolicy {
+            signature: BASE64.encode(mac.finalize().into_bytes()),
+            policy,
+        }
+    }
+
+    /// Checks the `policy` and `signature` fields of `params`, typically `RawParams`, and
+    /// the upload in `file_field` against the signed policy.
+    ///
+    /// Like S3, every other form field must be covered by a condition, except fields named
+    /// `x-ignore-*`. Failures are `Error::Forbidden`.
+    pub async fn verify(
+        &self,
+        params: &HashMap<String, Value>,
+        file_field: &str,
+    ) -> Result<PostPolicy, Error> {
+        let field = |name: &str| match params.get(name) {
+            Some(Value::XStr(s) | Value::String(s)) => Some(s.as_str()),
+            _ => None,
+        };
+
+        let (Some(policy), Some(signature)) = (field("policy"), field("signature")) else {
+            return Err(Error::Forbidden(
+                "Missing upload policy or signature".to_string(),
+            ));
+        };
+        let mut mac = self.mac();
+        mac.update(policy.as_bytes());
+        BASE64
+            .decode(signature)
+            .ok()
+            .and_then(|signature| mac.verify_slice(&signature).ok())
+            .ok_or_else(|| Error::Forbidden("Invalid upload policy signature".to_string()))?;
+        let policy: PostPolicy = BASE64
+            .decode(policy)
+            .ok()
+            .and_then(|json| serde_json::from_slice(&json).ok())
+            .ok_or_else(|| Error::Forbidden("Malformed upload policy".to_string()))?;
+
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_secs();
+        match parse_utc(&policy.expiration) {
+            Some(expiration) if now < expiration => {}
+            Some(_) => return Err(Error::Forbidden("Upload policy expired".to_string())),
+            None => {
+                return Err(Error::Forbidden(
+                    "Malformed upload policy expiration".to_string(),
+                ));
+            }
+        }
+
+        let Some(Value::UploadFile(file)) = params.get(file_field) else {
+            return Err(Error::Forbidden(format!(
+                "`{file_field}` is not an uploaded file"
+            )));
+        };
+        let size = tokio::fs::metadata(&file.temp_file_path)
+            .await
+            .map_err(|e| Error::io("Failed to read uploaded file size", e))?
+            .len();
+        let value_of = |name: &str| {
+            if name.eq_ignore_ascii_case("Content-Type") {
+                Some(file.content_type.as_str())
+            } else {
+                field(name)
+            }
+        };
+
+        let mut covered = HashSet::new();
+        for condition in &policy.conditions {
+            let (name, ok) = match condition {
+                serde_json::Value::Object(map) if map.len() == 1 => {
+                    let (name, expected) = map.iter().next().expect("map has one entry");
+                    let ok = expected
+                        .as_str()
+                        .is_some_and(|expected| value_of(name) == Some(expected));
+                    (name.as_str(), ok)
+                }
+                serde_json::Value::Array(items) => match items.as_slice() {
+                    [op, name, expected] if op == "eq" || op == "starts-with" => {
+                        let name = name.as_str().and_then(|n| n.strip_prefix('$'));
+                        let (Some(name), Some(expected)) = (name, expected.as_str()) else {
+                            return Err(unsupported(condition));
+                        };
+                        let value = value_of(name).unwrap_or_default();
+                        let ok = if op == "eq" {
+                            value == expected
+                        } else {
+                            value.starts_with(expected)
+                        };
+                        (name, ok)
+                    }
+                    [op, min, max] if op == "content-length-range" => {
+                        let (Some(min), Some(max)) = (min.as_u64(), max.as_u64()) else {
+                            return Err(unsupported(condition));
+                        };
+                        if !(min..=max).contains(&size) {
+                            return Err(Error::Forbidden(format!(
+                                "Uploaded file of {size} bytes is not between {min} and {max} bytes"
+                            )));
+                        }
+                        continue;
+                    }
+                    _ => return Err(unsupported(condition)),
+                },
+                _ => return Err(unsupported(condition)),
+            };
+            if !ok {
+                return Err(Error::Forbidden(format!(
+                    "Field `{name}` does not match the upload policy"
+                )));
+            }
+            covered.insert(name.to_ascii_lowercase());
+        }
+
+        for name in params.keys() {
+            let lower = name.to_ascii_lowercase();
+            if name != "policy"
+                && name != "signature"
+                && name != file_field
+                && !lower.starts_with("x-ignore-")
+                && !covered.contains(&lower)
+            {
+                return Err(Error::Forbidden(format!(
+                    "Field `{name}` is not covered by the upload policy"
+                )));
+            }
+        }
+        Ok(policy)
+    }
+}
+
+fn unsupported(condition: &serde_json::Value) -> Error {
+    Error::Forbidden(format!("Unsupported upload policy condition: {condition}"))
+}
+
+/// Days since 1970-01-01 of a proleptic Gregorian date.
+fn days_from_civil(y: i64, m: i64, d: i64) -> i64 {
+    let y = if m <= 2 { y - 1 } else { y };
+    let era = y.div_euclid(400);
+    let yoe = y - era * 400;
+    let doy = (153 * (m + if m > 2 { -3 } else { 9 }) + 2) / 5 + d - 1;
+    let doe = yoe * 365 + yoe / 4 - yoe / 100 + doy;
+    era * 146097 + doe - 719468
+}
+
+fn format_utc(secs: u64) -> String {
+    let days = (secs / 86400) as i64;
+    let rem = secs % 86400;
+    let z = days + 719468;
+    let era = z.div_euclid(146097);
+    let doe = z - era * 146097;
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let d = doy - (153 * mp + 2) / 5 + 1;
+    let m = if mp < 10 { mp + 3 } else { mp - 9 };
+    let y = yoe + era * 400 + i64::from(m <= 2);
+    format!(
+        "{y:04}-{m:02}-{d:02}T{:02}:{:02}:{:02}Z",
+        rem / 3600,
+        rem / 60 % 60,
+        rem % 60
+    )
+}
+
+/// Parses `YYYY-MM-DDTHH:MM:SS[.fff]Z` into seconds since the epoch.
+fn parse_utc(s: &str) -> Option<u64> {
+    let s = s.strip_suffix('Z')?;
+    let s = s.split_once('.').map_or(s, |(s, _)| s);
+    let (date, time) = s.split_once('T')?;
+    let mut date = date.splitn(3, '-').map(|p| p.parse::<i64>().ok());
+    let (y, m, d) = (date.next()??, date.next()??, date.next()??);
+    let mut time = time.splitn(3, ':').map(|p| p.parse::<i64>().ok());
+    let (hh, mm, ss) = (time.next()??, time.next()??, time.next()??);
+    if !(1..=12).contains(&m) || !(1..=31).contains(&d) || hh > 23 || mm > 59 || ss > 60 {
+        return None;
+    }
+    let secs = days_from_civil(y, m, d) * 86400 + hh * 3600 + mm * 60 + ss;
+    u64::try_from(secs).ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Write;
+
+    use super::*;
+    use crate::UploadFile;
+
+    #[test]
+    fn test_utc() {
+        assert_eq!(format_utc(0), "1970-01-01T00:00:00Z");
+        assert_eq!(format_utc(1706700600), "2024-01-31T11:30:00Z");
+        assert_eq!(parse_utc("2024-01-31T11:30:00.000Z"), Some(1706700600));
+        assert_eq!(
+            parse_utc("2024-02-29T00:00:00Z").map(format_utc).unwrap(),
+            "2024-02-29T00:00:00Z"
+        );
+        assert_eq!(parse_utc("2024-01-31 11:30:00"), None);
+    }
+
+    #[tokio::test]
+    async fn test_verify() {
+        let signer = PostPolicySigner::new("secret");
+        let mut temp_file = tempfile::NamedTempFile::new().unwrap();
+        temp_file.write_all(b"hello").unwrap();
+        let file = Value::UploadFile(UploadFile {
+            name: "hello.txt".to_string(),
+            content_type: "text/plain".to_string(),
+            temp_file_path: temp_file.path().to_string_lossy().to_string(),
+            checksum: None,
+            headers: vec![],
+            part_index: 0,
+            owned: true,
+        });
+
+        let params = |policy: &PostPolicy, key: &str| {
+            let signed = signer.sign(policy);
+            HashMap::from([
+                ("policy".to_string(), Value::xstr(signed.policy)),
+                ("signature".to_string(), Value::xstr(signed.signature)),
+                ("key".to_string(), Value::xstr(key)),
+                ("file".to_string(), file.clone()),
+            ])
+        };
+        let policy = PostPolicy::expires_in(Duration::from_secs(60))
+            .starts_with("key", "uploads/")
+            .starts_with("Content-Type", "text/")
+            .content_length_range(1, 5);
+
+        assert!(
+            signer
+                .verify(&params(&policy, "uploads/a.txt"), "file")
+                .await
+                .is_ok()
+        );
+
+        let forbidden = |result: Result<PostPolicy, Error>| match result {
+            Err(Error::Forbidden(e)) => e,
+            other => panic!("expected Forbidden, got {other:?}"),
+        };
+        assert_eq!(
+            forbidden(signer.verify(&params(&policy, "other/a.txt"), "file").await),
+            "Field `key` does not match the upload policy"
+        );
+
+        let mut tampered = params(&policy, "uploads/a.txt");
+        let other = PostPolicySigner::new("other").sign(&policy);
+        tampered.insert("signature".to_string(), Value::xstr(other.signature));
+        assert_eq!(
+            forbidden(signer.verify(&tampered, "file").await),
+            "Invalid upload policy signature"
+        );
+
+        let mut extra = params(&policy, "uploads/a.txt");
+        extra.insert("acl".to_string(), Value::xstr("public"));
+        extra.insert("x-ignore-note".to_string(), Value::xstr("x"));
+        assert_eq!(
+            forbidden(signer.verify(&extra, "file").await),
+            "Field `acl` is not covered by the upload policy"
+        );
+
+        let small = policy.clone().content_length_range(0, 4);
+        assert!(
+            forbidden(
+                signer
+                    .verify(&params(&small, "uploads/a.txt"), "file")
+                    .await
+            )
+            .starts_with("Uploaded file of 5 bytes")
+        );
+
+        let expired = PostPolicy::new(UNIX_EPOCH + Duration::from_secs(60));
+        assert_eq!(
+            forbidden(
+                signer
+                    .verify(&params(&expired, "uploads/a.txt"), "file")
+                    .await
+            ),
+            "Upload policy expired"
+        );
+    }
+}
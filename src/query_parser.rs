@@ -1,19 +1,22 @@
 // Port from: https://github.com/rack/rack/blob/main/lib/rack/query_parser.rb
 
-use form_urlencoded;
-use std::collections::HashMap;
+use std::borrow::Cow;
+use std::collections::{HashMap, HashSet};
 use std::error::Error;
 use std::fmt;
 
 use crate::Value;
 
-const DEFAULT_PARAM_DEPTH_LIMIT: usize = 100;
+pub(crate) const DEFAULT_PARAM_DEPTH_LIMIT: usize = 100;
+pub(crate) const DEFAULT_MAX_PARAMS: usize = 10_000;
+pub(crate) const DEFAULT_MAX_ARRAY_LENGTH: usize = 10_000;
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub enum QueryParserError {
     ParameterTypeError(String),
     InvalidParameterError(String),
     ParamsTooDeepError(String),
+    TooManyParameters(usize),
 }
 
 impl fmt::Display for QueryParserError {
@@ -22,23 +25,115 @@ impl fmt::Display for QueryParserError {
             QueryParserError::ParameterTypeError(msg) => write!(f, "Parameter type error: {}", msg),
             QueryParserError::InvalidParameterError(msg) => write!(f, "Invalid parameter: {}", msg),
             QueryParserError::ParamsTooDeepError(msg) => write!(f, "Parameters too deep: {}", msg),
+            QueryParserError::TooManyParameters(limit) => {
+                write!(f, "Too many parameters: limit is {}", limit)
+            }
         }
     }
 }
 
 impl Error for QueryParserError {}
 
+/// How keys with unbalanced or misplaced brackets are handled.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum ParseMode {
+    /// Like Rack, malformed brackets are kept as literal characters of the key.
+    #[default]
+    Lenient,
+    /// Malformed brackets are rejected with `InvalidParameterError`.
+    Strict,
+}
+
+/// Which implementation's bracket semantics to follow.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Compat {
+    #[default]
+    Rack,
+    /// PHP's `parse_str`: `.` and ` ` in the top level key become `_`, an unclosed `[`
+    /// becomes `_`, characters after a `]` are dropped, conflicting types are overwritten
+    /// instead of rejected, and every `[]` appends a new element.
+    Php,
+}
+
+#[derive(Debug, Clone)]
 pub struct QueryParser {
     param_depth_limit: usize,
+    max_params: usize,
+    max_array_length: usize,
+    mode: ParseMode,
+    compat: Compat,
+    denied_keys: Vec<String>,
+    strict_utf8: bool,
+    delimiters: Vec<char>,
+    repeated_keys: bool,
 }
 
 impl QueryParser {
     pub fn new(param_depth_limit: Option<usize>) -> Self {
         Self {
             param_depth_limit: param_depth_limit.unwrap_or(DEFAULT_PARAM_DEPTH_LIMIT),
+            max_params: DEFAULT_MAX_PARAMS,
+            max_array_length: DEFAULT_MAX_ARRAY_LENGTH,
+            mode: ParseMode::default(),
+            compat: Compat::default(),
+            denied_keys: vec![],
+            strict_utf8: false,
+            delimiters: vec!['&'],
+            repeated_keys: false,
         }
     }
 
+    pub fn with_compat(mut self, compat: Compat) -> Self {
+        self.compat = compat;
+        self
+    }
+
+    /// Maximum number of key/value pairs in one query string.
+    pub fn with_max_params(mut self, max_params: usize) -> Self {
+        self.max_params = max_params;
+        self
+    }
+
+    /// Maximum number of elements of one array, e.g. `foo[]=x` repeated, more are rejected
+    /// with `ParameterTypeError`.
+    pub fn with_max_array_length(mut self, max_array_length: usize) -> Self {
+        self.max_array_length = max_array_length;
+        self
+    }
+
+    pub fn with_mode(mut self, mode: ParseMode) -> Self {
+        self.mode = mode;
+        self
+    }
+
+    /// Reject keys containing one of `keys` as a segment, e.g. `a[__proto__][b]`.
+    pub fn with_denied_keys(mut self, keys: Vec<String>) -> Self {
+        self.denied_keys = keys;
+        self
+    }
+
+    /// Reject percent-encoded invalid UTF-8 instead of replacing it with U+FFFD.
+    pub fn with_strict_utf8(mut self, strict_utf8: bool) -> Self {
+        self.strict_utf8 = strict_utf8;
+        self
+    }
+
+    /// The characters separating pairs, `&` by default, e.g. `"&;"` to also split on the `;`
+    /// some legacy clients send. An empty set keeps `&`.
+    pub fn with_delimiters(mut self, delimiters: &str) -> Self {
+        if !delimiters.is_empty() {
+            self.delimiters = delimiters.chars().collect();
+        }
+        self
+    }
+
+    /// Collect the values of a key repeated without brackets into an array, OpenAPI's
+    /// `tag=a&tag=b` for `tag[]=a&tag[]=b`, instead of keeping the last one like Rack.
+    pub fn with_repeated_keys(mut self, repeated_keys: bool) -> Self {
+        self.repeated_keys = repeated_keys;
+        self
+    }
+
     pub fn parse_nested_query<'a>(
         &self,
         qs: impl Into<Option<&'a str>>,
@@ -52,6 +147,17 @@ impl QueryParser {
         &self,
         params: &mut HashMap<String, Value>,
         qs: impl Into<Option<&'a str>>,
+    ) -> Result<(), QueryParserError> {
+        self.parse_nested_query_with(params, qs, |_, _, _| {})
+    }
+
+    /// Same as `parse_nested_query_into`, reporting the top level key, the key and the value
+    /// of every pair.
+    pub(crate) fn parse_nested_query_with<'a>(
+        &self,
+        params: &mut HashMap<String, Value>,
+        qs: impl Into<Option<&'a str>>,
+        mut on_pair: impl FnMut(String, &str, &Value),
     ) -> Result<(), QueryParserError> {
         let qs = qs.into().unwrap_or("");
 
@@ -59,39 +165,83 @@ impl QueryParser {
             return Ok(());
         }
 
-        for pair in qs.split('&') {
+        let mut count = 0;
+        let mut bare_keys = HashSet::new();
+        for pair in qs.split(self.delimiters.as_slice()) {
             if pair.is_empty() {
                 continue;
             }
+            count += 1;
+            if count > self.max_params {
+                return Err(QueryParserError::TooManyParameters(self.max_params));
+            }
 
             let (key, value) = match pair.split_once('=') {
-                Some((k, v)) => {
-                    let k = form_urlencoded::parse(k.as_bytes())
-                        .next()
-                        .map(|(k, _)| k.into_owned())
-                        .unwrap_or_default();
-                    let v = form_urlencoded::parse(v.as_bytes())
-                        .next()
-                        .map(|(v, _)| v.into_owned())
-                        .unwrap_or_default();
-                    (k, Some(v))
-                }
-                None => {
-                    let k = form_urlencoded::parse(pair.as_bytes())
-                        .next()
-                        .map(|(k, _)| k.into_owned())
-                        .unwrap_or_default();
-                    (k, None)
-                }
+                Some((k, v)) => (self.decode(k)?.into_owned(), Some(self.decode(v)?)),
+                None => (self.decode(pair)?.into_owned(), None),
             };
 
             let value = Value::xstr_opt(value);
-            self._normalize_params(params, &key, value, 0)?;
+            self.check_key(&key)?;
+            if let Some(top) = self.top_level_key(&key) {
+                on_pair(top, &key, &value);
+            }
+            let repeated =
+                self.repeated_keys && !key.contains(['[', ']']) && !bare_keys.insert(key.clone());
+            match params.get_mut(&key) {
+                Some(Value::Array(values)) if repeated => {
+                    self.check_array_length(values, &key)?;
+                    values.push(value)
+                }
+                Some(first) if repeated => {
+                    *first = Value::Array(vec![std::mem::replace(first, Value::Null), value])
+                }
+                _ => self.normalize_params(params, &key, value, None)?,
+            }
         }
 
         Ok(())
     }
 
+    fn decode<'a>(&self, s: &'a str) -> Result<Cow<'a, str>, QueryParserError> {
+        // Most keys and values have nothing to decode, and are valid UTF-8 as they are
+        if !s.bytes().any(|b| b == b'%' || b == b'+') {
+            return Ok(Cow::Borrowed(s));
+        }
+        let bytes = decode_component(s.as_bytes());
+        match String::from_utf8(bytes) {
+            Ok(decoded) => Ok(Cow::Owned(decoded)),
+            Err(e) if !self.strict_utf8 => {
+                Ok(String::from_utf8_lossy(e.as_bytes()).into_owned().into())
+            }
+            Err(_) => Err(QueryParserError::InvalidParameterError(format!(
+                "invalid UTF-8 in `{}`",
+                s
+            ))),
+        }
+    }
+
+    /// The characters separating parameters.
+    #[cfg(server)]
+    pub(crate) fn delimiters(&self) -> &[char] {
+        &self.delimiters
+    }
+
+    /// The key a nested name is stored under in the root object, `None` if it is dropped.
+    pub(crate) fn top_level_key(&self, name: &str) -> Option<String> {
+        match self.compat {
+            Compat::Rack => {
+                let start = name
+                    .char_indices()
+                    .skip(1)
+                    .find(|&(_, c)| c == '[')
+                    .map_or(name.len(), |(i, _)| i);
+                (start > 0).then(|| name[..start].to_string())
+            }
+            Compat::Php => php_split_key(name).map(|(base, _)| base),
+        }
+    }
+
     pub fn parse_nested_value<'a>(
         &self,
         params: &mut HashMap<String, Value>,
@@ -104,17 +254,100 @@ impl QueryParser {
             return Ok(());
         }
 
-        self._normalize_params(params, key, value, 0)?;
+        self.check_key(key)?;
+        self.normalize_params(params, key, value, None)
+    }
+
+    /// Like `parse_nested_value`, but deep merges `value` into the value already at `key`:
+    /// objects are merged key by key, arrays are concatenated and other values replaced.
+    /// An object or array meeting a value of another type is a `ParameterTypeError`.
+    pub fn merge_nested_value<'a>(
+        &self,
+        params: &mut HashMap<String, Value>,
+        key: impl Into<Option<&'a str>>,
+        value: Value,
+    ) -> Result<(), QueryParserError> {
+        let key = key.into().unwrap_or("");
+
+        if key.is_empty() {
+            return Ok(());
+        }
+
+        self.check_key(key)?;
+        self.normalize_params(params, key, value, Some(key))
+    }
+
+    /// `merge` is the full key when the value is deep merged, see `merge_nested_value`.
+    fn normalize_params(
+        &self,
+        params: &mut HashMap<String, Value>,
+        name: &str,
+        v: Value,
+        merge: Option<&str>,
+    ) -> Result<(), QueryParserError> {
+        match self.compat {
+            Compat::Rack => self
+                ._normalize_params(params, name, v, 0, merge)
+                .map(|_| ()),
+            Compat::Php => {
+                let Some((base, segments)) = php_split_key(name) else {
+                    return Ok(());
+                };
+                if segments.len() >= self.param_depth_limit {
+                    return Err(QueryParserError::ParamsTooDeepError(
+                        "Parameters nested too deep".to_string(),
+                    ));
+                }
+                let limit = (name, self.max_array_length);
+                php_insert(
+                    params.entry(base).or_insert(Value::Null),
+                    &segments,
+                    v,
+                    merge,
+                    limit,
+                )
+            }
+        }
+    }
+
+    fn check_array_length(&self, array: &[Value], name: &str) -> Result<(), QueryParserError> {
+        if array.len() >= self.max_array_length {
+            return Err(array_too_long(name, self.max_array_length));
+        }
         Ok(())
     }
 
+    fn check_key(&self, name: &str) -> Result<(), QueryParserError> {
+        if self.mode == ParseMode::Strict && !name.is_empty() {
+            validate_key_brackets(name).map_err(|reason| {
+                QueryParserError::InvalidParameterError(format!(
+                    "malformed key `{}`: {}",
+                    name, reason
+                ))
+            })?;
+        }
+        if let Some(key) = name
+            .split(['[', ']'])
+            .find(|segment| self.denied_keys.iter().any(|k| k == segment))
+        {
+            return Err(QueryParserError::InvalidParameterError(format!(
+                "key `{}` is not allowed",
+                key
+            )));
+        }
+        Ok(())
+    }
+
+    /// Inserts `v` at `name` into `params`. Names that don't address a key of `params`, like
+    /// the `[]` of `x[][]`, return the value to use instead of `params`.
     fn _normalize_params(
         &self,
         params: &mut HashMap<String, Value>,
         name: &str,
         v: Value,
         depth: usize,
-    ) -> Result<Value, QueryParserError> {
+        merge: Option<&str>,
+    ) -> Result<Option<Value>, QueryParserError> {
         if depth >= self.param_depth_limit {
             return Err(QueryParserError::ParamsTooDeepError(
                 "Parameters nested too deep".to_string(),
@@ -124,11 +357,9 @@ impl QueryParser {
         let (k, after) = if name.is_empty() {
             ("", "")
         } else if depth == 0 {
-            if let Some(start) = name[1..].find('[') {
-                let start = start + 1;
-                (&name[..start], &name[start..])
-            } else {
-                (name, "")
+            match name.char_indices().skip(1).find(|&(_, c)| c == '[') {
+                Some((start, _)) => (&name[..start], &name[start..]),
+                None => (name, ""),
             }
         } else if let Some(stripped) = name.strip_prefix("[]") {
             ("[]", stripped)
@@ -143,13 +374,17 @@ impl QueryParser {
         };
 
         if k.is_empty() {
-            return Ok(Value::Null);
+            return Ok(Some(Value::Null));
         }
 
         if after.is_empty() {
             if k == "[]" && depth != 0 {
-                return Ok(Value::Array(vec![v]));
+                return Ok(Some(Value::Array(vec![v])));
             }
+            let v = match (params.remove(k), merge) {
+                (Some(old), Some(path)) => deep_merge(old, v, path)?,
+                _ => v,
+            };
             params.insert(k.to_string(), v);
         } else if after == "[" {
             params.insert(name.to_string(), v);
@@ -159,6 +394,7 @@ impl QueryParser {
                 .or_insert_with(|| Value::Array(Vec::new()));
 
             if let Value::Array(vec) = entry {
+                self.check_array_length(vec, k)?;
                 vec.push(v);
             } else {
                 return Err(QueryParserError::ParameterTypeError(format!(
@@ -184,23 +420,22 @@ impl QueryParser {
                 .entry(k.to_string())
                 .or_insert_with(|| Value::Array(Vec::new()));
             if let Value::Array(vec) = entry {
-                let mut new_params = HashMap::new();
-                if let Some(Value::Object(hash)) = vec.last_mut() {
-                    if !params_hash_has_key(hash, child_key) {
-                        let _ = self._normalize_params(&mut *hash, child_key, v.clone(), depth + 1);
-                    } else {
-                        let normalized = self._normalize_params(
-                            &mut new_params,
-                            child_key,
-                            v.clone(),
-                            depth + 1,
-                        )?;
-                        vec.push(normalized);
+                match vec.last_mut() {
+                    Some(Value::Object(hash)) if !params_hash_has_key(hash, child_key) => {
+                        let normalized =
+                            self._normalize_params(hash, child_key, v, depth + 1, merge);
+                        // Rack ignores type errors here, merge conflicts are reported
+                        if merge.is_some() {
+                            normalized?;
+                        }
+                    }
+                    _ => {
+                        self.check_array_length(vec, k)?;
+                        let mut hash = HashMap::new();
+                        let normalized =
+                            self._normalize_params(&mut hash, child_key, v, depth + 1, merge)?;
+                        vec.push(normalized.unwrap_or(Value::Object(hash)));
                     }
-                } else {
-                    let normalized =
-                        self._normalize_params(&mut new_params, child_key, v.clone(), depth + 1)?;
-                    vec.push(normalized);
                 }
             } else {
                 return Err(QueryParserError::ParameterTypeError(format!(
@@ -215,7 +450,7 @@ impl QueryParser {
                 .or_insert_with(|| Value::Object(HashMap::new()));
 
             if let Value::Object(hash) = entry {
-                self._normalize_params(hash, after, v, depth + 1)?;
+                self._normalize_params(hash, after, v, depth + 1, merge)?;
             } else {
                 return Err(QueryParserError::ParameterTypeError(format!(
                     "expected Object (got {}) for param `{}`",
@@ -225,8 +460,176 @@ impl QueryParser {
             }
         }
 
-        Ok(Value::Object(params.to_owned()))
+        Ok(None)
+    }
+}
+
+// Port from: php_register_variable_ex in https://github.com/php/php-src/blob/master/main/php_variables.c
+fn php_split_key(name: &str) -> Option<(String, Vec<&str>)> {
+    let name = name.trim_start_matches(' ');
+    let (base, mut rest) = match name.find('[') {
+        Some(start) => name.split_at(start),
+        None => (name, ""),
+    };
+    if base.is_empty() {
+        return None;
+    }
+    let mut base = base.replace([' ', '.'], "_");
+
+    let mut segments = Vec::new();
+    while let Some(inner) = rest.strip_prefix('[') {
+        let Some(end) = inner.find(']') else {
+            if segments.is_empty() {
+                // Only an unclosed first bracket is kept as part of the name
+                base.push('_');
+                base.push_str(inner);
+            }
+            break;
+        };
+        segments.push(&inner[..end]);
+        rest = &inner[end + 1..];
     }
+    Some((base, segments))
+}
+
+/// `limit` is the full key and the maximum array length.
+fn php_insert(
+    target: &mut Value,
+    segments: &[&str],
+    v: Value,
+    merge: Option<&str>,
+    limit: (&str, usize),
+) -> Result<(), QueryParserError> {
+    let Some((segment, rest)) = segments.split_first() else {
+        *target = match merge {
+            Some(path) => deep_merge(std::mem::replace(target, Value::Null), v, path)?,
+            None => v,
+        };
+        return Ok(());
+    };
+
+    if segment.is_empty() {
+        match target {
+            Value::Array(vec) => {
+                if vec.len() >= limit.1 {
+                    return Err(array_too_long(limit.0, limit.1));
+                }
+                vec.push(Value::Null);
+                php_insert(vec.last_mut().unwrap(), rest, v, merge, limit)?;
+            }
+            Value::Object(hash) => {
+                let index = hash
+                    .keys()
+                    .filter_map(|k| k.parse::<usize>().ok())
+                    .max()
+                    .map_or(0, |max| max + 1);
+                if index >= limit.1 {
+                    return Err(array_too_long(limit.0, limit.1));
+                }
+                php_insert(
+                    hash.entry(index.to_string()).or_insert(Value::Null),
+                    rest,
+                    v,
+                    merge,
+                    limit,
+                )?;
+            }
+            _ => {
+                *target = Value::Array(Vec::new());
+                php_insert(target, segments, v, merge, limit)?;
+            }
+        }
+        return Ok(());
+    }
+
+    match target {
+        Value::Object(hash) => php_insert(
+            hash.entry(segment.to_string()).or_insert(Value::Null),
+            rest,
+            v,
+            merge,
+            limit,
+        ),
+        Value::Array(vec) => {
+            let hash = vec
+                .drain(..)
+                .enumerate()
+                .map(|(i, v)| (i.to_string(), v))
+                .collect();
+            *target = Value::Object(hash);
+            php_insert(target, segments, v, merge, limit)
+        }
+        _ => {
+            *target = Value::Object(HashMap::new());
+            php_insert(target, segments, v, merge, limit)
+        }
+    }
+}
+
+fn array_too_long(name: &str, limit: usize) -> QueryParserError {
+    QueryParserError::ParameterTypeError(format!(
+        "array `{}` has more than {} elements",
+        name, limit
+    ))
+}
+
+/// Merges `new` into `old`, see `QueryParser::merge_nested_value`, `path` names the value
+/// in errors.
+fn deep_merge(old: Value, new: Value, path: &str) -> Result<Value, QueryParserError> {
+    match (old, new) {
+        (Value::Null, new) => Ok(new),
+        (Value::Object(mut old), Value::Object(new)) => {
+            for (key, value) in new {
+                let value = match old.remove(&key) {
+                    Some(existing) => deep_merge(existing, value, &format!("{path}[{key}]"))?,
+                    None => value,
+                };
+                old.insert(key, value);
+            }
+            Ok(Value::Object(old))
+        }
+        (Value::Array(mut old), Value::Array(new)) => {
+            old.extend(new);
+            Ok(Value::Array(old))
+        }
+        (old @ (Value::Object(_) | Value::Array(_)), new)
+        | (old, new @ (Value::Object(_) | Value::Array(_))) => {
+            Err(QueryParserError::ParameterTypeError(format!(
+                "cannot merge {} into {} for param `{}`",
+                new.type_name(),
+                old.type_name(),
+                path
+            )))
+        }
+        (_, new) => Ok(new),
+    }
+}
+
+// Accepts `name`, followed by any number of `[]` or `[key]` segments.
+fn validate_key_brackets(name: &str) -> Result<(), &'static str> {
+    let (base, mut rest) = match name.find('[') {
+        Some(start) => name.split_at(start),
+        None => (name, ""),
+    };
+    if base.is_empty() {
+        return Err("key starts with `[`");
+    }
+    if base.contains(']') {
+        return Err("unexpected `]`");
+    }
+    while !rest.is_empty() {
+        let Some(inner) = rest.strip_prefix('[') else {
+            return Err("unexpected characters after `]`");
+        };
+        let Some(end) = inner.find(']') else {
+            return Err("unclosed `[`");
+        };
+        if inner[..end].contains('[') {
+            return Err("nested `[`");
+        }
+        rest = &inner[end + 1..];
+    }
+    Ok(())
 }
 
 fn params_hash_has_key(hash: &HashMap<String, Value>, key: &str) -> bool {
@@ -253,11 +656,40 @@ fn params_hash_has_key(hash: &HashMap<String, Value>, key: &str) -> bool {
     true
 }
 
+/// Decodes `+` as a space and `%XX` escapes in one pass. A `%` not followed by two hex
+/// digits is kept as is.
+fn decode_component(s: &[u8]) -> Vec<u8> {
+    let mut decoded = Vec::with_capacity(s.len());
+    let hex = |b: u8| (b as char).to_digit(16).map(|d| d as u8);
+    let mut rest = s;
+    while let Some((&b, tail)) = rest.split_first() {
+        rest = tail;
+        match b {
+            b'+' => decoded.push(b' '),
+            b'%' => match tail {
+                [hi, lo, tail @ ..] => match (hex(*hi), hex(*lo)) {
+                    (Some(hi), Some(lo)) => {
+                        decoded.push(hi << 4 | lo);
+                        rest = tail;
+                    }
+                    _ => decoded.push(b'%'),
+                },
+                _ => decoded.push(b'%'),
+            },
+            b => decoded.push(b),
+        }
+    }
+    decoded
+}
+
 #[cfg(test)]
 mod tests {
     // Port from: https://github.com/rack/rack/blob/main/test/spec_utils.rb
 
-    use crate::query_parser::{DEFAULT_PARAM_DEPTH_LIMIT, QueryParser, Value};
+    use crate::query_parser::{
+        Compat, DEFAULT_MAX_PARAMS, DEFAULT_PARAM_DEPTH_LIMIT, ParseMode, QueryParser,
+        QueryParserError, Value,
+    };
     use maplit::hashmap;
     use pretty_assertions::assert_eq;
     use std::collections::HashMap;
@@ -462,6 +894,40 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_parse_with_delimiters() {
+        let parser = QueryParser::new(None).with_delimiters("&;");
+        assert_eq!(
+            Value::Object(parser.parse_nested_query("a=1;b[]=2&b[]=3;;c").unwrap()),
+            convert(r#"{"a": "1", "b": ["2", "3"], "c": null}"#)
+        );
+        let parser = QueryParser::new(None).with_delimiters(";");
+        assert_eq!(
+            Value::Object(parser.parse_nested_query("a=1&b=2;c=3").unwrap()),
+            convert(r#"{"a": "1&b=2", "c": "3"}"#)
+        );
+        // `;` is a plain character by default
+        "a=1;b=2".should_be(r#"{"a": "1;b=2"}"#);
+        "a=b=c".should_be(r#"{"a": "b=c"}"#);
+    }
+
+    #[test]
+    fn test_parse_repeated_keys() {
+        let parser = QueryParser::new(None).with_repeated_keys(true);
+        assert_eq!(
+            Value::Object(
+                parser
+                    .parse_nested_query("tag=a&tag=b&tag=c&id=1&f[]=x&f[]=y&empty&empty")
+                    .unwrap()
+            ),
+            convert(
+                r#"{"tag": ["a", "b", "c"], "id": "1", "f": ["x", "y"], "empty": [null, null]}"#
+            )
+        );
+        // Last one wins by default, like Rack
+        "tag=a&tag=b".should_be(r#"{"tag": "b"}"#);
+    }
+
     #[test]
     // can parse a query string with a key that has invalid UTF-8 encoded bytes
     fn test_parse_invalid_utf8() {
@@ -472,6 +938,54 @@ mod tests {
         assert_eq!(key, b"foo\xEF\xBF\xBDE");
     }
 
+    #[test]
+    fn test_max_array_length() {
+        let parser = QueryParser::new(None).with_max_array_length(2);
+        assert!(parser.parse_nested_query("a[]=1&a[]=2").is_ok());
+        assert!(parser.parse_nested_query("a[][x]=1&a[][x]=2").is_ok());
+        for qs in ["a[]=1&a[]=2&a[]=3", "a[][x]=1&a[][x]=2&a[][x]=3"] {
+            let error = parser.parse_nested_query(qs).unwrap_err();
+            assert!(
+                matches!(error, QueryParserError::ParameterTypeError(_)),
+                "{error}"
+            );
+        }
+
+        let repeated = parser.clone().with_repeated_keys(true);
+        assert!(repeated.parse_nested_query("a=1&a=2&a=3").is_err());
+
+        let php = parser.with_compat(Compat::Php);
+        assert!(php.parse_nested_query("a[]=1&a[]=2").is_ok());
+        assert!(php.parse_nested_query("a[]=1&a[]=2&a[]=3").is_err());
+        assert!(php.parse_nested_query("a[5]=1&a[]=2").is_err());
+    }
+
+    #[test]
+    fn test_decode() {
+        let parser = QueryParser::new(None);
+        let decode = |s| parser.decode(s).unwrap();
+        assert_eq!(decode("plain"), "plain");
+        assert_eq!(decode("a+b%20c"), "a b c");
+        assert_eq!(decode("caf%C3%A9%2b"), "café+");
+        assert_eq!(decode("100%"), "100%");
+        assert_eq!(decode("%zz%4"), "%zz%4");
+        assert_eq!(decode("%ff"), "\u{FFFD}");
+
+        let strict = QueryParser::new(None).with_strict_utf8(true);
+        assert!(strict.decode("%ff").is_err());
+        assert_eq!(strict.decode("%C3%A9").unwrap(), "é");
+
+        // Keys starting with a multibyte character
+        let params = parser
+            .parse_nested_query("%C3%A9=1&%C3%A9t%C3%A9[a]=2")
+            .unwrap();
+        assert_eq!(params["é"], Value::xstr("1"));
+        assert_eq!(
+            params["été"],
+            Value::Object(HashMap::from([("a".to_string(), Value::xstr("2"))]))
+        );
+    }
+
     #[test]
     fn only_moves_to_a_new_array_when_the_full_key_has_been_seen() {
         "x[][y][][z]=1&x[][y][][w]=2".should_be(r#"{"x": [{ "y": [{ "z": "1", "w": "2" }] }]}"#);
@@ -496,4 +1010,153 @@ mod tests {
             .should_be(r#"{"g": { "h": { "i":  "8" } }, "j":  { "k":  { "l[m]": "9" } }}"#);
         "l[[[[[[[[]]]]]]]=10".should_be(r#"{"l": {"[[[[[[[": {"]]]]]]": "10"}}}"#);
     }
+
+    #[test]
+    fn strict_mode_rejects_malformed_brackets() {
+        let parser = QueryParser::new(None).with_mode(ParseMode::Strict);
+        for qs in [
+            "[]=1",
+            "[a]=2",
+            "b[=3",
+            "c]=4",
+            "d[[]=5",
+            "e][]=6",
+            "f[[]]=7",
+            "g[h]i=8",
+            "j[k]l[m]=9",
+        ] {
+            assert!(
+                matches!(
+                    parser.parse_nested_query(qs),
+                    Err(QueryParserError::InvalidParameterError(_))
+                ),
+                "{qs} should be rejected"
+            );
+        }
+
+        let mut params = HashMap::new();
+        assert!(
+            parser
+                .parse_nested_value(&mut params, "user[name", Value::xstr("x"))
+                .is_err()
+        );
+    }
+
+    #[test]
+    fn strict_mode_accepts_well_formed_keys() {
+        let parser = QueryParser::new(None).with_mode(ParseMode::Strict);
+        assert_eq!(
+            Value::Object(
+                parser
+                    .parse_nested_query("a=1&b[]=2&c[d]=3&e[][f]=4&g[h][]=5&=6")
+                    .unwrap()
+            ),
+            convert(
+                r#"{"a": "1", "b": ["2"], "c": {"d": "3"}, "e": [{"f": "4"}], "g": {"h": ["5"]}}"#
+            )
+        );
+    }
+
+    #[test]
+    fn raise_an_exception_if_there_are_too_many_params() {
+        let parser = QueryParser::new(None).with_max_params(3);
+        assert!(parser.parse_nested_query("a=1&b=2&&c=3").is_ok());
+        assert!(matches!(
+            parser.parse_nested_query("a=1&b=2&c=3&d=4"),
+            Err(QueryParserError::TooManyParameters(3))
+        ));
+
+        let parser = QueryParser::new(None);
+        let query_string = "a=1&".repeat(DEFAULT_MAX_PARAMS + 1);
+        assert!(parser.parse_nested_query(&*query_string).is_err());
+    }
+
+    #[test]
+    fn php_compat_follows_parse_str() {
+        let parser = QueryParser::new(None).with_compat(Compat::Php);
+        let php = |qs: &str, expected: &str| {
+            assert_eq!(
+                Value::Object(parser.parse_nested_query(qs).unwrap()),
+                convert(expected),
+                "{qs}"
+            );
+        };
+
+        php("foo=bar&foo=quux", r#"{"foo": "quux"}"#);
+        php("+a.b=1&c+d=2", r#"{"a_b": "1", "c_d": "2"}"#);
+        php("foo[bar=1&baz[x=2", r#"{"foo_bar": "1", "baz_x": "2"}"#);
+        php(
+            "a[b]c=d&g[h]i[j]=8",
+            r#"{"a": {"b": "d"}, "g": {"h": "8"}}"#,
+        );
+        php("a=1&a[]=2", r#"{"a": ["2"]}"#);
+        php("a[b]=1&a[b][c]=2", r#"{"a": {"b": {"c": "2"}}}"#);
+        php(
+            "a[]=1&a[x]=2&a[]=3",
+            r#"{"a": {"0": "1", "x": "2", "1": "3"}}"#,
+        );
+        php("x[][y]=1&x[][z]=2", r#"{"x": [{"y": "1"}, {"z": "2"}]}"#);
+        php(
+            "x[y][][z]=1&x[y][][z]=2",
+            r#"{"x": {"y": [{"z": "1"}, {"z": "2"}]}}"#,
+        );
+        php("[a]=1&=2", r#"{}"#);
+
+        let deep_string = "[a]".repeat(DEFAULT_PARAM_DEPTH_LIMIT);
+        let query_string = format!("foo{}=bar", deep_string);
+        assert!(parser.parse_nested_query(&*query_string).is_err());
+    }
+
+    #[test]
+    fn rack_compat_rejects_conflicting_types() {
+        let parser = QueryParser::new(None);
+        assert!(parser.parse_nested_query("a=1&a[]=2").is_err());
+        assert!(parser.parse_nested_query("a[b]=1&a[b][c]=2").is_err());
+    }
+
+    #[test]
+    fn merges_nested_values() {
+        for compat in [Compat::Rack, Compat::Php] {
+            let parser = QueryParser::new(None).with_compat(compat);
+            let mut params = parser
+                .parse_nested_query("a[b][c]=1&a[list][]=x&d[]=1")
+                .unwrap();
+            parser
+                .merge_nested_value(&mut params, "a[b]", convert(r#"{"e": 2}"#))
+                .unwrap();
+            parser
+                .merge_nested_value(&mut params, "a", convert(r#"{"list": ["y"]}"#))
+                .unwrap();
+            assert_eq!(
+                Value::Object(params.clone()),
+                convert(r#"{"a": {"b": {"c": "1", "e": 2}, "list": ["x", "y"]}, "d": ["1"]}"#),
+                "{compat:?}"
+            );
+
+            let e = parser
+                .merge_nested_value(&mut params, "a", convert(r#"{"b": {"c": {"f": 3}}}"#))
+                .unwrap_err();
+            assert_eq!(
+                e.to_string(),
+                "Parameter type error: cannot merge object into string for param `a[b][c]`"
+            );
+        }
+    }
+
+    #[test]
+    fn reports_top_level_keys() {
+        let parser = QueryParser::new(None);
+        let mut params = HashMap::new();
+        let mut keys = Vec::new();
+        parser
+            .parse_nested_query_with(&mut params, "a=1&b[c]=2&d[]=3&=4&[e]=5", |k, _, _| {
+                keys.push(k)
+            })
+            .unwrap();
+        assert_eq!(keys, vec!["a", "b", "d", "[e]"]);
+
+        let parser = QueryParser::new(None).with_compat(Compat::Php);
+        assert_eq!(parser.top_level_key("a.b[c]"), Some("a_b".to_string()));
+        assert_eq!(parser.top_level_key("[c]"), None);
+    }
 }
@@ -0,0 +1,204 @@
+// Port from: https://github.com/rack/rack/blob/main/lib/rack/utils.rb (build_nested_query)
+
+use std::collections::HashMap;
+
+use serde::{Serialize, ser::Error as _};
+
+use crate::Value;
+
+/// Builds Rack style query strings from nested params, the inverse of `QueryParser`:
+///
+/// ```ignore
+/// let filter = Value::Object(QueryParser::new(None).parse_nested_query("q=a b&tags[]=x")?);
+/// assert_eq!(filter.to_query_string(), "q=a+b&tags%5B%5D=x");
+/// let location = format!("/search?{}", QuerySerializer::new().serialize(&search)?);
+/// ```
+///
+/// Objects become `key[field]`, arrays `key[]`, `null` a key without `=`. Keys are written in
+/// sorted order so the output is stable, and uploaded files are left out. Like Rack, arrays of
+/// objects only round-trip when every object has the same keys.
+#[derive(Debug, Clone, Default)]
+pub struct QuerySerializer {
+    repeated_keys: bool,
+}
+
+impl QuerySerializer {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Write arrays of scalars as `tag=a&tag=b` instead of `tag[]=a&tag[]=b`, for parsers
+    /// with `QueryParser::with_repeated_keys`.
+    pub fn with_repeated_keys(mut self, repeated_keys: bool) -> Self {
+        self.repeated_keys = repeated_keys;
+        self
+    }
+
+    /// Serializes any type that serializes to a map, e.g. a struct, through `serde_json`.
+    pub fn serialize<T: Serialize + ?Sized>(&self, value: &T) -> Result<String, serde_json::Error> {
+        match serde_json::to_value(value)? {
+            serde_json::Value::Object(map) => {
+                let params = map.iter().map(|(k, v)| (k.clone(), Value::from(v)));
+                Ok(self.serialize_params(&params.collect()))
+            }
+            value => Err(serde_json::Error::custom(format!(
+                "Query string must be built from a map, got {value}"
+            ))),
+        }
+    }
+
+    pub fn serialize_params(&self, params: &HashMap<String, Value>) -> String {
+        let mut pairs = vec![];
+        self.build_object(params, None, &mut pairs);
+        pairs.join("&")
+    }
+
+    fn build_object(
+        &self,
+        map: &HashMap<String, Value>,
+        prefix: Option<&str>,
+        pairs: &mut Vec<String>,
+    ) {
+        let mut keys = map.keys().collect::<Vec<_>>();
+        keys.sort();
+        for key in keys {
+            let name = match prefix {
+                Some(prefix) => format!("{prefix}[{key}]"),
+                None => key.clone(),
+            };
+            self.build(&map[key], &name, pairs);
+        }
+    }
+
+    fn build(&self, value: &Value, name: &str, pairs: &mut Vec<String>) {
+        match value {
+            Value::Object(map) => self.build_object(map, Some(name), pairs),
+            Value::Array(values) => {
+                let scalars = values
+                    .iter()
+                    .all(|v| !matches!(v, Value::Object(_) | Value::Array(_)));
+                let name = if self.repeated_keys && scalars && !name.contains('[') {
+                    name.to_string()
+                } else {
+                    format!("{name}[]")
+                };
+                for value in values {
+                    self.build(value, &name, pairs);
+                }
+            }
+            Value::Null => pairs.push(escape(name)),
+            Value::Bool(b) => pairs.push(format!("{}={b}", escape(name))),
+            Value::Number(n) => pairs.push(format!("{}={n}", escape(name))),
+            Value::String(s) | Value::XStr(s) => {
+                pairs.push(format!("{}={}", escape(name), escape(s)))
+            }
+            Value::UploadFile(_) => {}
+        }
+    }
+}
+
+fn escape(s: &str) -> String {
+    form_urlencoded::byte_serialize(s.as_bytes()).collect()
+}
+
+impl Value {
+    /// The query string of an object, e.g. `user%5Bname%5D=alice` for `{"user": {"name": "alice"}}`,
+    /// see `QuerySerializer`. Other values have no keys and give an empty string.
+    pub fn to_query_string(&self) -> String {
+        match self {
+            Value::Object(params) => QuerySerializer::new().serialize_params(params),
+            _ => String::new(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde::Serialize;
+
+    use crate::query_parser::QueryParser;
+
+    use super::*;
+
+    fn convert(json: &str) -> Value {
+        let json: serde_json::Value = serde_json::from_str(json).unwrap();
+        Value::from(&json)
+    }
+
+    #[test]
+    fn test_build_nested_query() {
+        let cases = [
+            (r#"{"foo": null}"#, "foo"),
+            (r#"{"foo": ""}"#, "foo="),
+            (r#"{"foo": "bar", "baz": 1.5}"#, "baz=1.5&foo=bar"),
+            (
+                r#"{"my weird field": "q1!2\"'w$5&7/z8)?"}"#,
+                "my+weird+field=q1%212%22%27w%245%267%2Fz8%29%3F",
+            ),
+            (r#"{"foo": ["bar", "baz"]}"#, "foo%5B%5D=bar&foo%5B%5D=baz"),
+            (r#"{"foo": []}"#, ""),
+            (r#"{"x": {"y": {"z": true}}}"#, "x%5By%5D%5Bz%5D=true"),
+            (
+                r#"{"x": {"y": [{"z": "1", "w": "a"}, {"z": "2", "w": "b"}]}}"#,
+                "x%5By%5D%5B%5D%5Bw%5D=a&x%5By%5D%5B%5D%5Bz%5D=1&x%5By%5D%5B%5D%5Bw%5D=b&x%5By%5D%5B%5D%5Bz%5D=2",
+            ),
+        ];
+        let parser = QueryParser::new(None);
+        for (json, expected) in cases {
+            let value = convert(json);
+            let query = value.to_query_string();
+            assert_eq!(query, expected);
+            // Parses back to the same params, as strings
+            let parsed = Value::Object(parser.parse_nested_query(query.as_str()).unwrap());
+            assert_eq!(parsed.to_query_string(), query);
+        }
+        assert_eq!(Value::string("foo").to_query_string(), "");
+    }
+
+    #[test]
+    fn test_serialize() {
+        #[derive(Serialize)]
+        struct Filter {
+            status: Vec<&'static str>,
+            owner: Owner,
+            page: u32,
+        }
+
+        #[derive(Serialize)]
+        struct Owner {
+            name: &'static str,
+        }
+
+        let filter = Filter {
+            status: vec!["open", "closed"],
+            owner: Owner { name: "alice" },
+            page: 2,
+        };
+        let query = QuerySerializer::new().serialize(&filter).unwrap();
+        assert_eq!(
+            query,
+            "owner%5Bname%5D=alice&page=2&status%5B%5D=open&status%5B%5D=closed"
+        );
+        let params = QueryParser::new(None)
+            .parse_nested_query(query.as_str())
+            .unwrap();
+        assert_eq!(params["owner"], convert(r#"{"name": "alice"}"#));
+        assert_eq!(params["status"], convert(r#"["open", "closed"]"#));
+
+        let query = QuerySerializer::new()
+            .with_repeated_keys(true)
+            .serialize(&filter)
+            .unwrap();
+        assert_eq!(
+            query,
+            "owner%5Bname%5D=alice&page=2&status=open&status=closed"
+        );
+        let params = QueryParser::new(None)
+            .with_repeated_keys(true)
+            .parse_nested_query(query.as_str())
+            .unwrap();
+        assert_eq!(params["status"], convert(r#"["open", "closed"]"#));
+
+        assert!(QuerySerializer::new().serialize(&[1, 2]).is_err());
+    }
+}
@@ -0,0 +1,412 @@
+//! Error recovery for `ParamsConfig::collect_errors`: a failed deserialization is repeated
+//! with the failing values replaced by placeholders, until every error is found.
+
+use std::cell::{Cell, RefCell};
+#[cfg(server)]
+use std::collections::HashMap;
+
+#[cfg(server)]
+use serde::de::DeserializeOwned;
+use serde::de::{self, Deserializer, IntoDeserializer, Visitor};
+
+use crate::Value;
+
+/// Stands for a value that failed, deserializes to a placeholder of the requested type.
+const PLACEHOLDER: &str = "\0axum-params:placeholder\0";
+
+/// Stop after that many errors, e.g. for a long array of invalid values.
+#[cfg(server)]
+const MAX_ERRORS: usize = 100;
+
+thread_local! {
+    static COLLECTING: Cell<bool> = const { Cell::new(false) };
+    /// The keys and indices of the value being deserialized, and of the first failure.
+    static PATH: RefCell<(Vec<String>, Option<Vec<String>>)> =
+        const { RefCell::new((vec![], None)) };
+}
+
+pub(crate) fn is_collecting() -> bool {
+    COLLECTING.get()
+}
+
+pub(crate) fn is_placeholder(value: &Value) -> bool {
+    is_collecting() && matches!(value, Value::XStr(s) if s == PLACEHOLDER)
+}
+
+/// Runs the deserialization of the value at `segment`, remembering where it failed first.
+pub(crate) fn at_segment<R, E>(segment: String, f: impl FnOnce() -> Result<R, E>) -> Result<R, E> {
+    PATH.with_borrow_mut(|(path, _)| path.push(segment));
+    let result = f();
+    PATH.with_borrow_mut(|(path, failed)| {
+        if result.is_err() && failed.is_none() {
+            *failed = Some(path.clone());
+        }
+        path.pop();
+    });
+    result
+}
+
+#[cfg(server)]
+struct Restore;
+
+#[cfg(server)]
+impl Drop for Restore {
+    fn drop(&mut self) {
+        COLLECTING.set(false);
+        PATH.with_borrow_mut(|path| *path = (vec![], None));
+    }
+}
+
+/// Every deserialization error of `T` in `params`, as `(bracket path, message)` pairs. An
+/// empty path is an error about the whole params.
+#[cfg(server)]
+pub(crate) fn collect_errors<T: DeserializeOwned>(
+    params: HashMap<String, Value>,
+) -> Vec<(String, String)> {
+    let _restore = Restore;
+    COLLECTING.set(true);
+    let mut params = Value::Object(params);
+    let mut errors: Vec<(Vec<String>, String)> = vec![];
+    while errors.len() < MAX_ERRORS {
+        PATH.with_borrow_mut(|path| *path = (vec![], None));
+        let result = crate::uploaded::skip_uploads(|| T::deserialize(params.clone()));
+        let Err(e) = result else {
+            break;
+        };
+        let message = e.to_string();
+        let mut path = PATH.with_borrow_mut(|(_, failed)| failed.take().unwrap_or_default());
+        // Raised by the struct missing the field, point at the field itself
+        if let Some(field) = message
+            .strip_prefix("missing field `")
+            .and_then(|field| field.strip_suffix('`'))
+        {
+            path.push(field.to_string());
+        }
+        // Nothing left to replace, e.g. a failing placeholder
+        let stuck = path.is_empty() || errors.iter().any(|(failed, _)| *failed == path);
+        if !errors
+            .iter()
+            .any(|error| *error == (path.clone(), message.clone()))
+        {
+            errors.push((path.clone(), message));
+        }
+        if stuck {
+            break;
+        }
+        replace(&mut params, &path);
+    }
+    errors
+        .into_iter()
+        .map(|(path, message)| (bracket_key(&path), message))
+        .collect()
+}
+
+/// Replaces the value at `path` by a placeholder, or the first value on the way that isn't
+/// an object or array.
+#[cfg(server)]
+fn replace(value: &mut Value, path: &[String]) {
+    let Some((key, rest)) = path.split_first() else {
+        *value = Value::xstr(PLACEHOLDER);
+        return;
+    };
+    match value {
+        Value::Object(map) => replace(map.entry(key.clone()).or_insert(Value::Null), rest),
+        Value::Array(vec) => match key.parse::<usize>().ok().and_then(|i| vec.get_mut(i)) {
+            Some(item) => replace(item, rest),
+            None => *value = Value::xstr(PLACEHOLDER),
+        },
+        _ => *value = Value::xstr(PLACEHOLDER),
+    }
+}
+
+#[cfg(server)]
+fn bracket_key(path: &[String]) -> String {
+    match path.split_first() {
+        Some((first, rest)) => rest
+            .iter()
+            .fold(first.clone(), |key, segment| format!("{key}[{segment}]")),
+        None => String::new(),
+    }
+}
+
+/// The deserializer of placeholders: the zero value of numbers, empty strings and
+/// collections, `None`, structs of placeholders and the first variant of enums.
+pub(crate) struct Placeholder;
+
+macro_rules! visit {
+    ($($method:ident => $visit:ident($($value:expr)?)),* $(,)?) => {
+        $(
+            fn $method<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Self::Error> {
+                visitor.$visit($($value)?)
+            }
+        )*
+    };
+}
+
+impl<'de> Deserializer<'de> for Placeholder {
+    type Error = de::value::Error;
+
+    visit! {
+        deserialize_any => visit_unit(),
+        deserialize_bool => visit_bool(false),
+        deserialize_i8 => visit_i64(0),
+        deserialize_i16 => visit_i64(0),
+        deserialize_i32 => visit_i64(0),
+        deserialize_i64 => visit_i64(0),
+        deserialize_i128 => visit_i64(0),
+        deserialize_u8 => visit_u64(0),
+        deserialize_u16 => visit_u64(0),
+        deserialize_u32 => visit_u64(0),
+        deserialize_u64 => visit_u64(0),
+        deserialize_u128 => visit_u64(0),
+        deserialize_f32 => visit_f64(0.0),
+        deserialize_f64 => visit_f64(0.0),
+        deserialize_char => visit_char('\0'),
+        deserialize_str => visit_str(""),
+        deserialize_string => visit_str(""),
+        deserialize_identifier => visit_str(""),
+        deserialize_bytes => visit_bytes(&[]),
+        deserialize_byte_buf => visit_bytes(&[]),
+        deserialize_option => visit_none(),
+        deserialize_unit => visit_unit(),
+        deserialize_ignored_any => visit_unit(),
+        deserialize_seq => visit_seq(Placeholders(0)),
+        deserialize_map => visit_map(PlaceholderFields(&[])),
+    }
+
+    fn deserialize_unit_struct<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        visitor: V,
+    ) -> Result<V::Value, Self::Error> {
+        visitor.visit_unit()
+    }
+
+    fn deserialize_newtype_struct<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        visitor: V,
+    ) -> Result<V::Value, Self::Error> {
+        visitor.visit_newtype_struct(self)
+    }
+
+    fn deserialize_tuple<V: Visitor<'de>>(
+        self,
+        len: usize,
+        visitor: V,
+    ) -> Result<V::Value, Self::Error> {
+        visitor.visit_seq(Placeholders(len))
+    }
+
+    fn deserialize_tuple_struct<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        len: usize,
+        visitor: V,
+    ) -> Result<V::Value, Self::Error> {
+        visitor.visit_seq(Placeholders(len))
+    }
+
+    fn deserialize_struct<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        fields: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value, Self::Error> {
+        visitor.visit_map(PlaceholderFields(fields))
+    }
+
+    fn deserialize_enum<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        variants: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value, Self::Error> {
+        visitor.visit_enum(PlaceholderVariant(
+            variants.first().copied().unwrap_or_default(),
+        ))
+    }
+}
+
+struct Placeholders(usize);
+
+impl<'de> de::SeqAccess<'de> for Placeholders {
+    type Error = de::value::Error;
+
+    fn next_element_seed<T>(&mut self, seed: T) -> Result<Option<T::Value>, Self::Error>
+    where
+        T: de::DeserializeSeed<'de>,
+    {
+        if self.0 == 0 {
+            return Ok(None);
+        }
+        self.0 -= 1;
+        seed.deserialize(Placeholder).map(Some)
+    }
+}
+
+struct PlaceholderFields(&'static [&'static str]);
+
+impl<'de> de::MapAccess<'de> for PlaceholderFields {
+    type Error = de::value::Error;
+
+    fn next_key_seed<K>(&mut self, seed: K) -> Result<Option<K::Value>, Self::Error>
+    where
+        K: de::DeserializeSeed<'de>,
+    {
+        let Some((field, rest)) = self.0.split_first() else {
+            return Ok(None);
+        };
+        self.0 = rest;
+        seed.deserialize(field.into_deserializer()).map(Some)
+    }
+
+    fn next_value_seed<V>(&mut self, seed: V) -> Result<V::Value, Self::Error>
+    where
+        V: de::DeserializeSeed<'de>,
+    {
+        seed.deserialize(Placeholder)
+    }
+}
+
+struct PlaceholderVariant(&'static str);
+
+impl<'de> de::EnumAccess<'de> for PlaceholderVariant {
+    type Error = de::value::Error;
+    type Variant = Placeholder;
+
+    fn variant_seed<V>(self, seed: V) -> Result<(V::Value, Self::Variant), Self::Error>
+    where
+        V: de::DeserializeSeed<'de>,
+    {
+        let variant = seed.deserialize(self.0.into_deserializer())?;
+        Ok((variant, Placeholder))
+    }
+}
+
+impl<'de> de::VariantAccess<'de> for Placeholder {
+    type Error = de::value::Error;
+
+    fn unit_variant(self) -> Result<(), Self::Error> {
+        Ok(())
+    }
+
+    fn newtype_variant_seed<T>(self, seed: T) -> Result<T::Value, Self::Error>
+    where
+        T: de::DeserializeSeed<'de>,
+    {
+        seed.deserialize(Placeholder)
+    }
+
+    fn tuple_variant<V>(self, len: usize, visitor: V) -> Result<V::Value, Self::Error>
+    where
+        V: Visitor<'de>,
+    {
+        visitor.visit_seq(Placeholders(len))
+    }
+
+    fn struct_variant<V>(
+        self,
+        fields: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value, Self::Error>
+    where
+        V: Visitor<'de>,
+    {
+        visitor.visit_map(PlaceholderFields(fields))
+    }
+}
+
+#[cfg(all(test, server))]
+mod tests {
+    use serde::Deserialize;
+
+    use super::*;
+
+    #[derive(Debug, Deserialize)]
+    #[allow(dead_code)]
+    struct SignUp {
+        name: String,
+        age: u32,
+        email: String,
+        nickname: Option<String>,
+        #[serde(default)]
+        tags: Vec<u8>,
+        address: Address,
+        role: Role,
+    }
+
+    #[derive(Debug, Deserialize)]
+    #[allow(dead_code)]
+    struct Address {
+        city: String,
+        zip: u32,
+    }
+
+    #[derive(Debug, Deserialize)]
+    #[serde(rename_all = "lowercase")]
+    #[allow(dead_code)]
+    enum Role {
+        Admin,
+        User,
+    }
+
+    #[test]
+    fn test_collect_errors() {
+        let params = HashMap::from([
+            ("age".to_string(), Value::xstr("old")),
+            (
+                "tags".to_string(),
+                Value::array(vec![Value::xstr("1"), Value::xstr("300")]),
+            ),
+            (
+                "address".to_string(),
+                Value::object(HashMap::from([("zip".to_string(), Value::xstr("x"))])),
+            ),
+            ("role".to_string(), Value::xstr("root")),
+        ]);
+        let mut errors = collect_errors::<SignUp>(params);
+        errors.sort();
+        assert_eq!(
+            errors,
+            [
+                ("address[city]", "missing field `city`"),
+                ("address[zip]", "invalid digit found in string"),
+                ("age", "invalid digit found in string"),
+                ("email", "missing field `email`"),
+                ("name", "missing field `name`"),
+                ("role", "unknown variant `root`, expected `admin` or `user`"),
+                ("tags[1]", "number too large to fit in target type"),
+            ]
+            .map(|(key, message)| (key.to_string(), message.to_string()))
+        );
+        assert!(!is_collecting());
+    }
+
+    #[tokio::test]
+    async fn test_collect_decode_errors() {
+        let error = crate::test::ParamsBuilder::new()
+            .query("age", "old")
+            .query("address[zip]", "75001")
+            .query("role", "user")
+            .config(crate::ParamsConfig::new().collect_errors(true))
+            .build::<SignUp>()
+            .await
+            .unwrap_err();
+        let crate::Error::DecodeErrors(_, fields) = &error else {
+            panic!("{error:?}");
+        };
+        let mut keys = fields
+            .iter()
+            .map(|field| field.key.as_str())
+            .collect::<Vec<_>>();
+        keys.sort();
+        assert_eq!(keys, ["address[city]", "age", "email", "name"]);
+        assert!(
+            error
+                .message()
+                .contains("`age`: invalid digit found in string"),
+            "{error:?}"
+        );
+    }
+}
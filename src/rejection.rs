@@ -0,0 +1,137 @@
+use std::{fmt, sync::Arc};
+
+use axum::{
+    extract::Request,
+    http::{HeaderMap, Method, Uri, request::Parts},
+    response::{IntoResponse, Response},
+};
+
+use crate::{Error, ParamsConfig};
+
+/// The request a rejection is for, passed to `ParamsConfig::on_rejection`.
+#[derive(Debug, Clone)]
+pub struct RejectionContext {
+    pub method: Method,
+    pub uri: Uri,
+    pub headers: HeaderMap,
+}
+
+type Handler = dyn Fn(Error, &RejectionContext) -> Response + Send + Sync;
+
+/// A handler installed with `ParamsConfig::on_rejection`.
+#[derive(Clone)]
+pub(crate) struct OnRejection(pub(crate) Arc<Handler>);
+
+impl fmt::Debug for OnRejection {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("OnRejection")
+    }
+}
+
+/// The rejection of the extractors: the `Error`, answered by the `ParamsConfig::on_rejection`
+/// handler of the request if there is one.
+pub struct Rejection {
+    error: Error,
+    handler: Option<(OnRejection, RejectionContext)>,
+    problem_details: bool,
+}
+
+impl Rejection {
+    pub fn error(&self) -> &Error {
+        &self.error
+    }
+
+    pub fn into_error(self) -> Error {
+        self.error
+    }
+}
+
+impl From<Error> for Rejection {
+    fn from(error: Error) -> Self {
+        Rejection {
+            error,
+            handler: None,
+            problem_details: false,
+        }
+    }
+}
+
+impl From<Rejection> for Error {
+    fn from(rejection: Rejection) -> Self {
+        rejection.error
+    }
+}
+
+impl fmt::Debug for Rejection {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        self.error.fmt(f)
+    }
+}
+
+impl fmt::Display for Rejection {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fmt::Display::fmt(&self.error, f)
+    }
+}
+
+impl std::error::Error for Rejection {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        Some(&self.error)
+    }
+}
+
+impl IntoResponse for Rejection {
+    fn into_response(self) -> Response {
+        match self.handler {
+            Some((OnRejection(handler), context)) => handler(self.error, &context),
+            None if self.problem_details => self.error.into_problem_response(),
+            None => self.error.into_response(),
+        }
+    }
+}
+
+/// Builds the `Rejection`s of one request, captured before the request is consumed.
+pub(crate) struct Rejecter {
+    handler: Option<(OnRejection, RejectionContext)>,
+    problem_details: bool,
+}
+
+impl Rejecter {
+    pub(crate) fn of(req: &Request) -> Self {
+        Self::new(req.extensions().get::<ParamsConfig>(), || {
+            RejectionContext {
+                method: req.method().clone(),
+                uri: req.uri().clone(),
+                headers: req.headers().clone(),
+            }
+        })
+    }
+
+    pub(crate) fn of_parts(parts: &Parts) -> Self {
+        Self::new(parts.extensions.get::<ParamsConfig>(), || {
+            RejectionContext {
+                method: parts.method.clone(),
+                uri: parts.uri.clone(),
+                headers: parts.headers.clone(),
+            }
+        })
+    }
+
+    fn new(config: Option<&ParamsConfig>, context: impl FnOnce() -> RejectionContext) -> Self {
+        // The request is only copied when a handler will read it
+        Rejecter {
+            handler: config
+                .and_then(|config| config.on_rejection.clone())
+                .map(|handler| (handler, context())),
+            problem_details: config.is_some_and(|config| config.problem_details),
+        }
+    }
+
+    pub(crate) fn reject(self, error: Error) -> Rejection {
+        Rejection {
+            error,
+            handler: self.handler,
+            problem_details: self.problem_details,
+        }
+    }
+}
@@ -0,0 +1,430 @@
+#[cfg(server)]
+use std::collections::HashMap;
+
+use crate::query_parser::{
+    DEFAULT_MAX_ARRAY_LENGTH, DEFAULT_MAX_PARAMS, DEFAULT_PARAM_DEPTH_LIMIT,
+};
+#[cfg(server)]
+use crate::{N, Number, Value};
+
+const KB: usize = 1024;
+const MB: usize = 1024 * KB;
+
+/// Limits and input checks applied while extracting parameters.
+///
+/// Start from one of the presets and adjust single knobs with the builder methods:
+///
+/// ```
+/// # #[cfg(any(feature = "axum-07", feature = "axum-08"))]
+/// # {
+/// use axum_params::{ParamsConfig, SecurityPolicy};
+///
+/// let policy = SecurityPolicy::strict().max_file_size(50 << 20);
+/// let config = ParamsConfig::new().security_policy(policy);
+/// # }
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SecurityPolicy {
+    pub(crate) max_depth: usize,
+    pub(crate) max_json_values: usize,
+    pub(crate) max_params: usize,
+    pub(crate) max_array_length: usize,
+    pub(crate) max_query_len: usize,
+    pub(crate) max_body_size: usize,
+    pub(crate) max_file_size: usize,
+    pub(crate) max_text_field_size: usize,
+    pub(crate) max_parts: usize,
+    pub(crate) max_params_size: usize,
+    pub(crate) allowed_content_types: Option<Vec<String>>,
+    pub(crate) denied_keys: Vec<String>,
+    pub(crate) sensitive_keys: Vec<String>,
+    pub(crate) strict_utf8: bool,
+}
+
+impl Default for SecurityPolicy {
+    fn default() -> Self {
+        Self::lenient()
+    }
+}
+
+impl SecurityPolicy {
+    /// Tight limits for public facing endpoints:
+    ///
+    /// - 1 MiB bodies, 10 MiB files and 64 KiB multipart text fields
+    /// - 4 KiB query strings, 1,000 parameters, 100 multipart parts and 2 MiB of merged params
+    /// - 16 levels of nesting, arrays of 1,000 elements and JSON documents of 10,000 values
+    /// - only JSON, form and multipart bodies
+    /// - no `__proto__`, `constructor` or `prototype` keys and no invalid UTF-8
+    pub fn strict() -> Self {
+        Self {
+            max_depth: 16,
+            max_json_values: 10_000,
+            max_params: 1_000,
+            max_array_length: 1_000,
+            max_query_len: 4 * KB,
+            max_body_size: MB,
+            max_file_size: 10 * MB,
+            max_text_field_size: 64 * KB,
+            max_parts: 100,
+            max_params_size: 2 * MB,
+            allowed_content_types: Some(vec![
+                "application/json".to_string(),
+                "application/x-www-form-urlencoded".to_string(),
+                "multipart/form-data".to_string(),
+            ]),
+            denied_keys: vec![
+                "__proto__".to_string(),
+                "constructor".to_string(),
+                "prototype".to_string(),
+            ],
+            sensitive_keys: vec![],
+            strict_utf8: true,
+        }
+    }
+
+    /// The default limits:
+    ///
+    /// - 2 MiB bodies and multipart text fields and 100 MiB files
+    /// - 16 KiB query strings, 10,000 parameters, 10,000 multipart parts and 8 MiB of merged
+    ///   params
+    /// - 100 levels of nesting, arrays of 10,000 elements and JSON documents of 1,000,000 values
+    /// - any content type and lossy UTF-8 decoding
+    pub fn lenient() -> Self {
+        Self {
+            max_depth: DEFAULT_PARAM_DEPTH_LIMIT,
+            max_json_values: 1_000_000,
+            max_params: DEFAULT_MAX_PARAMS,
+            max_array_length: DEFAULT_MAX_ARRAY_LENGTH,
+            max_query_len: 16 * KB,
+            max_body_size: 2 * MB,
+            max_file_size: 100 * MB,
+            max_text_field_size: 2 * MB,
+            max_parts: DEFAULT_MAX_PARAMS,
+            max_params_size: 8 * MB,
+            allowed_content_types: None,
+            denied_keys: vec![],
+            sensitive_keys: vec![],
+            strict_utf8: false,
+        }
+    }
+
+    /// The behavior of earlier releases: no size or count limits.
+    pub fn legacy_compat() -> Self {
+        Self {
+            max_json_values: usize::MAX,
+            max_params: usize::MAX,
+            max_array_length: usize::MAX,
+            max_query_len: usize::MAX,
+            max_body_size: usize::MAX,
+            max_file_size: usize::MAX,
+            max_text_field_size: usize::MAX,
+            max_parts: usize::MAX,
+            max_params_size: usize::MAX,
+            ..Self::lenient()
+        }
+    }
+
+    /// Maximum nesting of bracket keys and JSON objects / arrays.
+    pub fn max_depth(mut self, max_depth: usize) -> Self {
+        self.max_depth = max_depth;
+        self
+    }
+
+    /// Maximum number of values in a JSON body or multipart JSON field, counting objects,
+    /// arrays and scalars, e.g. 4 for `{"a": [1, 2]}`.
+    pub fn max_json_values(mut self, max_json_values: usize) -> Self {
+        self.max_json_values = max_json_values;
+        self
+    }
+
+    /// Maximum number of parameters in the query string, form body or multipart body.
+    pub fn max_params(mut self, max_params: usize) -> Self {
+        self.max_params = max_params;
+        self
+    }
+
+    /// Maximum number of elements of one array in the query string, form body or multipart
+    /// body, like `tag[]=a&tag[]=b`. JSON arrays are bounded by `max_json_values`.
+    pub fn max_array_length(mut self, max_array_length: usize) -> Self {
+        self.max_array_length = max_array_length;
+        self
+    }
+
+    /// Maximum length of the raw query string, checked before it is decoded.
+    pub fn max_query_len(mut self, max_query_len: usize) -> Self {
+        self.max_query_len = max_query_len;
+        self
+    }
+
+    /// Maximum size of bodies and multipart JSON fields that are buffered in memory.
+    /// Bodies over it reject the request with `Error::PayloadTooLarge`, before they are read
+    /// when their `Content-Length` already exceeds it.
+    pub fn max_body_size(mut self, max_body_size: usize) -> Self {
+        self.max_body_size = max_body_size;
+        self
+    }
+
+    /// Maximum size of a single uploaded file.
+    pub fn max_file_size(mut self, max_file_size: usize) -> Self {
+        self.max_file_size = max_file_size;
+        self
+    }
+
+    /// Maximum size of a multipart text field, larger fields reject the request with
+    /// `Error::PayloadTooLarge`.
+    pub fn max_text_field_size(mut self, max_text_field_size: usize) -> Self {
+        self.max_text_field_size = max_text_field_size;
+        self
+    }
+
+    /// Maximum number of parts of a multipart body, text fields, JSON fields and files
+    /// included. More parts reject the request with `Error::PayloadTooLarge`.
+    pub fn max_parts(mut self, max_parts: usize) -> Self {
+        self.max_parts = max_parts;
+        self
+    }
+
+    /// Maximum in-memory size of the params merged from every source, the bytes of their keys
+    /// and strings with one byte for each other value, checked after each source is merged.
+    /// It bounds combinations of sources that each stay within their own limits, larger
+    /// params reject the request with `Error::PayloadTooLarge`. Uploaded files count their
+    /// name and content type only.
+    pub fn max_params_size(mut self, max_params_size: usize) -> Self {
+        self.max_params_size = max_params_size;
+        self
+    }
+
+    /// Reject requests whose `Content-Type` is not one of `content_types`, `None` allows all.
+    pub fn allowed_content_types<I, S>(mut self, content_types: Option<I>) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        self.allowed_content_types =
+            content_types.map(|types| types.into_iter().map(Into::into).collect());
+        self
+    }
+
+    /// Reject any parameter whose name, at any nesting level, is one of `keys`.
+    pub fn denied_keys<I, S>(mut self, keys: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        self.denied_keys = keys.into_iter().map(Into::into).collect();
+        self
+    }
+
+    /// Hide the values of parameters whose name, at any nesting level, matches one of
+    /// `patterns` from error messages, e.g. `["password", "token", "*_secret"]`. `*` matches
+    /// any characters and case is ignored.
+    pub fn sensitive_keys<I, S>(mut self, patterns: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        self.sensitive_keys = patterns.into_iter().map(Into::into).collect();
+        self
+    }
+
+    /// Reject invalid UTF-8 in query strings, form bodies and multipart text fields instead
+    /// of replacing it with U+FFFD.
+    pub fn strict_utf8(mut self, strict_utf8: bool) -> Self {
+        self.strict_utf8 = strict_utf8;
+        self
+    }
+
+    #[cfg(server)]
+    pub(crate) fn allows_content_type(&self, content_type: &str) -> bool {
+        let essence = content_type.split(';').next().unwrap_or_default().trim();
+        self.allowed_content_types
+            .as_ref()
+            .is_none_or(|allowed| allowed.iter().any(|ct| ct.eq_ignore_ascii_case(essence)))
+    }
+
+    #[cfg(server)]
+    fn is_sensitive(&self, key: &str) -> bool {
+        self.sensitive_keys
+            .iter()
+            .any(|pattern| glob_match(&pattern.to_ascii_lowercase(), &key.to_ascii_lowercase()))
+    }
+}
+
+/// Whether `text` matches `pattern`, where `*` matches any characters.
+#[cfg(server)]
+pub(crate) fn glob_match(pattern: &str, text: &str) -> bool {
+    let mut pieces = pattern.split('*');
+    let first = pieces.next().unwrap_or_default();
+    let Some(mut rest) = text.strip_prefix(first) else {
+        return false;
+    };
+    let Some(last) = pieces.next_back() else {
+        return rest.is_empty();
+    };
+    for piece in pieces {
+        match rest.find(piece) {
+            Some(i) => rest = &rest[i + piece.len()..],
+            None => return false,
+        }
+    }
+    rest.ends_with(last)
+}
+
+/// The values of the sensitive parameters of a request, removed from error messages.
+#[cfg(server)]
+pub(crate) struct Redactor(Vec<String>);
+
+#[cfg(server)]
+impl Redactor {
+    pub(crate) fn new(policy: &SecurityPolicy, params: &HashMap<String, Value>) -> Self {
+        let mut values = vec![];
+        if !policy.sensitive_keys.is_empty() {
+            collect_sensitive(policy, params, &mut values);
+        }
+        // Longest first, so a value containing another one is removed whole
+        values.sort_by_key(|value: &String| std::cmp::Reverse(value.len()));
+        Redactor(values)
+    }
+
+    pub(crate) fn redact(&self, message: String) -> String {
+        self.0.iter().fold(message, |message, value| {
+            message.replace(value.as_str(), "[FILTERED]")
+        })
+    }
+}
+
+/// A copy of `params` with the values of sensitive parameters replaced by `[FILTERED]`.
+#[cfg(server)]
+pub(crate) fn redact_params(
+    policy: &SecurityPolicy,
+    params: &HashMap<String, Value>,
+) -> HashMap<String, Value> {
+    params
+        .iter()
+        .map(|(key, value)| {
+            let value = match value {
+                _ if policy.is_sensitive(key) => Value::string("[FILTERED]"),
+                value => redact_value(policy, value),
+            };
+            (key.clone(), value)
+        })
+        .collect()
+}
+
+/// A copy of `value` with the sensitive parameters of its objects, at any depth, redacted.
+#[cfg(server)]
+fn redact_value(policy: &SecurityPolicy, value: &Value) -> Value {
+    match value {
+        Value::Object(map) => Value::Object(redact_params(policy, map)),
+        Value::Array(vec) => Value::Array(vec.iter().map(|v| redact_value(policy, v)).collect()),
+        value => value.clone(),
+    }
+}
+
+#[cfg(server)]
+fn collect_sensitive(
+    policy: &SecurityPolicy,
+    params: &HashMap<String, Value>,
+    values: &mut Vec<String>,
+) {
+    for (key, value) in params {
+        if policy.is_sensitive(key) {
+            collect_scalars(value, values);
+        } else {
+            collect_sensitive_in(policy, value, values);
+        }
+    }
+}
+
+/// Collects the sensitive parameters of the objects of `value`, at any depth.
+#[cfg(server)]
+fn collect_sensitive_in(policy: &SecurityPolicy, value: &Value, values: &mut Vec<String>) {
+    match value {
+        Value::Object(map) => collect_sensitive(policy, map, values),
+        Value::Array(vec) => vec
+            .iter()
+            .for_each(|value| collect_sensitive_in(policy, value, values)),
+        _ => {}
+    }
+}
+
+#[cfg(server)]
+fn collect_scalars(value: &Value, values: &mut Vec<String>) {
+    match value {
+        Value::String(s) | Value::XStr(s) if !s.is_empty() => values.push(s.to_string()),
+        Value::Number(Number(n)) => values.push(match n {
+            N::PosInt(i) => i.to_string(),
+            N::NegInt(i) => i.to_string(),
+            N::PosInt128(i) => i.to_string(),
+            N::NegInt128(i) => i.to_string(),
+            N::Float(f) => f.to_string(),
+        }),
+        Value::Object(map) => map
+            .values()
+            .for_each(|value| collect_scalars(value, values)),
+        Value::Array(vec) => vec.iter().for_each(|value| collect_scalars(value, values)),
+        _ => {}
+    }
+}
+
+#[cfg(all(test, server))]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_presets() {
+        assert_eq!(SecurityPolicy::default(), SecurityPolicy::lenient());
+        assert!(SecurityPolicy::strict().max_params < SecurityPolicy::lenient().max_params);
+        assert_eq!(SecurityPolicy::legacy_compat().max_body_size, usize::MAX);
+    }
+
+    #[test]
+    fn test_allows_content_type() {
+        let policy = SecurityPolicy::strict();
+        assert!(policy.allows_content_type("application/json; charset=utf-8"));
+        assert!(policy.allows_content_type("Multipart/Form-Data; boundary=x"));
+        assert!(!policy.allows_content_type("text/plain"));
+        assert!(SecurityPolicy::lenient().allows_content_type("text/plain"));
+    }
+
+    #[test]
+    fn test_redactor() {
+        let policy = SecurityPolicy::lenient().sensitive_keys(["password", "*_secret"]);
+        assert!(policy.is_sensitive("Password"));
+        assert!(policy.is_sensitive("api_secret"));
+        assert!(!policy.is_sensitive("password_hint"));
+        assert!(!policy.is_sensitive("secret"));
+
+        let params = HashMap::from([
+            ("password".to_string(), Value::xstr("hunter2")),
+            ("name".to_string(), Value::xstr("alice")),
+            (
+                "keys".to_string(),
+                Value::Array(vec![Value::Object(HashMap::from([(
+                    "client_secret".to_string(),
+                    Value::string("s3cr3t"),
+                )]))]),
+            ),
+        ]);
+        let redactor = Redactor::new(&policy, &params);
+        assert_eq!(
+            redactor.redact("`hunter2` and \"s3cr3t\" for alice".to_string()),
+            "`[FILTERED]` and \"[FILTERED]\" for alice"
+        );
+    }
+
+    #[test]
+    fn test_redact_nested_arrays() {
+        let policy = SecurityPolicy::lenient().sensitive_keys(["password"]);
+        let params = HashMap::from([(
+            "users".to_string(),
+            Value::from(&serde_json::json!([[{"name": "alice", "password": "x1"}]])),
+        )]);
+        assert_eq!(
+            redact_params(&policy, &params)["users"],
+            Value::from(&serde_json::json!([[{"name": "alice", "password": "[FILTERED]"}]]))
+        );
+        let redactor = Redactor::new(&policy, &params);
+        assert_eq!(redactor.redact("x1".to_string()), "[FILTERED]");
+    }
+}
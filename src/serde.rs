@@ -1,14 +1,169 @@
-use crate::{N, Number};
+use crate::{
+    N, Number,
+    recovery::{self, Placeholder},
+};
 
 use super::Value;
-use log::debug;
+use base64::{
+    Engine, alphabet,
+    engine::{DecodePaddingMode, GeneralPurpose, GeneralPurposeConfig},
+};
 use serde::{
     Deserialize, Deserializer,
-    de::{self, MapAccess, SeqAccess, Visitor},
+    de::{self, DeserializeOwned, MapAccess, SeqAccess, Visitor},
 };
-use std::collections::HashMap;
+use std::{borrow::Cow, cell::Cell, collections::HashMap};
+
+/// Which value a `bool` field takes when a form sends several for it, e.g. Rails' hidden
+/// `flag[]=0` followed by the checkbox `flag[]=1`.
+///
+/// Repeated keys without brackets, `flag=0&flag=1`, keep the last value like Rack before
+/// this applies. Unchecked checkboxes are not sent at all, declare the field with
+/// `#[serde(default)]` to read them as `false`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Checkbox {
+    /// The last value wins, the checkbox rendered after its hidden field.
+    #[default]
+    Last,
+    /// `true` if any value is, independent of the field order.
+    AnyChecked,
+}
+
+/// The separators of numbers typed in form inputs, like `1.234,56` in most of Europe, see
+/// `ParamsConfig::number_format`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct NumberFormat {
+    decimal: char,
+    thousands: Option<char>,
+}
+
+impl NumberFormat {
+    /// `1.234,56`, e.g. German, Spanish or Italian.
+    pub const DECIMAL_COMMA: Self = Self::new(',', Some('.'));
+    /// `1'234.56`, Swiss.
+    pub const SWISS: Self = Self::new('.', Some('\''));
+
+    /// Thousands separators must group three digits, so `1.5` is rejected with a `.`
+    /// thousands separator instead of read as `15`.
+    pub const fn new(decimal: char, thousands: Option<char>) -> Self {
+        Self { decimal, thousands }
+    }
+
+    /// `s` with the separators of Rust's number parsing, `None` if it is misgrouped.
+    fn normalize(&self, s: &str) -> Option<String> {
+        let (integer, fraction) = match s.split_once(self.decimal) {
+            Some((integer, fraction)) => (integer, Some(fraction)),
+            None => (s, None),
+        };
+        let integer = match self.thousands {
+            Some(separator) if integer.contains(separator) => {
+                let mut groups = integer.split(separator);
+                let first = groups.next().unwrap_or_default();
+                let digits = first.trim_start_matches(['-', '+']);
+                if digits.is_empty() || digits.len() > 3 {
+                    return None;
+                }
+                let mut integer = first.to_string();
+                for group in groups {
+                    if group.len() != 3 || !group.bytes().all(|b| b.is_ascii_digit()) {
+                        return None;
+                    }
+                    integer.push_str(group);
+                }
+                integer
+            }
+            _ => integer.to_string(),
+        };
+        Some(match fraction {
+            Some(fraction) => format!("{integer}.{fraction}"),
+            None => integer,
+        })
+    }
+}
+
+/// String coercions applied while deserializing, set with the `ParamsConfig` builders.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct Coercions {
+    pub(crate) empty_as_none: bool,
+    pub(crate) checkbox: Checkbox,
+    pub(crate) number_format: Option<NumberFormat>,
+    pub(crate) seq_separator: Option<char>,
+}
+
+impl Coercions {
+    pub(crate) const DEFAULT: Self = Self {
+        empty_as_none: true,
+        checkbox: Checkbox::Last,
+        number_format: None,
+        seq_separator: None,
+    };
+}
+
+impl Default for Coercions {
+    fn default() -> Self {
+        Self::DEFAULT
+    }
+}
+
+thread_local! {
+    static COERCIONS: Cell<Coercions> = const { Cell::new(Coercions::DEFAULT) };
+}
+
+#[cfg(server)]
+struct RestoreCoercions(Coercions);
+
+#[cfg(server)]
+impl Drop for RestoreCoercions {
+    fn drop(&mut self) {
+        COERCIONS.set(self.0);
+    }
+}
+
+/// Deserializes with the coercions of a `ParamsConfig`, `Value`s deserialized elsewhere use
+/// the defaults.
+#[cfg(server)]
+pub(crate) fn with_coercions<R>(coercions: Coercions, f: impl FnOnce() -> R) -> R {
+    let _restore = RestoreCoercions(COERCIONS.replace(coercions));
+    f()
+}
+
+/// An empty string from the path, query string, form or multipart body that deserializes
+/// to `None` or the default value.
+fn is_blank(s: &str) -> bool {
+    s.is_empty() && COERCIONS.get().empty_as_none
+}
+
+/// The items of a value split on `ParamsConfig::seq_separator`, an empty value has none.
+fn split_seq(s: &str, separator: char) -> Vec<Value> {
+    if s.is_empty() {
+        return vec![];
+    }
+    s.split(separator)
+        .map(|item| Value::xstr(item.trim()))
+        .collect()
+}
 
-struct ParamsValueVisitor;
+/// A number from the path, query string, form or multipart body with the separators of
+/// `ParamsConfig::number_format` replaced by Rust's.
+fn delocalize(s: &str) -> Result<Cow<'_, str>, de::value::Error> {
+    match COERCIONS.get().number_format {
+        Some(format) => format
+            .normalize(s)
+            .map(Cow::Owned)
+            .ok_or_else(|| de::Error::custom(format!("misgrouped number `{s}`"))),
+        None => Ok(Cow::Borrowed(s)),
+    }
+}
+
+fn parse_bool(s: &str) -> Option<bool> {
+    match s.to_lowercase().as_str() {
+        "true" | "1" | "on" | "yes" => Some(true),
+        "false" | "0" | "off" | "no" => Some(false),
+        _ => None,
+    }
+}
+
+pub(crate) struct ParamsValueVisitor;
 
 impl<'de> Visitor<'de> for ParamsValueVisitor {
     type Value = Value;
@@ -29,6 +184,14 @@ impl<'de> Visitor<'de> for ParamsValueVisitor {
         Ok(Value::Number(Number::from(v)))
     }
 
+    fn visit_i128<E>(self, v: i128) -> Result<Self::Value, E> {
+        Ok(Value::Number(Number::from(v)))
+    }
+
+    fn visit_u128<E>(self, v: u128) -> Result<Self::Value, E> {
+        Ok(Value::Number(Number::from(v)))
+    }
+
     fn visit_f64<E>(self, v: f64) -> Result<Self::Value, E> {
         Ok(Value::Number(Number::from(v)))
     }
@@ -37,11 +200,11 @@ impl<'de> Visitor<'de> for ParamsValueVisitor {
     where
         E: de::Error,
     {
-        Ok(Value::XStr(v.to_owned()))
+        Ok(Value::xstr(v))
     }
 
     fn visit_string<E>(self, v: String) -> Result<Self::Value, E> {
-        Ok(Value::XStr(v))
+        Ok(Value::xstr(v))
     }
 
     fn visit_none<E>(self) -> Result<Self::Value, E> {
@@ -82,6 +245,209 @@ impl<'de> Visitor<'de> for ParamsValueVisitor {
     }
 }
 
+/// Deserializes a `#[serde(flatten)]` field with the string coercions of `Params`.
+///
+/// serde buffers the entries of flattened fields in its own format, where query and form
+/// values are plain strings that no longer deserialize into numbers or booleans. This routes
+/// them back through `Value`:
+///
+/// ```
+/// # use serde::Deserialize;
+/// #[derive(Deserialize)]
+/// struct Pagination {
+///     page: u32,
+///     per_page: Option<u32>,
+/// }
+///
+/// #[derive(Deserialize)]
+/// struct ListUsers {
+///     q: String,
+///     #[serde(flatten, deserialize_with = "axum_params::flatten")]
+///     pagination: Pagination,
+/// }
+/// ```
+///
+/// Strings from JSON bodies are coerced too once they pass through a flattened field.
+pub fn flatten<'de, D, T>(deserializer: D) -> Result<T, D::Error>
+where
+    D: Deserializer<'de>,
+    T: DeserializeOwned,
+{
+    // Structs have to take their fields, or they would also show up in flattened maps
+    let value = match struct_fields::<T>() {
+        Some(fields) => deserializer.deserialize_struct("", fields, ParamsValueVisitor)?,
+        None => deserializer.deserialize_map(ParamsValueVisitor)?,
+    };
+    T::deserialize(value).map_err(de::Error::custom)
+}
+
+/// Deserializes a field inside an internally tagged or untagged enum with the string
+/// coercions of `Params`.
+///
+/// serde buffers the content of `#[serde(tag = "...")]` and `#[serde(untagged)]` enums before
+/// it picks the variant, so numbers and booleans from the query string or a form body reach
+/// the variant as plain strings. Fields that are not strings need this helper:
+///
+/// ```
+/// # use serde::Deserialize;
+/// #[derive(Deserialize)]
+/// #[serde(tag = "type", rename_all = "snake_case")]
+/// enum Shape {
+///     Circle {
+///         #[serde(deserialize_with = "axum_params::coerce")]
+///         radius: f64,
+///     },
+///     Square {
+///         #[serde(deserialize_with = "axum_params::coerce")]
+///         side: u32,
+///     },
+/// }
+/// ```
+///
+/// Externally tagged enums, like `shape[circle][radius]=1.5`, keep the coercions without it.
+pub fn coerce<'de, D, T>(deserializer: D) -> Result<T, D::Error>
+where
+    D: Deserializer<'de>,
+    T: DeserializeOwned,
+{
+    let value = deserializer.deserialize_any(ParamsValueVisitor)?;
+    T::deserialize(value).map_err(de::Error::custom)
+}
+
+/// A string, or a `T` parsed from one, without leading and trailing whitespace, for fields
+/// that need it without `ParamsConfig::trim_strings`:
+///
+/// ```
+/// # use serde::Deserialize;
+/// # use axum_params::Trimmed;
+/// #[derive(Deserialize)]
+/// struct SignUp {
+///     email: Trimmed,
+///     age: Trimmed<u32>,
+///     nickname: Trimmed<Option<String>>,
+/// }
+/// ```
+///
+/// JSON strings are trimmed too. Blank values are empty once trimmed, so they deserialize
+/// like empty strings, e.g. to `None` with `Trimmed<Option<String>>`.
+#[derive(Debug, Clone, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Trimmed<T = String>(pub T);
+
+impl<T> Trimmed<T> {
+    pub fn into_inner(self) -> T {
+        self.0
+    }
+}
+
+impl<T> std::ops::Deref for Trimmed<T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        &self.0
+    }
+}
+
+impl<'de, T: DeserializeOwned> Deserialize<'de> for Trimmed<T> {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        let mut value = deserializer.deserialize_any(ParamsValueVisitor)?;
+        value.trim_strings();
+        T::deserialize(value)
+            .map(Trimmed)
+            .map_err(de::Error::custom)
+    }
+}
+
+/// Deserializes a byte field from a hex string.
+///
+/// Byte fields declared with `#[serde(with = "serde_bytes")]` or as `serde_bytes::ByteBuf`
+/// are read from base64, standard or URL safe, without it.
+///
+/// ```
+/// # use serde::Deserialize;
+/// #[derive(Deserialize)]
+/// struct Signature {
+///     #[serde(deserialize_with = "axum_params::hex")]
+///     digest: Vec<u8>,
+/// }
+/// ```
+pub fn hex<'de, D>(deserializer: D) -> Result<Vec<u8>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let s = String::deserialize(deserializer)?;
+    if s.len() % 2 != 0 {
+        return Err(de::Error::custom("invalid hex value"));
+    }
+    (0..s.len())
+        .step_by(2)
+        .map(|i| {
+            s.get(i..i + 2)
+                .and_then(|byte| u8::from_str_radix(byte, 16).ok())
+                .ok_or_else(|| de::Error::custom("invalid hex value"))
+        })
+        .collect()
+}
+
+const BASE64_CONFIG: GeneralPurposeConfig =
+    GeneralPurposeConfig::new().with_decode_padding_mode(DecodePaddingMode::Indifferent);
+const BASE64: GeneralPurpose = GeneralPurpose::new(&alphabet::STANDARD, BASE64_CONFIG);
+const BASE64_URL_SAFE: GeneralPurpose = GeneralPurpose::new(&alphabet::URL_SAFE, BASE64_CONFIG);
+
+/// Decodes standard or URL safe base64, with or without padding.
+fn decode_base64(s: &str) -> Result<Vec<u8>, de::value::Error> {
+    BASE64
+        .decode(s)
+        .or_else(|_| BASE64_URL_SAFE.decode(s))
+        .map_err(|_| de::Error::custom("invalid base64 value"))
+}
+
+/// The field names `T` passes to `deserialize_struct`, if it is a struct.
+fn struct_fields<T: DeserializeOwned>() -> Option<&'static [&'static str]> {
+    struct FieldsProbe<'a>(&'a mut Option<&'static [&'static str]>);
+
+    impl<'de> Deserializer<'de> for FieldsProbe<'_> {
+        type Error = de::value::Error;
+
+        fn deserialize_any<V: Visitor<'de>>(self, _: V) -> Result<V::Value, Self::Error> {
+            Err(de::Error::custom("not a struct"))
+        }
+
+        fn deserialize_struct<V: Visitor<'de>>(
+            self,
+            _name: &'static str,
+            fields: &'static [&'static str],
+            _: V,
+        ) -> Result<V::Value, Self::Error> {
+            *self.0 = Some(fields);
+            Err(de::Error::custom("probed"))
+        }
+
+        fn deserialize_option<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Self::Error> {
+            visitor.visit_some(self)
+        }
+
+        fn deserialize_newtype_struct<V: Visitor<'de>>(
+            self,
+            _name: &'static str,
+            visitor: V,
+        ) -> Result<V::Value, Self::Error> {
+            visitor.visit_newtype_struct(self)
+        }
+
+        serde::forward_to_deserialize_any! {
+            bool i8 i16 i32 i64 i128 u8 u16 u32 u64 u128 f32 f64 char str string bytes
+            byte_buf unit unit_struct seq tuple tuple_struct map enum identifier ignored_any
+        }
+    }
+
+    let mut fields = None;
+    let _ = T::deserialize(FieldsProbe(&mut fields));
+    fields
+}
+
 impl<'de> Deserialize<'de> for Value {
     fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
     where
@@ -94,6 +460,8 @@ impl<'de> Deserialize<'de> for Value {
 struct MapAccessor {
     map: std::collections::hash_map::IntoIter<String, Value>,
     current_value: Option<Value>,
+    /// The key of `current_value` while collecting errors.
+    current_key: Option<String>,
 }
 
 impl MapAccessor {
@@ -101,6 +469,7 @@ impl MapAccessor {
         MapAccessor {
             map: map.into_iter(),
             current_value: None,
+            current_key: None,
         }
     }
 }
@@ -115,6 +484,9 @@ impl<'de> MapAccess<'de> for MapAccessor {
         match self.map.next() {
             Some((key, value)) => {
                 self.current_value = Some(value);
+                if recovery::is_collecting() {
+                    self.current_key = Some(key.clone());
+                }
                 seed.deserialize(key.into_deserializer()).map(Some)
             }
             None => Ok(None),
@@ -125,15 +497,75 @@ impl<'de> MapAccess<'de> for MapAccessor {
     where
         V: de::DeserializeSeed<'de>,
     {
-        match self.current_value.take() {
-            Some(value) => seed.deserialize(value),
-            None => Err(de::Error::custom("value is missing")),
+        match (self.current_value.take(), self.current_key.take()) {
+            (Some(value), Some(key)) => recovery::at_segment(key, || match value {
+                value if recovery::is_placeholder(&value) => seed.deserialize(Placeholder),
+                value => seed.deserialize(value),
+            }),
+            (Some(value), None) => seed.deserialize(value),
+            (None, _) => Err(de::Error::custom("value is missing")),
+        }
+    }
+}
+
+struct EnumAccessor {
+    variant: String,
+    value: Value,
+}
+
+impl<'de> de::EnumAccess<'de> for EnumAccessor {
+    type Error = serde::de::value::Error;
+    type Variant = Value;
+
+    fn variant_seed<V>(self, seed: V) -> Result<(V::Value, Self::Variant), Self::Error>
+    where
+        V: de::DeserializeSeed<'de>,
+    {
+        let variant = seed.deserialize(self.variant.into_deserializer())?;
+        Ok((variant, self.value))
+    }
+}
+
+impl<'de> de::VariantAccess<'de> for Value {
+    type Error = serde::de::value::Error;
+
+    fn unit_variant(self) -> Result<(), Self::Error> {
+        match self {
+            Value::Null => Ok(()),
+            Value::XStr(s) | Value::String(s) if s.is_empty() => Ok(()),
+            _ => Err(de::Error::custom("unit variant takes no value")),
         }
     }
+
+    fn newtype_variant_seed<T>(self, seed: T) -> Result<T::Value, Self::Error>
+    where
+        T: de::DeserializeSeed<'de>,
+    {
+        seed.deserialize(self)
+    }
+
+    fn tuple_variant<V>(self, _len: usize, visitor: V) -> Result<V::Value, Self::Error>
+    where
+        V: Visitor<'de>,
+    {
+        self.deserialize_seq(visitor)
+    }
+
+    fn struct_variant<V>(
+        self,
+        _fields: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value, Self::Error>
+    where
+        V: Visitor<'de>,
+    {
+        self.deserialize_map(visitor)
+    }
 }
 
 struct SeqAccessor {
     seq: std::vec::IntoIter<Value>,
+    index: usize,
 }
 
 impl<'de> SeqAccess<'de> for SeqAccessor {
@@ -143,7 +575,16 @@ impl<'de> SeqAccess<'de> for SeqAccessor {
     where
         T: de::DeserializeSeed<'de>,
     {
+        let index = self.index;
+        self.index += 1;
         match self.seq.next() {
+            Some(value) if recovery::is_collecting() => {
+                recovery::at_segment(index.to_string(), || match value {
+                    value if recovery::is_placeholder(&value) => seed.deserialize(Placeholder),
+                    value => seed.deserialize(value),
+                })
+                .map(Some)
+            }
             Some(value) => seed.deserialize(value).map(Some),
             None => Ok(None),
         }
@@ -163,26 +604,51 @@ impl<'de> Deserializer<'de> for Value {
             Value::Number(Number(n)) => match n {
                 N::PosInt(i) => visitor.visit_u64(i),
                 N::NegInt(i) => visitor.visit_i64(i),
+                N::PosInt128(i) => visitor.visit_u128(i),
+                N::NegInt128(i) => visitor.visit_i128(i),
                 N::Float(f) => visitor.visit_f64(f),
             },
-            Value::String(s) => visitor.visit_string(s),
+            Value::String(s) => visitor.visit_string(s.into_string()),
             Value::Object(map) => visitor.visit_map(MapAccessor::new(map)),
             Value::Array(vec) => visitor.visit_seq(SeqAccessor {
                 seq: vec.into_iter(),
+                index: 0,
             }),
-            Value::XStr(s) => visitor.visit_string(s),
+            Value::XStr(s) => visitor.visit_string(s.into_string()),
             Value::UploadFile(file) => {
-                let map = HashMap::from([
-                    ("name".to_string(), Value::String(file.name.clone())),
+                let mut map = HashMap::from([
+                    ("name".to_string(), Value::string(file.name.as_str())),
                     (
                         "content_type".to_string(),
-                        Value::String(file.content_type.clone()),
+                        Value::string(file.content_type.as_str()),
                     ),
                     (
                         "temp_file_path".to_string(),
-                        Value::String(file.temp_file_path.to_string()),
+                        Value::string(file.temp_file_path.as_str()),
+                    ),
+                    (
+                        "part_index".to_string(),
+                        Value::number(file.part_index as u64),
                     ),
                 ]);
+                if let Some(checksum) = file.checksum {
+                    let checksum = HashMap::from([
+                        (
+                            "algorithm".to_string(),
+                            Value::string(checksum.algorithm.as_str()),
+                        ),
+                        ("hex".to_string(), Value::string(checksum.hex)),
+                    ]);
+                    map.insert("checksum".to_string(), Value::Object(checksum));
+                }
+                if !file.headers.is_empty() {
+                    let headers = file
+                        .headers
+                        .into_iter()
+                        .map(|(k, v)| Value::Array(vec![Value::string(k), Value::string(v)]))
+                        .collect();
+                    map.insert("headers".to_string(), Value::Array(headers));
+                }
                 visitor.visit_map(MapAccessor::new(map))
             }
         }
@@ -193,11 +659,27 @@ impl<'de> Deserializer<'de> for Value {
         V: Visitor<'de>,
     {
         match self {
-            Value::XStr(s) => match s.to_lowercase().as_str() {
-                "true" | "1" | "on" | "yes" => visitor.visit_bool(true),
-                "false" | "0" | "off" | "no" => visitor.visit_bool(false),
-                _ => Err(de::Error::custom("invalid boolean value")),
+            Value::XStr(s) if is_blank(&s) => visitor.visit_bool(false),
+            Value::XStr(s) => match parse_bool(&s) {
+                Some(b) => visitor.visit_bool(b),
+                None => Err(de::Error::custom("invalid boolean value")),
             },
+            // A hidden field and a checkbox sharing the name, like `flag[]=0&flag[]=1`
+            Value::Array(mut items) if !items.is_empty() => {
+                let checked = match COERCIONS.get().checkbox {
+                    Checkbox::Last => None,
+                    Checkbox::AnyChecked => items.iter().position(|v| match v {
+                        Value::Bool(b) => *b,
+                        Value::XStr(s) => parse_bool(s) == Some(true),
+                        _ => false,
+                    }),
+                };
+                let value = match checked {
+                    Some(i) => items.swap_remove(i),
+                    None => items.pop().expect("items is not empty"),
+                };
+                value.deserialize_bool(visitor)
+            }
             _ => self.deserialize_any(visitor),
         }
     }
@@ -207,7 +689,8 @@ impl<'de> Deserializer<'de> for Value {
         V: Visitor<'de>,
     {
         match self {
-            Value::XStr(s) => s
+            Value::XStr(s) if is_blank(&s) => visitor.visit_i8(0),
+            Value::XStr(s) => delocalize(&s)?
                 .parse()
                 .map_err(de::Error::custom)
                 .and_then(|v| visitor.visit_i8(v)),
@@ -220,7 +703,8 @@ impl<'de> Deserializer<'de> for Value {
         V: Visitor<'de>,
     {
         match self {
-            Value::XStr(s) => s
+            Value::XStr(s) if is_blank(&s) => visitor.visit_i16(0),
+            Value::XStr(s) => delocalize(&s)?
                 .parse()
                 .map_err(de::Error::custom)
                 .and_then(|v| visitor.visit_i16(v)),
@@ -233,7 +717,8 @@ impl<'de> Deserializer<'de> for Value {
         V: Visitor<'de>,
     {
         match self {
-            Value::XStr(s) => s
+            Value::XStr(s) if is_blank(&s) => visitor.visit_i32(0),
+            Value::XStr(s) => delocalize(&s)?
                 .parse()
                 .map_err(de::Error::custom)
                 .and_then(|v| visitor.visit_i32(v)),
@@ -245,9 +730,9 @@ impl<'de> Deserializer<'de> for Value {
     where
         V: Visitor<'de>,
     {
-        debug!("deserialize_i64 self: {:?}", self);
         match self {
-            Value::XStr(s) => s
+            Value::XStr(s) if is_blank(&s) => visitor.visit_i64(0),
+            Value::XStr(s) => delocalize(&s)?
                 .parse()
                 .map_err(de::Error::custom)
                 .and_then(|v| visitor.visit_i64(v)),
@@ -255,12 +740,27 @@ impl<'de> Deserializer<'de> for Value {
         }
     }
 
+    fn deserialize_i128<V>(self, visitor: V) -> Result<V::Value, Self::Error>
+    where
+        V: Visitor<'de>,
+    {
+        match self {
+            Value::XStr(s) if is_blank(&s) => visitor.visit_i128(0),
+            Value::XStr(s) => delocalize(&s)?
+                .parse()
+                .map_err(de::Error::custom)
+                .and_then(|v| visitor.visit_i128(v)),
+            _ => self.deserialize_any(visitor),
+        }
+    }
+
     fn deserialize_u8<V>(self, visitor: V) -> Result<V::Value, Self::Error>
     where
         V: Visitor<'de>,
     {
         match self {
-            Value::XStr(s) => s
+            Value::XStr(s) if is_blank(&s) => visitor.visit_u8(0),
+            Value::XStr(s) => delocalize(&s)?
                 .parse()
                 .map_err(de::Error::custom)
                 .and_then(|v| visitor.visit_u8(v)),
@@ -273,7 +773,8 @@ impl<'de> Deserializer<'de> for Value {
         V: Visitor<'de>,
     {
         match self {
-            Value::XStr(s) => s
+            Value::XStr(s) if is_blank(&s) => visitor.visit_u16(0),
+            Value::XStr(s) => delocalize(&s)?
                 .parse()
                 .map_err(de::Error::custom)
                 .and_then(|v| visitor.visit_u16(v)),
@@ -286,7 +787,8 @@ impl<'de> Deserializer<'de> for Value {
         V: Visitor<'de>,
     {
         match self {
-            Value::XStr(s) => s
+            Value::XStr(s) if is_blank(&s) => visitor.visit_u32(0),
+            Value::XStr(s) => delocalize(&s)?
                 .parse()
                 .map_err(de::Error::custom)
                 .and_then(|v| visitor.visit_u32(v)),
@@ -299,7 +801,8 @@ impl<'de> Deserializer<'de> for Value {
         V: Visitor<'de>,
     {
         match self {
-            Value::XStr(s) => s
+            Value::XStr(s) if is_blank(&s) => visitor.visit_u64(0),
+            Value::XStr(s) => delocalize(&s)?
                 .parse()
                 .map_err(de::Error::custom)
                 .and_then(|v| visitor.visit_u64(v)),
@@ -307,13 +810,27 @@ impl<'de> Deserializer<'de> for Value {
         }
     }
 
+    fn deserialize_u128<V>(self, visitor: V) -> Result<V::Value, Self::Error>
+    where
+        V: Visitor<'de>,
+    {
+        match self {
+            Value::XStr(s) if is_blank(&s) => visitor.visit_u128(0),
+            Value::XStr(s) => delocalize(&s)?
+                .parse()
+                .map_err(de::Error::custom)
+                .and_then(|v| visitor.visit_u128(v)),
+            _ => self.deserialize_any(visitor),
+        }
+    }
+
     fn deserialize_f32<V>(self, visitor: V) -> Result<V::Value, Self::Error>
     where
         V: Visitor<'de>,
     {
-        debug!("deserialize_f32 self: {:?}", self);
         match self {
-            Value::XStr(s) => s
+            Value::XStr(s) if is_blank(&s) => visitor.visit_f32(0.0),
+            Value::XStr(s) => delocalize(&s)?
                 .parse()
                 .map_err(de::Error::custom)
                 .and_then(|v| visitor.visit_f32(v)),
@@ -325,9 +842,9 @@ impl<'de> Deserializer<'de> for Value {
     where
         V: Visitor<'de>,
     {
-        debug!("deserialize_f64 self: {:?}", self);
         match self {
-            Value::XStr(s) => s
+            Value::XStr(s) if is_blank(&s) => visitor.visit_f64(0.0),
+            Value::XStr(s) => delocalize(&s)?
                 .parse()
                 .map_err(de::Error::custom)
                 .and_then(|v| visitor.visit_f64(v)),
@@ -351,12 +868,54 @@ impl<'de> Deserializer<'de> for Value {
         }
     }
 
+    fn deserialize_bytes<V>(self, visitor: V) -> Result<V::Value, Self::Error>
+    where
+        V: Visitor<'de>,
+    {
+        self.deserialize_byte_buf(visitor)
+    }
+
+    fn deserialize_byte_buf<V>(self, visitor: V) -> Result<V::Value, Self::Error>
+    where
+        V: Visitor<'de>,
+    {
+        match self {
+            Value::XStr(s) | Value::String(s) => visitor.visit_byte_buf(decode_base64(&s)?),
+            _ => self.deserialize_any(visitor),
+        }
+    }
+
+    fn deserialize_seq<V>(self, visitor: V) -> Result<V::Value, Self::Error>
+    where
+        V: Visitor<'de>,
+    {
+        let seq = match self {
+            Value::XStr(s) => match COERCIONS.get().seq_separator {
+                // A list in one value, `ids=1,2,3`, with `ParamsConfig::seq_separator`
+                Some(separator) => split_seq(&s, separator),
+                // A key sent without brackets, `tag=a`, or without a value, `tag`
+                None => vec![Value::XStr(s)],
+            },
+            // A single file part sent as `files` instead of `files[]`
+            Value::UploadFile(file) => vec![Value::UploadFile(file)],
+            Value::Null => vec![],
+            // `Vec<(String, String)>` reads the params back as pairs
+            Value::Object(map) => pairs(map),
+            _ => return self.deserialize_any(visitor),
+        };
+        visitor.visit_seq(SeqAccessor {
+            seq: seq.into_iter(),
+            index: 0,
+        })
+    }
+
     fn deserialize_option<V>(self, visitor: V) -> Result<V::Value, Self::Error>
     where
         V: Visitor<'de>,
     {
         match self {
             Value::Null => visitor.visit_none(),
+            Value::XStr(s) if is_blank(&s) => visitor.visit_none(),
             _ => visitor.visit_some(self),
         }
     }
@@ -372,6 +931,11 @@ impl<'de> Deserializer<'de> for Value {
     {
         match self {
             Value::XStr(s) | Value::String(s) => visitor.visit_enum(s.into_deserializer()),
+            // `shape[circle][radius]=1.5`, the variant content keeps the string coercions
+            Value::Object(map) if map.len() == 1 => {
+                let (variant, value) = map.into_iter().next().expect("map has one entry");
+                visitor.visit_enum(EnumAccessor { variant, value })
+            }
             _ => self.deserialize_any(visitor),
         }
     }
@@ -392,9 +956,310 @@ impl<'de> Deserializer<'de> for Value {
         }
     }
 
+    fn deserialize_tuple<V>(self, _len: usize, visitor: V) -> Result<V::Value, Self::Error>
+    where
+        V: Visitor<'de>,
+    {
+        match self {
+            // `range[0]=1&range[1]=2`, indices that didn't become an array
+            Value::Object(map)
+                if !map.is_empty() && map.keys().all(|k| k.parse::<usize>().is_ok()) =>
+            {
+                let mut items = map.into_iter().collect::<Vec<_>>();
+                items.sort_by_cached_key(|(k, _)| k.parse::<usize>().ok());
+                let seq = items.into_iter().map(|(_, v)| v).collect::<Vec<_>>();
+                visitor.visit_seq(SeqAccessor {
+                    seq: seq.into_iter(),
+                    index: 0,
+                })
+            }
+            _ => self.deserialize_seq(visitor),
+        }
+    }
+
+    fn deserialize_tuple_struct<V>(
+        self,
+        _name: &'static str,
+        len: usize,
+        visitor: V,
+    ) -> Result<V::Value, Self::Error>
+    where
+        V: Visitor<'de>,
+    {
+        self.deserialize_tuple(len, visitor)
+    }
+
+    fn deserialize_unit<V>(self, visitor: V) -> Result<V::Value, Self::Error>
+    where
+        V: Visitor<'de>,
+    {
+        match self {
+            // A key sent without a value, `confirm` or `confirm=`
+            Value::XStr(s) | Value::String(s) if s.is_empty() => visitor.visit_unit(),
+            // Params a unit struct ignores, like a struct ignores unknown fields
+            Value::Object(_) => visitor.visit_unit(),
+            _ => self.deserialize_any(visitor),
+        }
+    }
+
+    fn deserialize_unit_struct<V>(
+        self,
+        _name: &'static str,
+        visitor: V,
+    ) -> Result<V::Value, Self::Error>
+    where
+        V: Visitor<'de>,
+    {
+        self.deserialize_unit(visitor)
+    }
+
     serde::forward_to_deserialize_any! {
-        str string bytes byte_buf unit  seq tuple
-        tuple_struct map  unit_struct struct identifier ignored_any
+        str string map struct identifier ignored_any
+    }
+}
+
+/// `(key, value)` pairs of `map` in bracket notation, `filter[status]` or `tag[]`, sorted by
+/// key. Keys without a value get an empty string.
+fn pairs(map: HashMap<String, Value>) -> Vec<Value> {
+    let mut pairs = vec![];
+    for (key, value) in sorted(map) {
+        push_pairs(&mut pairs, key, value);
+    }
+    pairs.into_iter().map(pair).collect()
+}
+
+fn push_pairs(pairs: &mut Vec<(String, Value)>, key: String, value: Value) {
+    match value {
+        Value::Object(map) => {
+            for (k, v) in sorted(map) {
+                push_pairs(pairs, format!("{key}[{k}]"), v);
+            }
+        }
+        Value::Array(items) => {
+            for item in items {
+                push_pairs(pairs, format!("{key}[]"), item);
+            }
+        }
+        value => pairs.push((key, value)),
+    }
+}
+
+fn sorted(map: HashMap<String, Value>) -> Vec<(String, Value)> {
+    let mut entries: Vec<_> = map.into_iter().collect();
+    entries.sort_by(|a, b| a.0.cmp(&b.0));
+    entries
+}
+
+fn pair((key, value): (String, Value)) -> Value {
+    let value = match value {
+        Value::Null => Value::xstr(""),
+        value => value,
+    };
+    Value::Array(vec![Value::xstr(key), value])
+}
+
+#[cfg(server)]
+macro_rules! forward_to_value {
+    ($($method:ident($($arg:ident: $ty:ty),*)),* $(,)?) => {
+        $(
+            fn $method<V: Visitor<'de>>(
+                self,
+                $($arg: $ty,)*
+                visitor: V,
+            ) -> Result<V::Value, Self::Error> {
+                self.into_value().$method($($arg,)* visitor)
+            }
+        )*
+    };
+}
+
+/// The merged params with the keys of the query string and form body in request order.
+/// `Vec<(String, String)>` reads the pairs in that order and `HashMap<String, Vec<String>>`
+/// every value of a key repeated without brackets, other targets the last one like Rack.
+#[cfg(server)]
+pub(crate) struct Root {
+    params: HashMap<String, Value>,
+    sent: Vec<(String, Option<Value>)>,
+}
+
+#[cfg(server)]
+impl Root {
+    pub(crate) fn new(params: HashMap<String, Value>, sent: Vec<(String, Option<Value>)>) -> Self {
+        Root { params, sent }
+    }
+
+    fn into_value(self) -> Value {
+        Value::Object(self.params)
+    }
+
+    /// The values of the keys sent more than once without brackets, the last one as merged.
+    fn take_repeats(&mut self) -> HashMap<String, Vec<Value>> {
+        let mut repeats = HashMap::<String, Vec<Value>>::new();
+        for (key, value) in &mut self.sent {
+            if let Some(value) = value.take() {
+                repeats.entry(key.clone()).or_default().push(value);
+            }
+        }
+        repeats.retain(|key, values| match self.params.get(key) {
+            Some(Value::Object(_) | Value::Array(_)) | None => false,
+            Some(last) if values.len() > 1 => {
+                values.pop();
+                values.push(last.clone());
+                true
+            }
+            Some(_) => false,
+        });
+        repeats
+    }
+}
+
+#[cfg(server)]
+impl<'de> Deserializer<'de> for Root {
+    type Error = serde::de::value::Error;
+
+    fn deserialize_seq<V: Visitor<'de>>(mut self, visitor: V) -> Result<V::Value, Self::Error> {
+        let mut repeats = self.take_repeats();
+        let mut pairs = vec![];
+        for (key, value) in sorted(self.params) {
+            match repeats.remove(&key) {
+                Some(values) => pairs.extend(values.into_iter().map(|v| (key.clone(), v))),
+                None => push_pairs(&mut pairs, key, value),
+            }
+        }
+        // The n-th pair of a key takes the position of its n-th occurrence, keys of other
+        // sources go last
+        let mut positions = HashMap::<String, std::collections::VecDeque<usize>>::new();
+        for (position, (key, _)) in self.sent.into_iter().enumerate() {
+            positions.entry(key).or_default().push_back(position);
+        }
+        let mut pairs = pairs
+            .into_iter()
+            .map(|pair| {
+                let position = positions.get_mut(&pair.0).and_then(|p| p.pop_front());
+                (position.unwrap_or(usize::MAX), pair)
+            })
+            .collect::<Vec<_>>();
+        pairs.sort_by_key(|(position, _)| *position);
+        let seq = pairs.into_iter().map(|(_, p)| pair(p)).collect::<Vec<_>>();
+        visitor.visit_seq(SeqAccessor {
+            seq: seq.into_iter(),
+            index: 0,
+        })
+    }
+
+    fn deserialize_map<V: Visitor<'de>>(mut self, visitor: V) -> Result<V::Value, Self::Error> {
+        let mut repeats = self.take_repeats();
+        let entries = self
+            .params
+            .into_iter()
+            .map(|(key, value)| {
+                let values = repeats.remove(&key).unwrap_or_else(|| vec![value]);
+                (key, values)
+            })
+            .collect::<Vec<_>>();
+        visitor.visit_map(RootAccessor {
+            entries: entries.into_iter(),
+            current: None,
+        })
+    }
+
+    forward_to_value! {
+        deserialize_any(), deserialize_bool(), deserialize_i8(), deserialize_i16(),
+        deserialize_i32(), deserialize_i64(), deserialize_i128(), deserialize_u8(),
+        deserialize_u16(), deserialize_u32(), deserialize_u64(), deserialize_u128(),
+        deserialize_f32(), deserialize_f64(), deserialize_char(), deserialize_str(),
+        deserialize_string(), deserialize_bytes(), deserialize_byte_buf(),
+        deserialize_option(), deserialize_unit(), deserialize_identifier(),
+        deserialize_ignored_any(),
+        deserialize_unit_struct(name: &'static str),
+        deserialize_newtype_struct(name: &'static str),
+        deserialize_tuple(len: usize),
+        deserialize_tuple_struct(name: &'static str, len: usize),
+        deserialize_struct(name: &'static str, fields: &'static [&'static str]),
+        deserialize_enum(name: &'static str, variants: &'static [&'static str]),
+    }
+}
+
+#[cfg(server)]
+struct RootAccessor {
+    entries: std::vec::IntoIter<(String, Vec<Value>)>,
+    current: Option<Vec<Value>>,
+}
+
+#[cfg(server)]
+impl<'de> MapAccess<'de> for RootAccessor {
+    type Error = serde::de::value::Error;
+
+    fn next_key_seed<K>(&mut self, seed: K) -> Result<Option<K::Value>, Self::Error>
+    where
+        K: de::DeserializeSeed<'de>,
+    {
+        match self.entries.next() {
+            Some((key, values)) => {
+                self.current = Some(values);
+                seed.deserialize(key.into_deserializer()).map(Some)
+            }
+            None => Ok(None),
+        }
+    }
+
+    fn next_value_seed<V>(&mut self, seed: V) -> Result<V::Value, Self::Error>
+    where
+        V: de::DeserializeSeed<'de>,
+    {
+        match self.current.take() {
+            Some(mut values) if values.len() == 1 => seed.deserialize(values.remove(0)),
+            Some(values) => seed.deserialize(Repeats(values)),
+            None => Err(de::Error::custom("value is missing")),
+        }
+    }
+}
+
+/// The values of a key repeated without brackets, a sequence reads them all and other
+/// types the last one.
+#[cfg(server)]
+struct Repeats(Vec<Value>);
+
+#[cfg(server)]
+impl Repeats {
+    fn into_value(mut self) -> Value {
+        self.0.pop().unwrap_or(Value::Null)
+    }
+}
+
+#[cfg(server)]
+impl<'de> Deserializer<'de> for Repeats {
+    type Error = serde::de::value::Error;
+
+    fn deserialize_seq<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Self::Error> {
+        let seq = self
+            .0
+            .into_iter()
+            .map(|value| match value {
+                Value::Null => Value::xstr(""),
+                value => value,
+            })
+            .collect::<Vec<_>>();
+        visitor.visit_seq(SeqAccessor {
+            seq: seq.into_iter(),
+            index: 0,
+        })
+    }
+
+    forward_to_value! {
+        deserialize_any(), deserialize_bool(), deserialize_i8(), deserialize_i16(),
+        deserialize_i32(), deserialize_i64(), deserialize_i128(), deserialize_u8(),
+        deserialize_u16(), deserialize_u32(), deserialize_u64(), deserialize_u128(),
+        deserialize_f32(), deserialize_f64(), deserialize_char(), deserialize_str(),
+        deserialize_string(), deserialize_bytes(), deserialize_byte_buf(),
+        deserialize_option(), deserialize_unit(), deserialize_map(),
+        deserialize_identifier(), deserialize_ignored_any(),
+        deserialize_unit_struct(name: &'static str),
+        deserialize_newtype_struct(name: &'static str),
+        deserialize_tuple(len: usize),
+        deserialize_tuple_struct(name: &'static str, len: usize),
+        deserialize_struct(name: &'static str, fields: &'static [&'static str]),
+        deserialize_enum(name: &'static str, variants: &'static [&'static str]),
     }
 }
 
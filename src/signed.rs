@@ -0,0 +1,252 @@
+use std::fmt;
+
+use axum::{
+    body::{Body, to_bytes},
+    extract::{FromRequest, Request},
+};
+use base64::{Engine, engine::general_purpose::STANDARD as BASE64};
+use hmac::{Hmac, Mac};
+use serde::de::DeserializeOwned;
+use sha2::Sha256;
+
+use crate::{
+    Error, Params, ParamsCache, ParamsConfig, ParamsMetadata, Rejection, rejection::Rejecter,
+};
+
+type HmacSha256 = Hmac<Sha256>;
+
+/// Signs and verifies request bodies with HMAC-SHA256, for `SignedParams`.
+///
+/// The signature is sent in a header, hex or base64 encoded, after an optional prefix like
+/// GitHub's `X-Hub-Signature-256: sha256=<hex>`:
+///
+/// ```ignore
+/// let signer = WebhookSigner::new(secret)
+///     .header("x-hub-signature-256")
+///     .prefix("sha256=");
+/// let app = Router::new()
+///     .route("/webhooks/github", post(github_webhook))
+///     .layer(Extension(signer));
+/// ```
+#[derive(Clone)]
+pub struct WebhookSigner {
+    secret: Vec<u8>,
+    header: String,
+    prefix: String,
+}
+
+impl fmt::Debug for WebhookSigner {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("WebhookSigner")
+            .field("header", &self.header)
+            .field("prefix", &self.prefix)
+            .finish_non_exhaustive()
+    }
+}
+
+impl WebhookSigner {
+    /// Reads the signature from the `x-signature` header, without a prefix.
+    pub fn new(secret: impl Into<Vec<u8>>) -> Self {
+        Self {
+            secret: secret.into(),
+            header: "x-signature".to_string(),
+            prefix: String::new(),
+        }
+    }
+
+    /// The header carrying the signature.
+    pub fn header(mut self, header: impl Into<String>) -> Self {
+        self.header = header.into();
+        self
+    }
+
+    /// The text before the encoded signature, like `sha256=`.
+    pub fn prefix(mut self, prefix: impl Into<String>) -> Self {
+        self.prefix = prefix.into();
+        self
+    }
+
+    fn mac(&self, body: &[u8]) -> HmacSha256 {
+        let mut mac =
+            HmacSha256::new_from_slice(&self.secret).expect("HMAC takes keys of any size");
+        mac.update(body);
+        mac
+    }
+
+    /// The header value signing `body`, hex encoded, e.g. for tests or outgoing webhooks.
+    pub fn sign(&self, body: &[u8]) -> String {
+        let digest = self.mac(body).finalize().into_bytes();
+        let hex = digest
+            .iter()
+            .map(|byte| format!("{byte:02x}"))
+            .collect::<String>();
+        format!("{}{hex}", self.prefix)
+    }
+
+    /// Checks the header value `signature` against `body` in constant time. Failures are
+    /// `Error::Forbidden`.
+    pub fn verify(&self, body: &[u8], signature: &str) -> Result<(), Error> {
+        signature
+            .trim()
+            .strip_prefix(self.prefix.as_str())
+            .and_then(decode_signature)
+            .and_then(|signature| self.mac(body).verify_slice(&signature).ok())
+            .ok_or_else(|| Error::Forbidden("Invalid request signature".to_string()))
+    }
+}
+
+/// Decodes a hex or base64 signature.
+fn decode_signature(s: &str) -> Option<Vec<u8>> {
+    let hex = s
+        .len()
+        .is_multiple_of(2)
+        .then(|| {
+            (0..s.len())
+                .step_by(2)
+                .map(|i| u8::from_str_radix(s.get(i..i + 2)?, 16).ok())
+                .collect::<Option<Vec<_>>>()
+        })
+        .flatten();
+    hex.or_else(|| BASE64.decode(s).ok())
+}
+
+/// Like `Params`, after verifying the signature of the raw body with the `WebhookSigner`
+/// request extension (feature `signed`), for webhook endpoints:
+///
+/// ```ignore
+/// async fn github_webhook(SignedParams(event, _): SignedParams<PushEvent>) {}
+/// ```
+///
+/// The body is buffered, up to `SecurityPolicy::max_body_size`, and verified as received,
+/// before it is decompressed or parsed. A missing signer, a missing header or a wrong
+/// signature rejects with `Error::Forbidden`. Only the body is deserialized, path and query
+/// parameters are not signed.
+#[derive(Debug, Default)]
+pub struct SignedParams<T>(pub T, pub ParamsMetadata);
+
+#[cfg_attr(axum07, axum::async_trait)]
+impl<T, S> FromRequest<S> for SignedParams<T>
+where
+    T: DeserializeOwned,
+    S: Send + Sync,
+{
+    type Rejection = Rejection;
+
+    async fn from_request(req: Request, state: &S) -> Result<Self, Self::Rejection> {
+        let rejecter = Rejecter::of(&req);
+        let config = ParamsConfig::of(&req);
+        let (mut parts, body) = req.into_parts();
+        let verified = async {
+            let signer = parts.extensions.get::<WebhookSigner>().ok_or_else(|| {
+                Error::Forbidden("No `WebhookSigner` to verify the request".to_string())
+            })?;
+            let signature = parts
+                .headers
+                .get(signer.header.as_str())
+                .and_then(|value| value.to_str().ok())
+                .ok_or_else(|| {
+                    Error::Forbidden(format!("Missing `{}` signature header", signer.header))
+                })?;
+            let bytes = to_bytes(body, config.security.max_body_size)
+                .await
+                .map_err(|e| Error::ReadError(format!("Failed to read request body: {e}")))?;
+            signer.verify(&bytes, signature)?;
+            Ok(bytes)
+        };
+        let bytes = verified.await.map_err(|e| rejecter.reject(e))?;
+        // Only the body is signed, the path and query string must not add or override fields
+        parts.extensions.remove::<ParamsCache>();
+        parts
+            .extensions
+            .insert(config.merge_path(false).merge_query(false));
+        let req = Request::from_parts(parts, Body::from(bytes));
+        let Params(params, metadata) = Params::<T>::from_request(req, state).await?;
+        Ok(SignedParams(params, metadata))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use axum::{Extension, Router, http::StatusCode, routing::post};
+    use axum_test::TestServer;
+    use serde::Deserialize;
+
+    use super::*;
+
+    #[derive(Debug, Deserialize)]
+    struct PushEvent {
+        repository: String,
+        commits: u32,
+    }
+
+    #[test]
+    fn test_verify() {
+        let signer = WebhookSigner::new("secret").prefix("sha256=");
+        let signature = signer.sign(b"payload");
+        assert!(signature.starts_with("sha256="));
+        assert!(signer.verify(b"payload", &signature).is_ok());
+        assert!(signer.verify(b"payload!", &signature).is_err());
+        assert!(signer.verify(b"payload", &signature[7..]).is_err());
+
+        let base64 = BASE64.encode(decode_signature(&signature[7..]).unwrap());
+        assert!(
+            signer
+                .verify(b"payload", &format!("sha256={base64}"))
+                .is_ok()
+        );
+    }
+
+    #[tokio::test]
+    async fn test_signed_params() {
+        let signer = WebhookSigner::new("secret")
+            .header("x-hub-signature-256")
+            .prefix("sha256=");
+        let app = Router::new()
+            .route(
+                "/webhooks",
+                post(
+                    |SignedParams(event, _): SignedParams<PushEvent>| async move {
+                        format!("{}:{}", event.repository, event.commits)
+                    },
+                ),
+            )
+            .layer(Extension(signer.clone()));
+        let server = TestServer::new(app).unwrap();
+
+        let body = br#"{"repository":"axum-params","commits":3}"#;
+        let response = server
+            .post("/webhooks?commits=1&repository=evil")
+            .content_type("application/json")
+            .add_header("x-hub-signature-256", signer.sign(body))
+            .bytes(body[..].into())
+            .await;
+        assert_eq!(response.status_code(), StatusCode::OK);
+        assert_eq!(response.text(), "axum-params:3");
+
+        // The query string can't add a field the signed body leaves out
+        let body = br#"{"repository":"axum-params"}"#;
+        let response = server
+            .post("/webhooks?commits=1")
+            .content_type("application/json")
+            .add_header("x-hub-signature-256", signer.sign(body))
+            .bytes(body[..].into())
+            .await;
+        assert_eq!(response.status_code(), StatusCode::BAD_REQUEST);
+        assert!(response.text().contains("missing field `commits`"));
+
+        let response = server
+            .post("/webhooks")
+            .content_type("application/json")
+            .add_header("x-hub-signature-256", signer.sign(b"{}"))
+            .bytes(body[..].into())
+            .await;
+        assert_eq!(response.status_code(), StatusCode::FORBIDDEN);
+
+        let response = server
+            .post("/webhooks")
+            .content_type("application/json")
+            .bytes(body[..].into())
+            .await;
+        assert_eq!(response.status_code(), StatusCode::FORBIDDEN);
+    }
+}
@@ -0,0 +1,253 @@
+use std::fmt;
+
+#[cfg(server)]
+use ::serde::de::DeserializeOwned;
+#[cfg(server)]
+use axum::extract::{FromRequest, Request};
+
+#[cfg(server)]
+use crate::{Error, Params, ParamsConfig, ParamsMetadata, Rejection};
+
+/// Where a parameter was read from.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Source {
+    Path,
+    Query,
+    Json,
+    Form,
+    Multipart,
+    /// A body of another content type, see `ParamsConfig::raw_body` and
+    /// `ParamsConfig::text_body`.
+    Raw,
+    /// A request extension, see `ParamsConfig::extension`.
+    Extension,
+}
+
+impl Source {
+    pub const BODY: &'static [Source] =
+        &[Source::Json, Source::Form, Source::Multipart, Source::Raw];
+
+    pub fn is_body(self) -> bool {
+        Self::BODY.contains(&self)
+    }
+
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Source::Path => "path",
+            Source::Query => "query",
+            Source::Json => "json",
+            Source::Form => "form",
+            Source::Multipart => "multipart",
+            Source::Raw => "raw",
+            Source::Extension => "extension",
+        }
+    }
+}
+
+impl fmt::Display for Source {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// Declares which sources each top level field may come from.
+///
+/// Usually derived with `#[derive(ParamSources)]` (feature `macros`):
+///
+/// ```ignore
+/// #[derive(Deserialize, ParamSources)]
+/// struct UpdateUser {
+///     #[param(path)]
+///     user_id: i32,
+///     #[param(source = "body")]
+///     name: String,
+/// }
+/// ```
+pub trait ParamSources {
+    /// `(field name, allowed sources)` pairs, fields not listed may come from anywhere.
+    fn expected_sources() -> &'static [(&'static str, &'static [Source])];
+}
+
+/// Like `Params`, but rejects fields that arrived from a source not allowed by `ParamSources`.
+///
+/// Every leaf of a restricted field is checked, so `?user[is_admin]=1` is rejected for a
+/// body only `user` even when the body sends the rest of `user`. Behind `cache_params`, only
+/// the last source of each field is checked unless the cache was parsed with
+/// `ParamsConfig::field_sources`.
+#[cfg(server)]
+#[derive(Debug, Default)]
+pub struct StrictParams<T>(pub T, pub ParamsMetadata);
+
+#[cfg(server)]
+#[cfg_attr(axum07, axum::async_trait)]
+impl<T, S> FromRequest<S> for StrictParams<T>
+where
+    T: DeserializeOwned + ParamSources,
+    S: Send + Sync,
+{
+    type Rejection = Rejection;
+
+    async fn from_request(mut req: Request, state: &S) -> Result<Self, Self::Rejection> {
+        // The source of each leaf, objects merged from several sources are checked leaf by leaf
+        let config = ParamsConfig::of(&req).field_sources(true);
+        req.extensions_mut().insert(config);
+        let Params(payload, metadata) =
+            Params::extract(req, state, |_, metadata| check_sources::<T>(metadata)).await?;
+        Ok(StrictParams(payload, metadata))
+    }
+}
+
+#[cfg(server)]
+fn check_sources<T: ParamSources>(metadata: &ParamsMetadata) -> Result<(), Error> {
+    for (field, allowed) in T::expected_sources() {
+        let leaves = metadata.field_sources.iter().filter(|(path, _)| {
+            path.strip_prefix(field)
+                .is_some_and(|rest| rest.is_empty() || rest.starts_with('['))
+        });
+        let denied = metadata
+            .sources
+            .get_key_value(*field)
+            .into_iter()
+            .chain(leaves)
+            .filter(|(_, source)| !allowed.contains(source))
+            .min_by_key(|(path, _)| path.as_str());
+        if let Some((path, source)) = denied {
+            let allowed = allowed
+                .iter()
+                .map(|s| s.as_str())
+                .collect::<Vec<_>>()
+                .join(", ");
+            return Err(Error::DecodeError(format!(
+                "Parameter `{path}` must come from {allowed}, got {source}"
+            )));
+        }
+    }
+    Ok(())
+}
+
+#[cfg(all(test, server))]
+mod tests {
+    use super::*;
+    use axum::{Router, http::StatusCode, routing::post};
+    use axum_test::TestServer;
+    use serde::Deserialize;
+    use serde_json::json;
+
+    #[derive(Debug, Deserialize)]
+    #[cfg_attr(feature = "macros", derive(crate::ParamSources))]
+    struct UpdateUser {
+        #[cfg_attr(feature = "macros", param(path))]
+        user_id: i32,
+        #[cfg_attr(feature = "macros", param(source = "body"))]
+        #[serde(rename = "userName", default)]
+        name: String,
+        #[allow(dead_code)]
+        note: Option<String>,
+    }
+
+    #[cfg(not(feature = "macros"))]
+    impl ParamSources for UpdateUser {
+        fn expected_sources() -> &'static [(&'static str, &'static [Source])] {
+            &[("user_id", &[Source::Path]), ("userName", Source::BODY)]
+        }
+    }
+
+    #[test]
+    fn test_expected_sources() {
+        assert_eq!(
+            UpdateUser::expected_sources(),
+            &[
+                ("user_id", &[Source::Path][..]),
+                (
+                    "userName",
+                    &[Source::Json, Source::Form, Source::Multipart, Source::Raw][..]
+                ),
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_strict_params_sources() {
+        let app = Router::new().route(
+            &crate::route_path("/users/{user_id}"),
+            post(
+                |StrictParams(user, _): StrictParams<UpdateUser>| async move {
+                    format!("{}:{}", user.user_id, user.name)
+                },
+            ),
+        );
+        let server = TestServer::new(app).unwrap();
+
+        let response = server
+            .post("/users/1?note=x")
+            .json(&json!({"userName": "alice"}))
+            .await;
+        assert_eq!(response.status_code(), StatusCode::OK);
+        assert_eq!(response.text(), "1:alice");
+
+        // Query parameter overriding a body-only field
+        let response = server.post("/users/1?userName=mallory").await;
+        assert_eq!(response.status_code(), StatusCode::BAD_REQUEST);
+        assert!(response.text().contains("`userName` must come from"));
+
+        // Body overriding a path-only field
+        let response = server
+            .post("/users/1")
+            .json(&json!({"user_id": 2, "userName": "alice"}))
+            .await;
+        assert_eq!(response.status_code(), StatusCode::BAD_REQUEST);
+        assert!(response.text().contains("got json"));
+    }
+
+    #[derive(Debug, Deserialize)]
+    struct User {
+        name: String,
+        #[serde(default)]
+        is_admin: bool,
+    }
+
+    #[derive(Debug, Deserialize)]
+    #[cfg_attr(feature = "macros", derive(crate::ParamSources))]
+    struct Update {
+        #[cfg_attr(feature = "macros", param(source = "body"))]
+        user: User,
+    }
+
+    #[cfg(not(feature = "macros"))]
+    impl ParamSources for Update {
+        fn expected_sources() -> &'static [(&'static str, &'static [Source])] {
+            &[("user", Source::BODY)]
+        }
+    }
+
+    #[tokio::test]
+    async fn test_strict_params_merged_leaves() {
+        let app = Router::new().route(
+            "/x",
+            post(|StrictParams(update, _): StrictParams<Update>| async move {
+                format!("{}:{}", update.user.name, update.user.is_admin)
+            }),
+        );
+        let server = TestServer::new(app).unwrap();
+
+        let response = server
+            .post("/x")
+            .json(&json!({"user": {"name": "x"}}))
+            .await;
+        assert_eq!(response.text(), "x:false");
+
+        // A query leaf merged into a body only object
+        let response = server
+            .post("/x?user[is_admin]=1")
+            .json(&json!({"user": {"name": "x"}}))
+            .await;
+        assert_eq!(response.status_code(), StatusCode::BAD_REQUEST);
+        assert!(
+            response
+                .text()
+                .contains("`user[is_admin]` must come from json, form, multipart, raw, got query"),
+            "{}",
+            response.text()
+        );
+    }
+}
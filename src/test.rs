@@ -0,0 +1,309 @@
+//! Builders for extracting params in unit tests, without a router or a test server.
+//!
+//! ```ignore
+//! let Params(post, _) = ParamsBuilder::new()
+//!     .path("id", "1")
+//!     .query("draft", "true")
+//!     .multipart_field("post[title]", "Hello")
+//!     .multipart_file("post[cover]", std::fs::read("cover.jpg")?, "cover.jpg")
+//!     .build::<CreatePost>()
+//!     .await?;
+//! ```
+//!
+//! The request goes through the same extraction as one sent to a handler, with the
+//! `ParamsConfig` and extensions of the builder.
+
+use std::any::Any;
+
+use axum::{
+    body::Body,
+    extract::{FromRequest, Request},
+    http::{Extensions, Method, header},
+};
+use serde::de::DeserializeOwned;
+
+use crate::{Error, Params, ParamsConfig, RawParams};
+
+/// Path parameters of a built request, read when it wasn't routed.
+#[derive(Clone)]
+pub(crate) struct TestPathParams(pub(crate) Vec<(String, String)>);
+
+enum TestPart {
+    Field(String, String),
+    File {
+        name: String,
+        bytes: Vec<u8>,
+        file_name: String,
+        content_type: String,
+    },
+}
+
+const BOUNDARY: &str = "axum-params-test-boundary";
+
+/// A request to extract params from, sent as a multipart body when it has multipart parts,
+/// else as a JSON body when it has JSON.
+pub struct ParamsBuilder {
+    method: Method,
+    path: Vec<(String, String)>,
+    query: Vec<(String, String)>,
+    json: Option<serde_json::Value>,
+    parts: Vec<TestPart>,
+    extensions: Extensions,
+}
+
+impl Default for ParamsBuilder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl ParamsBuilder {
+    /// A `POST` request without params.
+    pub fn new() -> Self {
+        ParamsBuilder {
+            method: Method::POST,
+            path: vec![],
+            query: vec![],
+            json: None,
+            parts: vec![],
+            extensions: Extensions::new(),
+        }
+    }
+
+    pub fn method(mut self, method: Method) -> Self {
+        self.method = method;
+        self
+    }
+
+    /// A path parameter, as matched by the route.
+    pub fn path(mut self, key: impl Into<String>, value: impl Into<String>) -> Self {
+        self.path.push((key.into(), value.into()));
+        self
+    }
+
+    /// A query string parameter, `key` may be nested like `filter[status]`.
+    pub fn query(mut self, key: impl Into<String>, value: impl Into<String>) -> Self {
+        self.query.push((key.into(), value.into()));
+        self
+    }
+
+    /// The JSON body, an unnamed JSON part of multipart requests.
+    pub fn json(mut self, value: serde_json::Value) -> Self {
+        self.json = Some(value);
+        self
+    }
+
+    pub fn multipart_field(mut self, name: impl Into<String>, value: impl Into<String>) -> Self {
+        self.parts.push(TestPart::Field(name.into(), value.into()));
+        self
+    }
+
+    /// An uploaded file, sent as `application/octet-stream`.
+    pub fn multipart_file(
+        self,
+        name: impl Into<String>,
+        bytes: impl Into<Vec<u8>>,
+        file_name: impl Into<String>,
+    ) -> Self {
+        self.multipart_file_with_type(name, bytes, file_name, "application/octet-stream")
+    }
+
+    pub fn multipart_file_with_type(
+        mut self,
+        name: impl Into<String>,
+        bytes: impl Into<Vec<u8>>,
+        file_name: impl Into<String>,
+        content_type: impl Into<String>,
+    ) -> Self {
+        self.parts.push(TestPart::File {
+            name: name.into(),
+            bytes: bytes.into(),
+            file_name: file_name.into(),
+            content_type: content_type.into(),
+        });
+        self
+    }
+
+    /// The `ParamsConfig` of the extraction, like one added with `Extension`.
+    pub fn config(self, config: ParamsConfig) -> Self {
+        self.extension(config)
+    }
+
+    /// A request extension, e.g. one read by `ParamsConfig::extension`.
+    pub fn extension<E: Any + Clone + Send + Sync>(mut self, extension: E) -> Self {
+        self.extensions.insert(extension);
+        self
+    }
+
+    /// Extracts `Params<T>`.
+    pub async fn build<T: DeserializeOwned>(self) -> Result<Params<T>, Error> {
+        Params::from_request(self.into_request(), &())
+            .await
+            .map_err(Error::from)
+    }
+
+    /// Extracts the merged params before deserialization.
+    pub async fn build_raw(self) -> Result<RawParams, Error> {
+        RawParams::from_request(self.into_request(), &())
+            .await
+            .map_err(Error::from)
+    }
+
+    /// The request, for the other extractors like `StrictParams`.
+    pub fn into_request(self) -> Request {
+        let mut uri = "/".to_string();
+        if !self.query.is_empty() {
+            let query = form_urlencoded::Serializer::new(String::new())
+                .extend_pairs(&self.query)
+                .finish();
+            uri = format!("/?{query}");
+        }
+        let mut builder = Request::builder().method(self.method).uri(uri);
+        let body = if !self.parts.is_empty() {
+            builder = builder.header(
+                header::CONTENT_TYPE,
+                format!("multipart/form-data; boundary={BOUNDARY}"),
+            );
+            Body::from(multipart_body(self.json.as_ref(), &self.parts))
+        } else if let Some(json) = &self.json {
+            builder = builder.header(header::CONTENT_TYPE, "application/json");
+            Body::from(json.to_string())
+        } else {
+            Body::empty()
+        };
+        let mut req = builder.body(body).expect("valid test request");
+        *req.extensions_mut() = self.extensions;
+        if !self.path.is_empty() {
+            req.extensions_mut().insert(TestPathParams(self.path));
+        }
+        req
+    }
+}
+
+fn multipart_body(json: Option<&serde_json::Value>, parts: &[TestPart]) -> Vec<u8> {
+    let mut body = vec![];
+    let mut part = |disposition: String, content_type: Option<&str>, value: &[u8]| {
+        body.extend(
+            format!("--{BOUNDARY}\r\nContent-Disposition: form-data; {disposition}").bytes(),
+        );
+        if let Some(content_type) = content_type {
+            body.extend(format!("\r\nContent-Type: {content_type}").bytes());
+        }
+        body.extend(b"\r\n\r\n");
+        body.extend(value);
+        body.extend(b"\r\n");
+    };
+    if let Some(json) = json {
+        part(
+            "name=\"\"".to_string(),
+            Some("application/json"),
+            json.to_string().as_bytes(),
+        );
+    }
+    for test_part in parts {
+        match test_part {
+            TestPart::Field(name, value) => {
+                part(format!("name=\"{name}\""), None, value.as_bytes())
+            }
+            TestPart::File {
+                name,
+                bytes,
+                file_name,
+                content_type,
+            } => part(
+                format!("name=\"{name}\"; filename=\"{file_name}\""),
+                Some(content_type),
+                bytes,
+            ),
+        }
+    }
+    body.extend(format!("--{BOUNDARY}--\r\n").bytes());
+    body
+}
+
+#[cfg(test)]
+mod tests {
+    use serde::Deserialize;
+    use serde_json::json;
+    use tokio::io::AsyncReadExt;
+
+    use super::*;
+    use crate::{ParamsReaderExt, Source, UploadFile};
+
+    #[derive(Debug, Deserialize)]
+    struct CreatePost {
+        id: u32,
+        draft: bool,
+        post: Post,
+    }
+
+    #[derive(Debug, Deserialize)]
+    struct Post {
+        title: String,
+        tags: Vec<String>,
+        cover: UploadFile,
+    }
+
+    #[tokio::test]
+    async fn test_params_builder() {
+        let Params(params, metadata) = ParamsBuilder::new()
+            .path("id", "1")
+            .query("draft", "true")
+            .json(json!({"post": {"tags": ["a", "b"]}}))
+            .multipart_field("post[title]", "Hello")
+            .multipart_file_with_type("post[cover]", &b"jpeg"[..], "cover.jpg", "image/jpeg")
+            .build::<CreatePost>()
+            .await
+            .unwrap();
+        assert_eq!(params.id, 1);
+        assert!(params.draft);
+        assert_eq!(params.post.title, "Hello");
+        assert_eq!(params.post.tags, ["a", "b"]);
+        assert_eq!(params.post.cover.name, "cover.jpg");
+        assert_eq!(params.post.cover.content_type, "image/jpeg");
+        let mut content = String::new();
+        let mut file = params.post.cover.open().await.unwrap();
+        file.read_to_string(&mut content).await.unwrap();
+        assert_eq!(content, "jpeg");
+        assert_eq!(metadata.sources["id"], Source::Path);
+        assert_eq!(metadata.sources["draft"], Source::Query);
+
+        let raw = ParamsBuilder::new()
+            .json(json!({"user": {"name": "alice"}}))
+            .build_raw()
+            .await
+            .unwrap();
+        assert_eq!(
+            raw.get::<String>("user[name]").await.unwrap().as_deref(),
+            Some("alice")
+        );
+    }
+
+    #[tokio::test]
+    async fn test_params_builder_config() {
+        #[derive(Debug, Clone, serde::Serialize)]
+        struct UserId(u32);
+
+        #[derive(Debug, Deserialize)]
+        struct UserParams {
+            user_id: u32,
+        }
+
+        let result = ParamsBuilder::new()
+            .query("a", "1")
+            .query("b", "2")
+            .config(ParamsConfig::new().max_params(1))
+            .build_raw()
+            .await;
+        assert!(result.is_err());
+
+        let Params(params, _) = ParamsBuilder::new()
+            .query("user_id", "2")
+            .config(ParamsConfig::new().extension::<UserId>("user_id"))
+            .extension(UserId(1))
+            .build::<UserParams>()
+            .await
+            .unwrap();
+        assert_eq!(params.user_id, 1);
+    }
+}
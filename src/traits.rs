@@ -0,0 +1,126 @@
+use std::{collections::HashMap, pin::Pin};
+
+use ::serde::de::DeserializeOwned;
+
+use crate::{Error, Value, value::path_segments};
+
+pub type BoxFuture<'a, T> = Pin<Box<dyn Future<Output = T> + Send + 'a>>;
+
+/// Read access to extracted parameters.
+///
+/// The trait is object safe, so handlers and services can take a `&dyn ParamsReader` or a
+/// `Box<dyn ParamsReader>` and tests can pass a `HashMap<String, Value>` or their own mock.
+/// Typed access is provided by `ParamsReaderExt`.
+pub trait ParamsReader: Send + Sync {
+    /// The value at `path`, a top level key or a bracket path like `user[address][city]`
+    /// where array elements are addressed by index.
+    fn get_value<'a>(&'a self, path: &'a str) -> BoxFuture<'a, Result<Option<Value>, Error>>;
+}
+
+/// Typed helpers for every `ParamsReader`.
+pub trait ParamsReaderExt: ParamsReader {
+    /// Deserializes the value at `path`, `None` if it is missing.
+    fn get<'a, T: DeserializeOwned + 'a>(
+        &'a self,
+        path: &'a str,
+    ) -> impl Future<Output = Result<Option<T>, Error>> + Send + 'a {
+        async move {
+            match self.get_value(path).await? {
+                Some(value) => T::deserialize(value).map(Some).map_err(|e| {
+                    Error::DecodeError(format!("Failed to deserialize `{path}`: {e}"))
+                }),
+                None => Ok(None),
+            }
+        }
+    }
+
+    /// Like `get`, but a missing value is an error.
+    fn require<'a, T: DeserializeOwned + 'a>(
+        &'a self,
+        path: &'a str,
+    ) -> impl Future<Output = Result<T, Error>> + Send + 'a {
+        async move {
+            self.get(path)
+                .await?
+                .ok_or_else(|| Error::DecodeError(format!("Missing parameter `{path}`")))
+        }
+    }
+}
+
+impl<R: ParamsReader + ?Sized> ParamsReaderExt for R {}
+
+impl ParamsReader for HashMap<String, Value> {
+    fn get_value<'a>(&'a self, path: &'a str) -> BoxFuture<'a, Result<Option<Value>, Error>> {
+        Box::pin(async move { Ok(lookup(self, path).cloned()) })
+    }
+}
+
+fn lookup<'a>(params: &'a HashMap<String, Value>, path: &str) -> Option<&'a Value> {
+    let segments = path_segments(path)?;
+    let (root, rest) = segments.split_first()?;
+    rest.iter()
+        .try_fold(params.get(*root)?, |value, key| value.get(key))
+}
+
+#[cfg(all(test, server))]
+mod tests {
+    use super::*;
+    use crate::RawParams;
+    use axum::{
+        body::Body,
+        extract::{FromRequest, Request},
+        http,
+    };
+    use serde::Deserialize;
+
+    #[derive(Debug, Deserialize, PartialEq)]
+    struct Address {
+        city: String,
+        zip: u32,
+    }
+
+    struct MockReader;
+
+    impl ParamsReader for MockReader {
+        fn get_value<'a>(&'a self, path: &'a str) -> BoxFuture<'a, Result<Option<Value>, Error>> {
+            Box::pin(async move { Ok((path == "id").then(|| Value::xstr("42"))) })
+        }
+    }
+
+    #[tokio::test]
+    async fn test_params_reader() {
+        let req = Request::builder()
+            .method(http::Method::POST)
+            .uri("/test?id=1&tags[]=a&tags[]=b")
+            .header(http::header::CONTENT_TYPE, "application/json")
+            .body(Body::from(
+                r#"{"user": {"address": {"city": "Paris", "zip": 75001}}}"#,
+            ))
+            .unwrap();
+        let RawParams(params, _) = RawParams::from_request(req, &()).await.unwrap();
+
+        let reader: Box<dyn ParamsReader> = Box::new(params);
+        assert_eq!(reader.require::<i32>("id").await.unwrap(), 1);
+        assert_eq!(
+            reader.get::<Address>("user[address]").await.unwrap(),
+            Some(Address {
+                city: "Paris".to_string(),
+                zip: 75001
+            })
+        );
+        assert_eq!(
+            reader.get::<String>("user[address][city]").await.unwrap(),
+            Some("Paris".to_string())
+        );
+        assert_eq!(
+            reader.get::<String>("tags[1]").await.unwrap(),
+            Some("b".to_string())
+        );
+        assert_eq!(reader.get::<String>("tags[2]").await.unwrap(), None);
+        assert!(reader.require::<String>("missing").await.is_err());
+        assert!(reader.get::<i32>("user[address][city]").await.is_err());
+
+        let mock: &dyn ParamsReader = &MockReader;
+        assert_eq!(mock.require::<u64>("id").await.unwrap(), 42);
+    }
+}
@@ -0,0 +1,225 @@
+use std::{fmt, marker::PhantomData, ops::Deref};
+
+use serde::{Deserialize, Deserializer, de};
+
+use crate::UploadFile;
+
+/// The constraints of a `TypedUpload`, declared by a marker type:
+///
+/// ```ignore
+/// struct Avatar;
+///
+/// impl UploadKind for Avatar {
+///     const MIME_TYPES: &'static [&'static str] = &["image/png", "image/jpeg"];
+///     const MAX_SIZE: Option<u64> = Some(1 << 20);
+/// }
+/// ```
+pub trait UploadKind {
+    /// Accepted content types, `image/*` accepts every image type.
+    const MIME_TYPES: &'static [&'static str];
+    /// Maximum size in bytes, unlimited by default.
+    const MAX_SIZE: Option<u64> = None;
+}
+
+/// Uploads with an `image/*` content type.
+#[derive(Debug, Clone, Copy)]
+pub struct Image;
+
+impl UploadKind for Image {
+    const MIME_TYPES: &'static [&'static str] = &["image/*"];
+}
+
+/// Uploads with the `application/pdf` content type.
+#[derive(Debug, Clone, Copy)]
+pub struct Pdf;
+
+impl UploadKind for Pdf {
+    const MIME_TYPES: &'static [&'static str] = &["application/pdf"];
+}
+
+/// An uploaded file whose content type and size are checked against `K`, rejecting the
+/// request before the handler runs:
+///
+/// ```ignore
+/// #[derive(Deserialize)]
+/// struct CreatePost {
+///     title: String,
+///     cover: TypedUpload<Image>,
+///     attachments: Vec<TypedUpload<Pdf>>,
+/// }
+/// ```
+///
+/// The content type is the one sent by the client, the content is not inspected.
+pub struct TypedUpload<K> {
+    file: UploadFile,
+    kind: PhantomData<K>,
+}
+
+impl<K> TypedUpload<K> {
+    pub fn into_inner(self) -> UploadFile {
+        self.file
+    }
+}
+
+impl<K> Deref for TypedUpload<K> {
+    type Target = UploadFile;
+
+    fn deref(&self) -> &UploadFile {
+        &self.file
+    }
+}
+
+impl<K> Clone for TypedUpload<K> {
+    fn clone(&self) -> Self {
+        TypedUpload {
+            file: self.file.clone(),
+            kind: PhantomData,
+        }
+    }
+}
+
+impl<K> fmt::Debug for TypedUpload<K> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_tuple("TypedUpload").field(&self.file).finish()
+    }
+}
+
+impl<'de, K: UploadKind> Deserialize<'de> for TypedUpload<K> {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        let file = UploadFile::deserialize(deserializer)?;
+        if !K::MIME_TYPES
+            .iter()
+            .any(|pattern| matches_mime(pattern, &file.content_type))
+        {
+            return Err(de::Error::custom(format!(
+                "upload `{}` is {}, expected {}",
+                file.name,
+                file.content_type,
+                K::MIME_TYPES.join(" or ")
+            )));
+        }
+        if let Some(max_size) = K::MAX_SIZE {
+            if !file.owned {
+                return Err(de::Error::custom(format!(
+                    "`{}` is not an uploaded file",
+                    file.name
+                )));
+            }
+            let size = std::fs::metadata(&file.temp_file_path)
+                .map_err(|e| de::Error::custom(format!("upload `{}`: {e}", file.name)))?
+                .len();
+            if size > max_size {
+                return Err(de::Error::custom(format!(
+                    "upload `{}` exceeds the {max_size} bytes limit",
+                    file.name
+                )));
+            }
+        }
+        Ok(TypedUpload {
+            file,
+            kind: PhantomData,
+        })
+    }
+}
+
+/// Whether `content_type`, parameters aside, matches `pattern` like `image/png` or `image/*`.
+fn matches_mime(pattern: &str, content_type: &str) -> bool {
+    let essence = content_type
+        .split(';')
+        .next()
+        .unwrap_or_default()
+        .trim()
+        .to_ascii_lowercase();
+    match pattern.strip_suffix("/*") {
+        Some(kind) => essence
+            .strip_prefix(kind)
+            .is_some_and(|rest| rest.starts_with('/')),
+        None => essence == pattern.to_ascii_lowercase(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Params, test::ParamsBuilder};
+
+    struct SmallPng;
+
+    impl UploadKind for SmallPng {
+        const MIME_TYPES: &'static [&'static str] = &["image/png"];
+        const MAX_SIZE: Option<u64> = Some(4);
+    }
+
+    #[derive(Debug, Deserialize)]
+    struct Post {
+        cover: TypedUpload<Image>,
+        icon: Option<TypedUpload<SmallPng>>,
+    }
+
+    #[test]
+    fn test_matches_mime() {
+        assert!(matches_mime("image/*", "image/png"));
+        assert!(matches_mime("image/*", "Image/SVG+XML; charset=utf-8"));
+        assert!(!matches_mime("image/*", "imagex/png"));
+        assert!(matches_mime("application/pdf", "application/pdf"));
+        assert!(!matches_mime("application/pdf", "application/pdfx"));
+    }
+
+    #[tokio::test]
+    async fn test_typed_upload() {
+        let Params(post, _) = ParamsBuilder::new()
+            .multipart_file_with_type("cover", &b"gif"[..], "cover.gif", "image/gif")
+            .multipart_file_with_type("icon", &b"png"[..], "icon.png", "image/png")
+            .build::<Post>()
+            .await
+            .unwrap();
+        assert_eq!(post.cover.name, "cover.gif");
+        assert_eq!(post.icon.unwrap().content_type, "image/png");
+
+        let error = ParamsBuilder::new()
+            .multipart_file_with_type("cover", &b"%PDF"[..], "cover.pdf", "application/pdf")
+            .build::<Post>()
+            .await
+            .unwrap_err();
+        assert!(
+            error
+                .message()
+                .contains("upload `cover.pdf` is application/pdf, expected image/*"),
+            "{error:?}"
+        );
+
+        let error = ParamsBuilder::new()
+            .multipart_file_with_type("cover", &b"gif"[..], "cover.gif", "image/gif")
+            .multipart_file_with_type("icon", &b"large png"[..], "icon.png", "image/png")
+            .build::<Post>()
+            .await
+            .unwrap_err();
+        assert!(
+            error
+                .message()
+                .contains("upload `icon.png` exceeds the 4 bytes limit"),
+            "{error:?}"
+        );
+
+        let temp_file = tempfile::NamedTempFile::new().unwrap();
+        let forged = serde_json::json!({
+            "name": "icon.png",
+            "content_type": "image/png",
+            "temp_file_path": temp_file.path(),
+        });
+        let error = ParamsBuilder::new()
+            .json(serde_json::json!({"cover": forged, "icon": forged}))
+            .build::<Post>()
+            .await
+            .unwrap_err();
+        assert!(
+            error
+                .message()
+                .contains("`icon.png` is not an uploaded file"),
+            "{error:?}"
+        );
+    }
+}
@@ -1,11 +1,93 @@
+use std::cell::RefCell;
+
 use serde::{Deserialize, Serialize};
-use tokio::fs::File;
+
+use crate::Checksum;
+#[cfg(server)]
+use std::{
+    io,
+    path::{Path, PathBuf},
+};
+#[cfg(server)]
+use tokio::fs::{self, File};
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(try_from = "UploadFileFields")]
 pub struct UploadFile {
     pub name: String,
     pub content_type: String,
     pub(crate) temp_file_path: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) checksum: Option<Checksum>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub(crate) headers: Vec<(String, String)>,
+    pub(crate) part_index: usize,
+    /// Whether `temp_file_path` is a temp file of the extraction, only those are persisted.
+    #[serde(skip)]
+    #[cfg_attr(not(server), allow(dead_code))]
+    pub(crate) owned: bool,
+}
+
+/// The fields of a deserialized `UploadFile`, its `temp_file_path` is checked against the
+/// temp files of the extraction.
+#[derive(Deserialize)]
+struct UploadFileFields {
+    name: String,
+    content_type: String,
+    temp_file_path: String,
+    #[serde(default)]
+    checksum: Option<Checksum>,
+    #[serde(default)]
+    headers: Vec<(String, String)>,
+    #[serde(default)]
+    part_index: usize,
+}
+
+thread_local! {
+    static TEMP_FILES: RefCell<Option<Vec<String>>> = const { RefCell::new(None) };
+}
+
+#[cfg(server)]
+struct RestoreTempFiles(Option<Vec<String>>);
+
+#[cfg(server)]
+impl Drop for RestoreTempFiles {
+    fn drop(&mut self) {
+        TEMP_FILES.set(self.0.take());
+    }
+}
+
+/// Deserializes with the temp files of an extraction: an `UploadFile` pointing elsewhere,
+/// e.g. an object with a `temp_file_path` sent in a JSON body, is an error. `UploadFile`s
+/// deserialized elsewhere are not `owned`.
+#[cfg(server)]
+pub(crate) fn with_temp_files<R>(paths: Vec<String>, f: impl FnOnce() -> R) -> R {
+    let _restore = RestoreTempFiles(TEMP_FILES.replace(Some(paths)));
+    f()
+}
+
+impl TryFrom<UploadFileFields> for UploadFile {
+    type Error = String;
+
+    fn try_from(fields: UploadFileFields) -> Result<Self, String> {
+        let owned = TEMP_FILES.with_borrow(|paths| {
+            paths
+                .as_ref()
+                .map(|paths| paths.contains(&fields.temp_file_path))
+        });
+        if owned == Some(false) {
+            return Err(format!("`{}` is not an uploaded file", fields.name));
+        }
+        Ok(UploadFile {
+            name: fields.name,
+            content_type: fields.content_type,
+            temp_file_path: fields.temp_file_path,
+            checksum: fields.checksum,
+            headers: fields.headers,
+            part_index: fields.part_index,
+            owned: owned.unwrap_or(false),
+        })
+    }
 }
 
 impl PartialEq for UploadFile {
@@ -14,8 +96,293 @@ impl PartialEq for UploadFile {
     }
 }
 
+impl UploadFile {
+    /// The digest computed while the file was received, with `ParamsConfig::checksum`.
+    pub fn checksum(&self) -> Option<&Checksum> {
+        self.checksum.as_ref()
+    }
+
+    /// The headers of the multipart part, with lowercase names, e.g. `content-disposition` or
+    /// custom `x-*` headers of resumable upload clients.
+    pub fn headers(&self) -> &[(String, String)] {
+        &self.headers
+    }
+
+    /// The position of the part in the multipart body, starting at 0 and counting every part
+    /// like `Sizes::multipart_fields`. Sorting by it restores the order the client sent
+    /// files in after they are grouped into arrays or objects.
+    pub fn part_index(&self) -> usize {
+        self.part_index
+    }
+
+    /// The first part header named `name`, case-insensitive.
+    pub fn header(&self, name: &str) -> Option<&str> {
+        self.headers
+            .iter()
+            .find(|(key, _)| key.eq_ignore_ascii_case(name))
+            .map(|(_, value)| value.as_str())
+    }
+
+    /// The client's file name without directories, control characters and surrounding
+    /// whitespace, safe to join to a directory. Empty when nothing usable is left, e.g. for
+    /// `..`.
+    pub fn sanitized_name(&self) -> String {
+        sanitize_file_name(&self.name)
+    }
+
+    /// The lowercase extension of `sanitized_name`, without the dot.
+    pub fn extension(&self) -> Option<String> {
+        let name = self.sanitized_name();
+        let (stem, extension) = name.rsplit_once('.')?;
+        (!stem.is_empty() && !extension.is_empty()).then(|| extension.to_lowercase())
+    }
+}
+
+/// See `UploadFile::sanitized_name`.
+pub(crate) fn sanitize_file_name(name: &str) -> String {
+    let name = name.chars().filter(|c| !c.is_control()).collect::<String>();
+    let base = name.rsplit(['/', '\\']).next().unwrap_or_default().trim();
+    match base {
+        "." | ".." => String::new(),
+        base => base.to_string(),
+    }
+}
+
+/// How `UploadFile::persist_as_with` and `UploadFile::persist_to_dir_with` store a file.
+#[cfg(server)]
+#[derive(Debug, Clone, Default)]
+pub struct PersistOptions {
+    pub(crate) mode: Option<u32>,
+    pub(crate) create_dirs: bool,
+}
+
+#[cfg(server)]
+impl PersistOptions {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Unix permissions of the stored file, e.g. `0o644`. Uploads are spooled owner-only
+    /// (`0o600`) and keep that mode by default. Ignored on other platforms.
+    pub fn mode(mut self, mode: u32) -> Self {
+        self.mode = Some(mode);
+        self
+    }
+
+    /// Create the missing parent directories of the destination.
+    pub fn create_dirs(mut self, create_dirs: bool) -> Self {
+        self.create_dirs = create_dirs;
+        self
+    }
+}
+
+#[cfg(server)]
 impl UploadFile {
     pub fn open(&self) -> impl std::future::Future<Output = Result<File, std::io::Error>> + '_ {
         File::open(&self.temp_file_path)
     }
+
+    /// Where the file is stored, the temp file until it is persisted.
+    pub fn path(&self) -> &Path {
+        Path::new(&self.temp_file_path)
+    }
+
+    /// Moves the file to `path`, see `persist_as_with`.
+    pub async fn persist_as(&mut self, path: impl AsRef<Path>) -> io::Result<()> {
+        self.persist_as_with(path, &PersistOptions::default()).await
+    }
+
+    /// Moves the file into `dir` under the base name of the uploaded file name, returns the
+    /// new path, see `persist_as_with`.
+    pub async fn persist_to_dir(&mut self, dir: impl AsRef<Path>) -> io::Result<PathBuf> {
+        self.persist_to_dir_with(dir, &PersistOptions::default())
+            .await
+    }
+
+    /// Like `persist_to_dir`, with `options`. The file is stored under its `sanitized_name`,
+    /// names without a usable base name like `..` are rejected.
+    pub async fn persist_to_dir_with(
+        &mut self,
+        dir: impl AsRef<Path>,
+        options: &PersistOptions,
+    ) -> io::Result<PathBuf> {
+        let name = self.sanitized_name();
+        if name.is_empty() {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("Uploaded file name `{}` has no base name", self.name),
+            ));
+        }
+        let path = dir.as_ref().join(name);
+        self.persist_as_with(&path, options).await?;
+        Ok(path)
+    }
+
+    /// Moves the file to `path`, replacing an existing file, later `open` calls read it there.
+    ///
+    /// The file is renamed, which is atomic on the same filesystem. Across filesystems it is
+    /// copied next to `path` and renamed from there, then the temp file is removed.
+    ///
+    /// Only files spooled by the extraction are moved, an `UploadFile` deserialized from
+    /// other data is rejected with `PermissionDenied`.
+    pub async fn persist_as_with(
+        &mut self,
+        path: impl AsRef<Path>,
+        options: &PersistOptions,
+    ) -> io::Result<()> {
+        if !self.owned {
+            return Err(io::Error::new(
+                io::ErrorKind::PermissionDenied,
+                format!("`{}` is not an uploaded file", self.name),
+            ));
+        }
+        let path = path.as_ref();
+        let dir = path
+            .parent()
+            .filter(|dir| !dir.as_os_str().is_empty())
+            .unwrap_or(Path::new("."));
+        if options.create_dirs {
+            fs::create_dir_all(dir).await?;
+        }
+        // Set on the source, so the file never shows up with other permissions
+        #[cfg(unix)]
+        if let Some(mode) = options.mode {
+            use std::os::unix::fs::PermissionsExt;
+            fs::set_permissions(&self.temp_file_path, std::fs::Permissions::from_mode(mode))
+                .await?;
+        }
+        match fs::rename(&self.temp_file_path, path).await {
+            Ok(()) => {}
+            Err(e) if e.kind() == io::ErrorKind::CrossesDevices => {
+                let copy = tempfile::NamedTempFile::new_in(dir)?;
+                // Copies the permissions too
+                fs::copy(&self.temp_file_path, copy.path()).await?;
+                copy.persist(path).map_err(|e| e.error)?;
+                fs::remove_file(&self.temp_file_path).await?;
+            }
+            Err(e) => return Err(e),
+        }
+        self.temp_file_path = path.to_string_lossy().into_owned();
+        Ok(())
+    }
+}
+
+#[cfg(all(test, server))]
+mod tests {
+    use tokio::io::AsyncReadExt;
+
+    use super::*;
+
+    fn upload(name: &str, content: &str) -> (UploadFile, tempfile::NamedTempFile) {
+        let temp_file = tempfile::NamedTempFile::new().unwrap();
+        std::fs::write(temp_file.path(), content).unwrap();
+        let file = UploadFile {
+            name: name.to_string(),
+            content_type: "text/plain".to_string(),
+            temp_file_path: temp_file.path().to_string_lossy().into_owned(),
+            checksum: None,
+            headers: vec![],
+            part_index: 0,
+            owned: true,
+        };
+        (file, temp_file)
+    }
+
+    #[tokio::test]
+    async fn test_persist() {
+        let dir = tempfile::tempdir().unwrap();
+        let (mut file, temp_file) = upload("../../etc/notes.txt", "hello");
+        let path = file.persist_to_dir(dir.path()).await.unwrap();
+        assert_eq!(path, dir.path().join("notes.txt"));
+        assert_eq!(file.path(), path);
+        assert!(!temp_file.path().exists());
+        let mut content = String::new();
+        file.open()
+            .await
+            .unwrap()
+            .read_to_string(&mut content)
+            .await
+            .unwrap();
+        assert_eq!(content, "hello");
+
+        let (mut file, _temp_file) = upload("a.txt", "world");
+        let options = PersistOptions::new().mode(0o640).create_dirs(true);
+        let path = dir.path().join("nested/b.txt");
+        file.persist_as_with(&path, &options).await.unwrap();
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), "world");
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            let mode = std::fs::metadata(&path).unwrap().permissions().mode();
+            assert_eq!(mode & 0o777, 0o640);
+        }
+
+        let (mut file, _temp_file) = upload("..", "x");
+        let error = file.persist_to_dir(dir.path()).await.unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::InvalidInput);
+
+        // A file that wasn't spooled by an extraction is left in place
+        let (file, temp_file) = upload("c.txt", "secret");
+        let mut forged: UploadFile =
+            serde_json::from_value(serde_json::to_value(&file).unwrap()).unwrap();
+        let error = forged.persist_to_dir(dir.path()).await.unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::PermissionDenied);
+        assert!(temp_file.path().exists());
+        assert!(!dir.path().join("c.txt").exists());
+    }
+
+    #[tokio::test]
+    async fn test_forged_upload() {
+        #[derive(Debug, serde::Deserialize)]
+        struct Avatar {
+            #[allow(dead_code)]
+            avatar: UploadFile,
+        }
+        let (_, temp_file) = upload("a.png", "png");
+        let forged = serde_json::json!({
+            "avatar": {
+                "name": "a.png",
+                "content_type": "image/png",
+                "temp_file_path": temp_file.path(),
+            }
+        });
+        let error = crate::test::ParamsBuilder::new()
+            .json(forged)
+            .build::<Avatar>()
+            .await
+            .unwrap_err();
+        assert!(
+            error.message().contains("`a.png` is not an uploaded file"),
+            "{error:?}"
+        );
+        assert!(temp_file.path().exists());
+    }
+
+    #[tokio::test]
+    async fn test_sanitized_name() {
+        let name = |name: &str| upload(name, "").0;
+        assert_eq!(
+            name("C:\\Users\\me\\photo.JPG").sanitized_name(),
+            "photo.JPG"
+        );
+        assert_eq!(name("../../etc/passwd").sanitized_name(), "passwd");
+        assert_eq!(name("a\0b\r\n.txt ").sanitized_name(), "ab.txt");
+        assert_eq!(name("..\0/..").sanitized_name(), "");
+        assert_eq!(name("photo.JPG").extension().as_deref(), Some("jpg"));
+        assert_eq!(name("archive.tar.gz").extension().as_deref(), Some("gz"));
+        assert_eq!(name(".bashrc").extension(), None);
+        assert_eq!(name("README").extension(), None);
+
+        #[derive(serde::Deserialize)]
+        struct Avatar {
+            avatar: UploadFile,
+        }
+        let crate::Params(params, _) = crate::test::ParamsBuilder::new()
+            .multipart_file("avatar", &b"png"[..], "../../a\t.png")
+            .config(crate::ParamsConfig::new().sanitize_file_names(true))
+            .build::<Avatar>()
+            .await
+            .unwrap();
+        assert_eq!(params.avatar.name, "a.png");
+    }
 }
@@ -0,0 +1,196 @@
+use serde::{Deserialize, Deserializer, de};
+use std::{
+    any::{Any, TypeId},
+    cell::RefCell,
+    collections::HashMap,
+    fmt::Display,
+    ops::{Deref, DerefMut},
+    pin::Pin,
+};
+
+use axum::body::Bytes;
+
+use crate::UploadFile;
+
+/// Builds a value from an uploaded file during extraction, declare the field as
+/// `Uploaded<Self>`.
+///
+/// Conversions run after the whole body has been read, a failed conversion rejects the
+/// request like any other parameter error.
+pub trait FromUploadFile: Sized + Send + 'static {
+    type Rejection: Display;
+
+    fn from_upload_file(
+        file: &UploadFile,
+    ) -> impl Future<Output = Result<Self, Self::Rejection>> + Send;
+}
+
+impl FromUploadFile for Vec<u8> {
+    type Rejection = std::io::Error;
+
+    async fn from_upload_file(file: &UploadFile) -> Result<Self, Self::Rejection> {
+        tokio::fs::read(&file.temp_file_path).await
+    }
+}
+
+impl FromUploadFile for Bytes {
+    type Rejection = std::io::Error;
+
+    async fn from_upload_file(file: &UploadFile) -> Result<Self, Self::Rejection> {
+        tokio::fs::read(&file.temp_file_path).await.map(Bytes::from)
+    }
+}
+
+impl FromUploadFile for String {
+    type Rejection = std::io::Error;
+
+    async fn from_upload_file(file: &UploadFile) -> Result<Self, Self::Rejection> {
+        tokio::fs::read_to_string(&file.temp_file_path).await
+    }
+}
+
+/// An uploaded file converted with `FromUploadFile`.
+#[derive(Debug)]
+pub struct Uploaded<T> {
+    file: UploadFile,
+    value: Option<T>,
+}
+
+impl<T> Uploaded<T> {
+    /// The file the value was built from.
+    pub fn file(&self) -> &UploadFile {
+        &self.file
+    }
+
+    pub fn into_inner(self) -> T {
+        self.value
+            .expect("Uploaded is only constructed after conversion")
+    }
+}
+
+impl<T> Deref for Uploaded<T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        self.value
+            .as_ref()
+            .expect("Uploaded is only constructed after conversion")
+    }
+}
+
+impl<T> DerefMut for Uploaded<T> {
+    fn deref_mut(&mut self) -> &mut T {
+        self.value
+            .as_mut()
+            .expect("Uploaded is only constructed after conversion")
+    }
+}
+
+type Converted = Box<dyn Any + Send>;
+type ConvertFuture = Pin<Box<dyn Future<Output = Result<Converted, String>> + Send>>;
+type UploadKey = (String, TypeId);
+
+pub(crate) struct PendingUpload {
+    key: UploadKey,
+    pub(crate) file: UploadFile,
+    convert: fn(UploadFile) -> ConvertFuture,
+}
+
+impl PendingUpload {
+    pub(crate) async fn convert(self) -> Result<(UploadKey, Converted), String> {
+        Ok((self.key, (self.convert)(self.file).await?))
+    }
+}
+
+/// State of the deserialization pass `Uploaded` fields run in: the first pass collects
+/// the conversions, the second one takes their results. Collecting errors skips them.
+enum UploadPass {
+    Collect(Vec<PendingUpload>),
+    Resolve(HashMap<UploadKey, Converted>),
+    Skip,
+}
+
+thread_local! {
+    static UPLOAD_PASS: RefCell<Option<UploadPass>> = const { RefCell::new(None) };
+}
+
+struct RestorePass(Option<UploadPass>);
+
+impl Drop for RestorePass {
+    fn drop(&mut self) {
+        UPLOAD_PASS.with(|pass| *pass.borrow_mut() = self.0.take());
+    }
+}
+
+fn with_pass<R>(pass: UploadPass, f: impl FnOnce() -> R) -> (R, UploadPass) {
+    let _restore = RestorePass(UPLOAD_PASS.with(|p| p.replace(Some(pass))));
+    let r = f();
+    let pass = UPLOAD_PASS.with(|p| p.borrow_mut().take());
+    (r, pass.expect("upload pass is set"))
+}
+
+pub(crate) fn collect_uploads<R>(f: impl FnOnce() -> R) -> (R, Vec<PendingUpload>) {
+    match with_pass(UploadPass::Collect(vec![]), f) {
+        (r, UploadPass::Collect(pending)) => (r, pending),
+        _ => unreachable!(),
+    }
+}
+
+pub(crate) fn resolve_uploads<R>(ready: HashMap<UploadKey, Converted>, f: impl FnOnce() -> R) -> R {
+    with_pass(UploadPass::Resolve(ready), f).0
+}
+
+pub(crate) fn skip_uploads<R>(f: impl FnOnce() -> R) -> R {
+    with_pass(UploadPass::Skip, f).0
+}
+
+fn convert<T: FromUploadFile>(file: UploadFile) -> ConvertFuture {
+    Box::pin(async move {
+        T::from_upload_file(&file)
+            .await
+            .map(|v| Box::new(v) as Converted)
+            .map_err(|e| e.to_string())
+    })
+}
+
+impl<'de, T: FromUploadFile> Deserialize<'de> for Uploaded<T> {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        let file = UploadFile::deserialize(deserializer)?;
+        let key = (file.temp_file_path.clone(), TypeId::of::<T>());
+        UPLOAD_PASS.with(|pass| match pass.borrow_mut().as_mut() {
+            Some(UploadPass::Collect(pending)) => {
+                if pending.iter().any(|p| p.key == key) {
+                    return Err(de::Error::custom(format!(
+                        "upload `{}` is used more than once",
+                        file.name
+                    )));
+                }
+                pending.push(PendingUpload {
+                    key,
+                    file: file.clone(),
+                    convert: convert::<T>,
+                });
+                Ok(Uploaded { file, value: None })
+            }
+            Some(UploadPass::Resolve(ready)) => match ready.remove(&key) {
+                Some(value) => Ok(Uploaded {
+                    file,
+                    value: value.downcast().ok().map(|v| *v),
+                }),
+                None => Err(de::Error::custom(format!(
+                    "upload `{}` was not converted",
+                    file.name
+                ))),
+            },
+            Some(UploadPass::Skip) => Ok(Uploaded { file, value: None }),
+            // Only `Params` runs the passes, and it skips them when the request has no uploads
+            None => Err(de::Error::custom(format!(
+                "`{}` is not an uploaded file",
+                file.name
+            ))),
+        })
+    }
+}
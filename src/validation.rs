@@ -0,0 +1,152 @@
+use ::serde::de::DeserializeOwned;
+use axum::extract::{FromRequest, Request};
+
+use crate::{Error, InvalidField, Params, ParamsMetadata, Rejection, rejection::Rejecter};
+
+/// Like `Params`, then validates the params with `garde::Validate` (feature `garde`).
+///
+/// The validation context is the `T::Context` request extension, e.g. inserted by a
+/// middleware, or its default. Failures reject with `Error::InvalidFields`, listing each
+/// invalid field under its bracket path like `user[emails][0]`.
+///
+/// ```ignore
+/// #[derive(Deserialize, garde::Validate)]
+/// struct CreateUser {
+///     #[garde(length(min = 1, max = 32))]
+///     name: String,
+///     #[garde(dive)]
+///     address: Address,
+/// }
+///
+/// async fn create_user(ValidatedParams(user, _): ValidatedParams<CreateUser>) {}
+/// ```
+#[derive(Debug, Default)]
+pub struct ValidatedParams<T>(pub T, pub ParamsMetadata);
+
+#[cfg_attr(axum07, axum::async_trait)]
+impl<T, S> FromRequest<S> for ValidatedParams<T>
+where
+    T: DeserializeOwned + garde::Validate + Send,
+    T::Context: Default + Clone + Send + Sync + 'static,
+    S: Send + Sync,
+{
+    type Rejection = Rejection;
+
+    async fn from_request(req: Request, state: &S) -> Result<Self, Self::Rejection> {
+        let context = req
+            .extensions()
+            .get::<T::Context>()
+            .cloned()
+            .unwrap_or_default();
+        let rejecter = Rejecter::of(&req);
+        let Params(params, metadata) = Params::<T>::from_request(req, state).await?;
+        params
+            .validate_with(&context)
+            .map_err(|report| rejecter.reject(report_error(&report)))?;
+        Ok(ValidatedParams(params, metadata))
+    }
+}
+
+fn report_error(report: &garde::Report) -> Error {
+    let fields = report
+        .iter()
+        .map(|(path, error)| InvalidField {
+            key: bracket_path(&path.to_string()),
+            message: error.message().to_string(),
+        })
+        .collect::<Vec<_>>();
+    let message = fields
+        .iter()
+        .map(|field| match field.key.as_str() {
+            "" => field.message.clone(),
+            key => format!("`{key}`: {}", field.message),
+        })
+        .collect::<Vec<_>>()
+        .join(", ");
+    Error::InvalidFields(format!("Invalid parameters: {message}"), fields)
+}
+
+/// Converts a garde path like `user.emails[0]` to the bracket path `user[emails][0]`.
+fn bracket_path(path: &str) -> String {
+    let mut key = String::new();
+    for (i, segment) in path.split('.').enumerate() {
+        let (name, indices) = match segment.find('[') {
+            Some(start) => segment.split_at(start),
+            None => (segment, ""),
+        };
+        match i {
+            0 => key.push_str(name),
+            _ => key.push_str(&format!("[{name}]")),
+        }
+        key.push_str(indices);
+    }
+    key
+}
+
+#[cfg(test)]
+mod tests {
+    use axum::{Router, http::StatusCode, routing::post};
+    use axum_test::TestServer;
+    use serde::Deserialize;
+    use serde_json::json;
+
+    use super::*;
+    use crate::ParamsConfig;
+
+    #[derive(Debug, Deserialize, garde::Validate)]
+    struct CreateUser {
+        #[garde(length(min = 1))]
+        name: String,
+        #[garde(dive)]
+        addresses: Vec<Address>,
+    }
+
+    #[derive(Debug, Deserialize, garde::Validate)]
+    struct Address {
+        #[garde(length(min = 5))]
+        zip: String,
+    }
+
+    #[test]
+    fn test_bracket_path() {
+        assert_eq!(bracket_path("name"), "name");
+        assert_eq!(bracket_path("addresses[1].zip"), "addresses[1][zip]");
+        assert_eq!(bracket_path("user.emails[0]"), "user[emails][0]");
+        assert_eq!(bracket_path(""), "");
+    }
+
+    #[tokio::test]
+    async fn test_validated_params() {
+        let app = Router::new()
+            .route(
+                "/users",
+                post(|ValidatedParams(user, _): ValidatedParams<CreateUser>| async move {
+                    user.name
+                }),
+            )
+            .layer(axum::Extension(ParamsConfig::new().problem_details(true)));
+        let server = TestServer::new(app).unwrap();
+
+        let response = server
+            .post("/users")
+            .json(&json!({"name": "alice", "addresses": [{"zip": "75001"}]}))
+            .await;
+        assert_eq!(response.status_code(), StatusCode::OK);
+        assert_eq!(response.text(), "alice");
+
+        let response = server
+            .post("/users?name=")
+            .json(&json!({"addresses": [{"zip": "75001"}, {"zip": "1"}]}))
+            .await;
+        assert_eq!(response.status_code(), StatusCode::UNPROCESSABLE_ENTITY);
+        let body = response.json::<serde_json::Value>();
+        let mut fields = body["errors"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|error| error["field"].as_str().unwrap())
+            .collect::<Vec<_>>();
+        fields.sort();
+        assert_eq!(fields, ["addresses[1][zip]", "name"]);
+    }
+}
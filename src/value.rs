@@ -1,4 +1,4 @@
-use std::collections::HashMap;
+use std::{borrow::Borrow, borrow::Cow, collections::HashMap, fmt, ops::Deref};
 
 use crate::{Error, UploadFile};
 
@@ -7,6 +7,10 @@ pub(crate) enum N {
     PosInt(u64),
     /// Always less than zero.
     NegInt(i64),
+    /// Always greater than `u64::MAX`.
+    PosInt128(u128),
+    /// Always less than `i64::MIN`.
+    NegInt128(i128),
     /// Always finite.
     Float(f64),
 }
@@ -30,6 +34,28 @@ impl From<i64> for Number {
     }
 }
 
+impl From<u128> for Number {
+    fn from(v: u128) -> Self {
+        match u64::try_from(v) {
+            Ok(v) => Number(N::PosInt(v)),
+            Err(_) => Number(N::PosInt128(v)),
+        }
+    }
+}
+
+impl From<i128> for Number {
+    fn from(v: i128) -> Self {
+        if v >= 0 {
+            Number::from(v as u128)
+        } else {
+            match i64::try_from(v) {
+                Ok(v) => Number(N::NegInt(v)),
+                Err(_) => Number(N::NegInt128(v)),
+            }
+        }
+    }
+}
+
 impl From<f64> for Number {
     fn from(v: f64) -> Self {
         Number(N::Float(v))
@@ -52,19 +78,176 @@ impl IntoNumber for i64 {
     }
 }
 
+impl IntoNumber for u128 {
+    fn into_number(self) -> Number {
+        Number::from(self)
+    }
+}
+
+impl IntoNumber for i128 {
+    fn into_number(self) -> Number {
+        Number::from(self)
+    }
+}
+
 impl IntoNumber for f64 {
     fn into_number(self) -> Number {
         Number::from(self)
     }
 }
 
+impl fmt::Display for Number {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.0 {
+            N::PosInt(i) => i.fmt(f),
+            N::NegInt(i) => i.fmt(f),
+            N::PosInt128(i) => i.fmt(f),
+            N::NegInt128(i) => i.fmt(f),
+            N::Float(n) => n.fmt(f),
+        }
+    }
+}
+
+/// The text of `Value::String` and `Value::XStr`, it derefs to `str` and converts from and
+/// into `String`. With the `compact_str` feature, strings of up to 24 bytes are stored
+/// inline, which saves an allocation for most parameter values. Object keys are always
+/// `String`.
+#[derive(Clone, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct ParamString(Text);
+
+#[cfg(not(feature = "compact_str"))]
+type Text = String;
+#[cfg(feature = "compact_str")]
+type Text = compact_str::CompactString;
+
+impl ParamString {
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+
+    /// The text as a `String`, without copying heap allocated text.
+    #[cfg(not(feature = "compact_str"))]
+    pub fn into_string(self) -> String {
+        self.0
+    }
+
+    /// The text as a `String`, without copying heap allocated text.
+    #[cfg(feature = "compact_str")]
+    pub fn into_string(self) -> String {
+        self.0.into_string()
+    }
+}
+
+impl Deref for ParamString {
+    type Target = str;
+
+    fn deref(&self) -> &str {
+        &self.0
+    }
+}
+
+impl AsRef<str> for ParamString {
+    fn as_ref(&self) -> &str {
+        &self.0
+    }
+}
+
+impl Borrow<str> for ParamString {
+    fn borrow(&self) -> &str {
+        &self.0
+    }
+}
+
+impl fmt::Debug for ParamString {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fmt::Debug::fmt(self.as_str(), f)
+    }
+}
+
+impl fmt::Display for ParamString {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(self)
+    }
+}
+
+impl From<String> for ParamString {
+    // The identity without the `compact_str` feature
+    #[allow(clippy::useless_conversion)]
+    fn from(s: String) -> Self {
+        ParamString(s.into())
+    }
+}
+
+impl From<&String> for ParamString {
+    fn from(s: &String) -> Self {
+        ParamString(s.as_str().into())
+    }
+}
+
+impl From<&str> for ParamString {
+    fn from(s: &str) -> Self {
+        ParamString(s.into())
+    }
+}
+
+impl From<Cow<'_, str>> for ParamString {
+    fn from(s: Cow<'_, str>) -> Self {
+        match s {
+            Cow::Borrowed(s) => s.into(),
+            Cow::Owned(s) => s.into(),
+        }
+    }
+}
+
+impl From<ParamString> for String {
+    fn from(s: ParamString) -> Self {
+        s.into_string()
+    }
+}
+
+impl PartialEq<str> for ParamString {
+    fn eq(&self, other: &str) -> bool {
+        self.as_str() == other
+    }
+}
+
+impl PartialEq<&str> for ParamString {
+    fn eq(&self, other: &&str) -> bool {
+        self.as_str() == *other
+    }
+}
+
+impl PartialEq<String> for ParamString {
+    fn eq(&self, other: &String) -> bool {
+        self.as_str() == other
+    }
+}
+
+impl PartialEq<ParamString> for str {
+    fn eq(&self, other: &ParamString) -> bool {
+        self == other.as_str()
+    }
+}
+
+impl PartialEq<ParamString> for &str {
+    fn eq(&self, other: &ParamString) -> bool {
+        *self == other.as_str()
+    }
+}
+
+impl PartialEq<ParamString> for String {
+    fn eq(&self, other: &ParamString) -> bool {
+        self == other.as_str()
+    }
+}
+
 #[derive(Debug, Clone)]
 pub enum Value {
     Null,
     Bool(bool),
     Number(Number),
-    String(String),
-    XStr(String),
+    String(ParamString),
+    XStr(ParamString),
     Object(HashMap<String, Value>),
     Array(Vec<Value>),
     UploadFile(UploadFile),
@@ -98,9 +281,9 @@ impl PartialEq for Value {
 impl Value {
     pub fn merge(self, other: Value) -> Result<Value, Error> {
         match (self, other) {
-            // Object + Object = Merged object
+            // Object + Object = Deep merged object
             (Value::Object(mut a), Value::Object(b)) => {
-                a.extend(b);
+                deep_extend(&mut a, b);
                 Ok(Value::Object(a))
             }
             // Array + Array = Combined array
@@ -137,7 +320,7 @@ impl Value {
     ) -> Result<HashMap<String, Value>, Error> {
         match self {
             Value::Object(b) => {
-                a.extend(b);
+                deep_extend(&mut a, b);
                 Ok(a)
             }
             _ => Err(Error::MergeError(format!(
@@ -147,11 +330,15 @@ impl Value {
         }
     }
 
-    pub fn xstr<T: Into<String>>(v: T) -> Value {
+    pub fn string<T: Into<ParamString>>(v: T) -> Value {
+        Value::String(v.into())
+    }
+
+    pub fn xstr<T: Into<ParamString>>(v: T) -> Value {
         Value::XStr(v.into())
     }
 
-    pub fn xstr_opt<T: Into<String>>(v: Option<T>) -> Value {
+    pub fn xstr_opt<T: Into<ParamString>>(v: Option<T>) -> Value {
         match v {
             Some(v) => Value::XStr(v.into()),
             None => Value::Null,
@@ -178,6 +365,34 @@ impl Value {
         Value::Object(v)
     }
 
+    /// Removes leading and trailing whitespace from every string, recursively.
+    pub(crate) fn trim_strings(&mut self) {
+        match self {
+            Value::String(s) | Value::XStr(s) => {
+                let trimmed = s.trim();
+                if trimmed.len() != s.len() {
+                    *s = trimmed.into();
+                }
+            }
+            Value::Object(map) => map.values_mut().for_each(Value::trim_strings),
+            Value::Array(vec) => vec.iter_mut().for_each(Value::trim_strings),
+            _ => {}
+        }
+    }
+
+    /// The bytes of the keys and strings in this value, one for each other scalar, see
+    /// `SecurityPolicy::max_params_size`.
+    #[cfg(server)]
+    pub(crate) fn byte_size(&self) -> usize {
+        match self {
+            Value::String(s) | Value::XStr(s) => s.len(),
+            Value::Object(map) => map.iter().map(|(k, v)| k.len() + v.byte_size()).sum(),
+            Value::Array(vec) => vec.iter().map(Value::byte_size).sum(),
+            Value::UploadFile(file) => file.name.len() + file.content_type.len(),
+            Value::Null | Value::Bool(_) | Value::Number(_) => 1,
+        }
+    }
+
     pub fn type_name(&self) -> &'static str {
         match self {
             Value::Null => "null",
@@ -190,12 +405,124 @@ impl Value {
             Value::UploadFile(_) => "file",
         }
     }
+
+    /// The value of `key` in an object, or at index `key` in an array.
+    pub fn get(&self, key: &str) -> Option<&Value> {
+        match self {
+            Value::Object(map) => map.get(key),
+            Value::Array(vec) => vec.get(key.parse::<usize>().ok()?),
+            _ => None,
+        }
+    }
+
+    pub fn get_mut(&mut self, key: &str) -> Option<&mut Value> {
+        match self {
+            Value::Object(map) => map.get_mut(key),
+            Value::Array(vec) => vec.get_mut(key.parse::<usize>().ok()?),
+            _ => None,
+        }
+    }
+
+    /// The value at the bracket path `path` below this one, like `user[address][city]` or
+    /// `tags[0]`.
+    pub fn get_path(&self, path: &str) -> Option<&Value> {
+        path_segments(path)?
+            .into_iter()
+            .try_fold(self, |value, key| value.get(key))
+    }
+
+    pub fn get_path_mut(&mut self, path: &str) -> Option<&mut Value> {
+        path_segments(path)?
+            .into_iter()
+            .try_fold(self, |value, key| value.get_mut(key))
+    }
+
+    /// The value at the JSON Pointer `pointer` (RFC 6901), like `/user/address/city`, as
+    /// `serde_json::Value::pointer`.
+    pub fn pointer(&self, pointer: &str) -> Option<&Value> {
+        pointer_tokens(pointer)?
+            .iter()
+            .try_fold(self, |value, key| value.get(key))
+    }
+
+    pub fn pointer_mut(&mut self, pointer: &str) -> Option<&mut Value> {
+        pointer_tokens(pointer)?
+            .iter()
+            .try_fold(self, |value, key| value.get_mut(key))
+    }
+}
+
+/// The keys of the bracket path `path`, `None` if it is malformed.
+pub(crate) fn path_segments(path: &str) -> Option<Vec<&str>> {
+    let (root, rest) = match path.find('[') {
+        Some(i) if i > 0 => path.split_at(i),
+        _ => (path, ""),
+    };
+    let mut segments = vec![root];
+    for segment in rest.split(']') {
+        match segment.strip_prefix('[') {
+            Some(segment) => segments.push(segment),
+            None if segment.is_empty() => break,
+            None => return None,
+        }
+    }
+    Some(segments)
+}
+
+fn pointer_tokens(pointer: &str) -> Option<Vec<String>> {
+    if pointer.is_empty() {
+        return Some(vec![]);
+    }
+    let tokens = pointer.strip_prefix('/')?.split('/');
+    Some(
+        tokens
+            .map(|token| token.replace("~1", "/").replace("~0", "~"))
+            .collect(),
+    )
+}
+
+/// Extends `a` with `b`, objects on both sides are merged recursively and other values of `b`
+/// replace those of `a`.
+fn deep_extend(a: &mut HashMap<String, Value>, b: HashMap<String, Value>) {
+    for (key, value) in b {
+        match (a.get_mut(&key), value) {
+            (Some(Value::Object(a)), Value::Object(b)) => deep_extend(a, b),
+            (_, value) => {
+                a.insert(key, value);
+            }
+        }
+    }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
 
+    #[test]
+    fn test_param_string() {
+        let Value::String(s) = Value::String(String::from("alice").into()) else {
+            unreachable!()
+        };
+        assert_eq!(s, "alice");
+        assert_eq!(s.len(), 5);
+        assert_eq!(format!("{s} {s:?}"), "alice \"alice\"");
+        assert_eq!(String::from(s), "alice");
+    }
+
+    #[cfg(feature = "compact_str")]
+    #[test]
+    fn test_compact_strings() {
+        let Value::XStr(short) = Value::xstr("value") else {
+            unreachable!()
+        };
+        assert!(!short.0.is_heap_allocated());
+        let Value::XStr(long) = Value::xstr("x".repeat(64)) else {
+            unreachable!()
+        };
+        assert!(long.0.is_heap_allocated());
+        assert_eq!(long.into_string(), "x".repeat(64));
+    }
+
     #[test]
     fn test_number_from_u64() {
         let n = Number::from(42u64);
@@ -223,6 +550,24 @@ mod tests {
         assert!(matches!(n.0, N::NegInt(i64::MIN)));
     }
 
+    #[test]
+    fn test_number_from_128() {
+        let n = Number::from(42u128);
+        assert!(matches!(n.0, N::PosInt(42)));
+
+        let n = Number::from(u64::MAX as u128 + 1);
+        assert!(matches!(n.0, N::PosInt128(v) if v == 1 << 64));
+
+        let n = Number::from(-42i128);
+        assert!(matches!(n.0, N::NegInt(-42)));
+
+        let n = Number::from(i128::MIN);
+        assert!(matches!(n.0, N::NegInt128(i128::MIN)));
+
+        let n = Number::from(i128::MAX);
+        assert!(matches!(n.0, N::PosInt128(_)));
+    }
+
     #[test]
     fn test_number_from_f64() {
         let n = Number::from(42.0);
@@ -241,6 +586,94 @@ mod tests {
         assert!(matches!(n.0, N::Float(v) if v == f64::MAX));
     }
 
+    #[test]
+    fn test_deep_merge() {
+        let user = |entries: &[(&str, Value)]| {
+            Value::object(HashMap::from([(
+                "user".to_string(),
+                Value::object(
+                    entries
+                        .iter()
+                        .map(|(k, v)| (k.to_string(), v.clone()))
+                        .collect(),
+                ),
+            )]))
+        };
+        let merged = user(&[("name", Value::xstr("alice")), ("age", Value::xstr("3"))])
+            .merge(user(&[("age", Value::number(30u64))]))
+            .unwrap();
+        assert_eq!(
+            merged,
+            user(&[
+                ("name", Value::xstr("alice")),
+                ("age", Value::number(30u64))
+            ])
+        );
+
+        let Value::Object(params) = user(&[("name", Value::xstr("alice"))]) else {
+            unreachable!()
+        };
+        let merged = user(&[("tags", Value::array(vec![Value::xstr("a")]))])
+            .merge_into(params)
+            .unwrap();
+        assert_eq!(
+            Value::Object(merged),
+            user(&[
+                ("name", Value::xstr("alice")),
+                ("tags", Value::array(vec![Value::xstr("a")]))
+            ])
+        );
+    }
+
+    #[test]
+    fn test_pointer_and_path() {
+        let mut params = Value::object(HashMap::from([(
+            "user".to_string(),
+            Value::object(HashMap::from([
+                (
+                    "address".to_string(),
+                    Value::object(HashMap::from([("city".to_string(), Value::xstr("Paris"))])),
+                ),
+                (
+                    "tags".to_string(),
+                    Value::array(vec![Value::xstr("a"), Value::xstr("b")]),
+                ),
+                ("a/b".to_string(), Value::bool(true)),
+            ])),
+        )]));
+
+        assert_eq!(
+            params.pointer("/user/address/city"),
+            Some(&Value::xstr("Paris"))
+        );
+        assert_eq!(params.pointer("/user/tags/1"), Some(&Value::xstr("b")));
+        assert_eq!(params.pointer("/user/a~1b"), Some(&Value::bool(true)));
+        assert_eq!(params.pointer(""), Some(&params));
+        assert_eq!(params.pointer("user"), None);
+        assert_eq!(params.pointer("/user/tags/2"), None);
+
+        assert_eq!(
+            params.get_path("user[address][city]"),
+            Some(&Value::xstr("Paris"))
+        );
+        assert_eq!(params.get_path("user[tags][0]"), Some(&Value::xstr("a")));
+        assert_eq!(params.get_path("user[address]x"), None);
+
+        *params.get_path_mut("user[address][city]").unwrap() = Value::xstr("Lyon");
+        if let Some(Value::Array(tags)) = params.pointer_mut("/user/tags") {
+            tags.push(Value::xstr("c"));
+        }
+        if let Some(Value::Object(user)) = params.get_mut("user") {
+            user.remove("a/b");
+        }
+        assert_eq!(
+            params.pointer("/user/address/city"),
+            Some(&Value::xstr("Lyon"))
+        );
+        assert_eq!(params.get_path("user[tags][2]"), Some(&Value::xstr("c")));
+        assert_eq!(params.pointer("/user/a~1b"), None);
+    }
+
     #[test]
     fn test_number_equality() {
         // Same type comparisons